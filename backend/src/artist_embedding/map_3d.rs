@@ -6,6 +6,7 @@ use tokio::{sync::OnceCell, task::spawn_blocking};
 use crate::{
     artist_embedding::{parse_positions, ArtistEmbeddingContext},
     db_util::{get_artist_spotify_ids_by_internal_id, get_internal_ids_by_spotify_id},
+    models::ArtistCoords3d,
     spotify_api::fetch_artists,
     DbConn,
 };
@@ -120,6 +121,18 @@ pub(crate) async fn get_map_3d_artist_ctx(
         .await
 }
 
+/// Returns the position of a single artist in the 3D artist map, or `None` if they're not in it
+pub(crate) fn get_artist_3d_coords(
+    map_ctx_3d: &ArtistEmbeddingContext<3>,
+    artist_internal_id: usize,
+) -> Option<ArtistCoords3d> {
+    let [x, y, z] = map_ctx_3d
+        .artist_position_by_id
+        .get(&artist_internal_id)?
+        .pos;
+    Some(ArtistCoords3d { x, y, z })
+}
+
 async fn build_packed_3d_artist_coords(
     conn: &DbConn,
     spotify_access_token: &str,
@@ -180,3 +193,23 @@ pub async fn get_packed_3d_artist_coords(
         .await
         .map(|v| v.as_slice())
 }
+
+#[test]
+fn test_get_artist_3d_coords() {
+    use crate::artist_embedding::ArtistPos;
+
+    let mut artist_position_by_id = HashMap::default();
+    artist_position_by_id.insert(7, ArtistPos::new([1., -2., 0.5]));
+    artist_position_by_id.insert(9, ArtistPos::new([0., 3., 4.]));
+    let map_ctx_3d = ArtistEmbeddingContext::new(artist_position_by_id);
+
+    assert_eq!(
+        get_artist_3d_coords(&map_ctx_3d, 7),
+        Some(ArtistCoords3d {
+            x: 1.,
+            y: -2.,
+            z: 0.5
+        })
+    );
+    assert_eq!(get_artist_3d_coords(&map_ctx_3d, 8), None);
+}
@@ -149,8 +149,8 @@ pub fn get_artist_embedding_ctx() -> &'static ArtistEmbeddingContext<8> {
 pub struct AverageArtistDescriptor {
     pub id: usize,
     pub similarity_to_target_point: f32,
-    pub similarity_to_artist_1: f32,
-    pub similarity_to_artist_2: f32,
+    /// Similarity to each of the seed artists, in the order they were provided
+    pub similarity_to_seeds: Vec<f32>,
 }
 
 impl AverageArtistDescriptor {
@@ -158,8 +158,7 @@ impl AverageArtistDescriptor {
         AverageArtistDescriptor {
             id: std::usize::MAX,
             similarity_to_target_point: std::f32::NEG_INFINITY,
-            similarity_to_artist_1: std::f32::NEG_INFINITY,
-            similarity_to_artist_2: std::f32::NEG_INFINITY,
+            similarity_to_seeds: Vec::new(),
         }
     }
 }
@@ -189,15 +188,15 @@ fn cosine_similarity<const DIMS: usize>(
     sum
 }
 
-fn weighted_midpoint<const DIMS: usize>(
-    v1: &[f32; DIMS],
-    v1_bias: f32,
-    v2: &[f32; DIMS],
-    v2_bias: f32,
-) -> [f32; DIMS] {
+fn weighted_centroid<const DIMS: usize>(points: &[(&[f32; DIMS], f32)]) -> [f32; DIMS] {
     let mut out: [f32; DIMS] = [0.; DIMS];
-    for i in 0..v1.len() {
-        out[i] = (v1[i] * v1_bias + v2[i] * v2_bias) / 2.
+    for (v, bias) in points {
+        for i in 0..v.len() {
+            out[i] += v[i] * bias;
+        }
+    }
+    for x in &mut out {
+        *x /= points.len() as f32;
     }
     out
 }
@@ -216,29 +215,42 @@ pub enum ArtistEmbeddingError {
     ArtistIdNotFound(usize),
 }
 
-pub fn get_average_artists(
-    artist_1_id: usize,
-    artist_1_bias: f32,
-    artist_2_id: usize,
-    artist_2_bias: f32,
+/// Finds the `count` artists closest to the weighted centroid of all of the provided
+/// `(artist_id, bias)` seeds in embedding space.
+pub fn get_weighted_average_artists(
+    seeds: &[(usize, f32)],
     count: usize,
 ) -> Result<Vec<AverageArtistDescriptor>, ArtistEmbeddingError> {
     let mut out = vec![AverageArtistDescriptor::new_placeholder(); count];
 
     let ctx = get_artist_embedding_ctx();
-    let (pos_1, pos_2) = ctx.get_positions(artist_1_id, artist_2_id)?;
-    let midpoint = weighted_midpoint(&pos_1.pos, artist_1_bias, &pos_2.pos, artist_2_bias);
-    let normalized_midpoint = normalize_vector(&midpoint);
+    let seed_positions = seeds
+        .iter()
+        .map(|&(id, bias)| match ctx.artist_position_by_id.get(&id) {
+            Some(pos) => Ok((pos, bias)),
+            None => {
+                error!("Artist internal id={} not found in embedding", id);
+                Err(ArtistEmbeddingError::ArtistIdNotFound(id))
+            },
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let centroid = weighted_centroid(
+        &seed_positions
+            .iter()
+            .map(|(pos, bias)| (&pos.pos, *bias))
+            .collect::<Vec<_>>(),
+    );
+    let normalized_centroid = normalize_vector(&centroid);
 
     let mut worst_retained_similarity = std::f32::NEG_INFINITY;
-    // Compute cosine distances between the midpoint and all artists.  Retain the top `count`
-    // artists with the highest similarities to the midpoint.
+    // Compute cosine distances between the centroid and all artists.  Retain the top `count`
+    // artists with the highest similarities to the centroid.
     for (&id, pos) in ctx.artist_position_by_id.iter() {
-        if id == artist_1_id || id == artist_2_id {
+        if seeds.iter().any(|&(seed_id, _)| seed_id == id) {
             continue;
         }
 
-        let similarity = cosine_similarity(&normalized_midpoint, &pos.normalized_pos);
+        let similarity = cosine_similarity(&normalized_centroid, &pos.normalized_pos);
         if similarity < worst_retained_similarity {
             continue;
         }
@@ -255,8 +267,12 @@ pub fn get_average_artists(
         out[pos_to_replace] = AverageArtistDescriptor {
             id,
             similarity_to_target_point: similarity,
-            similarity_to_artist_1: cosine_similarity(&pos.normalized_pos, &pos_1.normalized_pos),
-            similarity_to_artist_2: cosine_similarity(&pos.normalized_pos, &pos_2.normalized_pos),
+            similarity_to_seeds: seed_positions
+                .iter()
+                .map(|(seed_pos, _)| {
+                    cosine_similarity(&pos.normalized_pos, &seed_pos.normalized_pos)
+                })
+                .collect(),
         };
 
         worst_retained_similarity = out.last().unwrap().similarity_to_target_point;
@@ -348,3 +364,15 @@ fn test_cosine_similarity_accuracy() {
     let expected = 0.80182517;
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn test_weighted_centroid_matches_two_artist_midpoint() {
+    let x: [f32; 2] = [1., 2.];
+    let y: [f32; 2] = [3., -4.];
+
+    assert_eq!(weighted_centroid(&[(&x, 1.), (&y, 0.5)]), [1.25, 0.]);
+    assert_eq!(weighted_centroid(&[(&x, 1.), (&y, 1.), (&[2., 2.], 3.)]), [
+        10. / 3.,
+        4. / 3.
+    ]);
+}
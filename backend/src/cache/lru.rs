@@ -0,0 +1,122 @@
+//! A simple in-memory LRU cache with optional expiry for entries.
+
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use fnv::FnvHashMap as HashMap;
+
+struct Entry<V> {
+    value: V,
+    inserted_at: Instant,
+    /// Key of this entry in `LruCache::recency`
+    last_used: u64,
+}
+
+/// Holds up to `capacity` entries, evicting the least recently used entry to make room for new
+/// ones.  If a TTL is set, entries older than it are treated as missing.
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    entries: HashMap<K, Entry<V>>,
+    /// Keys of all entries, ordered from least to most recently used
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        LruCache {
+            capacity,
+            ttl,
+            entries: HashMap::default(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Returns the entry for `key` and marks it as the most recently used, or `None` if there is
+    /// no entry or it has expired
+    pub fn get<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        let is_expired = match (self.entries.get(key), self.ttl) {
+            (None, _) => return None,
+            (Some(entry), Some(ttl)) => entry.inserted_at.elapsed() > ttl,
+            (Some(_), None) => false,
+        };
+        if is_expired {
+            self.remove(key);
+            return None;
+        }
+
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(key).unwrap();
+        let key = self.recency.remove(&entry.last_used).unwrap();
+        self.recency.insert(tick, key);
+        entry.last_used = tick;
+        Some(&entry.value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&key);
+        while self.entries.len() >= self.capacity {
+            let (_, lru_key) = self.recency.pop_first().unwrap();
+            self.entries.remove(&lru_key);
+        }
+
+        let tick = self.next_tick();
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(key, Entry {
+            value,
+            inserted_at: Instant::now(),
+            last_used: tick,
+        });
+    }
+
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.last_used);
+        Some(entry.value)
+    }
+}
+
+#[test]
+fn lru_expires_entries_after_ttl() {
+    let mut cache = LruCache::new(10, Some(Duration::from_millis(20)));
+    cache.insert("a", 1);
+    assert_eq!(cache.get(&"a"), Some(&1));
+
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(cache.get(&"a"), None);
+}
+
+#[test]
+fn lru_evicts_least_recently_used_entry() {
+    let mut cache = LruCache::new(2, None);
+    cache.insert((1, 0), vec![1]);
+    cache.insert((1, 1), vec![2]);
+    // Reading the first entry makes the second one the least recently used
+    assert!(cache.get(&(1, 0)).is_some());
+
+    cache.insert((1, 2), vec![3]);
+    assert_eq!(cache.get(&(1, 0)), Some(&vec![1]));
+    assert_eq!(cache.get(&(1, 1)), None);
+    assert_eq!(cache.get(&(1, 2)), Some(&vec![3]));
+}
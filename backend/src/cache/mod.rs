@@ -7,6 +7,8 @@ use serde_json;
 use crate::conf::CONF;
 
 pub mod local_cache;
+pub mod lru;
+pub mod stats_responses;
 
 lazy_static::lazy_static! {
     pub static ref REDIS_CONN_POOL: r2d2::Pool<RedisConnectionManager> = {
@@ -0,0 +1,167 @@
+//! Caches the serialized responses of stats endpoints computed from the loaded listening history.
+//! Keys include the version of the dataset the response was computed from so that stale responses
+//! are never served after the data is reloaded.  The whole hash expires some time after it's
+//! created so that it can't grow without bound.
+
+use std::future::Future;
+
+use r2d2_redis::redis::Commands;
+use serde::Serialize;
+use tokio::task::spawn_blocking;
+
+use super::{get_hash_items, get_redis_conn, set_hash_items};
+
+/// Builds the key that the response of `endpoint` for `params` computed from the dataset with
+/// version `dataset_version` is cached under
+pub(crate) fn build_cache_key(
+    endpoint: &str,
+    params: &impl Serialize,
+    dataset_version: u64,
+) -> String {
+    let params =
+        serde_json::to_string(params).expect("Serializing cache key params to JSON can't fail");
+    format!("{}:{}:{}", endpoint, dataset_version, params)
+}
+
+pub(crate) fn get_cached_response(hash_name: &str, key: &str) -> Result<Option<String>, String> {
+    Ok(get_hash_items::<String>(hash_name, &[key])?.pop().flatten())
+}
+
+/// Caches `body` under `key`.  If the hash doesn't have an expiry yet, it's set to expire in
+/// `ttl_secs`.
+pub(crate) fn set_cached_response(
+    hash_name: &str,
+    ttl_secs: u64,
+    key: &str,
+    body: &str,
+) -> Result<(), String> {
+    set_hash_items(hash_name, &[(key, body)])?;
+
+    let mut conn = get_redis_conn()?;
+    let ttl: i64 = conn.ttl(hash_name).map_err(|err| -> String {
+        error!("Error reading expiry of \"{}\": {:?}", hash_name, err);
+        "Error caching response".into()
+    })?;
+    // A TTL of -1 means that the hash exists but has no expiry
+    if ttl == -1 {
+        conn.expire::<&str, ()>(hash_name, ttl_secs as usize)
+            .map_err(|err| -> String {
+                error!("Error setting expiry of \"{}\": {:?}", hash_name, err);
+                "Error caching response".into()
+            })?;
+    }
+    Ok(())
+}
+
+/// Drops all cached responses
+pub(crate) fn clear_cached_responses(hash_name: &str) -> Result<(), String> {
+    get_redis_conn()?
+        .del::<&str, ()>(hash_name)
+        .map_err(|err| -> String {
+            error!(
+                "Error clearing cached responses in \"{}\": {:?}",
+                hash_name, err
+            );
+            "Error clearing cached responses".into()
+        })
+}
+
+/// Returns the response cached under `key` if there is one, otherwise computes it with `compute`
+/// and caches it.  Caching is best-effort; if Redis can't be reached, the response is computed as
+/// if it was a miss.  The returned flag is `true` if the response came from the cache.
+pub(crate) async fn get_or_compute<E>(
+    hash_name: &'static str,
+    ttl_secs: u64,
+    key: String,
+    compute: impl Future<Output = Result<String, E>>,
+) -> Result<(String, bool), E> {
+    get_or_compute_with(
+        key,
+        move |key| get_cached_response(hash_name, key).ok().flatten(),
+        // Errors are already logged and the response is still served, just uncached
+        move |key, body| {
+            let _ = set_cached_response(hash_name, ttl_secs, key, body);
+        },
+        compute,
+    )
+    .await
+}
+
+/// Implements `get_or_compute` on top of arbitrary blocking `lookup` and `store` functions
+async fn get_or_compute_with<E>(
+    key: String,
+    lookup: impl FnOnce(&str) -> Option<String> + Send + 'static,
+    store: impl FnOnce(&str, &str) + Send + 'static,
+    compute: impl Future<Output = Result<String, E>>,
+) -> Result<(String, bool), E> {
+    let (key, cached) = spawn_blocking(move || {
+        let cached = lookup(&key);
+        (key, cached)
+    })
+    .await
+    .unwrap();
+    if let Some(body) = cached {
+        return Ok((body, true));
+    }
+
+    let body = compute.await?;
+    let to_cache = body.clone();
+    spawn_blocking(move || store(&key, &to_cache))
+        .await
+        .unwrap();
+    Ok((body, false))
+}
+
+#[test]
+fn stats_response_cache_key() {
+    let key = build_cache_key("get_genre_history", &Some(10), 1);
+    assert_eq!(key, "get_genre_history:1:10");
+    assert_eq!(key, build_cache_key("get_genre_history", &Some(10), 1));
+    assert_ne!(key, build_cache_key("get_genre_history", &Some(10), 2));
+    assert_ne!(key, build_cache_key("get_genre_history", &None::<i32>, 1));
+    assert_ne!(key, build_cache_key("get_timeline", &Some(10), 1));
+}
+
+#[tokio::test]
+async fn stats_response_cache_hit() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use fnv::FnvHashMap as HashMap;
+
+    let compute_count = &AtomicUsize::new(0);
+    let compute = || async move {
+        compute_count.fetch_add(1, Ordering::Relaxed);
+        Ok::<_, String>("{\"foo\":1}".to_owned())
+    };
+    let cache: Arc<Mutex<HashMap<String, String>>> = Arc::new(Mutex::new(HashMap::default()));
+    let get = |key: String| {
+        let (lookup_cache, store_cache) = (Arc::clone(&cache), Arc::clone(&cache));
+        get_or_compute_with(
+            key,
+            move |key| lookup_cache.lock().unwrap().get(key).cloned(),
+            move |key, body| {
+                store_cache
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_owned(), body.to_owned());
+            },
+            compute(),
+        )
+    };
+
+    let key = build_cache_key("get_genre_history", &Some(10), 1);
+    let first = get(key.clone()).await.unwrap();
+    let second = get(key).await.unwrap();
+    assert_eq!(first, ("{\"foo\":1}".to_owned(), false));
+    assert_eq!(second, ("{\"foo\":1}".to_owned(), true));
+    assert_eq!(compute_count.load(Ordering::Relaxed), 1);
+
+    // Responses computed from a different version of the dataset aren't served
+    let key = build_cache_key("get_genre_history", &Some(10), 2);
+    let (_, hit) = get(key).await.unwrap();
+    assert!(!hit);
+    assert_eq!(compute_count.load(Ordering::Relaxed), 2);
+}
@@ -3,6 +3,149 @@ use std::env;
 use base64;
 use chrono::Duration;
 
+/// Determines which plays count as being listened to together when building the co-listen graph
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CoOccurrenceWindow {
+    /// Artists played within this many consecutive plays of each other.  A long marathon session
+    /// and a handful of plays spread over several weeks are treated the same.
+    Plays(usize),
+    /// Artists played within this duration of each other.  This better reflects actual listening
+    /// sessions since plays separated by a long gap are never paired.
+    Time(Duration),
+}
+
+impl Default for CoOccurrenceWindow {
+    fn default() -> Self { CoOccurrenceWindow::Plays(50) }
+}
+
+impl CoOccurrenceWindow {
+    fn build_from_env() -> Self {
+        let size = env::var("CO_OCCURRENCE_WINDOW_SIZE").ok().map(|size| {
+            size.parse::<usize>().expect(
+                "Invalid value provided for `CO_OCCURRENCE_WINDOW_SIZE`; must be an unsigned \
+                 integer",
+            )
+        });
+
+        match env::var("CO_OCCURRENCE_WINDOW_MODE").as_deref() {
+            Ok("plays") | Err(_) => CoOccurrenceWindow::Plays(size.unwrap_or(50)),
+            Ok("time") => CoOccurrenceWindow::Time(Duration::minutes(size.unwrap_or(30) as i64)),
+            Ok(other) => panic!(
+                "Invalid value provided for `CO_OCCURRENCE_WINDOW_MODE`: \"{}\"; must be one of \
+                 \"plays\" or \"time\"",
+                other
+            ),
+        }
+    }
+}
+
+/// Determines which of a CSV export's genre columns are used as the genres for each play
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum GenreColumnPrecedence {
+    /// Use `Artist Genres`, falling back to `Genres` if it's empty
+    #[default]
+    PreferArtistGenres,
+    /// Use `Genres`, falling back to `Artist Genres` if it's empty
+    PreferTrackGenres,
+    /// Use the genres from both columns, with duplicates removed
+    MergeBoth,
+}
+
+impl GenreColumnPrecedence {
+    fn build_from_env() -> Self {
+        match env::var("GENRE_COLUMN_PRECEDENCE").as_deref() {
+            Ok("prefer_artist_genres") | Err(_) => GenreColumnPrecedence::PreferArtistGenres,
+            Ok("prefer_track_genres") => GenreColumnPrecedence::PreferTrackGenres,
+            Ok("merge_both") => GenreColumnPrecedence::MergeBoth,
+            Ok(other) => panic!(
+                "Invalid value provided for `GENRE_COLUMN_PRECEDENCE`: \"{}\"; must be one of \
+                 \"prefer_artist_genres\", \"prefer_track_genres\", or \"merge_both\"",
+                other
+            ),
+        }
+    }
+}
+
+/// Lengths of the short and medium timeframes used for top artists and tracks.  The long timeframe
+/// always covers all available data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TimeframeWindows {
+    pub short_window_days: u32,
+    pub medium_window_days: u32,
+}
+
+impl Default for TimeframeWindows {
+    fn default() -> Self {
+        TimeframeWindows {
+            short_window_days: 28,
+            medium_window_days: 180,
+        }
+    }
+}
+
+impl TimeframeWindows {
+    fn build_from_env() -> Self {
+        let parse_days = |var_name: &str, default: u32| match env::var(var_name) {
+            Ok(days) => days.parse().unwrap_or_else(|_| {
+                panic!(
+                    "Invalid value provided for `{}`; must be an unsigned integer",
+                    var_name
+                )
+            }),
+            Err(_) => default,
+        };
+
+        let defaults = TimeframeWindows::default();
+        TimeframeWindows {
+            short_window_days: parse_days("SHORT_WINDOW_DAYS", defaults.short_window_days),
+            medium_window_days: parse_days("MEDIUM_WINDOW_DAYS", defaults.medium_window_days),
+        }
+    }
+
+    pub(crate) fn short(&self) -> Duration { Duration::days(self.short_window_days as i64) }
+
+    pub(crate) fn medium(&self) -> Duration { Duration::days(self.medium_window_days as i64) }
+}
+
+/// Rules used to merge variants of the same artist's name when loading listening history
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ArtistNameNormalization {
+    /// Strip `(feat. ...)`-style suffixes from artist names, crediting the artists they name as
+    /// featured artists instead
+    pub strip_featuring: bool,
+    /// Merge artist names that only differ in casing, keeping the most played spelling
+    pub merge_casing: bool,
+}
+
+impl Default for ArtistNameNormalization {
+    fn default() -> Self {
+        ArtistNameNormalization {
+            strip_featuring: true,
+            merge_casing: true,
+        }
+    }
+}
+
+impl ArtistNameNormalization {
+    fn build_from_env() -> Self {
+        let parse_flag = |var_name: &str, default: bool| match env::var(var_name) {
+            Ok(flag) => flag.parse().unwrap_or_else(|_| {
+                panic!(
+                    "Invalid value provided for `{}`; must be \"true\" or \"false\"",
+                    var_name
+                )
+            }),
+            Err(_) => default,
+        };
+
+        let defaults = ArtistNameNormalization::default();
+        ArtistNameNormalization {
+            strip_featuring: parse_flag("NORMALIZE_ARTIST_FEATURING", defaults.strip_featuring),
+            merge_casing: parse_flag("NORMALIZE_ARTIST_CASING", defaults.merge_casing),
+        }
+    }
+}
+
 pub(crate) struct Conf {
     pub client_id: String,
     pub client_secret: String,
@@ -12,10 +155,83 @@ pub(crate) struct Conf {
     // Internal Config
     pub artists_cache_hash_name: String,
     pub tracks_cache_hash_name: String,
+    /// Redis hash holding cached responses of stats endpoints computed from the CSV data
+    pub stats_cache_hash_name: String,
+    /// Redis hash mapping the IDs of CSV artists to the Spotify IDs resolved for them
+    pub csv_artist_spotify_ids_hash_name: String,
     // Scraper config
     pub min_update_interval: Duration,
     pub admin_api_token: String,
     pub telemetry_server_port: u16,
+    /// Maximum number of times a Spotify API request is attempted when it is rate limited or fails
+    /// with a server error
+    pub spotify_api_max_attempts: u32,
+    /// Maximum number of artists kept in the in-memory artist metadata cache
+    pub artist_metadata_cache_capacity: usize,
+    /// How long artists are kept in the in-memory artist metadata cache before being re-fetched
+    pub artist_metadata_cache_ttl: std::time::Duration,
+    /// Maximum number of packed artist relationship chunks kept in memory
+    pub relationship_chunk_cache_capacity: usize,
+    /// How long to wait for Spotify to issue a new access token before giving up
+    pub spotify_token_fetch_timeout: std::time::Duration,
+    /// Whether the genre history includes empty buckets for months without any listening so that
+    /// its timestamps are evenly spaced
+    pub genre_history_fill_gaps: bool,
+    /// How long each concurrent slot of a bulk user update waits between users
+    pub user_update_delay: std::time::Duration,
+    /// Maximum random delay added on top of `user_update_delay` so that concurrent slots don't
+    /// hit the Spotify API in lockstep
+    pub user_update_delay_jitter: std::time::Duration,
+    /// Plays shorter than this are dropped when loading listening history so that they don't count
+    /// towards play counts or top lists.  Setting it to 0 keeps every play.
+    pub min_ms_played: u64,
+    /// Number of co-listened artists kept for each artist loaded from listening history, from 1 to
+    /// 255
+    pub max_related_artists: usize,
+    /// Requests that take longer than this to handle are logged as warnings
+    pub slow_request_threshold: std::time::Duration,
+    /// Largest number of artists returned by the average artists endpoints
+    pub max_average_artists_count: usize,
+    /// Sustained number of requests per second each client IP can make to public `GET` routes
+    pub rate_limit_requests_per_second: f64,
+    /// Number of requests each client IP can make in a burst before being rate limited
+    pub rate_limit_burst: u32,
+    /// Header that a trusted reverse proxy puts the client's IP in, such as `X-Real-IP`.  If
+    /// unset, clients are rate limited by the IP of the connection, so when running behind a
+    /// proxy this must be set or all requests will share the proxy's limit.  It must only be
+    /// set if the proxy always overwrites the header, since clients could otherwise spoof it
+    /// to evade the limit.
+    pub rate_limit_ip_header: Option<String>,
+    /// Local path or `http(s)://` or `s3://` URL of the listening history loaded at startup.
+    /// Local paths can also be a directory, in which case all of the listening history files
+    /// in it are merged.  Files ending in `.json` (or `.json.gz`) are read as Spotify JSON
+    /// streaming history exports, which don't include genres, so genre history is unavailable
+    /// for them.
+    pub csv_path: String,
+    /// Origin allowed to make cross-origin requests to the API; defaults to `website_url`
+    pub cors_allowed_origin: String,
+    /// How often to check whether the local listening history file has changed and reload it if
+    /// so.  Not checked at all if unset.
+    pub csv_reload_interval_secs: Option<u64>,
+    /// ISO 3166-1 alpha-2 country code of the Spotify market that artist top tracks and search
+    /// results are fetched for.  It determines which tracks are available and have previews.
+    pub spotify_market: String,
+    /// Look up previews from a fallback source for averaged artists' top tracks that Spotify
+    /// doesn't have a preview URL for
+    pub preview_url_fallback: bool,
+    /// How long cached stats responses are kept.  They're all dropped together this long after the
+    /// first one is cached.
+    pub stats_cache_ttl_secs: u64,
+    /// Configured via `CO_OCCURRENCE_WINDOW_MODE` (`plays` or `time`) and
+    /// `CO_OCCURRENCE_WINDOW_SIZE` (number of plays or minutes respectively)
+    pub co_occurrence_window: CoOccurrenceWindow,
+    /// Configured via `SHORT_WINDOW_DAYS` and `MEDIUM_WINDOW_DAYS`
+    pub timeframe_windows: TimeframeWindows,
+    /// Configured via `GENRE_COLUMN_PRECEDENCE` (`prefer_artist_genres`, `prefer_track_genres`, or
+    /// `merge_both`)
+    pub genre_column_precedence: GenreColumnPrecedence,
+    /// Configured via `NORMALIZE_ARTIST_FEATURING` and `NORMALIZE_ARTIST_CASING`
+    pub artist_name_normalization: ArtistNameNormalization,
 }
 
 impl Conf {
@@ -34,6 +250,8 @@ impl Conf {
                 .expect("The `REDIS_URL` environment variable must be set."),
             artists_cache_hash_name: "artists".into(),
             tracks_cache_hash_name: "tracks".into(),
+            stats_cache_hash_name: "stats_responses".into(),
+            csv_artist_spotify_ids_hash_name: "csv_artist_spotify_ids".into(),
             min_update_interval: Duration::seconds(
                 env::var("MIN_UPDATE_INTERVAL_SECONDS")
                     .unwrap_or_else(|_| -> String { (60 * 60 * 6).to_string() })
@@ -49,6 +267,158 @@ impl Conf {
                 .unwrap_or_else(|_| -> String { "4101".to_string() })
                 .parse()
                 .expect("Invalid value provided for `TELEMETRY_SERVER_PORT`; must be a u16"),
+            spotify_api_max_attempts: env::var("SPOTIFY_API_MAX_ATTEMPTS")
+                .unwrap_or_else(|_| -> String { "5".to_string() })
+                .parse()
+                .expect("Invalid value provided for `SPOTIFY_API_MAX_ATTEMPTS`; must be a u32"),
+            artist_metadata_cache_capacity: env::var("ARTIST_METADATA_CACHE_CAPACITY")
+                .unwrap_or_else(|_| -> String { "10000".to_string() })
+                .parse()
+                .expect(
+                    "Invalid value provided for `ARTIST_METADATA_CACHE_CAPACITY`; must be an \
+                     unsigned integer",
+                ),
+            artist_metadata_cache_ttl: std::time::Duration::from_secs(
+                env::var("ARTIST_METADATA_CACHE_TTL_SECONDS")
+                    .unwrap_or_else(|_| -> String { (60 * 60).to_string() })
+                    .parse()
+                    .expect(
+                        "Invalid value provided for `ARTIST_METADATA_CACHE_TTL_SECONDS`; must be \
+                         an unsigned integer",
+                    ),
+            ),
+            relationship_chunk_cache_capacity: env::var("RELATIONSHIP_CHUNK_CACHE_CAPACITY")
+                .unwrap_or_else(|_| -> String { "256".to_string() })
+                .parse()
+                .expect(
+                    "Invalid value provided for `RELATIONSHIP_CHUNK_CACHE_CAPACITY`; must be an \
+                     unsigned integer",
+                ),
+            spotify_token_fetch_timeout: std::time::Duration::from_secs(
+                env::var("SPOTIFY_TOKEN_FETCH_TIMEOUT_SECONDS")
+                    .unwrap_or_else(|_| -> String { "10".to_string() })
+                    .parse()
+                    .expect(
+                        "Invalid value provided for `SPOTIFY_TOKEN_FETCH_TIMEOUT_SECONDS`; must \
+                         be an unsigned integer",
+                    ),
+            ),
+            genre_history_fill_gaps: env::var("GENRE_HISTORY_FILL_GAPS")
+                .unwrap_or_else(|_| -> String { "true".to_string() })
+                .parse()
+                .expect(
+                    "Invalid value provided for `GENRE_HISTORY_FILL_GAPS`; must be \"true\" or \
+                     \"false\"",
+                ),
+            user_update_delay: std::time::Duration::from_millis(
+                env::var("USER_UPDATE_DELAY_MS")
+                    .unwrap_or_else(|_| -> String { "1000".to_string() })
+                    .parse()
+                    .expect(
+                        "Invalid value provided for `USER_UPDATE_DELAY_MS`; must be an unsigned \
+                         integer",
+                    ),
+            ),
+            user_update_delay_jitter: std::time::Duration::from_millis(
+                env::var("USER_UPDATE_DELAY_JITTER_MS")
+                    .unwrap_or_else(|_| -> String { "500".to_string() })
+                    .parse()
+                    .expect(
+                        "Invalid value provided for `USER_UPDATE_DELAY_JITTER_MS`; must be an \
+                         unsigned integer",
+                    ),
+            ),
+            min_ms_played: env::var("MIN_MS_PLAYED")
+                .unwrap_or_else(|_| -> String { "0".to_string() })
+                .parse()
+                .expect("Invalid value provided for `MIN_MS_PLAYED`; must be an unsigned integer"),
+            max_related_artists: env::var("MAX_RELATED_ARTISTS")
+                .unwrap_or_else(|_| -> String { "20".to_string() })
+                .parse()
+                .ok()
+                .filter(|count| (1..=u8::MAX as usize).contains(count))
+                .expect(
+                    "Invalid value provided for `MAX_RELATED_ARTISTS`; must be an integer from 1 \
+                     to 255",
+                ),
+            slow_request_threshold: std::time::Duration::from_millis(
+                env::var("SLOW_REQUEST_MS")
+                    .unwrap_or_else(|_| -> String { "2000".to_string() })
+                    .parse()
+                    .expect(
+                        "Invalid value provided for `SLOW_REQUEST_MS`; must be an unsigned integer",
+                    ),
+            ),
+            max_average_artists_count: env::var("MAX_AVERAGE_ARTISTS_COUNT")
+                .unwrap_or_else(|_| -> String { "50".to_string() })
+                .parse()
+                .ok()
+                .filter(|count| *count >= 1)
+                .expect(
+                    "Invalid value provided for `MAX_AVERAGE_ARTISTS_COUNT`; must be a positive \
+                     integer",
+                ),
+            rate_limit_requests_per_second: env::var("RATE_LIMIT_REQUESTS_PER_SECOND")
+                .unwrap_or_else(|_| -> String { "10".to_string() })
+                .parse()
+                .ok()
+                .filter(|rate: &f64| *rate > 0.)
+                .expect(
+                    "Invalid value provided for `RATE_LIMIT_REQUESTS_PER_SECOND`; must be a \
+                     positive number",
+                ),
+            rate_limit_burst: env::var("RATE_LIMIT_BURST")
+                .unwrap_or_else(|_| -> String { "30".to_string() })
+                .parse()
+                .ok()
+                .filter(|burst| *burst >= 1)
+                .expect(
+                    "Invalid value provided for `RATE_LIMIT_BURST`; must be a positive integer",
+                ),
+            rate_limit_ip_header: env::var("RATE_LIMIT_IP_HEADER")
+                .ok()
+                .filter(|header| !header.is_empty()),
+            csv_path: env::var("CSV_PATH")
+                .unwrap_or_else(|_| -> String { "listening_history.csv".to_string() }),
+            cors_allowed_origin: env::var("CORS_ALLOWED_ORIGIN")
+                .or_else(|_| env::var("WEBSITE_URL"))
+                .expect("The `CORS_ALLOWED_ORIGIN` or `WEBSITE_URL` must be set."),
+            csv_reload_interval_secs: env::var("CSV_RELOAD_INTERVAL_SECS").ok().map(|secs| {
+                secs.parse().ok().filter(|secs| *secs > 0).expect(
+                    "Invalid value provided for `CSV_RELOAD_INTERVAL_SECS`; must be a positive \
+                     integer",
+                )
+            }),
+            spotify_market: Some(
+                env::var("SPOTIFY_MARKET").unwrap_or_else(|_| -> String { "US".to_string() }),
+            )
+            .filter(|market| {
+                market.len() == 2 && market.bytes().all(|byte| byte.is_ascii_alphabetic())
+            })
+            .map(|market| market.to_ascii_uppercase())
+            .expect(
+                "Invalid value provided for `SPOTIFY_MARKET`; must be a two-letter ISO 3166-1 \
+                 country code",
+            ),
+            preview_url_fallback: env::var("PREVIEW_URL_FALLBACK")
+                .unwrap_or_else(|_| -> String { "false".to_string() })
+                .parse()
+                .expect(
+                    "Invalid value provided for `PREVIEW_URL_FALLBACK`; must be \"true\" or \
+                     \"false\"",
+                ),
+            stats_cache_ttl_secs: env::var("STATS_CACHE_TTL_SECS")
+                .unwrap_or_else(|_| -> String { (60 * 60 * 24).to_string() })
+                .parse()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .expect(
+                    "Invalid value provided for `STATS_CACHE_TTL_SECS`; must be a positive integer",
+                ),
+            co_occurrence_window: CoOccurrenceWindow::build_from_env(),
+            timeframe_windows: TimeframeWindows::build_from_env(),
+            genre_column_precedence: GenreColumnPrecedence::build_from_env(),
+            artist_name_normalization: ArtistNameNormalization::build_from_env(),
         }
     }
 
@@ -1,27 +1,53 @@
 use rocket::{
     fairing::{Fairing, Info, Kind},
-    http::{Method, Status},
+    http::{Header, Method, Status},
     Request, Response,
 };
 
-pub(crate) struct CorsFairing;
+/// Request headers that browsers are allowed to send cross-origin
+const ALLOWED_HEADERS: &str = "Content-Type, If-None-Match, X-Request-Id, sentry-trace";
+/// Response headers that are readable by cross-origin scripts
+const EXPOSED_HEADERS: &str = "X-Request-Id, ETag";
+/// How long browsers can cache the result of a preflight request
+const PREFLIGHT_MAX_AGE_SECS: u32 = 60 * 60 * 24;
+
+pub(crate) struct CorsFairing {
+    allowed_origin: String,
+}
+
+impl CorsFairing {
+    pub fn new(allowed_origin: String) -> Self { CorsFairing { allowed_origin } }
+}
 
 #[rocket::async_trait]
 impl Fairing for CorsFairing {
     async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
-        // Add CORS headers to allow all origins to all outgoing requests
-        res.set_header(rocket::http::Header::new(
+        // Add CORS headers allowing the configured origin to all outgoing requests
+        res.set_header(Header::new(
             "Access-Control-Allow-Origin",
-            "*",
+            self.allowed_origin.clone(),
         ));
-        res.set_header(rocket::http::Header::new(
-            "Access-Control-Allow-Headers",
-            "sentry-trace",
+        res.set_header(Header::new("Vary", "Origin"));
+        res.set_header(Header::new(
+            "Access-Control-Expose-Headers",
+            EXPOSED_HEADERS,
         ));
 
-        // Respond to all `OPTIONS` requests with a `204` (no content) status
-        if res.status() == Status::NotFound && req.method() == Method::Options {
-            res.set_status(Status::NoContent);
+        if req.method() == Method::Options {
+            res.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                "GET, POST, OPTIONS",
+            ));
+            res.set_header(Header::new("Access-Control-Allow-Headers", ALLOWED_HEADERS));
+            res.set_header(Header::new(
+                "Access-Control-Max-Age",
+                PREFLIGHT_MAX_AGE_SECS.to_string(),
+            ));
+
+            // Respond to all preflight requests with a `204` (no content) status
+            if res.status() == Status::NotFound {
+                res.set_status(Status::NoContent);
+            }
         }
     }
 
@@ -32,3 +58,56 @@ impl Fairing for CorsFairing {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::{Header, Status},
+        local::blocking::Client,
+    };
+
+    use super::CorsFairing;
+
+    #[get("/stats/<username>")]
+    fn stats(username: &str) -> String { username.to_owned() }
+
+    #[test]
+    fn test_preflight_returns_cors_headers() {
+        let rocket = rocket::build()
+            .mount("/", routes![stats])
+            .attach(CorsFairing::new("https://spotifytrack.net".to_owned()));
+        let client = Client::tracked(rocket).unwrap();
+
+        let res = client
+            .options("/stats/user")
+            .header(Header::new("Origin", "https://spotifytrack.net"))
+            .header(Header::new("Access-Control-Request-Method", "GET"))
+            .dispatch();
+        assert_eq!(res.status(), Status::NoContent);
+        let headers = res.headers();
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Origin"),
+            Some("https://spotifytrack.net")
+        );
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Methods"),
+            Some("GET, POST, OPTIONS")
+        );
+        assert_eq!(
+            headers.get_one("Access-Control-Expose-Headers"),
+            Some("X-Request-Id, ETag")
+        );
+
+        // Regular requests get the allowed origin but no preflight headers
+        let res = client.get("/stats/user").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(
+            res.headers().get_one("Access-Control-Allow-Origin"),
+            Some("https://spotifytrack.net")
+        );
+        assert!(res
+            .headers()
+            .get_one("Access-Control-Allow-Methods")
+            .is_none());
+    }
+}
@@ -1,11 +1,31 @@
-use std::sync::Arc;
+use std::{
+    cmp::Reverse,
+    future::Future,
+    io::{BufRead, BufReader, Cursor, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
 
-use chrono::{DateTime, Utc};
-use fnv::FnvHashMap;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Utc};
+use flate2::read::GzDecoder;
+use fnv::{FnvHashMap, FnvHashSet};
+use object_store::{aws::AmazonS3Builder, path::Path as ObjectStorePath, ObjectStore};
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, task::spawn_blocking};
 
-use crate::models::{Artist, Track};
+use crate::{
+    cache::{get_hash_items, set_hash_items, stats_responses::clear_cached_responses},
+    conf::{
+        ArtistNameNormalization, CoOccurrenceWindow, GenreColumnPrecedence, TimeframeWindows, CONF,
+    },
+    models::{Artist, TimeFrames, Track, UserComparison},
+    spotify_api::get_reqwest_client,
+    time_util::{local_time_parts, to_local_time},
+};
 
 #[derive(Debug, Clone, Deserialize)]
 struct CsvRecord {
@@ -19,34 +39,227 @@ struct CsvRecord {
     genres: String,
     #[serde(rename = "Artist Genres")]
     artist_genres: String,
+    /// Not present in all exports
+    #[serde(rename = "Album Name", default)]
+    album_name: Option<String>,
+    /// Not present in all exports
+    #[serde(rename = "Track Duration (ms)", default)]
+    duration_ms: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// A single record from one of Spotify's JSON streaming history exports.  Both the extended
+/// streaming history format (`endsong_*.json`) and the basic account data format
+/// (`StreamingHistory*.json`) are supported.
+///
+/// Neither format includes genres, so genre-based stats (genre history, genre listings, etc.) are
+/// unavailable for data loaded from these files.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum StreamingHistoryRecord {
+    Extended {
+        ts: String,
+        /// `null` for podcast episodes and some other non-track items
+        master_metadata_track_name: Option<String>,
+        master_metadata_album_artist_name: Option<String>,
+        #[serde(default)]
+        master_metadata_album_album_name: Option<String>,
+        ms_played: u64,
+    },
+    Basic {
+        #[serde(rename = "endTime")]
+        end_time: String,
+        #[serde(rename = "artistName")]
+        artist_name: String,
+        #[serde(rename = "trackName")]
+        track_name: String,
+        #[serde(rename = "msPlayed")]
+        ms_played: u64,
+    },
+}
+
+impl CsvRecord {
+    fn into_listening_entry(
+        self,
+        genre_precedence: GenreColumnPrecedence,
+    ) -> Result<ListeningEntry, String> {
+        let timestamp = DateTime::parse_from_rfc3339(&self.ts)
+            .map_err(|e| format!("Failed to parse timestamp: {}", e))?
+            .with_timezone(&Utc);
+
+        let artist_genres = parse_genres(&self.artist_genres);
+        let track_genres = parse_genres(&self.genres);
+        let genres = match genre_precedence {
+            GenreColumnPrecedence::PreferArtistGenres if !artist_genres.is_empty() => artist_genres,
+            GenreColumnPrecedence::PreferArtistGenres => track_genres,
+            GenreColumnPrecedence::PreferTrackGenres if !track_genres.is_empty() => track_genres,
+            GenreColumnPrecedence::PreferTrackGenres => artist_genres,
+            GenreColumnPrecedence::MergeBoth => {
+                let mut genres = artist_genres;
+                for genre in track_genres {
+                    let lowercase_genre = genre.to_lowercase();
+                    if !genres.iter().any(|g| g.to_lowercase() == lowercase_genre) {
+                        genres.push(genre);
+                    }
+                }
+                genres
+            },
+        };
+
+        let (artist_name, featured_artist_names) = split_artist_names(self.artist_name);
+        Ok(ListeningEntry {
+            timestamp,
+            track_name: self.track_name,
+            artist_name,
+            featured_artist_names,
+            album_name: self.album_name.filter(|name| !name.is_empty()),
+            ms_played: self.ms_played,
+            duration_ms: self.duration_ms.filter(|duration_ms| *duration_ms > 0),
+            genres,
+        })
+    }
+}
+
+impl StreamingHistoryRecord {
+    /// Returns `Ok(None)` for records that aren't tracks, such as podcast episodes
+    fn into_listening_entry(self) -> Result<Option<ListeningEntry>, String> {
+        let (timestamp, track_name, artist_name, album_name, ms_played) = match self {
+            StreamingHistoryRecord::Extended {
+                ts,
+                master_metadata_track_name: Some(track_name),
+                master_metadata_album_artist_name: Some(artist_name),
+                master_metadata_album_album_name: album_name,
+                ms_played,
+            } => {
+                let timestamp = DateTime::parse_from_rfc3339(&ts)
+                    .map_err(|e| format!("Failed to parse timestamp: {}", e))?
+                    .with_timezone(&Utc);
+                (timestamp, track_name, artist_name, album_name, ms_played)
+            },
+            StreamingHistoryRecord::Extended { .. } => return Ok(None),
+            StreamingHistoryRecord::Basic {
+                end_time,
+                artist_name,
+                track_name,
+                ms_played,
+            } => {
+                let timestamp = NaiveDateTime::parse_from_str(&end_time, "%Y-%m-%d %H:%M")
+                    .map_err(|e| format!("Failed to parse timestamp: {}", e))?
+                    .and_utc();
+                (timestamp, track_name, artist_name, None, ms_played)
+            },
+        };
+
+        Ok(Some(ListeningEntry {
+            timestamp,
+            track_name,
+            artist_name,
+            featured_artist_names: Vec::new(),
+            album_name: album_name.filter(|name| !name.is_empty()),
+            ms_played,
+            duration_ms: None,
+            genres: Vec::new(),
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ListeningEntry {
     pub timestamp: DateTime<Utc>,
     pub track_name: String,
+    /// The first artist credited on the track.  Tracks are keyed by this artist.
     pub artist_name: String,
+    /// Any other artists credited on the track, for rows listing multiple artists
+    pub featured_artist_names: Vec<String>,
+    /// `None` if the source data doesn't include album names
+    pub album_name: Option<String>,
     pub ms_played: u64,
+    /// Full length of the track; `None` if the source data doesn't include track durations
+    pub duration_ms: Option<u32>,
     pub genres: Vec<String>,
 }
 
+impl ListeningEntry {
+    /// Returns the names of all artists credited on the track, starting with `artist_name`
+    pub(crate) fn artist_names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.artist_name.as_str())
+            .chain(self.featured_artist_names.iter().map(String::as_str))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CsvData {
     pub entries: Vec<ListeningEntry>,
     pub artists: FnvHashMap<String, Artist>,
     pub tracks: FnvHashMap<String, Track>,
+    /// IDs of the top artists for each timeframe; these are keys into `artists`
     pub top_artists_short: Vec<String>,
     pub top_artists_medium: Vec<String>,
     pub top_artists_long: Vec<String>,
+    /// IDs of the top tracks for each timeframe; these are keys into `tracks`
     pub top_tracks_short: Vec<String>,
     pub top_tracks_medium: Vec<String>,
     pub top_tracks_long: Vec<String>,
+    /// Number of times each artist was played, keyed by artist name
+    pub artist_play_counts: FnvHashMap<String, usize>,
+    /// Total time each artist was played in milliseconds, keyed by artist name
+    pub artist_ms_played: FnvHashMap<String, u64>,
+    /// Number of times each track was played, keyed by `(track_name, artist_name)`
+    pub track_play_counts: FnvHashMap<(String, String), usize>,
+    /// Total time each track was played in milliseconds, keyed by `(track_name, artist_name)`
+    pub track_ms_played: FnvHashMap<(String, String), u64>,
+    /// When each track was first played, keyed by `(track_name, artist_name)`
+    pub track_first_seen: FnvHashMap<(String, String), DateTime<Utc>>,
+    /// Day with the most time played along with its total time played in milliseconds
+    pub most_played_day: Option<(NaiveDate, u64)>,
+    /// Longest listening session when split with a gap of `DEFAULT_SESSION_GAP_MINUTES`
+    pub longest_session: Option<ListeningSession>,
+    /// Artists most often listened to alongside each artist, keyed by artist ID.  Each entry is
+    /// `(related_artist_id, co_occurrence_count)`, sorted by descending count.
+    pub related_artists: FnvHashMap<String, Vec<(String, u32)>>,
+    /// Stamp identifying this version of the data.  It's unique to every load and changes whenever
+    /// the data is modified, so it can be used to invalidate anything derived from it.
+    pub version: u64,
+}
+
+impl CsvData {
+    /// Whether there's no listening history at all, as happens when the source only has a header
+    /// or every row was filtered out
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+}
+
+/// An inclusive range of days used to scope stats.  Either bound may be left open.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub(crate) fn contains(&self, timestamp: DateTime<Utc>) -> bool {
+        let date = timestamp.date_naive();
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
 }
 
+/// Number of top artists and tracks precomputed for each timeframe when the data is loaded
+pub(crate) const PRECOMPUTED_TOP_N: usize = 50;
+/// Default gap between plays that starts a new listening session
+pub(crate) const DEFAULT_SESSION_GAP_MINUTES: u32 = 30;
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+
 lazy_static::lazy_static! {
     static ref CSV_DATA: RwLock<Option<Arc<CsvData>>> = RwLock::new(None);
+    /// Additional datasets registered under a username, used to compare listening histories
+    static ref NAMED_CSV_DATA: RwLock<FnvHashMap<String, Arc<CsvData>>> =
+        RwLock::new(FnvHashMap::default());
 }
 
+/// Most recently issued `CsvData` version stamp.  Stamps are seeded from the current time so that
+/// they don't repeat across restarts.
+static LAST_CSV_DATA_VERSION: AtomicU64 = AtomicU64::new(0);
+/// Set while the main dataset is being reloaded so that overlapping reloads are skipped
+static CSV_RELOAD_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+
 fn parse_genres(genres_str: &str) -> Vec<String> {
     genres_str
         .split(',')
@@ -55,62 +268,572 @@ fn parse_genres(genres_str: &str) -> Vec<String> {
         .collect()
 }
 
-/// Load and parse the CSV file
-pub async fn load_csv_data() -> Result<(), String> {
-    let csv_path = std::path::Path::new("listening_history.csv");
-    
+/// Splits the comma-separated `Artist Name(s)` column of collaborations into the first artist and
+/// the rest.  Names that themselves contain commas can't be told apart from collaborations and get
+/// split as well.
+fn split_artist_names(artist_names: String) -> (String, Vec<String>) {
+    let mut names: Vec<String> = Vec::new();
+    for name in artist_names.split(',').map(str::trim) {
+        if !name.is_empty() && !names.iter().any(|existing| existing == name) {
+            names.push(name.to_owned());
+        }
+    }
+    if names.is_empty() {
+        return (artist_names, Vec::new());
+    }
+
+    let featured_artist_names = names.split_off(1);
+    (names.pop().unwrap(), featured_artist_names)
+}
+
+/// Markers that start a featured artist suffix in an artist name, in lowercase
+const FEATURING_MARKERS: [&str; 4] = ["(feat.", "(ft.", "(featuring ", "[feat."];
+
+/// Splits a `(feat. ...)`-style suffix off of `artist_name`, returning the name without it along
+/// with the names of the artists it credits.  Credited artists are separated by commas or `&`.  The
+/// name is returned unchanged if nothing would be left of it.
+fn split_featuring_suffix(artist_name: &str) -> (&str, Vec<&str>) {
+    // ASCII lowercasing keeps byte offsets the same as in the original name
+    let lowercase_name = artist_name.to_ascii_lowercase();
+    let (ix, marker) = match FEATURING_MARKERS
+        .iter()
+        .filter_map(|marker| lowercase_name.find(marker).map(|ix| (ix, marker)))
+        .min()
+    {
+        Some(found) => found,
+        None => return (artist_name, Vec::new()),
+    };
+    let stripped = artist_name[..ix].trim_end();
+    if stripped.is_empty() {
+        return (artist_name, Vec::new());
+    }
+
+    let suffix = &artist_name[ix + marker.len()..];
+    let suffix = match suffix.find(|c| c == ')' || c == ']') {
+        Some(end_ix) => &suffix[..end_ix],
+        None => suffix,
+    };
+    let featured_artist_names = suffix
+        .split(|c| c == ',' || c == '&')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    (stripped, featured_artist_names)
+}
+
+/// Rewrites the artist names of all `entries` so that variants of the same artist are merged.
+/// Names are always trimmed, and depending on `normalization`, featured artist suffixes are split
+/// off into `featured_artist_names` and names differing only in casing are replaced with their most
+/// played spelling.
+pub(crate) fn normalize_artist_names(
+    entries: &mut [ListeningEntry],
+    normalization: ArtistNameNormalization,
+) {
+    // Returns the names of the artists credited in a stripped featuring suffix
+    let normalize = |name: &mut String| -> Vec<String> {
+        let trimmed = name.trim();
+        let (normalized, featured_artist_names) = if normalization.strip_featuring {
+            split_featuring_suffix(trimmed)
+        } else {
+            (trimmed, Vec::new())
+        };
+        let featured_artist_names = featured_artist_names
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        if normalized.len() != name.len() {
+            *name = normalized.to_owned();
+        }
+        featured_artist_names
+    };
+    for entry in entries.iter_mut() {
+        let mut stripped_names = normalize(&mut entry.artist_name);
+        for featured_artist_name in entry.featured_artist_names.iter_mut() {
+            stripped_names.extend(normalize(featured_artist_name));
+        }
+        entry.featured_artist_names.extend(stripped_names);
+    }
+
+    if normalization.merge_casing {
+        let mut spelling_play_counts: FnvHashMap<&str, usize> = FnvHashMap::default();
+        for name in entries.iter().flat_map(ListeningEntry::artist_names) {
+            *spelling_play_counts.entry(name).or_insert(0) += 1;
+        }
+        // Lowercased name -> its most played spelling, preferring the first alphabetically on ties
+        let mut display_names: FnvHashMap<String, (&str, usize)> = FnvHashMap::default();
+        for (spelling, play_count) in spelling_play_counts {
+            let display_name = display_names
+                .entry(spelling.to_lowercase())
+                .or_insert((spelling, play_count));
+            if (play_count, Reverse(spelling)) > (display_name.1, Reverse(display_name.0)) {
+                *display_name = (spelling, play_count);
+            }
+        }
+        let display_names: FnvHashMap<String, String> = display_names
+            .into_iter()
+            .map(|(lowercase_name, (spelling, _))| (lowercase_name, spelling.to_owned()))
+            .collect();
+
+        let canonicalize = |name: &mut String| match display_names.get(&name.to_lowercase()) {
+            Some(display_name) if *display_name != *name => *name = display_name.clone(),
+            _ => (),
+        };
+        for entry in entries.iter_mut() {
+            canonicalize(&mut entry.artist_name);
+            entry
+                .featured_artist_names
+                .iter_mut()
+                .for_each(canonicalize);
+        }
+    }
+
+    // Merging can leave artists credited more than once on the same play
+    for entry in entries.iter_mut() {
+        let mut seen_names: FnvHashSet<&str> = FnvHashSet::default();
+        seen_names.insert(entry.artist_name.as_str());
+        entry
+            .featured_artist_names
+            .retain(|name| seen_names.insert(name.as_str()));
+    }
+}
+
+/// Fake Spotify ID for an artist loaded from CSV data
+pub(crate) fn artist_id(artist_name: &str) -> String {
+    format!("csv_{}", artist_name.replace(' ', "_").to_lowercase())
+}
+
+/// Fake Spotify ID for a track loaded from CSV data
+pub(crate) fn track_id(track_name: &str, artist_name: &str) -> String {
+    format!(
+        "csv_{}",
+        format!("{}_{}", track_name, artist_name)
+            .replace(' ', "_")
+            .to_lowercase()
+    )
+}
+
+/// Fake Spotify ID for an album loaded from CSV data
+fn album_id(album_name: &str, artist_name: &str) -> String {
+    format!(
+        "csv_album_{}",
+        format!("{}_{}", album_name, artist_name)
+            .replace(' ', "_")
+            .to_lowercase()
+    )
+}
+
+/// Options controlling how aggregate data is computed from listening entries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct CsvBuildOptions {
+    /// Rules used to merge variants of the same artist's name before anything is aggregated
+    pub artist_name_normalization: ArtistNameNormalization,
+    /// Which plays count as being listened to together when building the co-listen graph
+    pub co_occurrence_window: CoOccurrenceWindow,
+    /// Time spans covered by the short and medium timeframes of top lists
+    pub timeframe_windows: TimeframeWindows,
+    /// Entries shorter than this are dropped before anything is aggregated; 0 keeps all of them
+    pub min_ms_played: u64,
+    /// Number of co-listened artists kept for each artist
+    pub max_related_artists: usize,
+}
+
+impl Default for CsvBuildOptions {
+    fn default() -> Self {
+        CsvBuildOptions {
+            artist_name_normalization: ArtistNameNormalization::default(),
+            co_occurrence_window: CoOccurrenceWindow::default(),
+            timeframe_windows: TimeframeWindows::default(),
+            min_ms_played: 0,
+            max_related_artists: 20,
+        }
+    }
+}
+
+impl CsvBuildOptions {
+    /// The configured loading options
+    fn from_conf() -> Self {
+        CsvBuildOptions {
+            artist_name_normalization: CONF.artist_name_normalization,
+            co_occurrence_window: CONF.co_occurrence_window,
+            timeframe_windows: CONF.timeframe_windows,
+            min_ms_played: CONF.min_ms_played,
+            max_related_artists: CONF.max_related_artists,
+        }
+    }
+}
+
+/// Parses the listening history at `csv_path` and computes all aggregate data from it.  If
+/// `csv_path` is a directory, all of the listening history files in it are merged.  This doesn't
+/// touch the currently loaded data, so a file that fails to parse leaves the existing dataset in
+/// place.
+fn parse_csv_file(
+    csv_path: &Path,
+    genre_precedence: GenreColumnPrecedence,
+    options: CsvBuildOptions,
+) -> Result<CsvData, String> {
+    let entries = if csv_path.is_dir() {
+        read_csv_entries_from_dir(csv_path, genre_precedence)?
+    } else {
+        read_csv_entries_from_file(csv_path, genre_precedence)?
+    };
+    Ok(build_csv_data(entries, options))
+}
+
+/// Parses the listening history file at `csv_path` using the configured loading options
+fn parse_csv_file_from_conf(csv_path: &Path) -> Result<CsvData, String> {
+    parse_csv_file(
+        csv_path,
+        CONF.genre_column_precedence,
+        CsvBuildOptions::from_conf(),
+    )
+}
+
+/// Parses an uploaded listening history CSV using the configured loading options
+pub(crate) fn parse_uploaded_csv(csv_bytes: &[u8]) -> Result<CsvData, String> {
+    parse_csv_entries(csv_bytes, CONF.genre_column_precedence).map(build_csv_data_from_conf)
+}
+
+/// Computes all aggregate data from `entries` using the configured loading options
+fn build_csv_data_from_conf(entries: Vec<ListeningEntry>) -> CsvData {
+    build_csv_data(entries, CsvBuildOptions::from_conf())
+}
+
+#[cfg(test)]
+pub(crate) fn parse_csv<R: Read>(reader: R) -> Result<CsvData, String> {
+    parse_csv_entries(reader, GenreColumnPrecedence::default())
+        .map(|entries| build_csv_data(entries, CsvBuildOptions::default()))
+}
+
+/// Opens the file at `path` for reading.  Gzip-compressed files are detected by either a `.gz`
+/// extension or the gzip magic header and are transparently decompressed.
+fn open_maybe_gzipped(path: &Path) -> Result<Box<dyn Read>, String> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open file {}: {}", path.display(), e))?;
+    let has_gz_extension = path.extension().is_some_and(|ext| ext == "gz");
+    maybe_gunzip(BufReader::new(file), has_gz_extension)
+        .map_err(|e| format!("Failed to read file {}: {}", path.display(), e))
+}
+
+/// Wraps `reader` in a gzip decoder if `has_gz_extension` is set or its contents start with the
+/// gzip magic header
+fn maybe_gunzip<R: BufRead + 'static>(
+    mut reader: R,
+    has_gz_extension: bool,
+) -> std::io::Result<Box<dyn Read>> {
+    let has_gzip_header = reader.fill_buf()?.starts_with(&GZIP_MAGIC_BYTES);
+    if has_gz_extension || has_gzip_header {
+        Ok(Box::new(GzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Returns `true` if `path` looks like one of Spotify's JSON streaming history exports, optionally
+/// gzipped
+fn is_streaming_history_json(path: &Path) -> bool {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    let file_name = file_name.strip_suffix(".gz").unwrap_or(file_name);
+    file_name.ends_with(".json")
+}
+
+/// Reads all listening entries out of the file at `path`, which can be either a listening history
+/// CSV or a Spotify JSON streaming history export; the format is picked by file extension.
+fn read_csv_entries_from_file(
+    path: &Path,
+    genre_precedence: GenreColumnPrecedence,
+) -> Result<Vec<ListeningEntry>, String> {
+    let reader = open_maybe_gzipped(path)?;
+    if is_streaming_history_json(path) {
+        parse_streaming_history_json_entries(reader)
+    } else {
+        parse_csv_entries(reader, genre_precedence)
+    }
+}
+
+/// Returns `true` if `csv_path` is a `http(s)://` or `s3://` URL rather than a local path
+fn is_remote_csv_path(csv_path: &str) -> bool {
+    ["http://", "https://", "s3://"]
+        .iter()
+        .any(|scheme| csv_path.starts_with(scheme))
+}
+
+/// Downloads the whole file at `url` into memory.  S3 credentials are read from the same
+/// environment variables used for external storage.
+async fn download_csv_bytes(url: &str) -> Result<Vec<u8>, String> {
+    if url.starts_with("s3://") {
+        let mut builder = AmazonS3Builder::from_env().with_url(url);
+        if let Ok(endpoint) = std::env::var("AWS_S3_ENDPOINT") {
+            builder = builder.with_endpoint(endpoint).with_region("auto");
+        }
+        let store = builder
+            .build()
+            .map_err(|e| format!("Failed to build S3 client for {}: {}", url, e))?;
+        let key = url
+            .trim_start_matches("s3://")
+            .split_once('/')
+            .map(|(_bucket, key)| key)
+            .ok_or_else(|| format!("No object key provided in S3 URL {}", url))?;
+        let res = store
+            .get(&ObjectStorePath::from(key))
+            .await
+            .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+        return res
+            .bytes()
+            .await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| format!("Failed to download {}: {}", url, e));
+    }
+
+    let res = get_reqwest_client()
+        .await
+        .get(url)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+    res.bytes()
+        .await
+        .map(|bytes| bytes.to_vec())
+        .map_err(|e| format!("Failed to download {}: {}", url, e))
+}
+
+/// Reads all listening entries out of `bytes` downloaded from `url`.  Like
+/// `read_csv_entries_from_file`, the format and compression are picked by the file extension in
+/// the URL's path, and gzipped data is also detected by its magic header.
+fn read_csv_entries_from_bytes(
+    url: &str,
+    bytes: Vec<u8>,
+    genre_precedence: GenreColumnPrecedence,
+) -> Result<Vec<ListeningEntry>, String> {
+    let path = Path::new(url.split(['?', '#']).next().unwrap_or(url));
+    let has_gz_extension = path.extension().is_some_and(|ext| ext == "gz");
+    let reader = maybe_gunzip(Cursor::new(bytes), has_gz_extension)
+        .map_err(|e| format!("Failed to read {}: {}", url, e))?;
+    if is_streaming_history_json(path) {
+        parse_streaming_history_json_entries(reader)
+    } else {
+        parse_csv_entries(reader, genre_precedence)
+    }
+}
+
+fn parse_streaming_history_json_entries<R: Read>(reader: R) -> Result<Vec<ListeningEntry>, String> {
+    let records: Vec<StreamingHistoryRecord> = serde_json::from_reader(reader)
+        .map_err(|e| format!("Failed to parse streaming history JSON: {}", e))?;
+
+    let mut entries = Vec::with_capacity(records.len());
+    for record in records {
+        if let Some(entry) = record.into_listening_entry()? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_csv_entries<R: Read>(
+    reader: R,
+    genre_precedence: GenreColumnPrecedence,
+) -> Result<Vec<ListeningEntry>, String> {
     let mut rdr = csv::ReaderBuilder::new()
         .has_headers(true)
-        .from_path(csv_path)
-        .map_err(|e| format!("Failed to open CSV file: {}", e))?;
+        .from_reader(reader);
 
     let mut entries = Vec::new();
-    let mut artist_play_counts: FnvHashMap<String, u64> = FnvHashMap::default();
-    let mut track_play_counts: FnvHashMap<(String, String), u64> = FnvHashMap::default();
-    let mut artist_genres_map: FnvHashMap<String, Vec<String>> = FnvHashMap::default();
-
     for result in rdr.deserialize() {
         let record: CsvRecord = result.map_err(|e| format!("Failed to parse CSV record: {}", e))?;
-        
-        let timestamp = DateTime::parse_from_rfc3339(&record.ts)
-            .map_err(|e| format!("Failed to parse timestamp: {}", e))?
-            .with_timezone(&Utc);
+        entries.push(record.into_listening_entry(genre_precedence)?);
+    }
 
-        let genres = if !record.artist_genres.is_empty() {
-            parse_genres(&record.artist_genres)
-        } else {
-            parse_genres(&record.genres)
+    Ok(entries)
+}
+
+/// Maximum number of failed rows included in a `CsvValidationReport`
+const MAX_REPORTED_CSV_ROW_ERRORS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct CsvRowError {
+    /// 1-based line number in the file, counting the header line
+    pub line: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct CsvValidationReport {
+    pub headers: Vec<String>,
+    /// Number of rows, not counting the header
+    pub total_rows: usize,
+    pub valid_rows: usize,
+    pub invalid_rows: usize,
+    /// The first `MAX_REPORTED_CSV_ROW_ERRORS` rows that failed to parse
+    pub errors: Vec<CsvRowError>,
+}
+
+/// Parses a listening history CSV row by row and reports every row that couldn't be loaded rather
+/// than stopping at the first one.  Genres are read according to `precedence` like they are when
+/// loading.  Nothing is loaded or aggregated.
+pub(crate) fn validate_csv<R: Read>(
+    reader: R,
+    precedence: GenreColumnPrecedence,
+) -> Result<CsvValidationReport, String> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(reader);
+    let headers = rdr
+        .byte_headers()
+        .map_err(|e| format!("Failed to read CSV headers: {}", e))?
+        .clone();
+
+    let mut report = CsvValidationReport {
+        headers: headers
+            .iter()
+            .map(|header| String::from_utf8_lossy(header).into_owned())
+            .collect(),
+        total_rows: 0,
+        valid_rows: 0,
+        invalid_rows: 0,
+        errors: Vec::new(),
+    };
+    for result in rdr.byte_records() {
+        report.total_rows += 1;
+        let line = match &result {
+            Ok(record) => record.position().map(|pos| pos.line()),
+            Err(err) => err.position().map(|pos| pos.line()),
         };
+        let res = result
+            .map_err(|e| format!("Failed to parse CSV record: {}", e))
+            .and_then(|record| {
+                record
+                    .deserialize::<CsvRecord>(Some(&headers))
+                    .map_err(|e| format!("Failed to parse CSV record: {}", e))
+            })
+            .and_then(|record| record.into_listening_entry(precedence));
 
-        entries.push(ListeningEntry {
-            timestamp,
-            track_name: record.track_name.clone(),
-            artist_name: record.artist_name.clone(),
-            ms_played: record.ms_played,
-            genres: genres.clone(),
-        });
+        match res {
+            Ok(_) => report.valid_rows += 1,
+            Err(reason) => {
+                report.invalid_rows += 1;
+                if report.errors.len() < MAX_REPORTED_CSV_ROW_ERRORS {
+                    report.errors.push(CsvRowError {
+                        // Rows are 1 line after the header if the reader didn't report a position
+                        line: line.unwrap_or(report.total_rows as u64 + 1),
+                        reason,
+                    });
+                }
+            },
+        }
+    }
 
-        *artist_play_counts.entry(record.artist_name.clone()).or_insert(0) += record.ms_played;
-        *track_play_counts
-            .entry((record.track_name.clone(), record.artist_name.clone()))
-            .or_insert(0) += record.ms_played;
-        artist_genres_map.insert(record.artist_name.clone(), genres);
+    Ok(report)
+}
+
+/// Runs all aggregations (top artists/tracks, artist/track metadata, play count indices) over the
+/// full set of listening entries.  Artist names are normalized and entries shorter than
+/// `options.min_ms_played` are dropped first.
+fn build_csv_data(mut entries: Vec<ListeningEntry>, options: CsvBuildOptions) -> CsvData {
+    let CsvBuildOptions {
+        artist_name_normalization,
+        co_occurrence_window,
+        timeframe_windows,
+        min_ms_played,
+        max_related_artists,
+    } = options;
+    normalize_artist_names(&mut entries, artist_name_normalization);
+    if min_ms_played > 0 {
+        let total_entry_count = entries.len();
+        entries.retain(|entry| entry.ms_played >= min_ms_played);
+        info!(
+            "Dropped {} entries shorter than {}ms",
+            total_entry_count - entries.len(),
+            min_ms_played
+        );
     }
 
     // Sort entries by timestamp
     entries.sort_by_key(|e| e.timestamp);
 
+    let mut artist_ms_played: FnvHashMap<String, u64> = FnvHashMap::default();
+    let mut artist_play_counts: FnvHashMap<String, usize> = FnvHashMap::default();
+    let mut track_ms_played: FnvHashMap<(String, String), u64> = FnvHashMap::default();
+    let mut track_play_counts: FnvHashMap<(String, String), usize> = FnvHashMap::default();
+    let mut track_first_seen: FnvHashMap<(String, String), DateTime<Utc>> = FnvHashMap::default();
+    let mut artist_genres_map: FnvHashMap<String, Vec<String>> = FnvHashMap::default();
+    let mut track_albums: FnvHashMap<(String, String), String> = FnvHashMap::default();
+    let mut track_durations: FnvHashMap<(String, String), u32> = FnvHashMap::default();
+    let mut track_featured_artists: FnvHashMap<(String, String), Vec<String>> =
+        FnvHashMap::default();
+
+    for entry in &entries {
+        // Plays are credited to every artist on the track
+        for artist_name in entry.artist_names() {
+            *artist_ms_played.entry(artist_name.to_owned()).or_insert(0) += entry.ms_played;
+            *artist_play_counts
+                .entry(artist_name.to_owned())
+                .or_insert(0) += 1;
+            artist_genres_map.insert(artist_name.to_owned(), entry.genres.clone());
+        }
+        *track_ms_played
+            .entry((entry.track_name.clone(), entry.artist_name.clone()))
+            .or_insert(0) += entry.ms_played;
+        *track_play_counts
+            .entry((entry.track_name.clone(), entry.artist_name.clone()))
+            .or_insert(0) += 1;
+        track_first_seen
+            .entry((entry.track_name.clone(), entry.artist_name.clone()))
+            .or_insert(entry.timestamp);
+        if !entry.featured_artist_names.is_empty() {
+            track_featured_artists.insert(
+                (entry.track_name.clone(), entry.artist_name.clone()),
+                entry.featured_artist_names.clone(),
+            );
+        }
+        if let Some(album_name) = &entry.album_name {
+            track_albums.insert(
+                (entry.track_name.clone(), entry.artist_name.clone()),
+                album_name.clone(),
+            );
+        }
+        if let Some(duration_ms) = entry.duration_ms {
+            track_durations.insert(
+                (entry.track_name.clone(), entry.artist_name.clone()),
+                duration_ms,
+            );
+        }
+    }
+
     // Calculate top artists and tracks
-    let (top_artists_short, top_artists_medium, top_artists_long) =
-        calculate_top_artists(&entries, &artist_play_counts);
-    let (top_tracks_short, top_tracks_medium, top_tracks_long) =
-        calculate_top_tracks(&entries, &track_play_counts);
+    let (top_artists_short, top_artists_medium, top_artists_long) = calculate_top_artists(
+        &entries,
+        &artist_ms_played,
+        timeframe_windows,
+        PRECOMPUTED_TOP_N,
+    );
+    let (top_tracks_short, top_tracks_medium, top_tracks_long) = calculate_top_tracks(
+        &entries,
+        &track_ms_played,
+        timeframe_windows,
+        PRECOMPUTED_TOP_N,
+    );
 
     // Build artist and track metadata
-    let artists = build_artists(&artist_play_counts, &artist_genres_map);
-    let tracks = build_tracks(&track_play_counts);
+    let artists = build_artists(&artist_ms_played, &artist_genres_map);
+    let tracks = build_tracks(
+        &track_ms_played,
+        &track_albums,
+        &track_durations,
+        &track_featured_artists,
+    );
+    let related_artists =
+        calculate_artist_relationships(&entries, co_occurrence_window, max_related_artists);
+    let most_played_day =
+        most_played_day(&calculate_listening_time(&entries, None).ms_played_by_day);
+    let longest_session = longest_listening_session(
+        &entries,
+        chrono::Duration::minutes(DEFAULT_SESSION_GAP_MINUTES as i64),
+    );
 
-    let csv_data = CsvData {
+    CsvData {
         entries,
         artists,
         tracks,
@@ -120,164 +843,3947 @@ pub async fn load_csv_data() -> Result<(), String> {
         top_tracks_short,
         top_tracks_medium,
         top_tracks_long,
-    };
+        artist_play_counts,
+        artist_ms_played,
+        track_play_counts,
+        track_ms_played,
+        track_first_seen,
+        most_played_day,
+        longest_session,
+        related_artists,
+        version: next_csv_data_version(),
+    }
+}
 
-    *CSV_DATA.write().await = Some(Arc::new(csv_data));
-    info!("Successfully loaded CSV data");
-    Ok(())
+/// Loads every `*.csv` and `*.json` streaming history file (optionally gzipped) in `dir`, merges
+/// their entries while dropping duplicates from overlapping exports, and replaces the currently
+/// loaded data with the result.  Returns the newly loaded data.
+pub async fn load_csv_data_from_dir(dir: impl AsRef<Path>) -> Result<Arc<CsvData>, String> {
+    let dir = dir.as_ref().to_owned();
+    let csv_data = spawn_blocking(move || {
+        read_csv_entries_from_dir(&dir, CONF.genre_column_precedence).map(build_csv_data_from_conf)
+    })
+    .await
+    .unwrap()?;
+    Ok(set_csv_data(csv_data).await)
 }
 
-/// Get a reference to the loaded CSV data
-pub async fn get_csv_data() -> Option<Arc<CsvData>> {
-    CSV_DATA.read().await.clone()
+/// Loads a Spotify JSON streaming history export (`endsong_*.json` or `StreamingHistory*.json`,
+/// optionally gzipped) and replaces the currently loaded data with it.  Returns the newly loaded
+/// data.
+///
+/// These exports don't include genres, so genre history will be unavailable.
+pub async fn load_streaming_history_json(path: impl AsRef<Path>) -> Result<Arc<CsvData>, String> {
+    let path = path.as_ref().to_owned();
+    let csv_data = spawn_blocking(move || {
+        open_maybe_gzipped(&path)
+            .and_then(parse_streaming_history_json_entries)
+            .map(build_csv_data_from_conf)
+    })
+    .await
+    .unwrap()?;
+    Ok(set_csv_data(csv_data).await)
 }
 
-fn calculate_top_artists(
-    entries: &[ListeningEntry],
-    artist_play_counts: &FnvHashMap<String, u64>,
-) -> (Vec<String>, Vec<String>, Vec<String>) {
-    // Use the latest timestamp from the data instead of current time
-    let latest_timestamp = entries.last().map(|e| e.timestamp).unwrap_or_else(Utc::now);
-    let four_weeks_ago = latest_timestamp - chrono::Duration::weeks(4);
-    let six_months_ago = latest_timestamp - chrono::Duration::days(180);
+/// Removes entries that are exact duplicates of each other, as can happen when multiple exports
+/// cover overlapping time ranges.  Two entries are considered duplicates if they share the same
+/// timestamp, track, artist, and play duration.
+fn dedup_entries(entries: Vec<ListeningEntry>) -> Vec<ListeningEntry> {
+    let mut seen: FnvHashSet<(DateTime<Utc>, String, String, u64)> = FnvHashSet::default();
+    entries
+        .into_iter()
+        .filter(|entry| {
+            seen.insert((
+                entry.timestamp,
+                entry.track_name.clone(),
+                entry.artist_name.clone(),
+                entry.ms_played,
+            ))
+        })
+        .collect()
+}
 
-    let mut short_counts: FnvHashMap<String, u64> = FnvHashMap::default();
-    let mut medium_counts: FnvHashMap<String, u64> = FnvHashMap::default();
+/// Atomically replaces the currently loaded data
+async fn set_csv_data(csv_data: CsvData) -> Arc<CsvData> {
+    let csv_data = Arc::new(csv_data);
+    *CSV_DATA.write().await = Some(Arc::clone(&csv_data));
+    info!(
+        "Successfully loaded CSV data; version={}, entries={}, artists={}, tracks={}",
+        csv_data.version,
+        csv_data.entries.len(),
+        csv_data.artists.len(),
+        csv_data.tracks.len()
+    );
+    if csv_data.is_empty() {
+        warn!("Loaded CSV data has no valid entries; stats routes will report an empty dataset");
+    }
+    csv_data
+}
 
-    for entry in entries.iter().rev() {
-        if entry.timestamp > four_weeks_ago {
-            *short_counts.entry(entry.artist_name.clone()).or_insert(0) += entry.ms_played;
-        }
-        if entry.timestamp > six_months_ago {
-            *medium_counts.entry(entry.artist_name.clone()).or_insert(0) += entry.ms_played;
-        }
+/// Reads every `*.csv` and `*.json` streaming history file (optionally gzipped) in `dir` and merges
+/// their entries while dropping duplicates from overlapping exports
+fn read_csv_entries_from_dir(
+    dir: &Path,
+    genre_precedence: GenreColumnPrecedence,
+) -> Result<Vec<ListeningEntry>, String> {
+    let mut csv_paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read CSV directory {}: {}", dir.display(), e))?
+        .filter_map(|dir_entry| dir_entry.ok().map(|dir_entry| dir_entry.path()))
+        .filter(|path| {
+            let file_name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            let file_name = file_name.strip_suffix(".gz").unwrap_or(file_name);
+            path.is_file() && (file_name.ends_with(".csv") || file_name.ends_with(".json"))
+        })
+        .collect();
+    csv_paths.sort_unstable();
+
+    if csv_paths.is_empty() {
+        return Err(format!(
+            "No listening history files found in {}",
+            dir.display()
+        ));
     }
 
-    let top_short = get_top_n(&short_counts, 50);
-    let top_medium = get_top_n(&medium_counts, 50);
-    let top_long = get_top_n(artist_play_counts, 50);
+    let mut entries = Vec::new();
+    for csv_path in &csv_paths {
+        let file_entries = read_csv_entries_from_file(csv_path, genre_precedence)?;
+        info!(
+            "Read {} entries from {}",
+            file_entries.len(),
+            csv_path.display()
+        );
+        entries.extend(file_entries);
+    }
 
-    (top_short, top_medium, top_long)
+    let total_entry_count = entries.len();
+    let entries = dedup_entries(entries);
+    info!(
+        "Dropped {} duplicate entries across {} CSV files",
+        total_entry_count - entries.len(),
+        csv_paths.len()
+    );
+    Ok(entries)
 }
 
-fn calculate_top_tracks(
-    entries: &[ListeningEntry],
-    track_play_counts: &FnvHashMap<(String, String), u64>,
-) -> (Vec<String>, Vec<String>, Vec<String>) {
-    // Use the latest timestamp from the data instead of current time
-    let latest_timestamp = entries.last().map(|e| e.timestamp).unwrap_or_else(Utc::now);
-    let four_weeks_ago = latest_timestamp - chrono::Duration::weeks(4);
-    let six_months_ago = latest_timestamp - chrono::Duration::days(180);
+/// Load and parse the listening history at `CONF.csv_path`, replacing the currently loaded data
+/// once the whole file has been parsed successfully.  `csv_path` can be either a local file or
+/// directory or a `http(s)://` or `s3://` URL to download it from.  Returns the newly loaded data.
+pub async fn load_csv_data() -> Result<Arc<CsvData>, String> {
+    let csv_path = CONF.csv_path.as_str();
+    if is_remote_csv_path(csv_path) {
+        let bytes = download_csv_bytes(csv_path).await?;
+        info!(
+            "Downloaded {} bytes of listening history from {}",
+            bytes.len(),
+            csv_path
+        );
+        let csv_data = spawn_blocking(move || {
+            read_csv_entries_from_bytes(csv_path, bytes, CONF.genre_column_precedence)
+                .map(build_csv_data_from_conf)
+        })
+        .await
+        .unwrap()?;
+        return Ok(set_csv_data(csv_data).await);
+    }
 
-    let mut short_counts: FnvHashMap<(String, String), u64> = FnvHashMap::default();
-    let mut medium_counts: FnvHashMap<(String, String), u64> = FnvHashMap::default();
+    let csv_path = local_csv_path(csv_path);
+    let csv_data = spawn_blocking(move || parse_csv_file_from_conf(&csv_path))
+        .await
+        .unwrap()?;
+    Ok(set_csv_data(csv_data).await)
+}
 
-    for entry in entries.iter().rev() {
-        let key = (entry.track_name.clone(), entry.artist_name.clone());
-        if entry.timestamp > four_weeks_ago {
-            *short_counts.entry(key.clone()).or_insert(0) += entry.ms_played;
-        }
-        if entry.timestamp > six_months_ago {
-            *medium_counts.entry(key.clone()).or_insert(0) += entry.ms_played;
-        }
+/// Falls back to a gzipped copy of the listening history at `csv_path` if there's no uncompressed
+/// one
+fn local_csv_path(csv_path: &str) -> PathBuf {
+    let gzipped_csv_path = PathBuf::from(format!("{}.gz", csv_path));
+    if !Path::new(csv_path).exists() && gzipped_csv_path.exists() {
+        gzipped_csv_path
+    } else {
+        PathBuf::from(csv_path)
     }
+}
 
-    let top_short = get_top_n_tracks(&short_counts, 50);
-    let top_medium = get_top_n_tracks(&medium_counts, 50);
-    let top_long = get_top_n_tracks(track_play_counts, 50);
+/// Clears `CSV_RELOAD_IN_PROGRESS` when dropped, even if the reload panicked
+struct CsvReloadGuard;
 
-    (top_short, top_medium, top_long)
+impl Drop for CsvReloadGuard {
+    fn drop(&mut self) { CSV_RELOAD_IN_PROGRESS.store(false, Ordering::SeqCst); }
 }
 
-fn get_top_n(counts: &FnvHashMap<String, u64>, n: usize) -> Vec<String> {
-    let mut sorted: Vec<_> = counts.iter().collect();
-    sorted.sort_by(|a, b| b.1.cmp(a.1));
-    sorted.iter().take(n).map(|(name, _)| (*name).clone()).collect()
+/// Runs `reload` unless another reload is already in progress, in which case `None` is returned
+/// without running it
+async fn run_exclusive_reload<T>(reload: impl Future<Output = T>) -> Option<T> {
+    if CSV_RELOAD_IN_PROGRESS.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    let _guard = CsvReloadGuard;
+    Some(reload.await)
 }
 
-fn get_top_n_tracks(counts: &FnvHashMap<(String, String), u64>, n: usize) -> Vec<String> {
-    let mut sorted: Vec<_> = counts.iter().collect();
-    sorted.sort_by(|a, b| b.1.cmp(a.1));
-    sorted
-        .iter()
-        .take(n)
-        .map(|((track, artist), _)| format!("{} - {}", track, artist))
-        .collect()
+/// Like `load_csv_data`, but also drops all stats responses cached for the previously loaded data.
+/// Those are keyed by its version so they'd never be served again, but they're dropped to free up
+/// the space.
+async fn load_csv_data_and_clear_cache() -> Result<Arc<CsvData>, String> {
+    let csv_data = load_csv_data().await?;
+    // Errors are already logged
+    let _ = spawn_blocking(|| clear_cached_responses(&CONF.stats_cache_hash_name))
+        .await
+        .unwrap();
+    Ok(csv_data)
 }
 
-fn build_artists(
-    artist_play_counts: &FnvHashMap<String, u64>,
-    artist_genres_map: &FnvHashMap<String, Vec<String>>,
-) -> FnvHashMap<String, Artist> {
-    let mut artists = FnvHashMap::default();
-    
-    for (artist_name, _) in artist_play_counts.iter() {
-        let genres = artist_genres_map
-            .get(artist_name)
-            .cloned();
-        
-        // Create a fake Spotify ID based on the artist name
-        let spotify_id = format!("csv_{}", artist_name.replace(' ', "_").to_lowercase());
-        
-        artists.insert(
-            spotify_id.clone(),
-            Artist {
-                id: spotify_id,
-                name: artist_name.clone(),
-                genres,
-                images: Some(vec![]),
-                popularity: Some(50), // Default popularity
-            },
-        );
+/// Like `load_csv_data_and_clear_cache`, but returns `None` without loading anything if a reload
+/// is already in progress
+pub async fn reload_csv_data() -> Option<Result<Arc<CsvData>, String>> {
+    run_exclusive_reload(load_csv_data_and_clear_cache()).await
+}
+
+/// Calls `load` to reload the listening history at `path` if its modified time differs from
+/// `last_modified`, which is updated once the reload has been attempted.  Returns `true` if the
+/// data was reloaded.
+async fn reload_csv_data_if_modified<F, Fut>(
+    path: &Path,
+    last_modified: &mut Option<SystemTime>,
+    previous_entry_count: usize,
+    load: F,
+) -> bool
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Arc<CsvData>, String>>,
+{
+    let modified = match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(err) => {
+            warn!(
+                "Failed to read modified time of {}: {}",
+                path.display(),
+                err
+            );
+            return false;
+        },
+    };
+    if *last_modified == Some(modified) {
+        return false;
+    }
+
+    info!("{} changed; reloading CSV data", path.display());
+    let csv_data = match run_exclusive_reload(load()).await {
+        Some(res) => res,
+        None => {
+            // Left unchanged so that the reload is retried on the next check
+            info!("Another CSV reload is already in progress; skipping");
+            return false;
+        },
+    };
+    // A file that fails to parse isn't retried until it changes again
+    *last_modified = Some(modified);
+    match csv_data {
+        Ok(csv_data) => {
+            info!(
+                "Reloaded CSV data from {}; entries {} -> {}",
+                path.display(),
+                previous_entry_count,
+                csv_data.entries.len()
+            );
+            true
+        },
+        Err(err) => {
+            error!(
+                "Error reloading CSV data; keeping previously loaded data: {}",
+                err
+            );
+            false
+        },
     }
-    
-    artists
 }
 
-fn build_tracks(track_play_counts: &FnvHashMap<(String, String), u64>) -> FnvHashMap<String, Track> {
-    let mut tracks = FnvHashMap::default();
-    
-    for ((track_name, artist_name), _) in track_play_counts.iter() {
-        // Create a fake Spotify ID based on track and artist name
-        let spotify_id = format!(
-            "csv_{}",
-            format!("{}_{}", track_name, artist_name)
-                .replace(' ', "_")
-                .to_lowercase()
-        );
-        
-        let artist_id = format!("csv_{}", artist_name.replace(' ', "_").to_lowercase());
-        
-        tracks.insert(
-            spotify_id.clone(),
-            Track {
-                id: spotify_id,
-                name: track_name.clone(),
-                artists: vec![Artist {
-                    id: artist_id,
-                    name: artist_name.clone(),
-                    genres: None,
-                    images: Some(vec![]),
-                    popularity: None,
-                }],
-                album: crate::models::Album {
-                    id: "csv_unknown".to_string(),
-                    name: "Unknown Album".to_string(),
-                    artists: vec![],
-                    images: vec![],
-                },
-                preview_url: None,
-            },
+/// Checks the modified time of the local listening history at `CONF.csv_path` every `interval` and
+/// reloads it whenever it changes.  Listening histories loaded from URLs aren't watched.  For a
+/// directory, only files being added, removed, or renamed in it change its modified time.
+pub async fn watch_csv_file(interval: Duration) {
+    if is_remote_csv_path(&CONF.csv_path) {
+        warn!(
+            "Not watching {} for changes since it isn't a local file",
+            CONF.csv_path
         );
+        return;
+    }
+
+    let csv_path = local_csv_path(&CONF.csv_path);
+    let mut last_modified = std::fs::metadata(&csv_path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let mut interval = tokio::time::interval(interval);
+    // The first tick completes immediately, and the data was just loaded at startup
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let entry_count = get_csv_data()
+            .await
+            .map_or(0, |csv_data| csv_data.entries.len());
+        reload_csv_data_if_modified(
+            &csv_path,
+            &mut last_modified,
+            entry_count,
+            load_csv_data_and_clear_cache,
+        )
+        .await;
     }
-    
-    tracks
 }
 
-#[cfg(test)]
+/// Returns the version stamp of the currently loaded data, or 0 if it hasn't finished loading
+pub async fn csv_data_version() -> u64 {
+    CSV_DATA
+        .read()
+        .await
+        .as_ref()
+        .map_or(0, |csv_data| csv_data.version)
+}
+
+/// Issues a new version stamp, greater than any issued before
+fn next_csv_data_version() -> u64 {
+    let now_ms = Utc::now().timestamp_millis().max(0) as u64;
+    let prev_version = LAST_CSV_DATA_VERSION
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |version| {
+            Some(now_ms.max(version + 1))
+        })
+        .unwrap();
+    now_ms.max(prev_version + 1)
+}
+
+/// Get a reference to the loaded CSV data
+pub async fn get_csv_data() -> Option<Arc<CsvData>> {
+    CSV_DATA.read().await.clone()
+}
+
+/// Looks up the IDs of the best-matching artists on Spotify that have been resolved for the CSV
+/// artists with the provided IDs.  Resolutions are stored in Redis keyed by CSV artist ID rather
+/// than in the loaded `CsvData`, so they're kept across reloads and don't modify the shared data.
+pub(crate) fn get_resolved_spotify_ids(artist_ids: &[&str]) -> Result<Vec<Option<String>>, String> {
+    get_hash_items(&CONF.csv_artist_spotify_ids_hash_name, artist_ids)
+}
+
+/// Stores resolved Spotify IDs for CSV artists.  `resolved_ids` maps CSV artist IDs to the IDs of
+/// their matching artists on Spotify.
+pub(crate) fn set_resolved_spotify_ids(
+    resolved_ids: &FnvHashMap<String, String>,
+) -> Result<(), String> {
+    let kv_pairs: Vec<(&str, &String)> = resolved_ids
+        .iter()
+        .map(|(artist_id, spotify_id)| (artist_id.as_str(), spotify_id))
+        .collect();
+    set_hash_items(&CONF.csv_artist_spotify_ids_hash_name, &kv_pairs)
+}
+
+/// Parses the listening history file at `path` and registers it as the dataset for `username`,
+/// replacing any dataset previously registered for them.  The main dataset is left untouched.
+pub async fn load_named_csv_data(
+    username: String,
+    path: impl AsRef<Path>,
+) -> Result<Arc<CsvData>, String> {
+    let csv_data = parse_csv_file_from_conf(path.as_ref())?;
+    Ok(register_named_csv_data(username, csv_data).await)
+}
+
+/// Registers `csv_data` as the dataset for `username`, replacing any dataset previously registered
+/// for them
+pub async fn register_named_csv_data(username: String, csv_data: CsvData) -> Arc<CsvData> {
+    let csv_data = Arc::new(csv_data);
+    info!(
+        "Registered CSV data for {}; entries={}, artists={}, tracks={}",
+        username,
+        csv_data.entries.len(),
+        csv_data.artists.len(),
+        csv_data.tracks.len()
+    );
+    NAMED_CSV_DATA
+        .write()
+        .await
+        .insert(username, Arc::clone(&csv_data));
+    csv_data
+}
+
+/// Returns the dataset registered for `username`, if there is one
+pub async fn get_named_csv_data(username: &str) -> Option<Arc<CsvData>> {
+    NAMED_CSV_DATA.read().await.get(username).cloned()
+}
+
+/// Returns the start of the calendar month containing `timestamp` in the local time of a user
+/// `tz_offset_minutes` ahead of UTC, used as the bucket key for all month-based histories.
+fn month_start(timestamp: DateTime<Utc>, tz_offset_minutes: i32) -> NaiveDateTime {
+    let date = local_time_parts(timestamp, tz_offset_minutes).date;
+    NaiveDate::from_ymd_opt(date.year(), date.month(), 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+}
+
+/// Splits `entries` (which must be sorted by timestamp) into consecutive calendar-month buckets in
+/// the local time of a user `tz_offset_minutes` ahead of UTC.
+fn bucket_entries_by_month(
+    entries: &[ListeningEntry],
+    tz_offset_minutes: i32,
+) -> Vec<(NaiveDateTime, &[ListeningEntry])> {
+    let bucket_key = |entry: &ListeningEntry| month_start(entry.timestamp, tz_offset_minutes);
+    let mut buckets = Vec::new();
+    let mut bucket_start_ix = 0;
+    for i in 1..=entries.len() {
+        let is_bucket_end =
+            i == entries.len() || bucket_key(&entries[i]) != bucket_key(&entries[i - 1]);
+        if is_bucket_end {
+            buckets.push((
+                bucket_key(&entries[bucket_start_ix]),
+                &entries[bucket_start_ix..i],
+            ));
+            bucket_start_ix = i;
+        }
+    }
+    buckets
+}
+
+/// Inserts an empty bucket for every calendar month between the first and last of `buckets` that
+/// doesn't have one
+fn fill_month_gaps(
+    buckets: Vec<(NaiveDateTime, &[ListeningEntry])>,
+) -> Vec<(NaiveDateTime, &[ListeningEntry])> {
+    let mut filled: Vec<(NaiveDateTime, &[ListeningEntry])> = Vec::with_capacity(buckets.len());
+    for (bucket_start, bucket_entries) in buckets {
+        if let Some(&(prev_bucket_start, _)) = filled.last() {
+            let mut month = prev_bucket_start
+                .checked_add_months(chrono::Months::new(1))
+                .unwrap();
+            while month < bucket_start {
+                filled.push((month, &[]));
+                month = month.checked_add_months(chrono::Months::new(1)).unwrap();
+            }
+        }
+        filled.push((bucket_start, bucket_entries));
+    }
+    filled
+}
+
+/// Sums up total time played in each calendar month in the local time of a user
+/// `tz_offset_minutes` ahead of UTC.  Months are keyed by their first day, and every month between
+/// the first and last entry is included with zero for months without any listening.
+pub(crate) fn calculate_monthly_ms_played(
+    entries: &[ListeningEntry],
+    tz_offset_minutes: i32,
+) -> Vec<(NaiveDate, u64)> {
+    fill_month_gaps(bucket_entries_by_month(entries, tz_offset_minutes))
+        .into_iter()
+        .map(|(bucket_start, bucket_entries)| {
+            let ms_played = bucket_entries.iter().map(|entry| entry.ms_played).sum();
+            (bucket_start.date(), ms_played)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DatasetInfo {
+    pub earliest_timestamp: Option<DateTime<Utc>>,
+    pub latest_timestamp: Option<DateTime<Utc>>,
+    pub entry_count: usize,
+    pub distinct_artist_count: usize,
+    pub distinct_track_count: usize,
+    /// Number of monthly buckets in the genre history
+    pub genre_history_bucket_count: usize,
+    /// Version stamp of the loaded data; changes every time it's reloaded
+    pub version: u64,
+}
+
+pub(crate) fn calculate_dataset_info(csv_data: &CsvData, fill_gaps: bool) -> DatasetInfo {
+    let mut genre_history_buckets = bucket_entries_by_month(&csv_data.entries, 0);
+    if fill_gaps {
+        genre_history_buckets = fill_month_gaps(genre_history_buckets);
+    }
+
+    DatasetInfo {
+        earliest_timestamp: csv_data.entries.first().map(|entry| entry.timestamp),
+        latest_timestamp: csv_data.entries.last().map(|entry| entry.timestamp),
+        entry_count: csv_data.entries.len(),
+        distinct_artist_count: csv_data.artists.len(),
+        distinct_track_count: csv_data.tracks.len(),
+        genre_history_bucket_count: genre_history_buckets.len(),
+        version: csv_data.version,
+    }
+}
+
+/// Computes a per-month popularity score for every genre.  Within each month, genres are ranked by
+/// total time played and scored by rank the same way as the genre treemap's artist rankings.
+///
+/// Every history in the returned map has exactly one element per returned timestamp, with `None`
+/// for months in which the genre wasn't listened to.  If `fill_gaps` is set, months without any
+/// listening between the first and last entry are included with `None` for every genre.  Months
+/// are in the local time of a user `tz_offset_minutes` ahead of UTC.
+pub(crate) fn calculate_genre_history(
+    entries: &[ListeningEntry],
+    fill_gaps: bool,
+    tz_offset_minutes: i32,
+) -> (Vec<NaiveDateTime>, FnvHashMap<String, Vec<Option<usize>>>) {
+    let mut buckets = bucket_entries_by_month(entries, tz_offset_minutes);
+    if fill_gaps {
+        buckets = fill_month_gaps(buckets);
+    }
+    let mut timestamps = Vec::with_capacity(buckets.len());
+    let mut history_by_genre: FnvHashMap<String, Vec<Option<usize>>> = FnvHashMap::default();
+
+    for (bucket_ix, (bucket_start, bucket_entries)) in buckets.into_iter().enumerate() {
+        timestamps.push(bucket_start);
+
+        let mut ms_played_by_genre: FnvHashMap<&str, u64> = FnvHashMap::default();
+        for entry in bucket_entries {
+            for genre in &entry.genres {
+                *ms_played_by_genre.entry(genre.as_str()).or_insert(0) += entry.ms_played;
+            }
+        }
+        let mut ranked_genres: Vec<_> = ms_played_by_genre.into_iter().collect();
+        ranked_genres.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        let genre_count = ranked_genres.len();
+        for (rank, (genre, _ms_played)) in ranked_genres.into_iter().enumerate() {
+            let history = history_by_genre
+                .entry(genre.to_owned())
+                .or_insert_with(|| vec![None; bucket_ix]);
+            history.push(Some(crate::stats::weight_data_point(genre_count, rank)));
+        }
+
+        // Genres not listened to this month get a `None` so all histories stay aligned
+        for history in history_by_genre.values_mut() {
+            if history.len() == bucket_ix {
+                history.push(None);
+            }
+        }
+    }
+
+    (timestamps, history_by_genre)
+}
+
+/// Returns the 0-based rank of an artist that was played for `artist_ms_played` among all artists,
+/// or `None` if it isn't in the top 50.  `sorted_ms_played` holds the total time played of every
+/// artist, sorted in descending order.
+fn artist_rank(sorted_ms_played: &[u64], artist_ms_played: u64) -> Option<u8> {
+    let rank = sorted_ms_played.partition_point(|&ms_played| ms_played > artist_ms_played);
+    if rank < 50 {
+        Some(rank as u8)
+    } else {
+        None
+    }
+}
+
+/// Computes the monthly ranking history of each of the artists in `artist_names`.  For each month,
+/// artists are ranked among the top artists as of the end of that month using the same
+/// short/medium/long windows as the top artists lists.  Time played per artist within each window
+/// is kept as a running total while scanning the entries once, so the cost doesn't grow with the
+/// number of artists.
+pub(crate) fn calculate_artists_popularity_history<'a>(
+    entries: &[ListeningEntry],
+    artist_names: &[&'a str],
+    timeframe_windows: TimeframeWindows,
+) -> FnvHashMap<&'a str, Vec<(NaiveDateTime, [Option<u8>; 3])>> {
+    let mut history_by_artist: FnvHashMap<&str, Vec<_>> = artist_names
+        .iter()
+        .map(|artist_name| (*artist_name, Vec::new()))
+        .collect();
+    // `(ms_played, play_count)` by artist in the short, medium, and long windows.  Play counts are
+    // tracked so that artists are dropped once all of their plays have left a window.
+    let mut totals_by_window: [FnvHashMap<&str, (u64, usize)>; 3] = Default::default();
+    let windows = [timeframe_windows.short(), timeframe_windows.medium()];
+    let mut window_start_ixs = [0; 2];
+    let mut end_ix = 0;
+    for (bucket_start, bucket_entries) in bucket_entries_by_month(entries, 0) {
+        for entry in bucket_entries {
+            for name in entry.artist_names() {
+                for totals in totals_by_window.iter_mut() {
+                    let (ms_played, play_count) = totals.entry(name).or_insert((0, 0));
+                    *ms_played += entry.ms_played;
+                    *play_count += 1;
+                }
+            }
+        }
+        end_ix += bucket_entries.len();
+        let entries_so_far = &entries[..end_ix];
+        let latest_timestamp = entries_so_far[end_ix - 1].timestamp;
+
+        for (window_ix, window) in windows.iter().enumerate() {
+            let start_ix =
+                entries_so_far.partition_point(|e| e.timestamp <= latest_timestamp - *window);
+            let totals = &mut totals_by_window[window_ix];
+            for entry in &entries_so_far[window_start_ixs[window_ix]..start_ix] {
+                for name in entry.artist_names() {
+                    let (ms_played, play_count) = totals.get_mut(name).unwrap();
+                    *ms_played -= entry.ms_played;
+                    *play_count -= 1;
+                    if *play_count == 0 {
+                        totals.remove(name);
+                    }
+                }
+            }
+            window_start_ixs[window_ix] = start_ix;
+        }
+
+        let sorted_ms_played_by_window: Vec<Vec<u64>> = totals_by_window
+            .iter()
+            .map(|totals| {
+                let mut sorted_ms_played: Vec<u64> =
+                    totals.values().map(|(ms_played, _)| *ms_played).collect();
+                sorted_ms_played.sort_unstable_by_key(|&ms_played| Reverse(ms_played));
+                sorted_ms_played
+            })
+            .collect();
+        for (artist_name, history) in history_by_artist.iter_mut() {
+            let mut ranks = [None; 3];
+            for (window_ix, rank) in ranks.iter_mut().enumerate() {
+                *rank = totals_by_window[window_ix]
+                    .get(artist_name)
+                    .and_then(|(ms_played, _)| {
+                        artist_rank(&sorted_ms_played_by_window[window_ix], *ms_played)
+                    });
+            }
+            history.push((bucket_start, ranks));
+        }
+    }
+    history_by_artist
+}
+
+/// Size of the buckets that plays are grouped into for artist history
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub(crate) enum HistoryGranularity {
+    Week,
+    Month,
+}
+
+impl HistoryGranularity {
+    /// Returns the first day of the bucket containing `date`.  Weeks start on Monday.
+    fn bucket_start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            HistoryGranularity::Week =>
+                date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64),
+            HistoryGranularity::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    fn next_bucket_start(self, bucket_start: NaiveDate) -> NaiveDate {
+        match self {
+            HistoryGranularity::Week => bucket_start + chrono::Duration::days(7),
+            HistoryGranularity::Month => bucket_start
+                .checked_add_months(chrono::Months::new(1))
+                .unwrap(),
+        }
+    }
+}
+
+/// Returns `(bucket_start, ms_played, play_count)` for the plays of `artist_name` in each bucket
+/// spanning the full listening history.  Buckets without any plays of the artist are included with
+/// zero counts so that the series is continuous.
+pub(crate) fn calculate_artist_play_history(
+    entries: &[ListeningEntry],
+    artist_name: &str,
+    granularity: HistoryGranularity,
+) -> Vec<(NaiveDate, u64, usize)> {
+    let (first, last) = match (entries.first(), entries.last()) {
+        (Some(first), Some(last)) => (first.timestamp.date_naive(), last.timestamp.date_naive()),
+        _ => return Vec::new(),
+    };
+
+    let mut totals_by_bucket: FnvHashMap<NaiveDate, (u64, usize)> = FnvHashMap::default();
+    let entries = entries
+        .iter()
+        .filter(|entry| entry.artist_names().any(|name| name == artist_name));
+    for entry in entries {
+        let bucket = granularity.bucket_start(entry.timestamp.date_naive());
+        let (ms_played, play_count) = totals_by_bucket.entry(bucket).or_insert((0, 0));
+        *ms_played += entry.ms_played;
+        *play_count += 1;
+    }
+
+    let mut history = Vec::new();
+    let mut bucket = granularity.bucket_start(first);
+    while bucket <= last {
+        let (ms_played, play_count) = totals_by_bucket.get(&bucket).copied().unwrap_or((0, 0));
+        history.push((bucket, ms_played, play_count));
+        bucket = granularity.next_bucket_start(bucket);
+    }
+    history
+}
+
+/// Name of the series that genres below the share threshold are merged into
+pub(crate) const OTHER_GENRE: &str = "other";
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct GenreShares {
+    pub timestamps: Vec<NaiveDateTime>,
+    /// Each genre's share (0-1) of the time played in each month, aligned with `timestamps`.  The
+    /// shares for a month sum to 1, or to 0 if nothing with genres was played that month.
+    pub shares_by_genre: FnvHashMap<String, Vec<f64>>,
+}
+
+/// Computes each genre's share of the time played in each calendar month, using the same buckets
+/// as `calculate_genre_history`.  Within each month, genres with a share below `min_share` are
+/// merged into an `OTHER_GENRE` series so that the number of series stays manageable.
+pub(crate) fn calculate_genre_shares(
+    entries: &[ListeningEntry],
+    fill_gaps: bool,
+    tz_offset_minutes: i32,
+    min_share: f64,
+) -> GenreShares {
+    let mut buckets = bucket_entries_by_month(entries, tz_offset_minutes);
+    if fill_gaps {
+        buckets = fill_month_gaps(buckets);
+    }
+
+    let mut timestamps = Vec::with_capacity(buckets.len());
+    let mut shares_by_bucket: Vec<FnvHashMap<&str, f64>> = Vec::with_capacity(buckets.len());
+    for (bucket_start, bucket_entries) in buckets {
+        timestamps.push(bucket_start);
+
+        let mut ms_played_by_genre: FnvHashMap<&str, u64> = FnvHashMap::default();
+        for entry in bucket_entries {
+            for genre in &entry.genres {
+                *ms_played_by_genre.entry(genre.as_str()).or_insert(0) += entry.ms_played;
+            }
+        }
+        let total_ms_played: u64 = ms_played_by_genre.values().sum();
+
+        let mut shares: FnvHashMap<&str, f64> = FnvHashMap::default();
+        for (genre, ms_played) in ms_played_by_genre {
+            let share = ms_played as f64 / total_ms_played as f64;
+            let genre = if share < min_share {
+                OTHER_GENRE
+            } else {
+                genre
+            };
+            *shares.entry(genre).or_insert(0.) += share;
+        }
+        shares_by_bucket.push(shares);
+    }
+
+    let mut shares_by_genre: FnvHashMap<String, Vec<f64>> = FnvHashMap::default();
+    for (bucket_ix, shares) in shares_by_bucket.into_iter().enumerate() {
+        for (genre, share) in shares {
+            shares_by_genre
+                .entry(genre.to_owned())
+                .or_insert_with(|| vec![0.; timestamps.len()])[bucket_ix] += share;
+        }
+    }
+
+    GenreShares {
+        timestamps,
+        shares_by_genre,
+    }
+}
+
+/// Sums up total time played per genre across all entries, sorted from most to least played.
+/// Genres are taken from each artist's metadata (artists without genres are skipped) and compared
+/// case-insensitively, so differently-cased variants of the same genre are merged.
+pub(crate) fn calculate_genre_play_times(csv_data: &CsvData) -> Vec<(String, u64)> {
+    sum_genre_play_times(&genres_by_artist_name(csv_data), &csv_data.entries)
+}
+
+fn genres_by_artist_name(csv_data: &CsvData) -> FnvHashMap<&str, &[String]> {
+    csv_data
+        .artists
+        .values()
+        .filter_map(|artist| {
+            artist
+                .genres
+                .as_deref()
+                .map(|genres| (artist.name.as_str(), genres))
+        })
+        .collect()
+}
+
+fn sum_genre_play_times<'a>(
+    genres_by_artist_name: &FnvHashMap<&str, &[String]>,
+    entries: impl IntoIterator<Item = &'a ListeningEntry>,
+) -> Vec<(String, u64)> {
+    let mut ms_played_by_genre: FnvHashMap<String, u64> = FnvHashMap::default();
+    for entry in entries {
+        // Genres shared by several of the artists on a track are only credited once
+        let genres: FnvHashSet<String> = entry
+            .artist_names()
+            .filter_map(|artist_name| genres_by_artist_name.get(artist_name))
+            .flat_map(|genres| genres.iter())
+            .map(|genre| genre.to_lowercase())
+            .collect();
+        for genre in genres {
+            *ms_played_by_genre.entry(genre).or_insert(0) += entry.ms_played;
+        }
+    }
+
+    let mut genre_play_times: Vec<_> = ms_played_by_genre.into_iter().collect();
+    genre_play_times.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    genre_play_times
+}
+
+/// Returns up to `limit` of the most played genres for each of the short, medium, and long
+/// timeframes along with their total time played, computed the same way as
+/// `calculate_genre_play_times`
+pub(crate) fn calculate_top_genres(
+    csv_data: &CsvData,
+    timeframe_windows: TimeframeWindows,
+    limit: usize,
+) -> TimeFrames<(String, u64)> {
+    let genres_by_artist_name = genres_by_artist_name(csv_data);
+    let (short_start, medium_start) = timeframe_starts(&csv_data.entries, timeframe_windows);
+    let top_genres_since = |start: Option<DateTime<Utc>>| {
+        let entries = csv_data
+            .entries
+            .iter()
+            .filter(|entry| start.is_none_or(|start| entry.timestamp > start));
+        let mut genre_play_times = sum_genre_play_times(&genres_by_artist_name, entries);
+        genre_play_times.truncate(limit);
+        genre_play_times
+    };
+
+    TimeFrames {
+        short: top_genres_since(Some(short_start)),
+        medium: top_genres_since(Some(medium_start)),
+        long: top_genres_since(None),
+    }
+}
+
+/// Aggregates that can be exported as CSV
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub(crate) enum ExportKind {
+    #[field(value = "top_artists")]
+    TopArtists,
+    #[field(value = "top_tracks")]
+    TopTracks,
+    #[field(value = "genres")]
+    Genres,
+}
+
+impl ExportKind {
+    pub(crate) fn file_name(&self) -> &'static str {
+        match self {
+            ExportKind::TopArtists => "top_artists.csv",
+            ExportKind::TopTracks => "top_tracks.csv",
+            ExportKind::Genres => "genres.csv",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TopArtistRow {
+    timeframe: String,
+    /// 1-based
+    rank: usize,
+    id: String,
+    name: String,
+    /// Comma-separated
+    genres: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TopTrackRow {
+    timeframe: String,
+    /// 1-based
+    rank: usize,
+    id: String,
+    name: String,
+    artist_name: String,
+    album_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct GenreRow {
+    genre: String,
+    ms_played: u64,
+}
+
+fn top_artist_rows(csv_data: &CsvData) -> Vec<TopArtistRow> {
+    let timeframes = [
+        ("short", &csv_data.top_artists_short),
+        ("medium", &csv_data.top_artists_medium),
+        ("long", &csv_data.top_artists_long),
+    ];
+    timeframes
+        .into_iter()
+        .flat_map(|(timeframe, ids)| {
+            ids.iter()
+                .filter_map(|id| csv_data.artists.get(id))
+                .enumerate()
+                .map(move |(ix, artist)| TopArtistRow {
+                    timeframe: timeframe.to_owned(),
+                    rank: ix + 1,
+                    id: artist.id.clone(),
+                    name: artist.name.clone(),
+                    genres: artist.genres.as_deref().unwrap_or_default().join(","),
+                })
+        })
+        .collect()
+}
+
+fn top_track_rows(csv_data: &CsvData) -> Vec<TopTrackRow> {
+    let timeframes = [
+        ("short", &csv_data.top_tracks_short),
+        ("medium", &csv_data.top_tracks_medium),
+        ("long", &csv_data.top_tracks_long),
+    ];
+    timeframes
+        .into_iter()
+        .flat_map(|(timeframe, ids)| {
+            ids.iter()
+                .filter_map(|id| csv_data.tracks.get(id))
+                .enumerate()
+                .map(move |(ix, track)| TopTrackRow {
+                    timeframe: timeframe.to_owned(),
+                    rank: ix + 1,
+                    id: track.id.clone(),
+                    name: track.name.clone(),
+                    artist_name: track
+                        .artists
+                        .first()
+                        .map(|artist| artist.name.clone())
+                        .unwrap_or_default(),
+                    album_name: track.album.name.clone(),
+                })
+        })
+        .collect()
+}
+
+fn write_csv_rows<T: Serialize>(rows: impl IntoIterator<Item = T>) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer
+            .serialize(row)
+            .map_err(|e| format!("Failed to write CSV record: {}", e))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| format!("Failed to write CSV: {}", e))
+}
+
+/// Serializes the precomputed top artists or tracks for every timeframe, or the total time played
+/// for every genre, as CSV
+pub(crate) fn export_csv(csv_data: &CsvData, kind: ExportKind) -> Result<Vec<u8>, String> {
+    match kind {
+        ExportKind::TopArtists => write_csv_rows(top_artist_rows(csv_data)),
+        ExportKind::TopTracks => write_csv_rows(top_track_rows(csv_data)),
+        ExportKind::Genres => write_csv_rows(
+            calculate_genre_play_times(csv_data)
+                .into_iter()
+                .map(|(genre, ms_played)| GenreRow { genre, ms_played }),
+        ),
+    }
+}
+
+/// Returns the `limit` most played tracks by artists with `genre` along with their play counts,
+/// sorted from most to least played.  Genres are compared case-insensitively.
+pub(crate) fn calculate_genre_top_tracks(
+    csv_data: &CsvData,
+    genre: &str,
+    limit: usize,
+) -> Vec<(Track, usize)> {
+    let genre = genre.to_lowercase();
+    let has_genre = |artist_id: &str| {
+        csv_data
+            .artists
+            .get(artist_id)
+            .and_then(|artist| artist.genres.as_ref())
+            .is_some_and(|genres| genres.iter().any(|g| g.to_lowercase() == genre))
+    };
+
+    let mut top_tracks: Vec<(Track, usize)> = csv_data
+        .tracks
+        .values()
+        .filter(|track| track.artists.iter().any(|artist| has_genre(&artist.id)))
+        .map(|track| {
+            let artist_name = track
+                .artists
+                .first()
+                .map(|artist| artist.name.clone())
+                .unwrap_or_default();
+            let play_count = csv_data
+                .track_play_counts
+                .get(&(track.name.clone(), artist_name))
+                .copied()
+                .unwrap_or(0);
+            (track.clone(), play_count)
+        })
+        .collect();
+    top_tracks.sort_unstable_by(|(track_a, plays_a), (track_b, plays_b)| {
+        plays_b
+            .cmp(plays_a)
+            .then_with(|| track_a.id.cmp(&track_b.id))
+    });
+    top_tracks.truncate(limit);
+    top_tracks
+}
+
+/// Returns the IDs of all top artists and tracks across every timeframe.  Items are ordered by
+/// their long-term rank, followed by any that only appear in the shorter timeframes.
+fn all_top_ids(csv_data: &CsvData) -> (Vec<&str>, Vec<&str>) {
+    let dedup = |lists: [&[String]; 3]| {
+        let mut seen = FnvHashSet::default();
+        lists
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|id| seen.insert(*id))
+            .collect::<Vec<_>>()
+    };
+
+    (
+        dedup([
+            &csv_data.top_artists_long,
+            &csv_data.top_artists_medium,
+            &csv_data.top_artists_short,
+        ]),
+        dedup([
+            &csv_data.top_tracks_long,
+            &csv_data.top_tracks_medium,
+            &csv_data.top_tracks_short,
+        ]),
+    )
+}
+
+/// Computes the top artists, tracks, and genres that two datasets have in common.  Artists and
+/// tracks are ordered by `user1`'s ranking.  Genres are ordered by the total time played across
+/// both datasets.
+pub(crate) fn calculate_comparison(
+    user1_username: String,
+    user1_data: &CsvData,
+    user2_username: String,
+    user2_data: &CsvData,
+) -> UserComparison {
+    let (user1_artist_ids, user1_track_ids) = all_top_ids(user1_data);
+    let (user2_artist_ids, user2_track_ids) = all_top_ids(user2_data);
+    let user2_artist_ids: FnvHashSet<&str> = user2_artist_ids.into_iter().collect();
+    let user2_track_ids: FnvHashSet<&str> = user2_track_ids.into_iter().collect();
+
+    let artists = user1_artist_ids
+        .into_iter()
+        .filter(|id| user2_artist_ids.contains(id))
+        .filter_map(|id| user1_data.artists.get(id).cloned())
+        .collect();
+    let tracks = user1_track_ids
+        .into_iter()
+        .filter(|id| user2_track_ids.contains(id))
+        .filter_map(|id| user1_data.tracks.get(id).cloned())
+        .collect();
+
+    let user2_genre_play_times: FnvHashMap<String, u64> =
+        calculate_genre_play_times(user2_data).into_iter().collect();
+    let mut genres: Vec<(String, u64)> = calculate_genre_play_times(user1_data)
+        .into_iter()
+        .filter_map(|(genre, ms_played)| {
+            let other_ms_played = user2_genre_play_times.get(&genre)?;
+            Some((genre, ms_played + other_ms_played))
+        })
+        .collect();
+    genres.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    UserComparison {
+        tracks,
+        artists,
+        genres: genres.into_iter().map(|(genre, _)| genre).collect(),
+        user1_username,
+        user2_username,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ListeningTime {
+    pub total_ms_played: u64,
+    pub total_hours: f64,
+    pub distinct_artist_count: usize,
+    pub distinct_track_count: usize,
+    /// Total milliseconds played for each day with at least one play, in chronological order
+    pub ms_played_by_day: Vec<(NaiveDate, u64)>,
+}
+
+/// Computes aggregate listening time over all entries, optionally limited to `date_range`
+pub(crate) fn calculate_listening_time(
+    entries: &[ListeningEntry],
+    date_range: Option<&DateRange>,
+) -> ListeningTime {
+    let mut total_ms_played = 0;
+    let mut artists: FnvHashSet<&str> = FnvHashSet::default();
+    let mut tracks: FnvHashSet<(&str, &str)> = FnvHashSet::default();
+    let mut ms_played_by_day: Vec<(NaiveDate, u64)> = Vec::new();
+
+    let entries = entries
+        .iter()
+        .filter(|e| date_range.is_none_or(|date_range| date_range.contains(e.timestamp)));
+    for entry in entries {
+        total_ms_played += entry.ms_played;
+        artists.extend(entry.artist_names());
+        tracks.insert((entry.track_name.as_str(), entry.artist_name.as_str()));
+
+        // Entries are sorted by timestamp, so all plays for a given day are contiguous
+        let day = entry.timestamp.date_naive();
+        match ms_played_by_day.last_mut() {
+            Some((last_day, ms_played)) if *last_day == day => *ms_played += entry.ms_played,
+            _ => ms_played_by_day.push((day, entry.ms_played)),
+        }
+    }
+
+    ListeningTime {
+        total_ms_played,
+        total_hours: total_ms_played as f64 / (1000. * 60. * 60.),
+        distinct_artist_count: artists.len(),
+        distinct_track_count: tracks.len(),
+        ms_played_by_day,
+    }
+}
+
+/// Total time played and number of plays within some bucket of time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub(crate) struct ListeningBucket {
+    pub ms_played: u64,
+    pub play_count: usize,
+}
+
+/// Buckets all plays by hour of day (0-23) in the local time of a user `tz_offset_minutes` ahead of
+/// UTC
+pub(crate) fn calculate_listening_clock(
+    entries: &[ListeningEntry],
+    tz_offset_minutes: i32,
+) -> [ListeningBucket; 24] {
+    let mut buckets = [ListeningBucket::default(); 24];
+    for entry in entries {
+        let hour = local_time_parts(entry.timestamp, tz_offset_minutes).hour as usize;
+        buckets[hour].ms_played += entry.ms_played;
+        buckets[hour].play_count += 1;
+    }
+    buckets
+}
+
+/// Buckets all plays by day of the week (Monday through Sunday) in the local time of a user
+/// `tz_offset_minutes` ahead of UTC
+pub(crate) fn calculate_listening_weekdays(
+    entries: &[ListeningEntry],
+    tz_offset_minutes: i32,
+) -> [ListeningBucket; 7] {
+    let mut buckets = [ListeningBucket::default(); 7];
+    for entry in entries {
+        let weekday = local_time_parts(entry.timestamp, tz_offset_minutes)
+            .weekday
+            .num_days_from_monday() as usize;
+        buckets[weekday].ms_played += entry.ms_played;
+        buckets[weekday].play_count += 1;
+    }
+    buckets
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct OnThisDayTrack {
+    pub track_name: String,
+    pub artist_name: String,
+    pub play_count: usize,
+    pub ms_played: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct OnThisDayYear {
+    pub year: i32,
+    pub total_ms_played: u64,
+    /// Sorted by descending time played
+    pub tracks: Vec<OnThisDayTrack>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct OnThisDay {
+    pub month: u32,
+    pub day: u32,
+    /// Only years with listening on this day are included, most recent first
+    pub years: Vec<OnThisDayYear>,
+}
+
+/// Collects the tracks played on `month`/`day` of every year in the local time of a user
+/// `tz_offset_minutes` ahead of UTC
+pub(crate) fn calculate_on_this_day(
+    entries: &[ListeningEntry],
+    month: u32,
+    day: u32,
+    tz_offset_minutes: i32,
+) -> OnThisDay {
+    // year -> (track_name, artist_name) -> (play_count, ms_played)
+    let mut plays_by_year: FnvHashMap<i32, FnvHashMap<(&str, &str), (usize, u64)>> =
+        FnvHashMap::default();
+    for entry in entries {
+        let local_time = to_local_time(entry.timestamp, tz_offset_minutes);
+        if local_time.month() != month || local_time.day() != day {
+            continue;
+        }
+
+        let track_plays = plays_by_year
+            .entry(local_time.year())
+            .or_default()
+            .entry((&entry.track_name, &entry.artist_name))
+            .or_default();
+        track_plays.0 += 1;
+        track_plays.1 += entry.ms_played;
+    }
+
+    let mut years: Vec<OnThisDayYear> = plays_by_year
+        .into_iter()
+        .map(|(year, plays_by_track)| {
+            let mut tracks: Vec<OnThisDayTrack> = plays_by_track
+                .into_iter()
+                .map(
+                    |((track_name, artist_name), (play_count, ms_played))| OnThisDayTrack {
+                        track_name: track_name.to_owned(),
+                        artist_name: artist_name.to_owned(),
+                        play_count,
+                        ms_played,
+                    },
+                )
+                .collect();
+            tracks.sort_unstable_by(|a, b| {
+                b.ms_played
+                    .cmp(&a.ms_played)
+                    .then_with(|| a.artist_name.cmp(&b.artist_name))
+                    .then_with(|| a.track_name.cmp(&b.track_name))
+            });
+            OnThisDayYear {
+                year,
+                total_ms_played: tracks.iter().map(|track| track.ms_played).sum(),
+                tracks,
+            }
+        })
+        .collect();
+    years.sort_unstable_by(|a, b| b.year.cmp(&a.year));
+
+    OnThisDay { month, day, years }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct ListeningStreaks {
+    pub longest_streak_days: usize,
+    pub longest_streak_start: Option<NaiveDate>,
+    pub longest_streak_end: Option<NaiveDate>,
+    /// Length of the streak ending on the latest day in the data
+    pub current_streak_days: usize,
+    pub active_day_count: usize,
+}
+
+/// Computes consecutive-day listening streaks with day boundaries in the local time of a user
+/// `tz_offset_minutes` ahead of UTC
+pub(crate) fn calculate_listening_streaks(
+    entries: &[ListeningEntry],
+    tz_offset_minutes: i32,
+) -> ListeningStreaks {
+    let mut active_days: Vec<NaiveDate> = entries
+        .iter()
+        .map(|entry| local_time_parts(entry.timestamp, tz_offset_minutes).date)
+        .collect();
+    active_days.sort_unstable();
+    active_days.dedup();
+
+    let mut longest: Option<(NaiveDate, NaiveDate)> = None;
+    let mut current: Option<(NaiveDate, NaiveDate)> = None;
+    let streak_len = |(start, end): (NaiveDate, NaiveDate)| (end - start).num_days() as usize + 1;
+    for &day in &active_days {
+        current = match current {
+            Some((start, end)) if end.succ_opt() == Some(day) => Some((start, day)),
+            _ => Some((day, day)),
+        };
+        if longest.is_none_or(|longest| streak_len(current.unwrap()) > streak_len(longest)) {
+            longest = current;
+        }
+    }
+
+    ListeningStreaks {
+        longest_streak_days: longest.map_or(0, streak_len),
+        longest_streak_start: longest.map(|(start, _)| start),
+        longest_streak_end: longest.map(|(_, end)| end),
+        current_streak_days: current.map_or(0, streak_len),
+        active_day_count: active_days.len(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct ListeningSession {
+    pub start: DateTime<Utc>,
+    /// When the last play in the session finished
+    pub end: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub track_count: usize,
+    /// Genre with the most time played during the session, if any plays had genres
+    pub dominant_genre: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct ListeningSessions {
+    pub sessions: Vec<ListeningSession>,
+    pub average_session_duration_ms: u64,
+}
+
+fn build_listening_session(entries: &[ListeningEntry]) -> ListeningSession {
+    let start = entries[0].timestamp;
+    let last = &entries[entries.len() - 1];
+    let end = last.timestamp + chrono::Duration::milliseconds(last.ms_played as i64);
+
+    let mut ms_played_by_genre: FnvHashMap<String, u64> = FnvHashMap::default();
+    for entry in entries {
+        for genre in &entry.genres {
+            *ms_played_by_genre.entry(genre.to_lowercase()).or_insert(0) += entry.ms_played;
+        }
+    }
+    let dominant_genre = ms_played_by_genre
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(genre, _)| genre);
+
+    ListeningSession {
+        start,
+        end,
+        duration_ms: (end - start).num_milliseconds().max(0) as u64,
+        track_count: entries.len(),
+        dominant_genre,
+    }
+}
+
+/// Splits `entries` (which must be sorted by timestamp) into listening sessions.  A new session is
+/// started whenever more than `gap` passes between one play finishing and the next one starting.
+pub(crate) fn calculate_listening_sessions(
+    entries: &[ListeningEntry],
+    gap: chrono::Duration,
+) -> ListeningSessions {
+    let mut sessions = Vec::new();
+    let mut session_start_ix = 0;
+    for i in 1..=entries.len() {
+        let is_session_end = i == entries.len() || {
+            let prev = &entries[i - 1];
+            let prev_end = prev.timestamp + chrono::Duration::milliseconds(prev.ms_played as i64);
+            entries[i].timestamp - prev_end > gap
+        };
+        if is_session_end {
+            sessions.push(build_listening_session(&entries[session_start_ix..i]));
+            session_start_ix = i;
+        }
+    }
+
+    let average_session_duration_ms = if sessions.is_empty() {
+        0
+    } else {
+        sessions
+            .iter()
+            .map(|session| session.duration_ms)
+            .sum::<u64>()
+            / sessions.len() as u64
+    };
+    ListeningSessions {
+        sessions,
+        average_session_duration_ms,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct SkipRate {
+    pub artist_name: String,
+    /// Only set for per-track skip rates
+    pub track_name: Option<String>,
+    pub play_count: usize,
+    pub skip_count: usize,
+    pub skip_rate: f64,
+    /// Average percentage of the track listened to over the plays with a known track duration;
+    /// `None` if no durations are known
+    pub average_completion_percentage: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SkipStats {
+    pub total_play_count: usize,
+    pub total_skip_count: usize,
+    pub skip_percentage: f64,
+    /// Average percentage of the track listened to over the plays with a known track duration;
+    /// `None` if no durations are known
+    pub average_completion_percentage: Option<f64>,
+    /// Sorted by descending skip rate
+    pub artists: Vec<SkipRate>,
+    /// Sorted by descending skip rate
+    pub tracks: Vec<SkipRate>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SkipCounts {
+    play_count: usize,
+    skip_count: usize,
+    /// Sum of the completion ratios of plays with a known track duration
+    completion_sum: f64,
+    completion_count: usize,
+}
+
+impl SkipCounts {
+    fn add(&mut self, skipped: bool, completion: Option<f64>) {
+        self.play_count += 1;
+        self.skip_count += skipped as usize;
+        if let Some(completion) = completion {
+            self.completion_sum += completion;
+            self.completion_count += 1;
+        }
+    }
+
+    fn average_completion_percentage(&self) -> Option<f64> {
+        if self.completion_count == 0 {
+            return None;
+        }
+        Some(self.completion_sum / self.completion_count as f64 * 100.)
+    }
+}
+
+/// Computes overall, per-artist, and per-track skip rates.  Plays of tracks with a known duration
+/// count as skips if less than `min_completion` (a ratio between 0 and 1) of the track was played;
+/// plays of other tracks count as skips if they're shorter than `skip_threshold_ms`.  Artists and
+/// tracks with fewer than `min_plays` plays are left out.
+pub(crate) fn calculate_skip_stats(
+    entries: &[ListeningEntry],
+    skip_threshold_ms: u64,
+    min_completion: f64,
+    min_plays: usize,
+) -> SkipStats {
+    let mut counts_by_artist: FnvHashMap<&str, SkipCounts> = FnvHashMap::default();
+    let mut counts_by_track: FnvHashMap<(&str, &str), SkipCounts> = FnvHashMap::default();
+    let mut total_counts = SkipCounts::default();
+    for entry in entries {
+        let completion = entry
+            .duration_ms
+            .map(|duration_ms| (entry.ms_played as f64 / duration_ms as f64).min(1.));
+        let skipped = match completion {
+            Some(completion) => completion < min_completion,
+            None => entry.ms_played < skip_threshold_ms,
+        };
+
+        total_counts.add(skipped, completion);
+        for artist_name in entry.artist_names() {
+            counts_by_artist
+                .entry(artist_name)
+                .or_default()
+                .add(skipped, completion);
+        }
+        counts_by_track
+            .entry((&entry.track_name, &entry.artist_name))
+            .or_default()
+            .add(skipped, completion);
+    }
+
+    let build_skip_rates = |counts: Vec<(&str, Option<&str>, SkipCounts)>| {
+        let mut skip_rates: Vec<SkipRate> = counts
+            .into_iter()
+            .filter(|(_, _, counts)| counts.play_count >= min_plays)
+            .map(|(artist_name, track_name, counts)| SkipRate {
+                artist_name: artist_name.to_owned(),
+                track_name: track_name.map(str::to_owned),
+                play_count: counts.play_count,
+                skip_count: counts.skip_count,
+                skip_rate: counts.skip_count as f64 / counts.play_count as f64,
+                average_completion_percentage: counts.average_completion_percentage(),
+            })
+            .collect();
+        skip_rates.sort_unstable_by(|a, b| {
+            b.skip_rate
+                .total_cmp(&a.skip_rate)
+                .then_with(|| b.play_count.cmp(&a.play_count))
+                .then_with(|| a.artist_name.cmp(&b.artist_name))
+                .then_with(|| a.track_name.cmp(&b.track_name))
+        });
+        skip_rates
+    };
+
+    SkipStats {
+        total_play_count: total_counts.play_count,
+        total_skip_count: total_counts.skip_count,
+        skip_percentage: if entries.is_empty() {
+            0.
+        } else {
+            total_counts.skip_count as f64 / entries.len() as f64 * 100.
+        },
+        average_completion_percentage: total_counts.average_completion_percentage(),
+        artists: build_skip_rates(
+            counts_by_artist
+                .into_iter()
+                .map(|(artist_name, counts)| (artist_name, None, counts))
+                .collect(),
+        ),
+        tracks: build_skip_rates(
+            counts_by_track
+                .into_iter()
+                .map(|((track_name, artist_name), counts)| (artist_name, Some(track_name), counts))
+                .collect(),
+        ),
+    }
+}
+
+/// Number of plays by how much of the track was played
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub(crate) struct CompletionHistogram {
+    pub under_25_percent: usize,
+    pub from_25_to_50_percent: usize,
+    pub from_50_to_75_percent: usize,
+    /// Includes plays of exactly the full track
+    pub from_75_to_100_percent: usize,
+    /// Plays longer than the track, e.g. from repeating it or seeking back
+    pub over_100_percent: usize,
+    /// Plays of tracks without a known duration, which aren't counted in any bucket
+    pub unknown_duration_count: usize,
+}
+
+/// Bins every play by its completion ratio (`ms_played / duration_ms`)
+pub(crate) fn calculate_completion_histogram(entries: &[ListeningEntry]) -> CompletionHistogram {
+    let mut histogram = CompletionHistogram::default();
+    for entry in entries {
+        let duration_ms = match entry.duration_ms {
+            Some(duration_ms) => duration_ms,
+            None => {
+                histogram.unknown_duration_count += 1;
+                continue;
+            },
+        };
+
+        let completion = entry.ms_played as f64 / duration_ms as f64;
+        let bucket = if completion < 0.25 {
+            &mut histogram.under_25_percent
+        } else if completion < 0.5 {
+            &mut histogram.from_25_to_50_percent
+        } else if completion < 0.75 {
+            &mut histogram.from_50_to_75_percent
+        } else if completion <= 1. {
+            &mut histogram.from_75_to_100_percent
+        } else {
+            &mut histogram.over_100_percent
+        };
+        *bucket += 1;
+    }
+    histogram
+}
+
+/// Yields the indices of every pair of plays `(earlier, later)` that fall within `window` of each
+/// other.  Expects `entries` to be sorted by timestamp.
+fn co_occurring_pairs(
+    entries: &[ListeningEntry],
+    window: CoOccurrenceWindow,
+) -> impl Iterator<Item = (usize, usize)> + '_ {
+    entries.iter().enumerate().flat_map(move |(i, entry)| {
+        entries[i + 1..]
+            .iter()
+            .enumerate()
+            .take_while(move |(offset, other)| match window {
+                CoOccurrenceWindow::Plays(size) => offset + 1 < size,
+                CoOccurrenceWindow::Time(duration) => other.timestamp - entry.timestamp <= duration,
+            })
+            .map(move |(offset, _other)| (i, i + 1 + offset))
+    })
+}
+
+/// Counts how often each pair of distinct artists is played within `window` of each other and
+/// keeps the most frequent `max_related_artists` pairings for every artist.  Expects `entries` to
+/// be sorted by timestamp.
+pub(crate) fn calculate_artist_relationships(
+    entries: &[ListeningEntry],
+    window: CoOccurrenceWindow,
+    max_related_artists: usize,
+) -> FnvHashMap<String, Vec<(String, u32)>> {
+    let mut co_occurrences: FnvHashMap<&str, FnvHashMap<&str, u32>> = FnvHashMap::default();
+    for (i, j) in co_occurring_pairs(entries, window) {
+        let (entry, other) = (&entries[i], &entries[j]);
+        for artist_name in entry.artist_names() {
+            for other_artist_name in other.artist_names() {
+                if other_artist_name == artist_name {
+                    continue;
+                }
+
+                *co_occurrences
+                    .entry(artist_name)
+                    .or_default()
+                    .entry(other_artist_name)
+                    .or_insert(0) += 1;
+                *co_occurrences
+                    .entry(other_artist_name)
+                    .or_default()
+                    .entry(artist_name)
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+
+    co_occurrences
+        .into_iter()
+        .map(|(artist_name, counts)| {
+            let mut related: Vec<(&str, u32)> = counts.into_iter().collect();
+            related.sort_unstable_by(|(name_a, count_a), (name_b, count_b)| {
+                count_b.cmp(count_a).then_with(|| name_a.cmp(name_b))
+            });
+            let related = related
+                .into_iter()
+                .take(max_related_artists)
+                .map(|(related_name, count)| (artist_id(related_name), count))
+                .collect();
+            (artist_id(artist_name), related)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct GenreNode {
+    pub genre: String,
+    pub play_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct GenreEdge {
+    pub source: String,
+    pub target: String,
+    pub weight: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct GenreGraph {
+    /// Sorted by descending play count
+    pub nodes: Vec<GenreNode>,
+    /// Sorted by descending weight
+    pub edges: Vec<GenreEdge>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct AlsoListenedArtist {
+    pub artist: Artist,
+    pub co_occurrence_count: u32,
+    /// `co_occurrence_count` relative to the most co-listened artist, from 0 to 1
+    pub score: f32,
+}
+
+/// Returns up to `limit` of the artists most often listened to alongside `artist_id` in this
+/// dataset, most co-listened first.  Returns `None` if the artist is unknown; artists that were
+/// only ever played on their own have no co-listened artists.
+pub(crate) fn calculate_also_listened(
+    csv_data: &CsvData,
+    artist_id: &str,
+    limit: usize,
+) -> Option<Vec<AlsoListenedArtist>> {
+    if !csv_data.artists.contains_key(artist_id) {
+        return None;
+    }
+
+    let related_artists = known_related_artists(csv_data, artist_id, 0).unwrap_or_default();
+    let max_count = related_artists
+        .iter()
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or(1);
+    Some(
+        related_artists
+            .into_iter()
+            .take(limit)
+            .map(|(id, count)| AlsoListenedArtist {
+                artist: csv_data.artists[id].clone(),
+                co_occurrence_count: count,
+                score: count as f32 / max_count as f32,
+            })
+            .collect(),
+    )
+}
+
+/// Returns the related artists for `artist_id` along with their co-occurrence counts, leaving out
+/// any that don't have metadata in `csv_data.artists` or were co-listened fewer than `min_count`
+/// times.  Returns `None` if the artist itself is unknown or has no known related artists.
+pub(crate) fn known_related_artists<'a>(
+    csv_data: &'a CsvData,
+    artist_id: &str,
+    min_count: u32,
+) -> Option<Vec<(&'a str, u32)>> {
+    if !csv_data.artists.contains_key(artist_id) {
+        return None;
+    }
+
+    let related_artists: Vec<(&str, u32)> = csv_data
+        .related_artists
+        .get(artist_id)?
+        .iter()
+        .filter(|(id, count)| *count >= min_count && csv_data.artists.contains_key(id))
+        .map(|(id, count)| (id.as_str(), *count))
+        .collect();
+    if related_artists.is_empty() {
+        return None;
+    }
+    Some(related_artists)
+}
+
+/// Builds a graph of genres weighted by how often each pair of distinct genres is played within
+/// `window` of each other.  Genres are compared case-insensitively.  Only edges that are among the
+/// `max_edges_per_genre` heaviest for at least one of their genres are kept.
+pub(crate) fn calculate_genre_graph(
+    entries: &[ListeningEntry],
+    window: CoOccurrenceWindow,
+    max_edges_per_genre: usize,
+) -> GenreGraph {
+    let lowercased_genres: Vec<Vec<String>> = entries
+        .iter()
+        .map(|entry| {
+            let mut genres: Vec<String> = entry
+                .genres
+                .iter()
+                .map(|genre| genre.to_lowercase())
+                .collect();
+            genres.sort_unstable();
+            genres.dedup();
+            genres
+        })
+        .collect();
+    let mut play_counts: FnvHashMap<&str, usize> = FnvHashMap::default();
+    for genres in &lowercased_genres {
+        for genre in genres {
+            *play_counts.entry(genre).or_insert(0) += 1;
+        }
+    }
+
+    // Keyed by `(lesser_genre, greater_genre)` so each undirected edge is only counted once
+    let mut edge_weights: FnvHashMap<(&str, &str), u32> = FnvHashMap::default();
+    for (i, j) in co_occurring_pairs(entries, window) {
+        for genre in &lowercased_genres[i] {
+            for other_genre in &lowercased_genres[j] {
+                if genre == other_genre {
+                    continue;
+                }
+
+                let key = if genre < other_genre {
+                    (genre.as_str(), other_genre.as_str())
+                } else {
+                    (other_genre.as_str(), genre.as_str())
+                };
+                *edge_weights.entry(key).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut edges: Vec<((&str, &str), u32)> = edge_weights.into_iter().collect();
+    edges.sort_unstable_by(|(key_a, weight_a), (key_b, weight_b)| {
+        weight_b.cmp(weight_a).then_with(|| key_a.cmp(key_b))
+    });
+    // Walk edges from heaviest to lightest, keeping each one if either of its genres still has
+    // room for more
+    let mut edge_counts: FnvHashMap<&str, usize> = FnvHashMap::default();
+    let edges = edges
+        .into_iter()
+        .filter(|((source, target), _)| {
+            let has_room = edge_counts.get(source).copied().unwrap_or(0) < max_edges_per_genre
+                || edge_counts.get(target).copied().unwrap_or(0) < max_edges_per_genre;
+            if has_room {
+                *edge_counts.entry(source).or_insert(0) += 1;
+                *edge_counts.entry(target).or_insert(0) += 1;
+            }
+            has_room
+        })
+        .map(|((source, target), weight)| GenreEdge {
+            source: source.to_owned(),
+            target: target.to_owned(),
+            weight,
+        })
+        .collect();
+
+    let mut nodes: Vec<GenreNode> = play_counts
+        .into_iter()
+        .map(|(genre, play_count)| GenreNode {
+            genre: genre.to_owned(),
+            play_count,
+        })
+        .collect();
+    nodes.sort_unstable_by(|a, b| {
+        b.play_count
+            .cmp(&a.play_count)
+            .then_with(|| a.genre.cmp(&b.genre))
+    });
+
+    GenreGraph { nodes, edges }
+}
+
+/// Returns when each artist (keyed by name) and track (keyed by `(track_name, artist_name)`) was
+/// first played, crediting every artist on a track.  Expects `entries` to be sorted by timestamp.
+pub(crate) fn calculate_first_seen(
+    entries: &[ListeningEntry],
+) -> (
+    FnvHashMap<&str, DateTime<Utc>>,
+    FnvHashMap<(&str, &str), DateTime<Utc>>,
+) {
+    let mut artists_first_seen: FnvHashMap<&str, DateTime<Utc>> = FnvHashMap::default();
+    let mut tracks_first_seen: FnvHashMap<(&str, &str), DateTime<Utc>> = FnvHashMap::default();
+    for entry in entries {
+        for artist_name in entry.artist_names() {
+            artists_first_seen
+                .entry(artist_name)
+                .or_insert(entry.timestamp);
+        }
+        tracks_first_seen
+            .entry((&entry.track_name, &entry.artist_name))
+            .or_insert(entry.timestamp);
+    }
+    (artists_first_seen, tracks_first_seen)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Discovery {
+    pub artist_name: String,
+    /// Only set for discovered tracks
+    pub track_name: Option<String>,
+    pub first_seen: DateTime<Utc>,
+    /// Total plays since being discovered
+    pub play_count: usize,
+    pub ms_played: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Discoveries {
+    /// First day of the month that discoveries were found in
+    pub month: NaiveDate,
+    /// Sorted by descending play count
+    pub artists: Vec<Discovery>,
+    /// Sorted by descending play count
+    pub tracks: Vec<Discovery>,
+}
+
+/// Finds all artists and tracks that were first played during the month starting at `month`,
+/// defaulting to the latest month in the data.  Returns `None` if there are no entries.
+pub(crate) fn calculate_discoveries(
+    entries: &[ListeningEntry],
+    month: Option<NaiveDate>,
+) -> Option<Discoveries> {
+    let month = match month {
+        Some(month) => month.with_day(1)?,
+        None => month_start(entries.last()?.timestamp, 0).date(),
+    };
+    let is_in_month = |timestamp: DateTime<Utc>| {
+        timestamp.year() == month.year() && timestamp.month() == month.month()
+    };
+
+    let (artists_first_seen, tracks_first_seen) = calculate_first_seen(entries);
+    let mut artist_totals: FnvHashMap<&str, (usize, u64)> = FnvHashMap::default();
+    let mut track_totals: FnvHashMap<(&str, &str), (usize, u64)> = FnvHashMap::default();
+    for entry in entries {
+        for artist_name in entry.artist_names() {
+            if is_in_month(artists_first_seen[artist_name]) {
+                let totals = artist_totals.entry(artist_name).or_default();
+                totals.0 += 1;
+                totals.1 += entry.ms_played;
+            }
+        }
+        let track_key = (entry.track_name.as_str(), entry.artist_name.as_str());
+        if is_in_month(tracks_first_seen[&track_key]) {
+            let totals = track_totals.entry(track_key).or_default();
+            totals.0 += 1;
+            totals.1 += entry.ms_played;
+        }
+    }
+
+    let sort_discoveries = |discoveries: &mut Vec<Discovery>| {
+        discoveries.sort_unstable_by(|a, b| {
+            b.play_count
+                .cmp(&a.play_count)
+                .then_with(|| b.ms_played.cmp(&a.ms_played))
+                .then_with(|| a.artist_name.cmp(&b.artist_name))
+                .then_with(|| a.track_name.cmp(&b.track_name))
+        })
+    };
+    let mut artists: Vec<Discovery> = artist_totals
+        .into_iter()
+        .map(|(artist_name, (play_count, ms_played))| Discovery {
+            artist_name: artist_name.to_owned(),
+            track_name: None,
+            first_seen: artists_first_seen[artist_name],
+            play_count,
+            ms_played,
+        })
+        .collect();
+    sort_discoveries(&mut artists);
+    let mut tracks: Vec<Discovery> = track_totals
+        .into_iter()
+        .map(
+            |((track_name, artist_name), (play_count, ms_played))| Discovery {
+                artist_name: artist_name.to_owned(),
+                track_name: Some(track_name.to_owned()),
+                first_seen: tracks_first_seen[&(track_name, artist_name)],
+                play_count,
+                ms_played,
+            },
+        )
+        .collect();
+    sort_discoveries(&mut tracks);
+
+    Some(Discoveries {
+        month,
+        artists,
+        tracks,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DeepCut {
+    pub track: Track,
+    pub play_count: usize,
+    pub first_seen: DateTime<Utc>,
+}
+
+/// Finds tracks played at least `min_plays` times but fewer than `max_plays` times.  They're sorted
+/// by when they were first played so that long-forgotten tracks come first.
+pub(crate) fn calculate_deep_cuts(
+    csv_data: &CsvData,
+    min_plays: usize,
+    max_plays: usize,
+) -> Vec<DeepCut> {
+    let mut deep_cuts: Vec<DeepCut> = csv_data
+        .track_play_counts
+        .iter()
+        .filter(|(_, &play_count)| play_count >= min_plays && play_count < max_plays)
+        .filter_map(|(track_key, &play_count)| {
+            let (track_name, artist_name) = track_key;
+            Some(DeepCut {
+                track: csv_data
+                    .tracks
+                    .get(&track_id(track_name, artist_name))?
+                    .clone(),
+                play_count,
+                first_seen: *csv_data.track_first_seen.get(track_key)?,
+            })
+        })
+        .collect();
+    deep_cuts.sort_unstable_by(|a, b| {
+        a.first_seen
+            .cmp(&b.first_seen)
+            .then_with(|| a.track.id.cmp(&b.track.id))
+    });
+    deep_cuts
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ListeningEntriesPage<'a> {
+    /// Total number of entries matching the filters, ignoring pagination
+    pub total: usize,
+    pub entries: Vec<&'a ListeningEntry>,
+}
+
+/// Returns the entries played within `date_range` of tracks crediting the artist named
+/// `artist_name` (compared case-insensitively), paginated with `limit` and `offset`.  Entries are
+/// in chronological order.
+pub(crate) fn filter_entries<'a>(
+    entries: &'a [ListeningEntry],
+    date_range: Option<&DateRange>,
+    artist_name: Option<&str>,
+    limit: usize,
+    offset: usize,
+) -> ListeningEntriesPage<'a> {
+    let artist_name = artist_name.map(str::to_lowercase);
+    let matching: Vec<&ListeningEntry> = entries
+        .iter()
+        .filter(|e| date_range.is_none_or(|date_range| date_range.contains(e.timestamp)))
+        .filter(|e| {
+            artist_name.as_ref().is_none_or(|artist_name| {
+                e.artist_names()
+                    .any(|name| name.to_lowercase() == *artist_name)
+            })
+        })
+        .collect();
+
+    ListeningEntriesPage {
+        total: matching.len(),
+        entries: matching.into_iter().skip(offset).take(limit).collect(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Collaborator {
+    pub artist: Artist,
+    /// Number of distinct tracks both artists are credited on
+    pub shared_track_count: usize,
+    /// Number of plays of those tracks
+    pub play_count: usize,
+}
+
+/// Returns the artists credited jointly with the artist with ID `artist_id` on at least one track,
+/// sorted by how many tracks they share.  Returns `None` if there's no artist with that ID.
+pub(crate) fn calculate_collaborators(
+    csv_data: &CsvData,
+    artist_id: &str,
+) -> Option<Vec<Collaborator>> {
+    let artist_name = csv_data.artists.get(artist_id)?.name.as_str();
+
+    let mut shared_tracks_by_artist: FnvHashMap<&str, FnvHashSet<(&str, &str)>> =
+        FnvHashMap::default();
+    let mut play_counts_by_artist: FnvHashMap<&str, usize> = FnvHashMap::default();
+    for entry in &csv_data.entries {
+        if !entry.artist_names().any(|name| name == artist_name) {
+            continue;
+        }
+
+        let track_key = (entry.track_name.as_str(), entry.artist_name.as_str());
+        for collaborator_name in entry.artist_names().filter(|name| *name != artist_name) {
+            shared_tracks_by_artist
+                .entry(collaborator_name)
+                .or_default()
+                .insert(track_key);
+            *play_counts_by_artist.entry(collaborator_name).or_insert(0) += 1;
+        }
+    }
+
+    let mut collaborators: Vec<Collaborator> = shared_tracks_by_artist
+        .into_iter()
+        .filter_map(|(collaborator_name, shared_tracks)| {
+            Some(Collaborator {
+                artist: csv_data
+                    .artists
+                    .get(&self::artist_id(collaborator_name))?
+                    .clone(),
+                shared_track_count: shared_tracks.len(),
+                play_count: play_counts_by_artist[collaborator_name],
+            })
+        })
+        .collect();
+    collaborators.sort_unstable_by(|a, b| {
+        b.shared_track_count
+            .cmp(&a.shared_track_count)
+            .then_with(|| b.play_count.cmp(&a.play_count))
+            .then_with(|| a.artist.name.cmp(&b.artist.name))
+    });
+    Some(collaborators)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct AlbumStats {
+    /// `None` if the source data has no album names, in which case this is the total for all of
+    /// the artist's tracks
+    pub id: Option<String>,
+    pub album_name: Option<String>,
+    pub artist_name: String,
+    pub ms_played: u64,
+    pub play_count: usize,
+    /// Number of distinct tracks played from the album
+    pub track_count: usize,
+}
+
+/// Returns the top `limit` albums by total ms played.
+///
+/// Not all exports include album names.  Entries without one are grouped by artist instead, so
+/// for data without an album column this is effectively a list of top artists.
+pub(crate) fn calculate_top_albums(entries: &[ListeningEntry], limit: usize) -> Vec<AlbumStats> {
+    let mut totals: FnvHashMap<(Option<&str>, &str), (u64, usize, FnvHashSet<&str>)> =
+        FnvHashMap::default();
+    for entry in entries {
+        let key = (entry.album_name.as_deref(), entry.artist_name.as_str());
+        let (ms_played, play_count, tracks) = totals.entry(key).or_default();
+        *ms_played += entry.ms_played;
+        *play_count += 1;
+        tracks.insert(&entry.track_name);
+    }
+
+    let mut albums: Vec<AlbumStats> = totals
+        .into_iter()
+        .map(
+            |((album_name, artist_name), (ms_played, play_count, tracks))| AlbumStats {
+                id: album_name.map(|album_name| album_id(album_name, artist_name)),
+                album_name: album_name.map(str::to_owned),
+                artist_name: artist_name.to_owned(),
+                ms_played,
+                play_count,
+                track_count: tracks.len(),
+            },
+        )
+        .collect();
+    albums.sort_unstable_by(|a, b| {
+        b.ms_played
+            .cmp(&a.ms_played)
+            .then_with(|| a.artist_name.cmp(&b.artist_name))
+            .then_with(|| a.album_name.cmp(&b.album_name))
+    });
+    albums.truncate(limit);
+    albums
+}
+
+/// Returns the start of the short and medium timeframes.  These are relative to the latest entry
+/// rather than the current time so that old exports still produce useful results.
+fn timeframe_starts(
+    entries: &[ListeningEntry],
+    timeframe_windows: TimeframeWindows,
+) -> (DateTime<Utc>, DateTime<Utc>) {
+    let latest_timestamp = entries.last().map(|e| e.timestamp).unwrap_or_else(Utc::now);
+    (
+        latest_timestamp - timeframe_windows.short(),
+        latest_timestamp - timeframe_windows.medium(),
+    )
+}
+
+fn calculate_top_artists(
+    entries: &[ListeningEntry],
+    artist_play_counts: &FnvHashMap<String, u64>,
+    timeframe_windows: TimeframeWindows,
+    limit: usize,
+) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let (short_start, medium_start) = timeframe_starts(entries, timeframe_windows);
+
+    let mut short_counts: FnvHashMap<String, u64> = FnvHashMap::default();
+    let mut medium_counts: FnvHashMap<String, u64> = FnvHashMap::default();
+
+    for entry in entries.iter().rev() {
+        for artist_name in entry.artist_names() {
+            if entry.timestamp > short_start {
+                *short_counts.entry(artist_name.to_owned()).or_insert(0) += entry.ms_played;
+            }
+            if entry.timestamp > medium_start {
+                *medium_counts.entry(artist_name.to_owned()).or_insert(0) += entry.ms_played;
+            }
+        }
+    }
+
+    let top_short = get_top_n(&short_counts, limit);
+    let top_medium = get_top_n(&medium_counts, limit);
+    let top_long = get_top_n(artist_play_counts, limit);
+
+    (top_short, top_medium, top_long)
+}
+
+fn calculate_top_tracks(
+    entries: &[ListeningEntry],
+    track_play_counts: &FnvHashMap<(String, String), u64>,
+    timeframe_windows: TimeframeWindows,
+    limit: usize,
+) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let (short_start, medium_start) = timeframe_starts(entries, timeframe_windows);
+
+    let mut short_counts: FnvHashMap<(String, String), u64> = FnvHashMap::default();
+    let mut medium_counts: FnvHashMap<(String, String), u64> = FnvHashMap::default();
+
+    for entry in entries.iter().rev() {
+        let key = (entry.track_name.clone(), entry.artist_name.clone());
+        if entry.timestamp > short_start {
+            *short_counts.entry(key.clone()).or_insert(0) += entry.ms_played;
+        }
+        if entry.timestamp > medium_start {
+            *medium_counts.entry(key.clone()).or_insert(0) += entry.ms_played;
+        }
+    }
+
+    let top_short = get_top_n_tracks(&short_counts, limit);
+    let top_medium = get_top_n_tracks(&medium_counts, limit);
+    let top_long = get_top_n_tracks(track_play_counts, limit);
+
+    (top_short, top_medium, top_long)
+}
+
+/// Returns the IDs of the top `limit` artists and tracks for the short, medium, and long
+/// timeframes.  The precomputed lists are used if they're long enough, and otherwise they're
+/// recomputed from the entries using the precomputed play time totals.
+pub(crate) fn get_top_ids(
+    csv_data: &CsvData,
+    timeframe_windows: TimeframeWindows,
+    limit: usize,
+) -> ([Vec<String>; 3], [Vec<String>; 3]) {
+    if limit <= PRECOMPUTED_TOP_N {
+        let truncated = |ids: &[String]| ids.iter().take(limit).cloned().collect::<Vec<_>>();
+        return (
+            [
+                truncated(&csv_data.top_artists_short),
+                truncated(&csv_data.top_artists_medium),
+                truncated(&csv_data.top_artists_long),
+            ],
+            [
+                truncated(&csv_data.top_tracks_short),
+                truncated(&csv_data.top_tracks_medium),
+                truncated(&csv_data.top_tracks_long),
+            ],
+        );
+    }
+
+    let (artists_short, artists_medium, artists_long) = calculate_top_artists(
+        &csv_data.entries,
+        &csv_data.artist_ms_played,
+        timeframe_windows,
+        limit,
+    );
+    let (tracks_short, tracks_medium, tracks_long) = calculate_top_tracks(
+        &csv_data.entries,
+        &csv_data.track_ms_played,
+        timeframe_windows,
+        limit,
+    );
+    ([artists_short, artists_medium, artists_long], [
+        tracks_short,
+        tracks_medium,
+        tracks_long,
+    ])
+}
+
+/// Computes the top artists and tracks over just the entries that fall within `date_range`, in the
+/// same format as the precomputed top artists/tracks on `CsvData`.
+pub(crate) fn calculate_top_in_range(
+    entries: &[ListeningEntry],
+    date_range: &DateRange,
+    limit: usize,
+) -> (Vec<String>, Vec<String>) {
+    let mut artist_counts: FnvHashMap<String, u64> = FnvHashMap::default();
+    let mut track_counts: FnvHashMap<(String, String), u64> = FnvHashMap::default();
+
+    for entry in entries.iter().filter(|e| date_range.contains(e.timestamp)) {
+        for artist_name in entry.artist_names() {
+            *artist_counts.entry(artist_name.to_owned()).or_insert(0) += entry.ms_played;
+        }
+        *track_counts
+            .entry((entry.track_name.clone(), entry.artist_name.clone()))
+            .or_insert(0) += entry.ms_played;
+    }
+
+    (
+        get_top_n(&artist_counts, limit),
+        get_top_n_tracks(&track_counts, limit),
+    )
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct WrappedSummary {
+    pub year: i32,
+    /// Sorted by descending time played
+    pub top_artists: Vec<Artist>,
+    /// Sorted by descending time played
+    pub top_tracks: Vec<Track>,
+    /// Genre and total time played in milliseconds, sorted by descending time played
+    pub top_genres: Vec<(String, u64)>,
+    pub total_minutes: u64,
+    pub distinct_artist_count: usize,
+    /// The day with the most time played along with the time played on it in milliseconds
+    pub top_day: (NaiveDate, u64),
+}
+
+/// Summarizes listening over the calendar `year` (in UTC) with the top `limit` artists, tracks,
+/// and genres, total time played, and the most-played day.  Returns `None` if nothing was played
+/// during `year`.
+pub(crate) fn calculate_wrapped(
+    csv_data: &CsvData,
+    year: i32,
+    limit: usize,
+) -> Option<WrappedSummary> {
+    let date_range = DateRange {
+        from: Some(NaiveDate::from_ymd_opt(year, 1, 1)?),
+        to: Some(NaiveDate::from_ymd_opt(year, 12, 31)?),
+    };
+
+    let listening_time = calculate_listening_time(&csv_data.entries, Some(&date_range));
+    let top_day = most_played_day(&listening_time.ms_played_by_day)?;
+
+    let (top_artist_ids, top_track_ids) =
+        calculate_top_in_range(&csv_data.entries, &date_range, limit);
+    let entries = csv_data
+        .entries
+        .iter()
+        .filter(|entry| date_range.contains(entry.timestamp));
+    let mut top_genres = sum_genre_play_times(&genres_by_artist_name(csv_data), entries);
+    top_genres.truncate(limit);
+
+    Some(WrappedSummary {
+        year,
+        top_artists: top_artist_ids
+            .iter()
+            .filter_map(|id| csv_data.artists.get(id).cloned())
+            .collect(),
+        top_tracks: top_track_ids
+            .iter()
+            .filter_map(|id| csv_data.tracks.get(id).cloned())
+            .collect(),
+        top_genres,
+        total_minutes: listening_time.total_ms_played / (1000 * 60),
+        distinct_artist_count: listening_time.distinct_artist_count,
+        top_day,
+    })
+}
+
+/// Returns the day with the most time played, preferring the earliest day on ties
+fn most_played_day(ms_played_by_day: &[(NaiveDate, u64)]) -> Option<(NaiveDate, u64)> {
+    ms_played_by_day
+        .iter()
+        .copied()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+}
+
+/// All-time records, each paired with its total time played in milliseconds.  Fields are `None`
+/// if they can't be computed, such as when there's no listening history or no genres.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ListeningRecords {
+    pub top_artist: Option<(Artist, u64)>,
+    pub top_track: Option<(Track, u64)>,
+    pub top_genre: Option<(String, u64)>,
+    pub top_day: Option<(NaiveDate, u64)>,
+    /// Sessions are split the same way as `calculate_listening_sessions` with `session_gap`
+    pub longest_session: Option<ListeningSession>,
+}
+
+/// Returns the session with the longest duration, preferring the earliest session on ties
+fn longest_listening_session(
+    entries: &[ListeningEntry],
+    session_gap: chrono::Duration,
+) -> Option<ListeningSession> {
+    calculate_listening_sessions(entries, session_gap)
+        .sessions
+        .into_iter()
+        .max_by(|a, b| {
+            a.duration_ms
+                .cmp(&b.duration_ms)
+                .then_with(|| b.start.cmp(&a.start))
+        })
+}
+
+/// Finds the all-time #1 artist, track, genre, and day along with the longest listening session.
+/// Sessions are only split from scratch if `session_gap` isn't the default.
+pub(crate) fn calculate_records(
+    csv_data: &CsvData,
+    session_gap: chrono::Duration,
+) -> ListeningRecords {
+    let top_artist = csv_data
+        .top_artists_long
+        .first()
+        .and_then(|id| csv_data.artists.get(id))
+        .map(|artist| {
+            let ms_played = csv_data
+                .artist_ms_played
+                .get(&artist.name)
+                .copied()
+                .unwrap_or(0);
+            (artist.clone(), ms_played)
+        });
+    let top_track = csv_data
+        .top_tracks_long
+        .first()
+        .and_then(|id| csv_data.tracks.get(id))
+        .map(|track| {
+            // Tracks are keyed by their primary artist, which is always listed first
+            let artist_name = track
+                .artists
+                .first()
+                .map_or("", |artist| artist.name.as_str());
+            let ms_played = csv_data
+                .track_ms_played
+                .get(&(track.name.clone(), artist_name.to_owned()))
+                .copied()
+                .unwrap_or(0);
+            (track.clone(), ms_played)
+        });
+
+    let longest_session =
+        if session_gap == chrono::Duration::minutes(DEFAULT_SESSION_GAP_MINUTES as i64) {
+            csv_data.longest_session.clone()
+        } else {
+            longest_listening_session(&csv_data.entries, session_gap)
+        };
+
+    ListeningRecords {
+        top_artist,
+        top_track,
+        top_genre: calculate_genre_play_times(csv_data).into_iter().next(),
+        top_day: csv_data.most_played_day,
+        longest_session,
+    }
+}
+
+/// Returns the IDs of the `n` artists with the highest counts
+fn get_top_n(counts: &FnvHashMap<String, u64>, n: usize) -> Vec<String> {
+    let mut sorted: Vec<_> = counts.iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(a.1));
+    sorted
+        .iter()
+        .take(n)
+        .map(|(name, _)| artist_id(name))
+        .collect()
+}
+
+/// Returns the IDs of the `n` tracks with the highest counts
+fn get_top_n_tracks(counts: &FnvHashMap<(String, String), u64>, n: usize) -> Vec<String> {
+    let mut sorted: Vec<_> = counts.iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(a.1));
+    sorted
+        .iter()
+        .take(n)
+        .map(|((track, artist), _)| track_id(track, artist))
+        .collect()
+}
+
+fn build_artists(
+    artist_play_counts: &FnvHashMap<String, u64>,
+    artist_genres_map: &FnvHashMap<String, Vec<String>>,
+) -> FnvHashMap<String, Artist> {
+    let mut artists = FnvHashMap::default();
+    
+    for (artist_name, _) in artist_play_counts.iter() {
+        let genres = artist_genres_map
+            .get(artist_name)
+            .cloned();
+        
+        let spotify_id = artist_id(artist_name);
+        
+        artists.insert(
+            spotify_id.clone(),
+            Artist {
+                id: spotify_id,
+                name: artist_name.clone(),
+                genres,
+                images: Some(vec![]),
+                popularity: Some(50), // Default popularity
+            },
+        );
+    }
+    
+    artists
+}
+
+/// `track_albums` maps `(track_name, artist_name)` to album name.  Tracks without an entry there
+/// are assigned to a placeholder "Unknown Album".  `track_durations` is keyed the same way; tracks
+/// without an entry there have an unknown duration.  `track_featured_artists` is also keyed the
+/// same way and lists the artists credited on the track other than its first one.
+fn build_tracks(
+    track_play_counts: &FnvHashMap<(String, String), u64>,
+    track_albums: &FnvHashMap<(String, String), String>,
+    track_durations: &FnvHashMap<(String, String), u32>,
+    track_featured_artists: &FnvHashMap<(String, String), Vec<String>>,
+) -> FnvHashMap<String, Track> {
+    let mut tracks = FnvHashMap::default();
+    
+    for ((track_name, artist_name), _) in track_play_counts.iter() {
+        let spotify_id = track_id(track_name, artist_name);
+        let key = (track_name.clone(), artist_name.clone());
+        let album = match track_albums.get(&key) {
+            Some(album_name) => crate::models::Album {
+                id: album_id(album_name, artist_name),
+                name: album_name.clone(),
+                artists: vec![],
+                images: vec![],
+            },
+            None => crate::models::Album {
+                id: "csv_unknown".to_string(),
+                name: "Unknown Album".to_string(),
+                artists: vec![],
+                images: vec![],
+            },
+        };
+        
+        let track_artists = std::iter::once(artist_name)
+            .chain(track_featured_artists.get(&key).into_iter().flatten())
+            .map(|artist_name| Artist {
+                id: artist_id(artist_name),
+                name: artist_name.clone(),
+                genres: None,
+                images: Some(vec![]),
+                popularity: None,
+            })
+            .collect();
+
+        tracks.insert(spotify_id.clone(), Track {
+            id: spotify_id,
+            name: track_name.clone(),
+            artists: track_artists,
+            album,
+            duration_ms: track_durations.get(&key).copied(),
+            preview_url: None,
+            external_ids: None,
+        });
+    }
+    
+    tracks
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
 
+    const FIXTURE_CSV: &str = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,120000,\"rock, indie\",\"rock,indie\"
+2023-01-02T10:00:00Z,Song A,Artist One,60000,\"rock, indie\",\"rock,indie\"
+2023-01-03T10:00:00Z,Song B,Artist One,180000,\"rock, indie\",\"rock,indie\"
+2023-02-01T10:00:00Z,Song C,Artist Two,200000,jazz,
+2023-02-02T10:00:00Z,Song A,Artist One,30000,\"rock, indie\",\"rock,indie\"
+2023-03-01T10:00:00Z,Song C,Artist Two,200000,jazz,
+";
+
+    fn entry(ts: &str, artist_name: &str, genres: &[&str]) -> ListeningEntry {
+        ListeningEntry {
+            timestamp: DateTime::parse_from_rfc3339(ts)
+                .unwrap()
+                .with_timezone(&Utc),
+            track_name: format!("{} track", artist_name),
+            artist_name: artist_name.to_string(),
+            featured_artist_names: Vec::new(),
+            album_name: None,
+            ms_played: 60_000,
+            duration_ms: None,
+            genres: genres.iter().map(|g| g.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_genre_history_lengths_match_timestamps() {
+        let entries = vec![
+            entry("2023-01-10T10:00:00Z", "Artist One", &["rock"]),
+            entry("2023-02-10T10:00:00Z", "Artist One", &["rock"]),
+            entry("2023-02-11T10:00:00Z", "Artist Two", &["jazz"]),
+            entry("2023-03-10T10:00:00Z", "Artist One", &["rock"]),
+        ];
+
+        let (timestamps, history_by_genre) = calculate_genre_history(&entries, true, 0);
+        assert_eq!(timestamps.len(), 3);
+        for history in history_by_genre.values() {
+            assert_eq!(history.len(), timestamps.len());
+        }
+        assert_eq!(history_by_genre["jazz"], vec![
+            None,
+            Some(crate::stats::weight_data_point(2, 0)),
+            None
+        ]);
+    }
+
+    #[test]
+    fn test_genre_history_buckets_by_calendar_month() {
+        let entries = vec![
+            entry("2023-01-31T23:59:59Z", "Artist One", &["rock"]),
+            entry("2023-02-01T00:00:00Z", "Artist Two", &["jazz"]),
+            entry("2023-02-28T23:59:59Z", "Artist Two", &["jazz"]),
+            entry("2023-03-01T00:00:00Z", "Artist Three", &["pop"]),
+        ];
+
+        let (timestamps, history_by_genre) = calculate_genre_history(&entries, true, 0);
+        let month_starts: Vec<String> = timestamps.iter().map(|ts| ts.to_string()).collect();
+        assert_eq!(month_starts, [
+            "2023-01-01 00:00:00",
+            "2023-02-01 00:00:00",
+            "2023-03-01 00:00:00"
+        ]);
+        let top_score = Some(crate::stats::weight_data_point(1, 0));
+        assert_eq!(history_by_genre["rock"], vec![top_score, None, None]);
+        assert_eq!(history_by_genre["jazz"], vec![None, top_score, None]);
+        assert_eq!(history_by_genre["pop"], vec![None, None, top_score]);
+
+        // Month boundaries follow the local time of the provided offset
+        let (timestamps, history_by_genre) = calculate_genre_history(&entries, true, -300);
+        let month_starts: Vec<String> = timestamps.iter().map(|ts| ts.to_string()).collect();
+        assert_eq!(month_starts, ["2023-01-01 00:00:00", "2023-02-01 00:00:00"]);
+        assert!(history_by_genre["rock"][0].is_some() && history_by_genre["rock"][1].is_none());
+        assert!(history_by_genre["pop"][0].is_none() && history_by_genre["pop"][1].is_some());
+    }
+
+    #[test]
+    fn test_monthly_ms_played_fills_gap_months() {
+        let entries = vec![
+            entry("2023-01-10T10:00:00Z", "Artist One", &[]),
+            entry("2023-01-31T23:30:00Z", "Artist One", &[]),
+            entry("2023-03-05T10:00:00Z", "Artist Two", &[]),
+        ];
+
+        let month = |month: u32| NaiveDate::from_ymd_opt(2023, month, 1).unwrap();
+        assert_eq!(calculate_monthly_ms_played(&entries, 0), [
+            (month(1), 120_000),
+            (month(2), 0),
+            (month(3), 60_000)
+        ]);
+        // Months follow the user's local time
+        assert_eq!(calculate_monthly_ms_played(&entries, 60), [
+            (month(1), 60_000),
+            (month(2), 60_000),
+            (month(3), 60_000)
+        ]);
+        assert!(calculate_monthly_ms_played(&[], 0).is_empty());
+    }
+
+    #[test]
+    fn test_genre_shares_sum_to_one() {
+        let mut entries = vec![
+            entry("2023-01-10T10:00:00Z", "Artist One", &["rock", "indie"]),
+            entry("2023-01-11T10:00:00Z", "Artist Two", &["jazz"]),
+            entry("2023-01-12T10:00:00Z", "Artist Two", &["jazz"]),
+            entry("2023-03-10T10:00:00Z", "Artist Three", &["pop"]),
+            entry("2023-03-11T10:00:00Z", "Artist Four", &["metal"]),
+        ];
+        entries[4].ms_played = 1_000;
+
+        let shares = calculate_genre_shares(&entries, true, 0, 0.05);
+        assert_eq!(shares.timestamps.len(), 3);
+        for (bucket_ix, expected_total) in [(0, 1.), (1, 0.), (2, 1.)] {
+            let total: f64 = shares
+                .shares_by_genre
+                .values()
+                .map(|history| history[bucket_ix])
+                .sum();
+            assert!(
+                (total - expected_total).abs() < 1e-9,
+                "bucket {}: {}",
+                bucket_ix,
+                total
+            );
+        }
+        assert!((shares.shares_by_genre["jazz"][0] - 0.5).abs() < 1e-9);
+        assert_eq!(shares.shares_by_genre["jazz"][2], 0.);
+        // Metal is under 5% of March's listening so it's merged into the other series
+        assert!(!shares.shares_by_genre.contains_key("metal"));
+        assert!(shares.shares_by_genre[OTHER_GENRE][2] > 0.);
+    }
+
+    #[test]
+    fn test_genre_history_fills_month_gaps() {
+        let entries = vec![
+            entry("2023-01-10T10:00:00Z", "Artist One", &["rock"]),
+            entry("2023-04-10T10:00:00Z", "Artist One", &["rock"]),
+        ];
+
+        let (timestamps, history_by_genre) = calculate_genre_history(&entries, true, 0);
+        let months: Vec<u32> = timestamps.iter().map(|ts| ts.month()).collect();
+        assert_eq!(months, [1, 2, 3, 4]);
+        let top_score = Some(crate::stats::weight_data_point(1, 0));
+        assert_eq!(history_by_genre["rock"], vec![
+            top_score, None, None, top_score
+        ]);
+
+        let (timestamps, history_by_genre) = calculate_genre_history(&entries, false, 0);
+        assert_eq!(timestamps.len(), 2);
+        assert_eq!(history_by_genre["rock"], vec![top_score, top_score]);
+    }
+
+    #[test]
+    fn test_artist_popularity_history() {
+        let entries = vec![
+            entry("2023-01-05T10:00:00Z", "Artist One", &[]),
+            entry("2023-01-06T10:00:00Z", "Artist One", &[]),
+            entry("2023-01-07T10:00:00Z", "Artist Two", &[]),
+            entry("2023-02-20T10:00:00Z", "Artist Two", &[]),
+            entry("2023-02-21T10:00:00Z", "Artist Two", &[]),
+            entry("2023-02-22T10:00:00Z", "Artist Two", &[]),
+        ];
+
+        let windows = TimeframeWindows::default();
+        let history_by_artist =
+            calculate_artists_popularity_history(&entries, &["Artist One", "Artist Two"], windows);
+        let history = &history_by_artist["Artist One"];
+        let months: Vec<NaiveDateTime> = history.iter().map(|(ts, _)| *ts).collect();
+        assert_eq!(months, vec![
+            NaiveDate::from_ymd_opt(2023, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            NaiveDate::from_ymd_opt(2023, 2, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+        ]);
+        // Artist One falls out of the short-term window in February
+        assert_eq!(history[0].1, [Some(0), Some(0), Some(0)]);
+        assert_eq!(history[1].1, [None, Some(1), Some(1)]);
+
+        let history = &history_by_artist["Artist Two"];
+        assert_eq!(history[0].1, [Some(1), Some(1), Some(1)]);
+        assert_eq!(history[1].1, [Some(0), Some(0), Some(0)]);
+    }
+
+    #[test]
+    fn test_listening_clock_applies_tz_offset() {
+        let entries = vec![entry("2023-01-05T23:30:00Z", "Artist One", &[])];
+
+        let clock = calculate_listening_clock(&entries, 90);
+        assert_eq!(clock[1], ListeningBucket {
+            ms_played: 60_000,
+            play_count: 1,
+        });
+        assert_eq!(
+            clock.iter().map(|bucket| bucket.play_count).sum::<usize>(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_listening_weekdays_applies_tz_offset() {
+        // 2023-01-05 is a Thursday; with the offset applied this play happens early on Friday
+        let entries = vec![entry("2023-01-05T23:30:00Z", "Artist One", &[])];
+
+        let weekdays = calculate_listening_weekdays(&entries, 0);
+        assert_eq!(weekdays[3].play_count, 1);
+
+        let weekdays = calculate_listening_weekdays(&entries, 90);
+        assert_eq!(weekdays[3].play_count, 0);
+        assert_eq!(weekdays[4], ListeningBucket {
+            ms_played: 60_000,
+            play_count: 1,
+        });
+    }
+
+    #[test]
+    fn test_artist_relationships_within_window() {
+        let entries = vec![
+            entry("2023-01-01T00:00:00Z", "Artist One", &[]),
+            entry("2023-01-01T00:05:00Z", "Artist Two", &[]),
+            entry("2023-01-01T00:10:00Z", "Artist One", &[]),
+            entry("2023-01-01T00:15:00Z", "Artist Three", &[]),
+        ];
+
+        let related = calculate_artist_relationships(&entries, CoOccurrenceWindow::Plays(2), 20);
+        assert_eq!(related["csv_artist_one"], vec![
+            ("csv_artist_two".to_string(), 2),
+            ("csv_artist_three".to_string(), 1),
+        ]);
+        assert_eq!(related["csv_artist_three"], vec![(
+            "csv_artist_one".to_string(),
+            1
+        )]);
+    }
+
+    #[test]
+    fn test_time_based_artist_relationships_skip_cross_day_pairs() {
+        let entries = vec![
+            entry("2023-01-01T22:00:00Z", "Artist One", &[]),
+            entry("2023-01-01T22:10:00Z", "Artist Two", &[]),
+            entry("2023-01-02T22:00:00Z", "Artist Three", &[]),
+            entry("2023-01-02T22:20:00Z", "Artist One", &[]),
+        ];
+
+        let related = calculate_artist_relationships(
+            &entries,
+            CoOccurrenceWindow::Time(chrono::Duration::minutes(30)),
+            20,
+        );
+        assert_eq!(related["csv_artist_two"], vec![(
+            "csv_artist_one".to_string(),
+            1
+        )]);
+        assert_eq!(related["csv_artist_three"], vec![(
+            "csv_artist_one".to_string(),
+            1
+        )]);
+        assert_eq!(related["csv_artist_one"], vec![
+            ("csv_artist_three".to_string(), 1),
+            ("csv_artist_two".to_string(), 1),
+        ]);
+
+        // The same plays counted by position pair up across days
+        let related = calculate_artist_relationships(&entries, CoOccurrenceWindow::Plays(2), 20);
+        assert_eq!(related["csv_artist_two"].len(), 2);
+    }
+
+    #[test]
+    fn test_max_related_artists() {
+        let entries: Vec<ListeningEntry> = (0..10)
+            .map(|i| entry("2023-01-01T10:00:00Z", &format!("Artist {}", i), &[]))
+            .collect();
+        let build = |max_related_artists| {
+            build_csv_data(entries.clone(), CsvBuildOptions {
+                co_occurrence_window: CoOccurrenceWindow::Plays(50),
+                max_related_artists,
+                ..CsvBuildOptions::default()
+            })
+        };
+
+        let data = build(3);
+        assert!(data
+            .related_artists
+            .values()
+            .all(|related| related.len() == 3));
+        let data = build(20);
+        assert!(data
+            .related_artists
+            .values()
+            .all(|related| related.len() == 9));
+    }
+
+    #[test]
+    fn test_listening_streaks() {
+        let entries = vec![
+            entry("2023-01-01T12:00:00Z", "Artist One", &[]),
+            entry("2023-01-02T12:00:00Z", "Artist One", &[]),
+            entry("2023-01-02T18:00:00Z", "Artist Two", &[]),
+            entry("2023-01-03T12:00:00Z", "Artist One", &[]),
+            entry("2023-01-05T12:00:00Z", "Artist One", &[]),
+            entry("2023-01-06T23:30:00Z", "Artist One", &[]),
+        ];
+
+        let streaks = calculate_listening_streaks(&entries, 0);
+        assert_eq!(streaks, ListeningStreaks {
+            longest_streak_days: 3,
+            longest_streak_start: NaiveDate::from_ymd_opt(2023, 1, 1),
+            longest_streak_end: NaiveDate::from_ymd_opt(2023, 1, 3),
+            current_streak_days: 2,
+            active_day_count: 5,
+        });
+
+        // Shifted forward, the last play falls on the 7th and breaks the current streak
+        let streaks = calculate_listening_streaks(&entries, 60);
+        assert_eq!(streaks.current_streak_days, 1);
+        assert_eq!(streaks.active_day_count, 5);
+    }
+
+    #[test]
+    fn test_skip_stats() {
+        let data = parse_csv(FIXTURE_CSV.as_bytes()).expect("Fixture CSV should parse");
+        let mut entries = data.entries.clone();
+        entries[0].ms_played = 10_000;
+
+        let stats = calculate_skip_stats(&entries, 30_000, 0.5, 1);
+        assert_eq!(stats.total_play_count, entries.len());
+        assert_eq!(stats.total_skip_count, 1);
+        let skipped_artist = &stats.artists[0];
+        assert_eq!(skipped_artist.artist_name, entries[0].artist_name);
+        assert_eq!(skipped_artist.skip_count, 1);
+        assert!(stats.artists[1..].iter().all(|rate| rate.skip_count == 0));
+
+        let stats = calculate_skip_stats(&entries, 30_000, 0.5, 1_000);
+        assert_eq!(stats.total_skip_count, 1);
+        assert!(stats.artists.is_empty());
+        assert!(stats.tracks.is_empty());
+    }
+
+    #[test]
+    fn test_track_durations_parsed_when_present() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres,Track Duration (ms)
+2023-01-01T10:00:00Z,Song A,Artist One,200000,rock,rock,200000
+2023-01-01T10:05:00Z,Song A,Artist One,50000,rock,rock,200000
+2023-01-01T10:10:00Z,Song B,Artist Two,100000,pop,pop,
+";
+        let data = parse_csv(csv.as_bytes()).expect("CSV with durations should parse");
+        assert_eq!(data.entries[0].duration_ms, Some(200_000));
+        assert_eq!(data.entries[2].duration_ms, None);
+        assert_eq!(
+            data.tracks[&track_id("Song A", "Artist One")].duration_ms,
+            Some(200_000)
+        );
+        assert_eq!(
+            data.tracks[&track_id("Song B", "Artist Two")].duration_ms,
+            None
+        );
+
+        // The 50 second play only covers a quarter of the track, so it's a skip even though it's
+        // over the flat threshold
+        let stats = calculate_skip_stats(&data.entries, 30_000, 0.5, 1);
+        assert_eq!(stats.total_skip_count, 1);
+        assert_eq!(stats.average_completion_percentage, Some(62.5));
+        let song_a = stats
+            .tracks
+            .iter()
+            .find(|rate| rate.track_name.as_deref() == Some("Song A"))
+            .unwrap();
+        assert_eq!(song_a.skip_count, 1);
+        assert_eq!(song_a.average_completion_percentage, Some(62.5));
+        let song_b = stats
+            .tracks
+            .iter()
+            .find(|rate| rate.track_name.as_deref() == Some("Song B"))
+            .unwrap();
+        assert_eq!(song_b.skip_count, 0);
+        assert_eq!(song_b.average_completion_percentage, None);
+    }
+
+    #[test]
+    fn test_skip_stats_without_durations_use_threshold() {
+        let data = parse_csv(FIXTURE_CSV.as_bytes()).expect("Fixture CSV should parse");
+        assert!(data.entries.iter().all(|entry| entry.duration_ms.is_none()));
+        assert!(data
+            .tracks
+            .values()
+            .all(|track| track.duration_ms.is_none()));
+
+        let stats = calculate_skip_stats(&data.entries, 100_000, 0.5, 1);
+        let expected_skips = data
+            .entries
+            .iter()
+            .filter(|entry| entry.ms_played < 100_000)
+            .count();
+        assert_eq!(stats.total_skip_count, expected_skips);
+        assert_eq!(stats.average_completion_percentage, None);
+    }
+
+    #[test]
+    fn test_validate_csv_reports_bad_rows() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,120000,rock,rock
+not a timestamp,Song B,Artist Two,60000,pop,pop
+2023-01-01T10:10:00Z,Song C,Artist Three,lots,jazz,
+2023-01-01T10:15:00Z,Song D,Artist Four,200000,jazz,
+";
+        let report = validate_csv(csv.as_bytes(), GenreColumnPrecedence::default())
+            .expect("Headers should be readable");
+        assert_eq!(report.headers[..2], ["ts", "Track Name"]);
+        assert_eq!(report.total_rows, 4);
+        assert_eq!(report.valid_rows, 2);
+        assert_eq!(report.invalid_rows, 2);
+        let lines: Vec<u64> = report.errors.iter().map(|err| err.line).collect();
+        assert_eq!(lines, [3, 4]);
+        assert!(report.errors[0].reason.contains("timestamp"));
+
+        let report =
+            validate_csv(FIXTURE_CSV.as_bytes(), GenreColumnPrecedence::default()).unwrap();
+        assert_eq!(report.invalid_rows, 0);
+        assert_eq!(report.valid_rows, report.total_rows);
+    }
+
+    #[test]
+    fn test_genre_column_precedence() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,120000,\"Rock, shoegaze\",\"rock,indie\"
+2023-01-01T10:05:00Z,Song B,Artist Two,60000,pop,
+";
+        let genres = |precedence| -> Vec<Vec<String>> {
+            parse_csv_entries(csv.as_bytes(), precedence)
+                .expect("CSV should parse")
+                .into_iter()
+                .map(|entry| entry.genres)
+                .collect()
+        };
+
+        assert_eq!(genres(GenreColumnPrecedence::PreferArtistGenres), [
+            vec!["rock", "indie"],
+            vec!["pop"]
+        ]);
+        assert_eq!(genres(GenreColumnPrecedence::PreferTrackGenres), [
+            vec!["Rock", "shoegaze"],
+            vec!["pop"]
+        ]);
+        assert_eq!(genres(GenreColumnPrecedence::MergeBoth), [
+            vec!["rock", "indie", "shoegaze"],
+            vec!["pop"]
+        ]);
+    }
+
+    #[test]
+    fn test_on_this_day() {
+        let entries = vec![
+            entry("2021-03-14T10:00:00Z", "Artist One", &[]),
+            entry("2021-03-14T11:00:00Z", "Artist One", &[]),
+            entry("2022-03-14T23:30:00Z", "Artist Two", &[]),
+            entry("2022-03-15T10:00:00Z", "Artist Three", &[]),
+            entry("2023-03-14T10:00:00Z", "Artist Three", &[]),
+        ];
+
+        let on_this_day = calculate_on_this_day(&entries, 3, 14, 0);
+        let years: Vec<i32> = on_this_day.years.iter().map(|year| year.year).collect();
+        assert_eq!(years, [2023, 2022, 2021]);
+        let plays_2021 = &on_this_day.years[2];
+        assert_eq!(plays_2021.total_ms_played, 120_000);
+        assert_eq!(plays_2021.tracks.len(), 1);
+        assert_eq!(plays_2021.tracks[0].play_count, 2);
+
+        // The late night play moves to the next day an hour ahead of UTC
+        let on_this_day = calculate_on_this_day(&entries, 3, 15, 60);
+        assert_eq!(on_this_day.years.len(), 1);
+        let artists: Vec<&str> = on_this_day.years[0]
+            .tracks
+            .iter()
+            .map(|track| track.artist_name.as_str())
+            .collect();
+        assert_eq!(artists, ["Artist Three", "Artist Two"]);
+
+        assert!(calculate_on_this_day(&entries, 7, 4, 0).years.is_empty());
+    }
+
+    #[test]
+    fn test_min_ms_played_drops_short_plays() {
+        let mut entries = vec![
+            entry("2023-01-01T10:00:00Z", "Artist One", &[]),
+            entry("2023-01-01T10:05:00Z", "Artist One", &[]),
+            entry("2023-01-01T10:10:00Z", "Artist Two", &[]),
+        ];
+        entries[1].ms_played = 500;
+        entries[2].ms_played = 1_000;
+        let build = |min_ms_played| {
+            build_csv_data(entries.clone(), CsvBuildOptions {
+                min_ms_played,
+                ..CsvBuildOptions::default()
+            })
+        };
+
+        let data = build(1_000);
+        assert_eq!(data.entries.len(), 2);
+        assert_eq!(data.artist_play_counts["Artist One"], 1);
+        assert_eq!(data.artist_play_counts["Artist Two"], 1);
+
+        let data = build(0);
+        assert_eq!(data.entries.len(), 3);
+        assert_eq!(data.artist_play_counts["Artist One"], 2);
+    }
+
+    #[test]
+    fn test_empty_csv() {
+        let header = FIXTURE_CSV.lines().next().unwrap();
+        let data = parse_csv(header.as_bytes()).expect("Header-only CSV should parse");
+        assert!(data.is_empty());
+        assert!(data.artists.is_empty());
+        assert_eq!(
+            get_top_ids(&data, TimeframeWindows::default(), 10),
+            Default::default()
+        );
+        assert!(calculate_genre_history(&data.entries, true, 0).0.is_empty());
+
+        let dataset_info = calculate_dataset_info(&data, true);
+        assert_eq!(dataset_info.entry_count, 0);
+        assert_eq!(dataset_info.earliest_timestamp, None);
+        assert_eq!(dataset_info.genre_history_bucket_count, 0);
+    }
+
+    #[test]
+    fn test_version_changes_on_every_load() {
+        let first = parse_csv(FIXTURE_CSV.as_bytes()).expect("Fixture CSV should parse");
+        let second = parse_csv(FIXTURE_CSV.as_bytes()).expect("Fixture CSV should parse");
+        assert!(second.version > first.version);
+        assert_eq!(
+            calculate_dataset_info(&second, false).version,
+            second.version
+        );
+    }
+
+    #[test]
+    fn test_exported_top_artists_round_trip() {
+        let data = parse_csv(FIXTURE_CSV.as_bytes()).expect("Fixture CSV should parse");
+        let exported = export_csv(&data, ExportKind::TopArtists).expect("Export should succeed");
+
+        let mut rdr = csv::Reader::from_reader(exported.as_slice());
+        let rows: Vec<TopArtistRow> = rdr
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .expect("Exported CSV should parse");
+        assert_eq!(rows, top_artist_rows(&data));
+        let long_ids: Vec<&str> = rows
+            .iter()
+            .filter(|row| row.timeframe == "long")
+            .map(|row| row.id.as_str())
+            .collect();
+        assert_eq!(long_ids, data.top_artists_long);
+        assert!(rows.iter().any(|row| row.genres.contains(',')));
+    }
+
+    #[test]
+    fn test_also_listened() {
+        let entries = vec![
+            entry("2023-01-01T10:00:00Z", "Artist One", &[]),
+            entry("2023-01-01T10:05:00Z", "Artist Two", &[]),
+            entry("2023-01-01T10:10:00Z", "Artist One", &[]),
+            entry("2023-01-01T10:15:00Z", "Artist Three", &[]),
+            entry("2023-01-02T10:00:00Z", "Artist Two", &[]),
+            entry("2023-01-02T10:05:00Z", "Artist One", &[]),
+            entry("2023-01-03T10:00:00Z", "Artist Four", &[]),
+        ];
+        let data = build_csv_data(entries, CsvBuildOptions {
+            co_occurrence_window: CoOccurrenceWindow::Time(chrono::Duration::minutes(10)),
+            ..CsvBuildOptions::default()
+        });
+
+        let also_listened = calculate_also_listened(&data, &artist_id("Artist One"), 10).unwrap();
+        let names: Vec<&str> = also_listened
+            .iter()
+            .map(|item| item.artist.name.as_str())
+            .collect();
+        assert_eq!(names, ["Artist Two", "Artist Three"]);
+        assert_eq!(also_listened[0].score, 1.);
+        let expected_score = also_listened[1].co_occurrence_count as f32
+            / also_listened[0].co_occurrence_count as f32;
+        assert_eq!(also_listened[1].score, expected_score);
+        assert!(also_listened[1].score < 1.);
+
+        let also_listened = calculate_also_listened(&data, &artist_id("Artist One"), 1).unwrap();
+        assert_eq!(also_listened.len(), 1);
+        assert!(
+            calculate_also_listened(&data, &artist_id("Artist Four"), 10)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(calculate_also_listened(&data, "csv_missing", 10).is_none());
+    }
+
+    #[test]
+    fn test_genre_graph_caps_edges_per_genre() {
+        let entries = vec![
+            entry("2023-01-01T00:00:00Z", "Artist One", &["Rock"]),
+            entry("2023-01-01T00:05:00Z", "Artist Two", &["indie", "pop"]),
+            entry("2023-01-01T00:10:00Z", "Artist One", &["rock"]),
+            entry("2023-01-01T00:15:00Z", "Artist Three", &["jazz"]),
+            entry("2023-01-01T00:20:00Z", "Artist Two", &["indie", "pop"]),
+        ];
+
+        let graph = calculate_genre_graph(&entries, CoOccurrenceWindow::Plays(2), 10);
+        let genres: Vec<(&str, usize)> = graph
+            .nodes
+            .iter()
+            .map(|node| (node.genre.as_str(), node.play_count))
+            .collect();
+        assert_eq!(genres, vec![
+            ("indie", 2),
+            ("pop", 2),
+            ("rock", 2),
+            ("jazz", 1)
+        ]);
+        let edge = |source: &str, target: &str, weight| GenreEdge {
+            source: source.to_string(),
+            target: target.to_string(),
+            weight,
+        };
+        assert_eq!(graph.edges, vec![
+            edge("indie", "rock", 2),
+            edge("pop", "rock", 2),
+            edge("indie", "jazz", 1),
+            edge("jazz", "pop", 1),
+            edge("jazz", "rock", 1),
+        ]);
+
+        // Once both of an edge's genres have their single edge, it's dropped
+        let graph = calculate_genre_graph(&entries, CoOccurrenceWindow::Plays(2), 1);
+        assert_eq!(graph.edges, vec![
+            edge("indie", "rock", 2),
+            edge("pop", "rock", 2),
+            edge("indie", "jazz", 1),
+        ]);
+    }
+
+    #[test]
+    fn test_discoveries() {
+        let data = parse_csv(FIXTURE_CSV.as_bytes()).expect("Fixture CSV should parse");
+
+        let month = NaiveDate::from_ymd_opt(2023, 2, 14);
+        let discoveries = calculate_discoveries(&data.entries, month).expect("Fixture has entries");
+        assert_eq!(
+            discoveries.month,
+            NaiveDate::from_ymd_opt(2023, 2, 1).unwrap()
+        );
+        assert_eq!(discoveries.artists.len(), 1);
+        assert_eq!(discoveries.artists[0].artist_name, "Artist Two");
+        // Plays after the discovery month count too
+        assert_eq!(discoveries.artists[0].play_count, 2);
+        assert_eq!(discoveries.tracks.len(), 1);
+        assert_eq!(discoveries.tracks[0].track_name.as_deref(), Some("Song C"));
+
+        // Defaults to the latest month, in which nothing new was played
+        let discoveries = calculate_discoveries(&data.entries, None).expect("Fixture has entries");
+        assert_eq!(
+            discoveries.month,
+            NaiveDate::from_ymd_opt(2023, 3, 1).unwrap()
+        );
+        assert!(discoveries.artists.is_empty());
+        assert!(discoveries.tracks.is_empty());
+
+        assert!(calculate_discoveries(&[], None).is_none());
+    }
+
+    #[test]
+    fn test_deep_cuts_play_count_band() {
+        let mut entries = Vec::new();
+        // Artist One is played once, Two twice, Three four times, and Four five times
+        for (artist_name, play_count) in [
+            ("Artist Four", 5),
+            ("Artist Three", 4),
+            ("Artist Two", 2),
+            ("Artist One", 1),
+        ] {
+            for day in 1..=play_count {
+                entries.push(entry(
+                    &format!("2023-01-0{}T10:00:00Z", day),
+                    artist_name,
+                    &[],
+                ));
+            }
+        }
+        entries.push(entry("2022-12-01T10:00:00Z", "Artist Two", &[]));
+        let data = build_csv_data(entries, CsvBuildOptions::default());
+
+        // `min_plays` is inclusive and `max_plays` is exclusive
+        let deep_cuts = calculate_deep_cuts(&data, 3, 5);
+        let names: Vec<&str> = deep_cuts
+            .iter()
+            .map(|cut| cut.track.name.as_str())
+            .collect();
+        assert_eq!(names, ["Artist Two track", "Artist Three track"]);
+        assert_eq!(deep_cuts[0].play_count, 3);
+        assert_eq!(deep_cuts[1].play_count, 4);
+        // Sorted by when the track was first played
+        assert_eq!(
+            deep_cuts[0].first_seen,
+            DateTime::parse_from_rfc3339("2022-12-01T10:00:00Z").unwrap()
+        );
+
+        let deep_cuts = calculate_deep_cuts(&data, 1, 2);
+        assert_eq!(deep_cuts.len(), 1);
+        assert_eq!(deep_cuts[0].track.name, "Artist One track");
+    }
+
+    #[test]
+    fn test_filter_entries_by_artist() {
+        let entries = vec![
+            entry("2023-01-01T10:00:00Z", "Artist One", &[]),
+            entry("2023-01-02T10:00:00Z", "Artist Two", &[]),
+            entry("2023-01-03T10:00:00Z", "Artist One", &[]),
+            entry("2023-02-01T10:00:00Z", "Artist One", &[]),
+            entry("2023-02-02T10:00:00Z", "Artist Three", &[]),
+        ];
+
+        // Artist names are matched case-insensitively
+        let page = filter_entries(&entries, None, Some("artist one"), 100, 0);
+        assert_eq!(page.total, 3);
+        assert!(page.entries.iter().all(|e| e.artist_name == "Artist One"));
+        let days: Vec<u32> = page.entries.iter().map(|e| e.timestamp.day()).collect();
+        assert_eq!(days, [1, 3, 1]);
+
+        // Pagination doesn't affect the total
+        let page = filter_entries(&entries, None, Some("Artist One"), 1, 1);
+        assert_eq!(page.total, 3);
+        assert_eq!(page.entries, [&entries[2]]);
+
+        let january = DateRange {
+            from: None,
+            to: Some(NaiveDate::from_ymd_opt(2023, 1, 31).unwrap()),
+        };
+        let page = filter_entries(&entries, Some(&january), Some("Artist One"), 100, 0);
+        assert_eq!(page.total, 2);
+
+        let page = filter_entries(&entries, None, None, 100, 0);
+        assert_eq!(page.total, entries.len());
+        let page = filter_entries(&entries, None, Some("Artist Four"), 100, 0);
+        assert_eq!(page.total, 0);
+        assert!(page.entries.is_empty());
+    }
+
+    #[test]
+    fn test_completion_histogram() {
+        let mut entries = Vec::new();
+        for (ms_played, duration_ms) in [
+            (10_000, Some(200_000)),
+            (50_000, Some(200_000)),
+            (100_000, Some(200_000)),
+            (199_000, Some(200_000)),
+            (200_000, Some(200_000)),
+            (350_000, Some(200_000)),
+            (120_000, None),
+            (5_000, None),
+        ] {
+            let mut play = entry("2023-01-01T10:00:00Z", "Artist One", &[]);
+            play.ms_played = ms_played;
+            play.duration_ms = duration_ms;
+            entries.push(play);
+        }
+
+        assert_eq!(
+            calculate_completion_histogram(&entries),
+            CompletionHistogram {
+                under_25_percent: 1,
+                from_25_to_50_percent: 1,
+                from_50_to_75_percent: 1,
+                from_75_to_100_percent: 2,
+                over_100_percent: 1,
+                unknown_duration_count: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_wrapped_is_scoped_to_year() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2022-12-30T10:00:00Z,Old Song,Artist Old,600000,jazz,jazz
+2022-12-31T10:00:00Z,Old Song,Artist Old,600000,jazz,jazz
+2023-01-01T10:00:00Z,Song A,Artist A,180000,rock,rock
+2023-01-01T11:00:00Z,Song B,Artist B,120000,pop,pop
+2023-03-05T10:00:00Z,Song A,Artist A,240000,rock,rock
+2023-12-31T23:00:00Z,Song B,Artist B,60000,pop,pop
+";
+        let data = parse_csv(csv.as_bytes()).expect("CSV should parse");
+
+        let wrapped = calculate_wrapped(&data, 2023, 10).expect("2023 has listening history");
+        assert_eq!(wrapped.year, 2023);
+        let artist_names: Vec<&str> = wrapped
+            .top_artists
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(artist_names, ["Artist A", "Artist B"]);
+        let track_names: Vec<&str> = wrapped.top_tracks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(track_names, ["Song A", "Song B"]);
+        assert_eq!(wrapped.top_genres, [
+            ("rock".to_string(), 420_000),
+            ("pop".to_string(), 180_000)
+        ]);
+        assert_eq!(wrapped.total_minutes, 10);
+        assert_eq!(wrapped.distinct_artist_count, 2);
+        assert_eq!(
+            wrapped.top_day,
+            (NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(), 300_000)
+        );
+
+        let wrapped = calculate_wrapped(&data, 2022, 1).expect("2022 has listening history");
+        assert_eq!(wrapped.top_artists.len(), 1);
+        assert_eq!(wrapped.top_artists[0].name, "Artist Old");
+        assert_eq!(wrapped.total_minutes, 20);
+        // Ties go to the earliest day
+        assert_eq!(
+            wrapped.top_day.0,
+            NaiveDate::from_ymd_opt(2022, 12, 30).unwrap()
+        );
+
+        assert!(calculate_wrapped(&data, 2021, 10).is_none());
+    }
+
+    #[test]
+    fn test_records_match_top_aggregates() {
+        let data = parse_csv(FIXTURE_CSV.as_bytes()).expect("CSV should parse");
+        let records = calculate_records(&data, chrono::Duration::minutes(30));
+
+        let (top_artist, ms_played) = records.top_artist.expect("There should be a top artist");
+        assert_eq!(top_artist.id, data.top_artists_long[0]);
+        assert_eq!(ms_played, data.artist_ms_played[&top_artist.name]);
+        let (top_track, ms_played) = records.top_track.expect("There should be a top track");
+        assert_eq!(top_track.id, data.top_tracks_long[0]);
+        assert_eq!((top_track.name.as_str(), ms_played), ("Song C", 400_000));
+        assert!(records.top_genre.is_some());
+        assert_eq!(records.top_day.unwrap().1, 200_000);
+        assert_eq!(records.longest_session.unwrap().track_count, 1);
+        let records = calculate_records(&data, chrono::Duration::weeks(52 * 100));
+        assert_eq!(
+            records.longest_session.unwrap().track_count,
+            data.entries.len()
+        );
+
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,120000,,
+";
+        let data = parse_csv(csv.as_bytes()).expect("CSV should parse");
+        let records = calculate_records(&data, chrono::Duration::minutes(30));
+        assert!(records.top_artist.is_some());
+        assert!(records.top_genre.is_none());
+
+        let header_only = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres\n";
+        let data = parse_csv(header_only.as_bytes()).expect("CSV should parse");
+        let records = calculate_records(&data, chrono::Duration::minutes(30));
+        assert!(records.top_artist.is_none());
+        assert!(records.top_track.is_none());
+        assert!(records.top_day.is_none());
+        assert!(records.longest_session.is_none());
+    }
+
+    #[test]
+    fn test_artist_name_variants_are_merged() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Drake,60000,rap,rap
+2023-01-02T10:00:00Z,Song B,drake ,60000,rap,rap
+2023-01-03T10:00:00Z,Song C,Drake (feat. Future),60000,rap,rap
+2023-01-04T10:00:00Z,Song D,\"Future, DRAKE\",60000,rap,rap
+";
+        let entries = parse_csv_entries(csv.as_bytes(), GenreColumnPrecedence::default())
+            .expect("CSV should parse");
+
+        let unmerged = build_csv_data(entries.clone(), CsvBuildOptions {
+            artist_name_normalization: ArtistNameNormalization {
+                strip_featuring: false,
+                merge_casing: false,
+            },
+            ..CsvBuildOptions::default()
+        });
+        assert_eq!(unmerged.artist_play_counts["Drake"], 1);
+        assert_eq!(unmerged.artist_play_counts["drake"], 1);
+        assert_eq!(unmerged.artist_play_counts["Drake (feat. Future)"], 1);
+
+        let data = build_csv_data(entries, CsvBuildOptions::default());
+        assert_eq!(data.artist_play_counts["Drake"], 4);
+        assert_eq!(data.artist_play_counts["Future"], 2);
+        assert_eq!(data.artist_play_counts.len(), 2);
+        assert_eq!(data.artists[&artist_id("Drake")].name, "Drake");
+        assert_eq!(data.entries[2].featured_artist_names, ["Future"]);
+        assert_eq!(data.entries[3].featured_artist_names, ["Drake"]);
+    }
+
+    #[test]
+    fn test_split_featuring_suffix() {
+        assert_eq!(
+            split_featuring_suffix("Drake (feat. Future & Lil Baby) [Remix]"),
+            ("Drake", vec!["Future", "Lil Baby"])
+        );
+        assert_eq!(split_featuring_suffix("Drake"), ("Drake", Vec::new()));
+        assert_eq!(
+            split_featuring_suffix("(feat. Future)"),
+            ("(feat. Future)", Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_collaborations_credit_every_artist() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Duet,\"Artist A, Artist B\",120000,pop,pop
+2023-01-02T10:00:00Z,Duet,\"Artist A, Artist B\",60000,pop,pop
+2023-01-03T10:00:00Z,Solo,Artist B,180000,pop,pop
+2023-01-04T10:00:00Z,Trio,\"Artist C, Artist A, Artist D\",30000,pop,pop
+";
+        let data = parse_csv(csv.as_bytes()).expect("CSV should parse");
+
+        assert_eq!(data.entries[0].artist_name, "Artist A");
+        assert_eq!(data.entries[0].featured_artist_names, ["Artist B"]);
+        assert_eq!(data.artist_play_counts["Artist A"], 3);
+        assert_eq!(data.artist_play_counts["Artist B"], 3);
+        assert_eq!(data.artist_ms_played["Artist B"], 360_000);
+        assert!(data.artists.contains_key("csv_artist_a"));
+        assert!(data.artists.contains_key("csv_artist_b"));
+        assert!(!data.artists.contains_key("csv_artist_a,_artist_b"));
+        let duet_artists: Vec<&str> = data.tracks[&track_id("Duet", "Artist A")]
+            .artists
+            .iter()
+            .map(|artist| artist.name.as_str())
+            .collect();
+        assert_eq!(duet_artists, ["Artist A", "Artist B"]);
+
+        let collaborators = calculate_collaborators(&data, "csv_artist_a").unwrap();
+        let names: Vec<&str> = collaborators
+            .iter()
+            .map(|c| c.artist.name.as_str())
+            .collect();
+        assert_eq!(names, ["Artist B", "Artist C", "Artist D"]);
+        assert_eq!(collaborators[0].shared_track_count, 1);
+        assert_eq!(collaborators[0].play_count, 2);
+
+        let collaborators = calculate_collaborators(&data, "csv_artist_d").unwrap();
+        let names: Vec<&str> = collaborators
+            .iter()
+            .map(|c| c.artist.name.as_str())
+            .collect();
+        assert_eq!(names, ["Artist A", "Artist C"]);
+        assert!(calculate_collaborators(&data, "csv_artist_e").is_none());
+
+        // Ranged and other aggregates credit every artist the same way
+        let date_range = DateRange {
+            from: NaiveDate::from_ymd_opt(2023, 1, 1),
+            to: NaiveDate::from_ymd_opt(2023, 1, 2),
+        };
+        let (top_artists, top_tracks) = calculate_top_in_range(&data.entries, &date_range, 10);
+        assert_eq!(top_artists.len(), 2);
+        assert!(top_artists.contains(&"csv_artist_a".to_string()));
+        assert!(top_artists.contains(&"csv_artist_b".to_string()));
+        assert_eq!(top_tracks, [track_id("Duet", "Artist A")]);
+        let listening_time = calculate_listening_time(&data.entries, Some(&date_range));
+        assert_eq!(listening_time.distinct_artist_count, 2);
+        let (artists_first_seen, _) = calculate_first_seen(&data.entries);
+        assert_eq!(artists_first_seen["Artist B"], data.entries[0].timestamp);
+        assert_eq!(artists_first_seen["Artist D"], data.entries[3].timestamp);
+    }
+
+    #[test]
+    fn test_short_window_is_configurable() {
+        let entries = vec![
+            entry("2023-03-01T10:00:00Z", "Artist One", &[]),
+            entry("2023-03-01T11:00:00Z", "Artist One", &[]),
+            entry("2023-03-20T10:00:00Z", "Artist Two", &[]),
+        ];
+        let build = |short_window_days| {
+            let timeframe_windows = TimeframeWindows {
+                short_window_days,
+                ..TimeframeWindows::default()
+            };
+            build_csv_data(entries.clone(), CsvBuildOptions {
+                timeframe_windows,
+                ..CsvBuildOptions::default()
+            })
+        };
+
+        let data = build(28);
+        assert_eq!(data.top_artists_short, vec![
+            artist_id("Artist One"),
+            artist_id("Artist Two")
+        ]);
+        let data = build(7);
+        assert_eq!(data.top_artists_short, vec![artist_id("Artist Two")]);
+        assert_eq!(data.top_artists_medium.len(), 2);
+    }
+
+    #[test]
+    fn test_genre_top_tracks() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,60000,Rock,Rock
+2023-01-02T10:00:00Z,Song B,Artist One,60000,Rock,Rock
+2023-01-03T10:00:00Z,Song B,Artist One,60000,Rock,Rock
+2023-01-04T10:00:00Z,Song C,Artist Two,60000,jazz,jazz
+";
+        let data = parse_csv(csv.as_bytes()).expect("CSV should parse");
+
+        let top_tracks: Vec<(String, usize)> = calculate_genre_top_tracks(&data, "rock", 20)
+            .into_iter()
+            .map(|(track, play_count)| (track.name, play_count))
+            .collect();
+        assert_eq!(top_tracks, vec![
+            ("Song B".to_string(), 2),
+            ("Song A".to_string(), 1)
+        ]);
+        assert_eq!(calculate_genre_top_tracks(&data, "ROCK", 1).len(), 1);
+        assert!(calculate_genre_top_tracks(&data, "pop", 20).is_empty());
+    }
+
+    #[test]
+    fn test_artist_play_history_fills_gaps() {
+        let entries = vec![
+            entry("2023-01-02T10:00:00Z", "Artist One", &[]),
+            entry("2023-01-03T10:00:00Z", "Artist One", &[]),
+            entry("2023-01-20T10:00:00Z", "Artist Two", &[]),
+            entry("2023-03-01T10:00:00Z", "Artist One", &[]),
+        ];
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        let monthly =
+            calculate_artist_play_history(&entries, "Artist One", HistoryGranularity::Month);
+        assert_eq!(monthly, vec![
+            (date("2023-01-01"), 120_000, 2),
+            (date("2023-02-01"), 0, 0),
+            (date("2023-03-01"), 60_000, 1),
+        ]);
+
+        let weekly =
+            calculate_artist_play_history(&entries, "Artist Two", HistoryGranularity::Week);
+        assert_eq!(weekly.len(), 9);
+        assert_eq!(weekly[0], (date("2023-01-02"), 0, 0));
+        assert_eq!(weekly[2], (date("2023-01-16"), 60_000, 1));
+        assert_eq!(weekly[8].0, date("2023-02-27"));
+    }
+
+    #[test]
+    fn test_top_ids_limit() {
+        let entries = (0..60)
+            .map(|i| entry("2023-01-01T10:00:00Z", &format!("Artist {}", i), &[]))
+            .collect();
+        let data = build_csv_data(entries, CsvBuildOptions::default());
+
+        let (top_artists, top_tracks) = get_top_ids(&data, TimeframeWindows::default(), 5);
+        for ids in top_artists.iter().chain(top_tracks.iter()) {
+            assert_eq!(ids.len(), 5);
+        }
+        assert_eq!(top_artists[2], data.top_artists_long[..5]);
+
+        // More than were precomputed, so these have to be recomputed from the entries
+        let (top_artists, top_tracks) = get_top_ids(&data, TimeframeWindows::default(), 100);
+        for ids in top_artists.iter().chain(top_tracks.iter()) {
+            assert_eq!(ids.len(), 60);
+        }
+    }
+
+    #[test]
+    fn test_listening_sessions_split_on_gap() {
+        let entries = vec![
+            entry("2023-01-01T10:00:00Z", "Artist One", &["rock"]),
+            entry("2023-01-01T10:05:00Z", "Artist Two", &["Jazz"]),
+            entry("2023-01-01T12:05:00Z", "Artist Two", &["jazz"]),
+        ];
+
+        let sessions = calculate_listening_sessions(&entries, chrono::Duration::minutes(30));
+        assert_eq!(sessions.sessions.len(), 2);
+        let (first, second) = (&sessions.sessions[0], &sessions.sessions[1]);
+        assert_eq!(first.track_count, 2);
+        assert_eq!(first.duration_ms, 6 * 60_000);
+        assert_eq!(second.track_count, 1);
+        assert_eq!(second.duration_ms, 60_000);
+        assert_eq!(second.dominant_genre.as_deref(), Some("jazz"));
+        assert_eq!(
+            sessions.average_session_duration_ms,
+            (6 * 60_000 + 60_000) / 2
+        );
+    }
+
+    #[test]
+    fn test_top_genres_by_timeframe() {
+        let entries = vec![
+            entry("2022-01-01T10:00:00Z", "Artist One", &["rock"]),
+            entry("2023-01-01T10:00:00Z", "Artist Three", &["pop"]),
+            entry("2023-03-01T10:00:00Z", "Artist Two", &["jazz"]),
+            entry("2023-03-02T10:00:00Z", "Artist Two", &["jazz"]),
+        ];
+        let data = build_csv_data(entries, CsvBuildOptions::default());
+
+        let top_genres = calculate_top_genres(&data, TimeframeWindows::default(), 2);
+        let genre = |genre: &str, ms_played| (genre.to_string(), ms_played);
+        assert_eq!(top_genres.short, vec![genre("jazz", 120_000)]);
+        assert_eq!(top_genres.medium, vec![
+            genre("jazz", 120_000),
+            genre("pop", 60_000)
+        ]);
+        assert_eq!(top_genres.long, vec![
+            genre("jazz", 120_000),
+            genre("pop", 60_000)
+        ]);
+    }
+
+    #[test]
+    fn test_top_ids_resolve_to_metadata() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Back In Black,AC/DC,120000,rock,rock
+2023-01-02T10:00:00Z,Song  A,Artist  One,60000,rock,rock
+2023-01-03T10:00:00Z,Jóga,Björk,90000,pop,pop
+";
+        let data = parse_csv(csv.as_bytes()).expect("CSV should parse");
+
+        let top_artists = [
+            &data.top_artists_short,
+            &data.top_artists_medium,
+            &data.top_artists_long,
+        ];
+        for top_artists in top_artists {
+            assert_eq!(top_artists.len(), 3);
+            assert!(top_artists.iter().all(|id| data.artists.contains_key(id)));
+        }
+        let top_tracks = [
+            &data.top_tracks_short,
+            &data.top_tracks_medium,
+            &data.top_tracks_long,
+        ];
+        for top_tracks in top_tracks {
+            assert_eq!(top_tracks.len(), 3);
+            assert!(top_tracks.iter().all(|id| data.tracks.contains_key(id)));
+        }
+        assert_eq!(data.artists[&data.top_artists_long[0]].name, "AC/DC");
+        assert_eq!(data.tracks[&data.top_tracks_long[0]].name, "Back In Black");
+    }
+
+    #[test]
+    fn test_comparison() {
+        let user1 = parse_csv(FIXTURE_CSV.as_bytes()).expect("Fixture CSV should parse");
+        let user2_csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-05-01T10:00:00Z,Song C,Artist Two,100000,jazz,
+2023-05-02T10:00:00Z,Song D,Artist Two,100000,jazz,
+2023-05-03T10:00:00Z,Song E,Artist Three,500000,\"pop, rock\",\"pop,rock\"
+";
+        let user2 = parse_csv(user2_csv.as_bytes()).expect("CSV should parse");
+
+        let comparison = calculate_comparison("one".into(), &user1, "two".into(), &user2);
+        let artist_names: Vec<&str> = comparison.artists.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(artist_names, vec!["Artist Two"]);
+        let track_names: Vec<&str> = comparison.tracks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(track_names, vec!["Song C"]);
+        // rock has 390s of play from user 1 and 500s from user 2, jazz has 400s and 200s
+        assert_eq!(comparison.genres, vec![
+            "rock".to_string(),
+            "jazz".to_string()
+        ]);
+        assert_eq!(comparison.user2_username, "two");
+    }
+
+    #[test]
+    fn test_top_albums() {
+        let csv = "ts,Track Name,Artist Name(s),Album Name,ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,First Album,120000,rock,rock
+2023-01-02T10:00:00Z,Song B,Artist One,First Album,60000,rock,rock
+2023-01-03T10:00:00Z,Song C,Artist One,Second Album,150000,rock,rock
+2023-01-04T10:00:00Z,Song D,Artist Two,,30000,jazz,jazz
+";
+        let data = parse_csv(csv.as_bytes()).expect("CSV with album column should parse");
+        let track = &data.tracks["csv_song_a_artist_one"];
+        assert_eq!(track.album.name, "First Album");
+        assert_eq!(track.album.id, "csv_album_first_album_artist_one");
+        assert_eq!(data.tracks["csv_song_d_artist_two"].album.id, "csv_unknown");
+
+        let albums = calculate_top_albums(&data.entries, 10);
+        assert_eq!(albums.len(), 3);
+        assert_eq!(albums[0].album_name.as_deref(), Some("First Album"));
+        assert_eq!(albums[0].ms_played, 180_000);
+        assert_eq!(albums[0].track_count, 2);
+        assert_eq!(albums[1].album_name.as_deref(), Some("Second Album"));
+        assert_eq!(albums[2].album_name, None);
+        assert_eq!(albums[2].artist_name, "Artist Two");
+        assert_eq!(calculate_top_albums(&data.entries, 1).len(), 1);
+
+        // Without an album column, tracks fall back to being grouped by artist
+        let data = parse_csv(FIXTURE_CSV.as_bytes()).expect("Fixture CSV should parse");
+        let albums = calculate_top_albums(&data.entries, 10);
+        assert_eq!(albums.len(), 2);
+        assert!(albums.iter().all(|album| album.album_name.is_none()));
+        assert_eq!(albums[0].artist_name, "Artist Two");
+        assert_eq!(albums[0].ms_played, 400_000);
+    }
+
+    #[test]
+    fn test_track_play_counts_match_naive_count() {
+        let data = parse_csv(FIXTURE_CSV.as_bytes()).expect("Fixture CSV should parse");
+
+        for track in data.tracks.values() {
+            let artist_name = &track.artists[0].name;
+            let naive_count = data
+                .entries
+                .iter()
+                .filter(|e| e.track_name == track.name && e.artist_name == *artist_name)
+                .count();
+            let indexed_count = data
+                .track_play_counts
+                .get(&(track.name.clone(), artist_name.clone()))
+                .copied()
+                .unwrap_or(0);
+            assert_eq!(
+                indexed_count, naive_count,
+                "Mismatched count for {}",
+                track.name
+            );
+        }
+
+        assert_eq!(
+            data.track_play_counts[&("Song A".to_string(), "Artist One".to_string())],
+            3
+        );
+    }
+
+    #[test]
+    fn test_gzipped_csv_matches_plaintext() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("csv_loader_gz_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let plain_path = dir.join("history.csv");
+        let gz_path = dir.join("history.csv.gz");
+        // Deliberately lacks the `.gz` extension so detection has to use the magic header
+        let gz_no_ext_path = dir.join("history_compressed.csv");
+
+        std::fs::write(&plain_path, FIXTURE_CSV).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(FIXTURE_CSV.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        std::fs::write(&gz_path, &compressed).unwrap();
+        std::fs::write(&gz_no_ext_path, &compressed).unwrap();
+
+        let parse = |path: &Path| {
+            parse_csv_file(
+                path,
+                GenreColumnPrecedence::default(),
+                CsvBuildOptions::default(),
+            )
+        };
+        let plain = parse(&plain_path).expect("Plaintext CSV should parse");
+        let gzipped = parse(&gz_path).expect("Gzipped CSV should parse");
+        let gzipped_no_ext =
+            parse(&gz_no_ext_path).expect("Gzipped CSV without extension should parse");
+        // All three files hold the same plays, so they're deduplicated when the directory is loaded
+        let merged = parse(&dir).expect("Directory of CSVs should parse");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!plain.entries.is_empty());
+        assert_eq!(plain.entries, gzipped.entries);
+        assert_eq!(plain.entries, gzipped_no_ext.entries);
+        assert_eq!(plain.entries, merged.entries);
+    }
+
+    #[tokio::test]
+    async fn test_load_csv_data_from_dir() {
+        let dir = std::env::temp_dir().join(format!("csv_loader_dir_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let header = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres\n";
+        std::fs::write(
+            dir.join("2022.csv"),
+            format!(
+                "{}{}{}",
+                header,
+                "2022-12-31T10:00:00Z,Song A,Artist One,120000,rock,rock\n",
+                "2023-01-01T10:00:00Z,Song B,Artist Two,60000,pop,pop\n"
+            ),
+        )
+        .unwrap();
+        // Overlaps the first export by one play, and has another play that only differs from it by
+        // how long it was played for
+        std::fs::write(
+            dir.join("2023.csv"),
+            format!(
+                "{}{}{}{}",
+                header,
+                "2023-01-01T10:00:00Z,Song B,Artist Two,60000,pop,pop\n",
+                "2023-01-01T10:00:00Z,Song B,Artist Two,30000,pop,pop\n",
+                "2023-01-02T10:00:00Z,Song C,Artist Three,200000,jazz,jazz\n"
+            ),
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a listening history").unwrap();
+
+        let csv_data = load_csv_data_from_dir(&dir).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        let csv_data = csv_data.expect("Directory of CSVs should load");
+
+        assert_eq!(csv_data.entries.len(), 4);
+        assert_eq!(csv_data.artists.len(), 3);
+        assert_eq!(
+            csv_data
+                .entries
+                .iter()
+                .filter(|entry| entry.track_name == "Song B")
+                .count(),
+            2
+        );
+
+        let empty_dir =
+            std::env::temp_dir().join(format!("csv_loader_empty_dir_{}", std::process::id()));
+        std::fs::create_dir_all(&empty_dir).unwrap();
+        let res = load_csv_data_from_dir(&empty_dir).await;
+        std::fs::remove_dir_all(&empty_dir).unwrap();
+        assert!(
+            res.is_err(),
+            "A directory without listening histories shouldn't load"
+        );
+    }
+
+    /// Serves `body` over HTTP to a single request on a local port, returning the URL of the server
+    fn serve_once(body: Vec<u8>) -> String {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = stream.read(&mut buf).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..read]);
+            }
+
+            let headers = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(headers.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_read_csv_from_url() {
+        use std::io::Write;
+
+        assert!(is_remote_csv_path("https://example.com/history.csv"));
+        assert!(is_remote_csv_path("s3://bucket/history.csv.gz"));
+        assert!(!is_remote_csv_path("listening_history.csv"));
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(FIXTURE_CSV.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let precedence = GenreColumnPrecedence::default();
+        let plain_url = format!(
+            "{}/history.csv",
+            serve_once(FIXTURE_CSV.as_bytes().to_vec())
+        );
+        let bytes = download_csv_bytes(&plain_url)
+            .await
+            .expect("Download should succeed");
+        let plain = read_csv_entries_from_bytes(&plain_url, bytes, precedence)
+            .expect("Downloaded CSV should parse");
+
+        // Deliberately lacks the `.gz` extension so detection has to use the magic header
+        let gz_url = format!("{}/history.csv?version=2", serve_once(compressed));
+        let bytes = download_csv_bytes(&gz_url)
+            .await
+            .expect("Download should succeed");
+        let gzipped = read_csv_entries_from_bytes(&gz_url, bytes, precedence)
+            .expect("Downloaded gzipped CSV should parse");
+
+        assert!(!plain.is_empty());
+        assert_eq!(plain, gzipped);
+    }
+
+    #[test]
+    fn test_streaming_history_json_formats() {
+        let json = r#"[
+            {
+                "ts": "2021-05-01T12:00:00Z",
+                "master_metadata_track_name": "Song A",
+                "master_metadata_album_artist_name": "Artist One",
+                "ms_played": 120000
+            },
+            {
+                "ts": "2021-05-01T12:05:00Z",
+                "master_metadata_track_name": null,
+                "master_metadata_album_artist_name": null,
+                "ms_played": 900000
+            },
+            {
+                "endTime": "2021-05-02 08:30",
+                "artistName": "Artist Two",
+                "trackName": "Song B",
+                "msPlayed": 200000
+            }
+        ]"#;
+
+        let entries = parse_streaming_history_json_entries(json.as_bytes())
+            .expect("Streaming history JSON should parse");
+        assert_eq!(entries.len(), 2, "Podcast entries should be skipped");
+        assert_eq!(entries[0].artist_name, "Artist One");
+        assert_eq!(entries[1].track_name, "Song B");
+        assert_eq!(
+            entries[1].timestamp,
+            DateTime::parse_from_rfc3339("2021-05-02T08:30:00Z").unwrap()
+        );
+        assert!(entries.iter().all(|entry| entry.genres.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_load_streaming_history_json() {
+        let json = r#"[
+            {
+                "ts": "2021-05-01T12:00:00Z",
+                "master_metadata_track_name": "Song A",
+                "master_metadata_album_artist_name": "Artist One",
+                "ms_played": 120000
+            },
+            {
+                "ts": "2021-05-02T12:00:00Z",
+                "master_metadata_track_name": "Song B",
+                "master_metadata_album_artist_name": "Artist Two",
+                "ms_played": 60000
+            }
+        ]"#;
+        let path = std::env::temp_dir().join(format!("endsong_{}.json", std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        assert!(is_streaming_history_json(&path));
+        let csv_data = load_streaming_history_json(&path).await;
+        std::fs::remove_file(&path).unwrap();
+        let csv_data = csv_data.expect("Streaming history JSON should load");
+
+        assert_eq!(csv_data.entries.len(), 2);
+        assert_eq!(csv_data.artists.len(), 2);
+        let (_, history_by_genre) = calculate_genre_history(&csv_data.entries, false, 0);
+        assert!(history_by_genre.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reload_csv_data_when_file_changes() {
+        const USERNAME: &str = "__test_reload";
+
+        let dir = std::env::temp_dir().join(format!("csv_loader_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.csv");
+        let path_ref = &path;
+        let load = move || async move {
+            let csv_data = parse_csv(std::fs::File::open(path_ref).unwrap())?;
+            Ok(register_named_csv_data(USERNAME.to_owned(), csv_data).await)
+        };
+        let set_modified = |modified: SystemTime| {
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(modified).unwrap();
+        };
+
+        std::fs::write(&path, FIXTURE_CSV).unwrap();
+        let initial_modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        let mut last_modified = None;
+        assert!(reload_csv_data_if_modified(&path, &mut last_modified, 0, load).await);
+        assert_eq!(last_modified, Some(initial_modified));
+        let entry_count = FIXTURE_CSV.lines().count() - 1;
+        assert_eq!(
+            get_named_csv_data(USERNAME).await.unwrap().entries.len(),
+            entry_count
+        );
+
+        // Nothing happens if the file hasn't changed
+        assert!(!reload_csv_data_if_modified(&path, &mut last_modified, entry_count, load).await);
+
+        let new_row = "2023-04-01T10:00:00Z,Song D,Artist Three,90000,pop,pop\n";
+        std::fs::write(&path, format!("{}{}", FIXTURE_CSV, new_row)).unwrap();
+        set_modified(initial_modified + Duration::from_secs(10));
+        assert!(reload_csv_data_if_modified(&path, &mut last_modified, entry_count, load).await);
+        let csv_data = get_named_csv_data(USERNAME).await.unwrap();
+        assert_eq!(csv_data.entries.len(), entry_count + 1);
+        assert!(csv_data.artists.contains_key(&artist_id("Artist Three")));
+
+        // Overlapping reloads are skipped and retried on the next check
+        std::fs::write(&path, FIXTURE_CSV).unwrap();
+        set_modified(initial_modified + Duration::from_secs(20));
+        let overlapping_reload =
+            reload_csv_data_if_modified(&path, &mut last_modified, entry_count + 1, load);
+        assert_eq!(run_exclusive_reload(overlapping_reload).await, Some(false));
+        assert_eq!(
+            get_named_csv_data(USERNAME).await.unwrap().entries.len(),
+            entry_count + 1
+        );
+        assert!(
+            reload_csv_data_if_modified(&path, &mut last_modified, entry_count + 1, load).await
+        );
+        assert_eq!(
+            get_named_csv_data(USERNAME).await.unwrap().entries.len(),
+            entry_count
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[tokio::test]
     async fn test_csv_loader() {
         // Test loading CSV data
@@ -842,6 +842,7 @@ pub(crate) async fn update_user_last_updated(
         })
 }
 
+/// Returns first-seen events in `[start_day, end_day)`
 pub(crate) async fn get_artist_timeline_events(
     conn: &DbConn,
     user_id: i64,
@@ -855,7 +856,7 @@ pub(crate) async fn get_artist_timeline_events(
             artists_users_first_seen::dsl::user_id.eq(user_id).and(
                 artists_users_first_seen::dsl::first_seen
                     .ge(start_day)
-                    .and(artists_users_first_seen::dsl::first_seen.le(end_day)),
+                    .and(artists_users_first_seen::dsl::first_seen.lt(end_day)),
             ),
         )
         .order_by(artists_users_first_seen::dsl::first_seen)
@@ -870,6 +871,7 @@ pub(crate) async fn get_artist_timeline_events(
     conn.run(move |conn| query.load(conn)).await
 }
 
+/// Returns first-seen events in `[start_day, end_day)`
 pub(crate) async fn get_track_timeline_events(
     conn: &DbConn,
     user_id: i64,
@@ -883,7 +885,7 @@ pub(crate) async fn get_track_timeline_events(
             tracks_users_first_seen::dsl::user_id.eq(user_id).and(
                 tracks_users_first_seen::dsl::first_seen
                     .ge(start_day)
-                    .and(tracks_users_first_seen::dsl::first_seen.le(end_day)),
+                    .and(tracks_users_first_seen::dsl::first_seen.lt(end_day)),
             ),
         )
         .order_by(tracks_users_first_seen::dsl::first_seen)
@@ -938,6 +940,40 @@ pub(crate) async fn get_all_top_artists_for_user(
     conn.run(move |conn| query.load(conn)).await
 }
 
+/// Returns the Spotify IDs of the user's top artists from their latest update as
+/// `(timeframe_id, spotify_id)`, ordered by timeframe and then by rank within it.
+pub(crate) async fn get_latest_ranked_top_artists_for_user(
+    conn: &DbConn,
+    user_id: i64,
+) -> Result<Vec<(u8, String)>, diesel::result::Error> {
+    use crate::schema::{artist_rank_snapshots, spotify_items};
+
+    let query = artist_rank_snapshots::table
+        .filter(artist_rank_snapshots::dsl::user_id.eq(user_id))
+        .select(artist_rank_snapshots::dsl::update_time)
+        .order_by(artist_rank_snapshots::dsl::update_time.desc());
+    let last_update_time: Option<NaiveDateTime> =
+        conn.run(move |conn| query.first(conn).optional()).await?;
+    let last_update_time = match last_update_time {
+        Some(last_update_time) => last_update_time,
+        None => return Ok(Vec::new()),
+    };
+
+    let query = artist_rank_snapshots::table
+        .filter(artist_rank_snapshots::dsl::user_id.eq(user_id))
+        .filter(artist_rank_snapshots::dsl::update_time.eq(last_update_time))
+        .inner_join(spotify_items::table)
+        .order_by((
+            artist_rank_snapshots::dsl::timeframe.asc(),
+            artist_rank_snapshots::dsl::ranking.asc(),
+        ))
+        .select((
+            artist_rank_snapshots::dsl::timeframe,
+            spotify_items::dsl::spotify_id,
+        ));
+    conn.run(move |conn| query.load(conn)).await
+}
+
 pub(crate) async fn refresh_user_access_token(
     conn: &DbConn,
     user: &mut User,
@@ -28,12 +28,15 @@ pub mod db_util;
 pub mod external_storage;
 pub mod metrics;
 pub mod models;
+pub mod openapi;
+pub mod rate_limit;
 pub mod routes;
 pub mod schema;
 pub mod shared_playlist_gen;
 pub mod spotify_api;
 pub mod spotify_token;
 pub mod stats;
+pub mod time_util;
 
 use crate::{cache::local_cache::init_spotify_id_map_cache, conf::CONF};
 
@@ -88,6 +91,11 @@ pub async fn main() {
     csv_loader::load_csv_data()
         .await
         .expect("Failed to load CSV data");
+    if let Some(csv_reload_interval_secs) = CONF.csv_reload_interval_secs {
+        tokio::task::spawn(csv_loader::watch_csv_file(Duration::from_secs(
+            csv_reload_interval_secs,
+        )));
+    }
 
     let all_routes = routes![
         routes::index,
@@ -96,22 +104,35 @@ pub async fn main() {
         routes::authorize,
         routes::update_user,
         routes::get_artist_stats,
+        routes::get_artist_summary,
+        routes::get_artist_collaborators,
+        routes::get_artists_stats_batch,
+        routes::get_artist_history,
+        routes::get_csv_artist_image_url,
         routes::get_genre_history,
+        routes::get_monthly_minutes,
+        routes::get_genre_shares,
         routes::populate_tracks_artists_mapping_table,
         routes::populate_artists_genres_mapping_table,
         routes::get_genre_stats,
+        routes::get_genre_top_tracks,
         routes::get_timeline,
         routes::compare_users,
         routes::get_related_artists_graph,
         routes::get_related_artists,
+        routes::get_also_listened_artists,
         routes::get_display_name,
         routes::dump_redis_related_artists_to_database,
         routes::crawl_related_artists,
+        routes::get_related_artists_cache_stats,
         routes::search_artist,
+        routes::search,
         routes::get_average_artists_route,
         routes::get_artist_image_url,
         routes::get_packed_3d_artist_coords_route,
+        routes::get_artist_3d_coords_route,
         routes::refetch_cached_artists_missing_popularity,
+        routes::get_tracks_missing_previews,
         routes::get_artists_by_internal_ids,
         routes::get_packed_artist_relationships_by_internal_ids,
         routes::get_preview_urls_by_internal_id,
@@ -120,6 +141,35 @@ pub async fn main() {
         routes::transfer_user_data_to_external_storage,
         routes::transfer_user_data_from_external_storage,
         routes::bulk_transfer_user_data_to_external_storage,
+        routes::reload_csv,
+        routes::validate_csv,
+        routes::get_genres,
+        routes::get_top_genres,
+        routes::export_stats,
+        routes::get_listening_time,
+        routes::get_listening_clock,
+        routes::get_listening_weekdays,
+        routes::get_artist_similarity,
+        routes::get_weighted_average_artists_route,
+        routes::get_listening_streaks,
+        routes::get_listening_sessions,
+        routes::get_records,
+        routes::get_on_this_day,
+        routes::get_skip_stats,
+        routes::get_completion_histogram,
+        routes::health,
+        routes::get_openapi_spec,
+        routes::get_dataset_info,
+        routes::get_genre_graph,
+        routes::get_discoveries,
+        routes::get_wrapped,
+        routes::get_deep_cuts,
+        routes::get_entries,
+        routes::get_albums,
+        routes::register_csv,
+        routes::upload_comparison_csv,
+        routes::warm_relationship_chunks,
+        routes::resolve_csv_artist_ids,
     ];
 
     // Pre-populate the packed 3D artist map embedding to make the first request for it instant
@@ -132,7 +182,14 @@ pub async fn main() {
         .mount("/api/", all_routes)
         .manage(Mutex::new(SpotifyTokenData::new().await))
         .attach(DbConn::fairing())
-        .attach(cors::CorsFairing);
+        .attach(cors::CorsFairing::new(CONF.cors_allowed_origin.clone()))
+        .attach(metrics::EndpointMetricsFairing)
+        .attach(routes::DatasetVersionFairing)
+        .attach(rate_limit::RateLimitFairing::new(
+            CONF.rate_limit_requests_per_second,
+            CONF.rate_limit_burst,
+            CONF.rate_limit_ip_header.clone(),
+        ));
 
     builder.launch().await.expect("Error launching Rocket");
     info!("Rocket exited cleanly");
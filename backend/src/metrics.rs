@@ -1,7 +1,15 @@
+use std::{borrow::Cow, time::Duration};
+
 use foundations::telemetry::metrics::{metrics, Counter, HistogramBuilder, TimeHistogram};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    Request, Response,
+};
 
 use foundations;
 
+use crate::conf::CONF;
+
 #[metrics]
 pub(crate) mod metrics {
     /// Total number of requests made to all Spotify API endpoints
@@ -57,6 +65,54 @@ pub(crate) mod metrics {
         buckets: &[0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 0.75, 1.0, 2.5, 5.0, 10.0, 15.0, 20.0, 30.0, 45.0, 60.0, 120.0, 300.0],
     }]
     pub fn endpoint_response_time(query_name: &'static str) -> TimeHistogram;
+
+    /// Total number of stats responses served from the Redis response cache
+    pub fn stats_response_cache_hits_total(endpoint_name: &'static str) -> Counter;
+
+    /// Total number of stats responses that had to be computed because they weren't cached
+    pub fn stats_response_cache_misses_total(endpoint_name: &'static str) -> Counter;
+
+    /// Total number of requests handled by each endpoint
+    pub fn endpoint_requests_total(endpoint_name: &'static str) -> Counter;
+
+    /// Total number of requests to each endpoint that resulted in a 4xx or 5xx response
+    pub fn endpoint_errors_total(endpoint_name: &'static str) -> Counter;
 }
 
 pub use metrics::*;
+
+/// Records how long a request to `endpoint_name` took, logging a warning if it took longer than the
+/// configured slow request threshold
+pub(crate) fn observe_endpoint_response_time(endpoint_name: &'static str, elapsed: Duration) {
+    endpoint_response_time(endpoint_name).observe(elapsed.as_nanos() as u64);
+    if elapsed > CONF.slow_request_threshold {
+        warn!("Slow request to {}; took {:?}", endpoint_name, elapsed);
+    }
+}
+
+/// Records request and error counts for every endpoint, keyed by the name of the route's handler
+pub(crate) struct EndpointMetricsFairing;
+
+#[rocket::async_trait]
+impl Fairing for EndpointMetricsFairing {
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let endpoint_name = match req.route().and_then(|route| route.name.as_ref()) {
+            Some(Cow::Borrowed(name)) => name,
+            Some(Cow::Owned(_)) => "unknown",
+            None => "unmatched",
+        };
+
+        endpoint_requests_total(endpoint_name).inc();
+        let status_class = res.status().class();
+        if status_class.is_client_error() || status_class.is_server_error() {
+            endpoint_errors_total(endpoint_name).inc();
+        }
+    }
+
+    fn info(&self) -> Info {
+        Info {
+            name: "Endpoint Metrics Fairing",
+            kind: Kind::Response,
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::{default::Default, fmt::Debug, vec};
 use chrono::{NaiveDate, NaiveDateTime};
 use float_ord::FloatOrd;
 use fnv::FnvHashMap as HashMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -143,7 +144,7 @@ pub(crate) struct ArtistGenrePair {
     pub genre: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub(crate) struct TimeFrames<T: Serialize> {
     pub short: Vec<T>,
     pub medium: Vec<T>,
@@ -243,7 +244,7 @@ impl<'a, T: Serialize> TimeFrames<T> {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub(crate) struct StatsSnapshot {
     pub last_update_time: NaiveDateTime,
     pub tracks: TimeFrames<Track>,
@@ -282,14 +283,14 @@ pub(crate) struct Followers {
     pub total: usize,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub(crate) struct Image {
     // pub height: Option<usize>,
     pub url: String,
     // pub width: Option<usize>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub(crate) struct Album {
     // pub album_group: Option<String>,
     // pub album_type: String,
@@ -328,13 +329,15 @@ pub(crate) struct ArtistRankHistoryResItem {
     pub timeframe: u8,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub(crate) struct Track {
     pub album: Album,
     pub artists: Vec<Artist>,
     // pub available_markets: Vec<String>,
     // pub disc_number: usize,
-    // pub duration_ms: usize,
+    /// Not known for tracks loaded from exports that don't include track durations
+    #[serde(default)]
+    pub duration_ms: Option<u32>,
     // pub explicit: bool,
     // pub href: Option<String>,
     pub id: String,
@@ -344,6 +347,16 @@ pub(crate) struct Track {
     pub preview_url: Option<String>,
     /* pub track_number: usize,
      * pub uri: String, */
+    /// Not known for tracks loaded from CSV data
+    #[serde(default)]
+    pub external_ids: Option<ExternalIds>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub(crate) struct ExternalIds {
+    /// International Standard Recording Code
+    #[serde(default)]
+    pub isrc: Option<String>,
 }
 
 impl Track {
@@ -358,7 +371,9 @@ impl Track {
             artists: Vec::new(),
             id: String::new(),
             name: "Unknown Track".to_owned(),
+            duration_ms: None,
             preview_url: None,
+            external_ids: None,
         }
     }
 }
@@ -368,7 +383,7 @@ pub(crate) struct TopArtistsResponse {
     pub items: Vec<Artist>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub(crate) struct Artist {
     // pub followers: Option<Followers>,
     pub genres: Option<Vec<String>>,
@@ -497,7 +512,7 @@ impl HasSpotifyId for Track {
     fn get_spotify_id(&self) -> &str { &self.id }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(tag = "type")]
 pub(crate) enum TimelineEventType {
     #[serde(rename = "firstUpdate")]
@@ -508,7 +523,7 @@ pub(crate) enum TimelineEventType {
     TopTrackFirstSeen { track: Track },
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub(crate) struct TimelineEvent {
     pub date: NaiveDate,
     pub id: usize,
@@ -516,9 +531,11 @@ pub(crate) struct TimelineEvent {
     pub event_type: TimelineEventType,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub(crate) struct Timeline {
     pub events: Vec<TimelineEvent>,
+    /// Total number of events matching the request, including those outside of the requested page
+    pub total: usize,
 }
 
 #[derive(Serialize)]
@@ -611,11 +628,15 @@ pub(crate) struct GetRelatedArtistsResponse {
     pub artists: Vec<Artist>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct RelatedArtistsGraph {
     pub extra_artists: HashMap<String, Artist>,
     pub related_artists: HashMap<String, Vec<String>>,
+    /// Co-listen weight for each entry in `related_artists`, normalized to 0-1 per source artist
+    pub related_artist_weights: HashMap<String, Vec<f32>>,
+    /// Number of times each entry in `related_artists` was co-listened with the source artist
+    pub related_artist_counts: HashMap<String, Vec<u32>>,
 }
 
 #[derive(Clone, Insertable)]
@@ -637,12 +658,54 @@ pub(crate) struct ArtistSearchResult {
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
+pub(crate) struct ArtistSearchResponse {
+    pub results: Vec<ArtistSearchResult>,
+    /// Total number of matching artists, including those outside of the requested page
+    pub total: usize,
+}
+
+/// An artist from the loaded listening history.  `id` is its CSV artist ID, not a Spotify ID.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CsvArtistSearchResult {
+    pub id: String,
+    pub name: String,
+}
+
+/// A track from the loaded listening history.  `id` is its CSV track ID, not a Spotify ID.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrackSearchResult {
+    pub id: String,
+    pub name: String,
+    pub artist_name: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub(crate) enum SearchResult {
+    Artist(CsvArtistSearchResult),
+    Track(TrackSearchResult),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchResponse {
+    pub results: Vec<SearchResult>,
+    /// Total number of matching artists and tracks, including those outside of the requested page
+    pub total: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub(crate) struct AverageArtistItem {
     pub artist: Artist,
     pub top_tracks: Vec<Track>,
     pub similarity_to_target_point: f32,
     pub similarity_to_artist_1: f32,
     pub similarity_to_artist_2: f32,
+    /// Similarity to each of the seed artists, in the order they were provided
+    pub similarity_to_seeds: Vec<f32>,
 }
 
 impl AverageArtistItem {
@@ -666,13 +729,28 @@ impl AverageArtistItem {
         }
 
         // If distance(this, artist_a) is close to distance(this, artist_b), then we add weight to
-        // this artist since it represents a better mix between both artists
+        // this artist since it represents a better mix between both artists.  With more than two
+        // seed artists, the spread between the closest and furthest seed is used instead.
         //
         // (1 - abs(0.97 - 0.97))^2 = 1 - 0.9 = 0.1
         // (1 - abs(0.94 - 0.99))^2 = 0.9025 - 0.9 = 0.025
         // (1 - abs(0.90 - 0.99))^2 = 0.8281 - 0.9 - -0.0719
         // (1 - abs(0.63520014 - 0.91005754))^2 = 0.5258 - 0.9 = -0.374
-        let distances_diff = (self.similarity_to_artist_1 - self.similarity_to_artist_2).abs();
+        let max_similarity = self
+            .similarity_to_seeds
+            .iter()
+            .copied()
+            .fold(f32::MIN, f32::max);
+        let min_similarity = self
+            .similarity_to_seeds
+            .iter()
+            .copied()
+            .fold(f32::MAX, f32::min);
+        let distances_diff = if self.similarity_to_seeds.is_empty() {
+            0.
+        } else {
+            max_similarity - min_similarity
+        };
         let distances_diff_factor = (1. - distances_diff.abs()).powi(2) - 0.9;
         score += distances_diff_factor * 1.8;
 
@@ -680,10 +758,48 @@ impl AverageArtistItem {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct AverageArtistsResponse {
     pub artists: Vec<AverageArtistItem>,
     pub similarity: f32,
     pub distance: f32,
+    /// Set if some artists were dropped because their data failed to be fetched from Spotify
+    pub partial: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AverageArtistsSeed {
+    pub spotify_id: String,
+    pub bias: Option<f32>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AverageArtistsRequest {
+    pub artists: Vec<AverageArtistsSeed>,
+    pub count: Option<usize>,
+    /// Artists less similar than this to the averaged point are dropped from the results
+    pub min_similarity: Option<f32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WeightedAverageArtistsResponse {
+    pub artists: Vec<AverageArtistItem>,
+    /// Set if some artists were dropped because their data failed to be fetched from Spotify
+    pub partial: bool,
+}
+
+/// Position of an artist in the 3D artist map
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct ArtistCoords3d {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ArtistSimilarityResponse {
+    pub distance: f32,
+    pub similarity: f32,
 }
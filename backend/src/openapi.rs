@@ -0,0 +1,150 @@
+//! Generates an OpenAPI spec describing the shapes of the responses returned by the stats
+//! endpoints.  Schemas are derived from the response types themselves so that the spec can't drift
+//! out of sync with what's actually served.
+
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    models::{AverageArtistsResponse, RelatedArtistsGraph, StatsSnapshot, Timeline},
+    routes::{ArtistStats, GenreStats},
+};
+
+lazy_static::lazy_static! {
+    /// The spec never changes while the server is running, so it's only built once
+    pub static ref OPENAPI_SPEC: String = build_openapi_spec().to_string();
+}
+
+struct Endpoint {
+    path: &'static str,
+    summary: &'static str,
+    response_schema: fn(&mut SchemaGenerator) -> Schema,
+}
+
+fn endpoint<T: JsonSchema>(path: &'static str, summary: &'static str) -> Endpoint {
+    Endpoint {
+        path,
+        summary,
+        response_schema: |gen| gen.subschema_for::<T>(),
+    }
+}
+
+fn endpoints() -> Vec<Endpoint> {
+    vec![
+        endpoint::<StatsSnapshot>(
+            "/stats/{username}",
+            "Top tracks and artists for each timeframe",
+        ),
+        endpoint::<ArtistStats>(
+            "/stats/{username}/artist/{artist_id}",
+            "Stats for a single artist",
+        ),
+        endpoint::<GenreStats>(
+            "/stats/{username}/genre/{genre}",
+            "Top artists and popularity history for a single genre",
+        ),
+        endpoint::<Timeline>(
+            "/stats/{username}/timeline",
+            "Artists and tracks first seen within a range of days",
+        ),
+        endpoint::<RelatedArtistsGraph>(
+            "/stats/{username}/related_artists_graph",
+            "Graph of related artists among the user's top artists",
+        ),
+        endpoint::<AverageArtistsResponse>(
+            "/average_artists/{artist_1_spotify_id}/{artist_2_spotify_id}",
+            "Artists in between two artists",
+        ),
+    ]
+}
+
+/// Returns the names of the `{param}` segments of `path`
+fn path_params(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+}
+
+pub(crate) fn build_openapi_spec() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+
+    let mut paths = Map::new();
+    for Endpoint {
+        path,
+        summary,
+        response_schema,
+    } in endpoints()
+    {
+        let parameters: Vec<Value> = path_params(path)
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect();
+        let response_schema = response_schema(&mut gen);
+        paths.insert(
+            path.to_owned(),
+            json!({
+                "get": {
+                    "summary": summary,
+                    "parameters": parameters,
+                    "responses": {
+                        "200": {
+                            "description": "OK",
+                            "content": {
+                                "application/json": { "schema": response_schema },
+                            },
+                        },
+                    },
+                },
+            }),
+        );
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Spotifytrack",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": { "schemas": gen.take_definitions() },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_openapi_spec, path_params};
+
+    #[test]
+    fn test_spec_includes_stats_snapshot_schema() {
+        let spec = build_openapi_spec();
+        let schemas = &spec["components"]["schemas"];
+
+        let snapshot_properties = &schemas["StatsSnapshot"]["properties"];
+        assert!(snapshot_properties["artists"].is_object());
+        assert!(snapshot_properties["tracks"].is_object());
+        assert_eq!(
+            spec["paths"]["/stats/{username}"]["get"]["responses"]["200"]["content"]
+                ["application/json"]["schema"]["$ref"],
+            "#/components/schemas/StatsSnapshot"
+        );
+        // Nested types are referenced rather than inlined
+        assert!(schemas["Artist"]["properties"]["name"].is_object());
+    }
+
+    #[test]
+    fn test_path_params() {
+        let params: Vec<&str> =
+            path_params("/average_artists/{artist_1_spotify_id}/{artist_2_spotify_id}").collect();
+        assert_eq!(params, ["artist_1_spotify_id", "artist_2_spotify_id"]);
+        assert_eq!(path_params("/stats/{username}/timeline").count(), 1);
+    }
+}
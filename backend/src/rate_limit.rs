@@ -0,0 +1,238 @@
+//! Per-IP rate limiting for public routes.  Each client IP gets a token bucket that refills at a
+//! steady rate; requests made once it's empty are answered with `429 Too Many Requests` without
+//! running the requested route.
+//!
+//! Client IPs are taken from the connection unless a header set by a trusted reverse proxy is
+//! configured with `RATE_LIMIT_IP_HEADER`; see `Conf::rate_limit_ip_header`.
+
+use std::{
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rocket::{
+    fairing::{self, Fairing, Info, Kind},
+    http::{uri::Origin, Method},
+    Build, Data, Request, Rocket,
+};
+
+use crate::{cache::lru::LruCache, routes::ApiError};
+
+/// Number of client IPs tracked at once.  The least recently seen IPs are dropped to make room,
+/// which resets their buckets to full.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+/// Path that rate limited requests are rerouted to
+const RATE_LIMITED_PATH: &str = "/__rate_limited";
+/// Routes that are never rate limited since they're used by health checks
+const EXEMPT_PATHS: &[&str] = &["/", "/health"];
+
+#[derive(Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Refills the bucket for the time elapsed since it was last used and takes a token out of it
+    /// if there is one.  Returns whether a token was taken.
+    fn try_take(&mut self, now: Instant, requests_per_second: f64, burst: u32) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * requests_per_second).min(burst as f64);
+        self.last_refill = now;
+
+        if self.tokens < 1. {
+            return false;
+        }
+        self.tokens -= 1.;
+        true
+    }
+}
+
+pub(crate) struct RateLimitFairing {
+    requests_per_second: f64,
+    burst: u32,
+    /// Header to read client IPs from instead of the connection's address
+    ip_header: Option<String>,
+    buckets: Mutex<LruCache<IpAddr, TokenBucket>>,
+}
+
+impl RateLimitFairing {
+    pub fn new(requests_per_second: f64, burst: u32, ip_header: Option<String>) -> Self {
+        // Buckets that have been idle long enough to refill completely are equivalent to missing
+        // ones, so they can be expired
+        let ttl = Duration::try_from_secs_f64(burst as f64 / requests_per_second).ok();
+        RateLimitFairing {
+            requests_per_second,
+            burst,
+            ip_header,
+            buckets: Mutex::new(LruCache::new(MAX_TRACKED_CLIENTS, ttl)),
+        }
+    }
+
+    /// Returns whether a request from `ip` made at `now` is allowed
+    fn check(&self, ip: IpAddr, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut bucket = buckets.get(&ip).copied().unwrap_or(TokenBucket {
+            tokens: self.burst as f64,
+            last_refill: now,
+        });
+        let allowed = bucket.try_take(now, self.requests_per_second, self.burst);
+        buckets.insert(ip, bucket);
+        allowed
+    }
+
+    /// Returns the IP of the client that made `req`, read from `ip_header` if it's configured and
+    /// holds a valid IP and from the connection otherwise
+    fn client_ip(&self, req: &Request<'_>) -> Option<IpAddr> {
+        self.ip_header
+            .as_deref()
+            .and_then(|header| req.headers().get_one(header))
+            .and_then(|ip| ip.trim().parse().ok())
+            .or_else(|| req.remote().map(|remote| remote.ip()))
+    }
+}
+
+fn is_rate_limited_route(req: &Request<'_>) -> bool {
+    // Admin routes are all `POST`s
+    if req.method() != Method::Get {
+        return false;
+    }
+
+    let path = req.uri().path().as_str();
+    let path = path.strip_prefix("/api").unwrap_or(path);
+    let path = path
+        .strip_suffix('/')
+        .filter(|path| !path.is_empty())
+        .unwrap_or(path);
+    !EXEMPT_PATHS.contains(&path)
+}
+
+#[get("/__rate_limited")]
+fn rate_limited() -> ApiError {
+    ApiError::TooManyRequests("Too many requests; try again later".into())
+}
+
+#[rocket::async_trait]
+impl Fairing for RateLimitFairing {
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.mount("/", routes![rate_limited]))
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        if !is_rate_limited_route(req) {
+            return;
+        }
+        let ip = match self.client_ip(req) {
+            Some(ip) => ip,
+            None => return,
+        };
+
+        if !self.check(ip, Instant::now()) {
+            req.set_uri(Origin::parse(RATE_LIMITED_PATH).unwrap());
+        }
+    }
+
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate Limit Fairing",
+            kind: Kind::Ignite | Kind::Request,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use rocket::{
+        http::{Header, Status},
+        local::blocking::Client,
+    };
+
+    use super::RateLimitFairing;
+
+    #[get("/stats/<username>")]
+    fn stats(username: &str) -> String { username.to_owned() }
+
+    #[get("/health")]
+    fn health() -> &'static str { "ok" }
+
+    #[test]
+    fn test_exceeding_rate_limit_returns_429() {
+        let rocket = rocket::build()
+            .mount("/", routes![stats, health])
+            .attach(RateLimitFairing::new(0.001, 2, None));
+        let client = Client::tracked(rocket).unwrap();
+        let get = |path: &'static str, ip: &str| {
+            client
+                .get(path)
+                .remote(format!("{}:8000", ip).parse().unwrap())
+                .dispatch()
+                .status()
+        };
+
+        assert_eq!(get("/stats/user", "10.0.0.1"), Status::Ok);
+        assert_eq!(get("/stats/user", "10.0.0.1"), Status::Ok);
+        assert_eq!(get("/stats/user", "10.0.0.1"), Status::TooManyRequests);
+        let res = client
+            .get("/stats/user")
+            .remote("10.0.0.1:8000".parse().unwrap())
+            .dispatch();
+        let body: serde_json::Value = res.into_json().unwrap();
+        assert_eq!(body["error"]["code"], "too_many_requests");
+        // Buckets are tracked per IP
+        assert_eq!(get("/stats/user", "10.0.0.2"), Status::Ok);
+        // Health checks are never limited
+        assert_eq!(get("/health", "10.0.0.1"), Status::Ok);
+    }
+
+    #[test]
+    fn test_ip_header_is_only_trusted_when_configured() {
+        let get = |client: &Client, ip: &str| {
+            client
+                .get("/stats/user")
+                .remote("10.0.0.1:8000".parse().unwrap())
+                .header(Header::new("X-Real-IP", ip.to_owned()))
+                .dispatch()
+                .status()
+        };
+
+        let rocket = rocket::build()
+            .mount("/", routes![stats])
+            .attach(RateLimitFairing::new(0.001, 1, None));
+        let client = Client::tracked(rocket).unwrap();
+        assert_eq!(get(&client, "10.0.0.2"), Status::Ok);
+        assert_eq!(get(&client, "10.0.0.3"), Status::TooManyRequests);
+
+        let rocket = rocket::build()
+            .mount("/", routes![stats])
+            .attach(RateLimitFairing::new(
+                0.001,
+                1,
+                Some("X-Real-IP".to_owned()),
+            ));
+        let client = Client::tracked(rocket).unwrap();
+        assert_eq!(get(&client, "10.0.0.2"), Status::Ok);
+        assert_eq!(get(&client, "10.0.0.3"), Status::Ok);
+        assert_eq!(get(&client, "10.0.0.2"), Status::TooManyRequests);
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let fairing = RateLimitFairing::new(10., 2, None);
+        let ip = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(fairing.check(ip, now));
+        assert!(fairing.check(ip, now));
+        assert!(!fairing.check(ip, now));
+        assert!(fairing.check(ip, now + Duration::from_millis(100)));
+        assert!(!fairing.check(ip, now + Duration::from_millis(100)));
+        // Refills are capped at the burst size
+        let later = now + Duration::from_secs(60);
+        assert!(fairing.check(ip, later));
+        assert!(fairing.check(ip, later));
+        assert!(!fairing.check(ip, later));
+    }
+}
@@ -0,0 +1,168 @@
+//! Responders that serialize large responses built from the loaded CSV data incrementally, one item
+//! at a time.  Items are looked up from the shared `CsvData` as the body is written rather than
+//! being cloned into an intermediate struct and serialized all at once.
+
+use std::{iter, sync::Arc};
+
+use chrono::NaiveDateTime;
+use rocket::{
+    http::ContentType,
+    response::{self, stream::ByteStream, Responder, Response},
+    Request,
+};
+use serde::Serialize;
+
+use crate::csv_loader::CsvData;
+
+type JsonChunks = Box<dyn Iterator<Item = Vec<u8>> + Send>;
+
+/// A JSON response body produced lazily as a sequence of chunks
+pub(crate) struct StreamedJson(JsonChunks);
+
+impl StreamedJson {
+    pub(crate) fn into_bytes(self) -> Vec<u8> { self.0.flatten().collect() }
+}
+
+impl<'r> Responder<'r, 'r> for StreamedJson {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'r> {
+        Response::build_from(ByteStream(futures::stream::iter(self.0)).respond_to(req)?)
+            .header(ContentType::JSON)
+            .ok()
+    }
+}
+
+fn to_json<T: Serialize + ?Sized>(val: &T) -> Vec<u8> {
+    serde_json::to_vec(val).expect("Serializing response items to JSON can't fail")
+}
+
+fn raw(s: &'static str) -> JsonChunks { Box::new(iter::once(s.as_bytes().to_vec())) }
+
+/// Wraps already-serialized `items` in `open` and `close`, separated by commas
+fn json_seq(
+    open: &'static str,
+    items: impl Iterator<Item = Vec<u8>> + Send + 'static,
+    close: &'static str,
+) -> JsonChunks {
+    let items = items.enumerate().map(|(ix, mut item)| {
+        if ix > 0 {
+            item.insert(0, b',');
+        }
+        item
+    });
+    Box::new(raw(open).chain(items).chain(raw(close)))
+}
+
+type LookupFn = fn(&CsvData, &str) -> Option<Vec<u8>>;
+
+/// Serializes a `TimeFrames` object containing the items with the provided IDs.  IDs that
+/// `lookup` can't find are skipped.
+fn timeframes(csv_data: &Arc<CsvData>, ids: [Vec<String>; 3], lookup: LookupFn) -> JsonChunks {
+    let [short, medium, long] = ids;
+    let items = |ids: Vec<String>| {
+        let csv_data = Arc::clone(csv_data);
+        json_seq(
+            "[",
+            ids.into_iter().filter_map(move |id| lookup(&csv_data, &id)),
+            "]",
+        )
+    };
+
+    Box::new(
+        raw("{\"short\":")
+            .chain(items(short))
+            .chain(raw(",\"medium\":"))
+            .chain(items(medium))
+            .chain(raw(",\"long\":"))
+            .chain(items(long))
+            .chain(raw("}")),
+    )
+}
+
+/// Streams a `StatsSnapshot` containing the top artists and tracks with the provided IDs for each
+/// timeframe
+pub(crate) fn stats_snapshot(
+    csv_data: Arc<CsvData>,
+    last_update_time: NaiveDateTime,
+    top_artist_ids: [Vec<String>; 3],
+    top_track_ids: [Vec<String>; 3],
+) -> StreamedJson {
+    let tracks = timeframes(&csv_data, top_track_ids, |csv_data, id| {
+        csv_data.tracks.get(id).map(to_json)
+    });
+    let artists = timeframes(&csv_data, top_artist_ids, |csv_data, id| {
+        csv_data.artists.get(id).map(to_json)
+    });
+
+    let mut opening = b"{\"last_update_time\":".to_vec();
+    opening.extend(to_json(&last_update_time));
+    StreamedJson(Box::new(
+        iter::once(opening)
+            .chain(raw(",\"tracks\":"))
+            .chain(tracks)
+            .chain(raw(",\"artists\":"))
+            .chain(artists)
+            .chain(raw("}")),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::NaiveDate;
+
+    use super::stats_snapshot;
+    use crate::{csv_loader::parse_csv, models::StatsSnapshot};
+
+    const CSV: &str = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,120000,rock,rock
+2023-01-01T10:05:00Z,Song B,Artist Two,60000,pop,pop
+2023-01-01T10:10:00Z,Song A,Artist One,180000,rock,rock
+2023-01-01T10:15:00Z,Song C,Artist Three,200000,jazz,
+2023-01-01T10:20:00Z,Song B,Artist Two,30000,pop,pop
+";
+
+    #[test]
+    fn test_streamed_stats_snapshot_matches_serialized_snapshot() {
+        let csv_data = Arc::new(parse_csv(CSV.as_bytes()).expect("CSV should parse"));
+        let last_update_time = NaiveDate::from_ymd_opt(2023, 2, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let top_artist_ids = [
+            csv_data.top_artists_short.clone(),
+            csv_data.top_artists_medium.clone(),
+            vec!["missing".to_string()],
+        ];
+        let top_track_ids = [
+            csv_data.top_tracks_short.clone(),
+            Vec::new(),
+            csv_data.top_tracks_long.clone(),
+        ];
+
+        let mut snapshot = StatsSnapshot::new(last_update_time);
+        for (timeframe_id, ids) in top_artist_ids.iter().enumerate() {
+            for artist in ids.iter().filter_map(|id| csv_data.artists.get(id)) {
+                snapshot
+                    .artists
+                    .add_item_by_id(timeframe_id as u8, artist.clone());
+            }
+        }
+        for (timeframe_id, ids) in top_track_ids.iter().enumerate() {
+            for track in ids.iter().filter_map(|id| csv_data.tracks.get(id)) {
+                snapshot
+                    .tracks
+                    .add_item_by_id(timeframe_id as u8, track.clone());
+            }
+        }
+
+        let streamed = stats_snapshot(
+            Arc::clone(&csv_data),
+            last_update_time,
+            top_artist_ids,
+            top_track_ids,
+        );
+        let streamed: serde_json::Value = serde_json::from_slice(&streamed.into_bytes()).unwrap();
+        assert_eq!(streamed, serde_json::to_value(&snapshot).unwrap());
+    }
+}
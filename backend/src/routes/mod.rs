@@ -1,18 +1,30 @@
-use std::{cmp::Reverse, convert::Infallible, sync::Arc, time::Instant};
+use std::{
+    cmp::Reverse,
+    convert::Infallible,
+    future::Future,
+    hash::Hasher,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
-use chrono::{NaiveDateTime, Utc};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Utc};
 use diesel::{self, prelude::*};
 use fnv::{FnvHashMap as HashMap, FnvHashSet};
 use futures::{stream::FuturesUnordered, StreamExt, TryFutureExt, TryStreamExt};
 use redis::Commands;
 use rocket::{
     data::ToByteUnit,
-    http::{RawStr, Status},
+    fairing::{Fairing, Info, Kind},
+    http::{ContentType, RawStr, Status},
     request::Outcome,
-    response::{status, Redirect},
+    response::{self, content::RawJson, status, Redirect, Responder, Response},
     serde::json::Json,
-    State,
+    Either, Request, State,
 };
+use schemars::JsonSchema;
 use tokio::{
     sync::Mutex,
     task::{block_in_place, spawn_blocking},
@@ -20,209 +32,1175 @@ use tokio::{
 
 use crate::{
     artist_embedding::{
-        get_artist_embedding_ctx, get_average_artists,
-        map_3d::{get_map_3d_artist_ctx, get_packed_3d_artist_coords},
-        ArtistEmbeddingError,
+        get_artist_embedding_ctx, get_weighted_average_artists,
+        map_3d::{get_artist_3d_coords, get_map_3d_artist_ctx, get_packed_3d_artist_coords},
+        ArtistEmbeddingError, AverageArtistDescriptor,
     },
     benchmarking::{mark, start},
-    cache::{get_hash_items, get_redis_conn, set_hash_items},
+    cache::{
+        get_hash_items, get_redis_conn,
+        lru::LruCache,
+        set_hash_items,
+        stats_responses::{build_cache_key, get_or_compute},
+    },
     conf::CONF,
     db_util::{
         self, get_all_top_artists_for_user, get_artist_spotify_ids_by_internal_id,
         get_internal_ids_by_spotify_id, insert_related_artists,
     },
-    metrics::{endpoint_response_time, user_updates_failure_total, user_updates_success_total},
+    metrics::{
+        observe_endpoint_response_time, stats_response_cache_hits_total,
+        stats_response_cache_misses_total, user_updates_failure_total, user_updates_success_total,
+    },
     models::{
-        Artist, ArtistSearchResult, AverageArtistItem, AverageArtistsResponse, CompareToRequest,
-        CreateSharedPlaylistRequest, NewRelatedArtistEntry, NewUser, OAuthTokenResponse, Playlist,
-        RelatedArtistsGraph, StatsSnapshot, TimeFrames, Timeline, TimelineEvent, TimelineEventType,
-        Track, User, UserComparison,
+        Artist, ArtistCoords3d, ArtistSearchResponse, ArtistSearchResult, ArtistSimilarityResponse,
+        AverageArtistItem, AverageArtistsRequest, AverageArtistsResponse, CompareToRequest,
+        CreateSharedPlaylistRequest, CsvArtistSearchResult, NewRelatedArtistEntry, NewUser,
+        OAuthTokenResponse, Playlist, RelatedArtistsGraph, SearchResponse, SearchResult,
+        TimeFrames, Timeline, TimelineEvent, TimelineEventType, Track, TrackSearchResult, User,
+        UserComparison, WeightedAverageArtistsResponse,
     },
     spotify_api::{
-        fetch_artists, fetch_top_tracks_for_artist, get_multiple_related_artists,
-        get_reqwest_client, search_artists,
+        fetch_artists, fetch_top_tracks_for_artist, fill_missing_preview_urls,
+        get_multiple_related_artists, get_reqwest_client, search_artists,
+        DEFAULT_RELATED_ARTISTS_FETCH_BATCH_SIZE, DEFAULT_RELATED_ARTISTS_FETCH_CONCURRENCY,
+        MIN_ARTIST_MATCH_CONFIDENCE,
     },
+    time_util::local_day_start_to_utc,
     DbConn, SpotifyTokenData,
 };
 
+mod json_stream;
+
 const SPOTIFY_TOKEN_FETCH_URL: &str = "https://accounts.spotify.com/api/token";
+/// Largest `limit` accepted by routes that return top-N lists
+const MAX_TOP_N_LIMIT: usize = 500;
+
+/// Error returned by routes that need to distinguish between missing resources, bad input, and
+/// other failures.  Responds with the matching status code and a JSON body of the form
+/// `{"error": {"code": "...", "message": "..."}}`.
+#[derive(Debug)]
+pub(crate) enum ApiError {
+    NotFound(String),
+    BadRequest(String),
+    /// A request to an upstream service such as the Spotify API failed
+    Upstream(String),
+    ServiceUnavailable(String),
+    TooManyRequests(String),
+    Internal(String),
+}
+
+impl ApiError {
+    fn status(&self) -> Status {
+        match self {
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Upstream(_) => Status::BadGateway,
+            ApiError::ServiceUnavailable(_) => Status::ServiceUnavailable,
+            ApiError::TooManyRequests(_) => Status::TooManyRequests,
+            ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "not_found",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Upstream(_) => "upstream_error",
+            ApiError::ServiceUnavailable(_) => "service_unavailable",
+            ApiError::TooManyRequests(_) => "too_many_requests",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl From<String> for ApiError {
+    fn from(err: String) -> Self { ApiError::Internal(err) }
+}
+
+#[derive(Serialize)]
+struct ApiErrorDetails {
+    code: &'static str,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ApiErrorBody {
+    error: ApiErrorDetails,
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let (status, code) = (self.status(), self.code());
+        let message = match self {
+            ApiError::NotFound(message)
+            | ApiError::BadRequest(message)
+            | ApiError::Upstream(message)
+            | ApiError::ServiceUnavailable(message)
+            | ApiError::TooManyRequests(message)
+            | ApiError::Internal(message) => message,
+        };
+        let body = ApiErrorBody {
+            error: ApiErrorDetails { code, message },
+        };
+        status::Custom(status, Json(body)).respond_to(req)
+    }
+}
+
+/// Returns the loaded CSV dataset, or a 503 if it hasn't finished loading yet or has no entries
+async fn require_csv_data() -> Result<Arc<crate::csv_loader::CsvData>, ApiError> {
+    let csv_data = crate::csv_loader::get_csv_data()
+        .await
+        .ok_or_else(|| ApiError::ServiceUnavailable("CSV data not loaded".to_string()))?;
+    if csv_data.is_empty() {
+        return Err(ApiError::ServiceUnavailable(
+            "Dataset is empty; the loaded listening history has no valid entries".to_string(),
+        ));
+    }
+    Ok(csv_data)
+}
+
+fn serialize_response<T: Serialize>(response: &T) -> Result<String, ApiError> {
+    serde_json::to_string(response).map_err(|err| {
+        error!("Error serializing response: {:?}", err);
+        ApiError::Internal("Error serializing response".to_string())
+    })
+}
+
+/// Serves the cached response of `endpoint` for `params` if one was computed from the currently
+/// loaded CSV data, otherwise computes the serialized response with `compute` and caches it.
+/// Reloading the CSV data is the only thing that invalidates cached responses, so anything else
+/// that a response depends on (like the database for the timeline) can be stale for up to
+/// `stats_cache_ttl_secs`.
+async fn cached_stats_response(
+    endpoint: &'static str,
+    params: impl Serialize,
+    compute: impl Future<Output = Result<String, ApiError>>,
+) -> Result<RawJson<String>, ApiError> {
+    let key = build_cache_key(
+        endpoint,
+        &params,
+        crate::csv_loader::csv_data_version().await,
+    );
+    let (body, hit) = get_or_compute(
+        &CONF.stats_cache_hash_name,
+        CONF.stats_cache_ttl_secs,
+        key,
+        compute,
+    )
+    .await?;
+    if hit {
+        stats_response_cache_hits_total(endpoint).inc();
+    } else {
+        stats_response_cache_misses_total(endpoint).inc();
+    }
+    Ok(RawJson(body))
+}
 
 #[get("/")]
 pub(crate) fn index() -> &'static str { "Application successfully started!" }
 
-/// Retrieves the current top tracks and artist for the current user (now uses CSV data)
-#[get("/stats/<username>")]
+#[derive(Serialize)]
+pub(crate) struct HealthStatus {
+    csv_data_loaded: bool,
+    redis_connected: bool,
+}
+
+/// Serves an OpenAPI spec describing the responses of the stats endpoints
+#[get("/openapi.json")]
+pub(crate) fn get_openapi_spec() -> RawJson<&'static str> {
+    RawJson(crate::openapi::OPENAPI_SPEC.as_str())
+}
+
+/// Readiness check which only succeeds once CSV data has been loaded and Redis is reachable.
+/// Responds with 503 otherwise.  `index` should be used for liveness checks.
+#[get("/health")]
+pub(crate) async fn health() -> status::Custom<Json<HealthStatus>> {
+    let csv_data_loaded = crate::csv_loader::get_csv_data().await.is_some();
+    let redis_connected = spawn_blocking(|| -> Result<(), String> {
+        let mut redis_conn = get_redis_conn()?;
+        redis::cmd("PING")
+            .query::<String>(&mut *redis_conn)
+            .map_err(|err| {
+                error!("Error pinging Redis during health check: {:?}", err);
+                String::from("Redis error")
+            })?;
+        Ok(())
+    })
+    .await
+    .is_ok_and(|res| res.is_ok());
+
+    let status = if csv_data_loaded && redis_connected {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    status::Custom(
+        status,
+        Json(HealthStatus {
+            csv_data_loaded,
+            redis_connected,
+        }),
+    )
+}
+
+/// Parses optional `from`/`to` query params in `YYYY-MM-DD` format into a date range.  Returns
+/// `None` if neither param was provided.
+fn parse_date_range(
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Option<crate::csv_loader::DateRange>, String> {
+    let parse_date = |param_name: &str, date: Option<&str>| -> Result<_, String> {
+        date.map(|date| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                format!(
+                    "Invalid `{}` provided; expected a date in YYYY-MM-DD format, got \"{}\"",
+                    param_name, date
+                )
+            })
+        })
+        .transpose()
+    };
+    let (from, to) = (parse_date("from", from)?, parse_date("to", to)?);
+
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err(format!(
+                "Invalid date range; `from` ({}) is after `to` ({})",
+                from, to
+            ));
+        }
+    }
+
+    if from.is_none() && to.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(crate::csv_loader::DateRange { from, to }))
+}
+
+/// Retrieves the current top tracks and artist for the current user (now uses CSV data).
+///
+/// If `from` and/or `to` (`YYYY-MM-DD`, inclusive) are provided, the top tracks and artists are
+/// computed over just the listening history in that range instead of the preset short/medium/long
+/// timeframes.  The results for the range are set into all three timeframes.
+///
+/// Up to `limit` (default 50, max 500) artists and tracks are returned for each timeframe.
+#[get("/stats/<username>?<from>&<to>&<limit>")]
 #[allow(unused_variables)]
 pub(crate) async fn get_current_stats(
     conn: DbConn,
     conn2: DbConn,
     username: String,
+    from: Option<&str>,
+    to: Option<&str>,
+    limit: Option<usize>,
     token_data: &State<Mutex<SpotifyTokenData>>,
-) -> Result<Option<Json<StatsSnapshot>>, String> {
+) -> Result<RawJson<String>, ApiError> {
     let start_tok = start();
+    let limit = limit
+        .unwrap_or(crate::csv_loader::PRECOMPUTED_TOP_N)
+        .min(MAX_TOP_N_LIMIT);
+    let date_range = parse_date_range(from, to).map_err(ApiError::BadRequest)?;
+
+    let res = cached_stats_response("get_current_stats", (from, to, limit), async {
+        // Load data from CSV instead of database
+        let csv_data = require_csv_data().await?;
+
+        let (top_artists, top_tracks) = match date_range {
+            Some(date_range) => {
+                let (top_artists, top_tracks) = crate::csv_loader::calculate_top_in_range(
+                    &csv_data.entries,
+                    &date_range,
+                    limit,
+                );
+                ([top_artists.clone(), top_artists.clone(), top_artists], [
+                    top_tracks.clone(),
+                    top_tracks.clone(),
+                    top_tracks,
+                ])
+            },
+            None => crate::csv_loader::get_top_ids(&csv_data, CONF.timeframe_windows, limit),
+        };
 
-    // Load data from CSV instead of database
-    let csv_data = crate::csv_loader::get_csv_data()
-        .await
-        .ok_or_else(|| "CSV data not loaded".to_string())?;
+        // The snapshot is serialized directly from the CSV data rather than cloning all of the
+        // artists and tracks into a `StatsSnapshot` first
+        let snapshot = json_stream::stats_snapshot(
+            csv_data,
+            chrono::Utc::now().naive_utc(),
+            top_artists,
+            top_tracks,
+        );
+        Ok(String::from_utf8(snapshot.into_bytes()).expect("Serialized JSON is valid UTF-8"))
+    })
+    .await;
 
-    let mut snapshot = StatsSnapshot::new(chrono::Utc::now().naive_utc());
-
-    // Add top artists
-    for (timeframe_id, artist_ids) in [
-        (0, &csv_data.top_artists_short),
-        (1, &csv_data.top_artists_medium),
-        (2, &csv_data.top_artists_long),
-    ] {
-        for artist_name in artist_ids {
-            let artist_id = format!("csv_{}", artist_name.replace(' ', "_").to_lowercase());
-            if let Some(artist) = csv_data.artists.get(&artist_id) {
-                snapshot.artists.add_item_by_id(timeframe_id, artist.clone());
-            }
+    observe_endpoint_response_time("get_current_stats", start_tok.elapsed());
+    res
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct ArtistStats {
+    pub artist: Artist,
+    pub tracks_by_id: HashMap<String, Track>,
+    pub popularity_history: Vec<(NaiveDateTime, [Option<u8>; 3])>,
+    pub top_tracks: Vec<(String, usize)>,
+}
+
+/// Retrieves stats for a single artist (now uses CSV data).  If `limit` is provided, only that
+/// many of the artist's top tracks are returned.
+#[get("/stats/<username>/artist/<artist_id>?<limit>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_artist_stats(
+    username: String,
+    artist_id: String,
+    limit: Option<usize>,
+) -> Result<Json<ArtistStats>, ApiError> {
+    let start_tok = start();
+
+    let csv_data = require_csv_data().await?;
+
+    let stats = build_artists_stats(&csv_data, &[artist_id.as_str()], limit)
+        .remove(&artist_id)
+        .ok_or_else(|| ApiError::NotFound(format!("No artist found with id={}", artist_id)))?;
+    observe_endpoint_response_time("get_artists_stats", start_tok.elapsed());
+    Ok(Json(stats))
+}
+
+/// Finds the tracks of each of `artist_ids` along with their play counts in a single pass over the
+/// loaded tracks, sorted from most to least played.  Unknown artists are left out.
+fn top_tracks_by_artist_id<'a>(
+    csv_data: &crate::csv_loader::CsvData,
+    artist_ids: &[&'a str],
+) -> HashMap<&'a str, Vec<(String, usize)>> {
+    let mut top_tracks_by_artist_id: HashMap<&str, Vec<(String, usize)>> = artist_ids
+        .iter()
+        .filter(|artist_id| csv_data.artists.contains_key(**artist_id))
+        .map(|artist_id| (*artist_id, Vec::new()))
+        .collect();
+    for (track_id, track) in csv_data.tracks.iter() {
+        // Play counts are keyed by the first artist credited on the track
+        let play_count = track
+            .artists
+            .first()
+            .and_then(|artist| {
+                csv_data
+                    .track_play_counts
+                    .get(&(track.name.clone(), artist.name.clone()))
+            })
+            .copied()
+            .unwrap_or(0);
+        for track_artist in &track.artists {
+            let top_tracks = match top_tracks_by_artist_id.get_mut(track_artist.id.as_str()) {
+                Some(top_tracks) => top_tracks,
+                None => continue,
+            };
+            top_tracks.push((track_id.clone(), play_count));
         }
     }
 
-    // Add top tracks
-    for (timeframe_id, track_ids) in [
-        (0, &csv_data.top_tracks_short),
-        (1, &csv_data.top_tracks_medium),
-        (2, &csv_data.top_tracks_long),
-    ] {
-        for track_key in track_ids {
-            let track_id = format!("csv_{}", track_key.replace(' ', "_").to_lowercase());
-            if let Some(track) = csv_data.tracks.get(&track_id) {
-                snapshot.tracks.add_item_by_id(timeframe_id, track.clone());
-            }
-        }
+    for top_tracks in top_tracks_by_artist_id.values_mut() {
+        top_tracks.sort_unstable_by_key(|(_track_id, play_count)| Reverse(*play_count));
     }
+    top_tracks_by_artist_id
+}
 
-    endpoint_response_time("get_current_stats").observe(start_tok.elapsed().as_nanos() as u64);
+/// Computes `ArtistStats` for each of `artist_ids`.  Unknown artists are left out of the returned
+/// map.  If `limit` is provided, only that many of each artist's top tracks are included.
+fn build_artists_stats(
+    csv_data: &crate::csv_loader::CsvData,
+    artist_ids: &[&str],
+    limit: Option<usize>,
+) -> HashMap<String, ArtistStats> {
+    let top_tracks_by_artist_id = top_tracks_by_artist_id(csv_data, artist_ids);
+    let artist_names: Vec<&str> = top_tracks_by_artist_id
+        .keys()
+        .map(|artist_id| csv_data.artists[*artist_id].name.as_str())
+        .collect();
+    let popularity_history_by_artist_name = crate::csv_loader::calculate_artists_popularity_history(
+        &csv_data.entries,
+        &artist_names,
+        CONF.timeframe_windows,
+    );
+
+    top_tracks_by_artist_id
+        .into_iter()
+        .map(|(artist_id, mut top_tracks)| {
+            let artist = csv_data.artists[artist_id].clone();
+            if let Some(limit) = limit {
+                top_tracks.truncate(limit.min(MAX_TOP_N_LIMIT));
+            }
+            let tracks_by_id = top_tracks
+                .iter()
+                .map(|(track_id, _play_count)| {
+                    (track_id.clone(), csv_data.tracks[track_id].clone())
+                })
+                .collect();
 
-    Ok(Some(Json(snapshot)))
+            let popularity_history =
+                popularity_history_by_artist_name[artist.name.as_str()].clone();
+
+            (artist_id.to_owned(), ArtistStats {
+                artist,
+                tracks_by_id,
+                popularity_history,
+                top_tracks,
+            })
+        })
+        .collect()
 }
 
+/// Returns the artists credited alongside the artist with ID `artist_id` on tracks in the listening
+/// history, sorted by the number of tracks they share (uses CSV data)
+#[get("/stats/<username>/artist/<artist_id>/collaborators")]
+#[allow(unused_variables)]
+pub(crate) async fn get_artist_collaborators(
+    username: String,
+    artist_id: String,
+) -> Result<Json<Vec<crate::csv_loader::Collaborator>>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let collaborators = crate::csv_loader::calculate_collaborators(&csv_data, &artist_id)
+        .ok_or_else(|| ApiError::NotFound(format!("No artist found with id={}", artist_id)))?;
+    observe_endpoint_response_time("get_artist_collaborators", start.elapsed());
+    Ok(Json(collaborators))
+}
+
+/// Number of top tracks included in an `ArtistSummary`
+const ARTIST_SUMMARY_TOP_TRACK_COUNT: usize = 5;
+
+/// A compact version of `ArtistStats` for list views and hover cards
 #[derive(Serialize)]
-pub(crate) struct ArtistStats {
+pub(crate) struct ArtistSummary {
     pub artist: Artist,
-    pub tracks_by_id: HashMap<String, Track>,
-    pub popularity_history: Vec<(NaiveDateTime, [Option<u8>; 3])>,
+    pub total_plays: usize,
+    pub total_ms: u64,
+    /// `(track_name, play_count)` for the artist's most played tracks
     pub top_tracks: Vec<(String, usize)>,
 }
 
-#[get("/stats/<username>/artist/<artist_id>")]
-pub(crate) async fn get_artist_stats(
+fn build_artist_summary(
+    csv_data: &crate::csv_loader::CsvData,
+    artist_id: &str,
+) -> Option<ArtistSummary> {
+    let artist = csv_data.artists.get(artist_id)?.clone();
+    let top_tracks = top_tracks_by_artist_id(csv_data, &[artist_id])
+        .remove(artist_id)
+        .unwrap_or_default()
+        .into_iter()
+        .take(ARTIST_SUMMARY_TOP_TRACK_COUNT)
+        .map(|(track_id, play_count)| (csv_data.tracks[&track_id].name.clone(), play_count))
+        .collect();
+
+    Some(ArtistSummary {
+        total_plays: csv_data
+            .artist_play_counts
+            .get(&artist.name)
+            .copied()
+            .unwrap_or(0),
+        total_ms: csv_data
+            .artist_ms_played
+            .get(&artist.name)
+            .copied()
+            .unwrap_or(0),
+        artist,
+        top_tracks,
+    })
+}
+
+/// Retrieves a compact summary of a single artist's stats with only their total plays, total time
+/// played, and top 5 tracks (uses CSV data)
+#[get("/stats/<username>/artist/<artist_id>/summary")]
+#[allow(unused_variables)]
+pub(crate) async fn get_artist_summary(
+    username: String,
+    artist_id: String,
+) -> Result<Json<ArtistSummary>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let summary = build_artist_summary(&csv_data, &artist_id)
+        .ok_or_else(|| ApiError::NotFound(format!("No artist found with id={}", artist_id)))?;
+    observe_endpoint_response_time("get_artist_summary", start.elapsed());
+    Ok(Json(summary))
+}
+
+/// Maximum number of artists that can be requested at once from `/stats/<username>/artists`
+const MAX_BATCH_ARTIST_STATS_COUNT: usize = 100;
+
+/// Retrieves stats for each of the artists with the provided IDs, keyed by artist ID.  Artists that
+/// aren't found are left out.  If `limit` is provided, only that many of each artist's top tracks
+/// are returned (uses CSV data).
+#[post("/stats/<username>/artists?<limit>", data = "<artist_ids>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_artists_stats_batch(
+    username: String,
+    artist_ids: Json<Vec<String>>,
+    limit: Option<usize>,
+) -> Result<Json<HashMap<String, ArtistStats>>, ApiError> {
+    let start = Instant::now();
+
+    if artist_ids.len() > MAX_BATCH_ARTIST_STATS_COUNT {
+        return Err(ApiError::BadRequest(format!(
+            "Too many artist IDs provided; max is {}",
+            MAX_BATCH_ARTIST_STATS_COUNT
+        )));
+    }
+
+    let csv_data = require_csv_data().await?;
+
+    let artist_ids: Vec<&str> = artist_ids.iter().map(String::as_str).collect();
+    let stats = build_artists_stats(&csv_data, &artist_ids, limit);
+    observe_endpoint_response_time("get_artists_stats_batch", start.elapsed());
+    Ok(Json(stats))
+}
+
+/// Returns the time played and play count for a single artist in each week or month of the
+/// listening history, selected with `granularity` (defaults to `month`).  Buckets without any plays
+/// are included with zero counts (uses CSV data).
+#[get("/stats/<username>/artist/<artist_id>/history?<granularity>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_artist_history(
+    username: String,
+    artist_id: String,
+    granularity: Option<crate::csv_loader::HistoryGranularity>,
+) -> Result<Json<Vec<(NaiveDate, u64, usize)>>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let artist = csv_data
+        .artists
+        .get(&artist_id)
+        .ok_or_else(|| ApiError::NotFound(format!("No artist found with id={}", artist_id)))?;
+
+    let history = crate::csv_loader::calculate_artist_play_history(
+        &csv_data.entries,
+        &artist.name,
+        granularity.unwrap_or(crate::csv_loader::HistoryGranularity::Month),
+    );
+    observe_endpoint_response_time("get_artist_history", start.elapsed());
+    Ok(Json(history))
+}
+
+/// Returns the URL of an image for an artist from the CSV data.  The artist's resolved Spotify ID
+/// is used if it has one, and otherwise Spotify is searched for the artist by name.  The artist's
+/// Spotify metadata is cached in Redis along with that of all other fetched artists.
+#[get("/stats/<username>/artist/<artist_id>/image")]
+#[allow(unused_variables)]
+pub(crate) async fn get_csv_artist_image_url(
     conn: DbConn,
-    conn2: DbConn,
     token_data: &State<Mutex<SpotifyTokenData>>,
     username: String,
     artist_id: String,
-) -> Result<Option<Json<ArtistStats>>, String> {
-    let start_tok = start();
-    let user = match db_util::get_user_by_spotify_id(&conn, username).await? {
-        Some(user) => user,
-        None => {
-            return Ok(None);
-        },
-    };
-    mark(start_tok, "Finished getting spotify user by id");
+) -> Result<String, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+    let artist = csv_data
+        .artists
+        .get(&artist_id)
+        .ok_or_else(|| ApiError::NotFound(format!("No artist found with id={}", artist_id)))?;
 
     let spotify_access_token = {
         let token_data = &mut *(&*token_data).lock().await;
         token_data.get().await
-    }?;
+    }
+    .map_err(ApiError::Upstream)?;
 
-    let tok = start();
-    let user_clone = user.clone();
-    let artist_id_clone = artist_id.clone();
-    let spotify_access_token_clone = spotify_access_token.clone();
-    let (artist_popularity_history, (tracks_by_id, top_track_scores)) = match tokio::join!(
-        crate::db_util::get_artist_rank_history_single_artist(&user, conn, artist_id.clone()),
-        async move {
-            let (tracks_by_id, track_history) = match db_util::get_track_stats_history(
-                &user_clone,
-                conn2,
-                &spotify_access_token_clone,
-                artist_id_clone,
+    let resolved_spotify_id =
+        block_in_place(|| crate::csv_loader::get_resolved_spotify_ids(&[&artist.id]))?
+            .into_iter()
+            .next()
+            .flatten();
+    let spotify_id = match resolved_spotify_id {
+        Some(spotify_id) => spotify_id,
+        None => {
+            let results = search_artists(
+                &conn,
+                spotify_access_token.clone(),
+                &artist.name,
+                &CONF.spotify_market,
             )
-            .await?
-            {
-                Some(res) => res,
-                None => return Ok(None),
+            .await
+            .map_err(ApiError::Upstream)?;
+            let best_match = crate::spotify_api::pick_artist_search_match(&artist.name, &results)
+                .filter(|(_spotify_id, confidence)| *confidence > MIN_ARTIST_MATCH_CONFIDENCE);
+            let spotify_id = match best_match {
+                Some((spotify_id, _confidence)) => spotify_id,
+                None =>
+                    return Err(ApiError::NotFound(format!(
+                        "No image found for {}",
+                        artist.name
+                    ))),
             };
-            let top_track_scores = crate::stats::compute_track_popularity_scores(&track_history);
 
-            Ok(Some((tracks_by_id, top_track_scores)))
-        },
-    ) {
-        (Err(err), _) | (Ok(_), Err(err)) => return Err(err),
-        (Ok(None), _) | (_, Ok(None)) => return Ok(None),
-        (Ok(Some(a)), Ok(Some(b))) => (a, b),
-    };
-    mark(tok, "Fetched artists stats and top tracks");
+            let resolved_ids = std::iter::once((artist.id.clone(), spotify_id.clone())).collect();
+            block_in_place(|| crate::csv_loader::set_resolved_spotify_ids(&resolved_ids))?;
+            spotify_id
+        },
+    };
+
+    let image_url = fetch_artists(&spotify_access_token, &[&spotify_id])
+        .await
+        .map_err(ApiError::Upstream)?
+        .into_iter()
+        .next()
+        .and_then(|artist| artist.images)
+        .and_then(|images| images.into_iter().next())
+        .map(|image| image.url)
+        .ok_or_else(|| ApiError::NotFound(format!("No image found for {}", artist.name)))?;
+    observe_endpoint_response_time("get_csv_artist_image_url", start.elapsed());
+    Ok(image_url)
+}
+
+#[derive(Serialize)]
+pub(crate) struct GenresHistory {
+    pub timestamps: Vec<NaiveDateTime>,
+    pub history_by_genre: HashMap<String, Vec<Option<usize>>>,
+}
+
+/// Retrieves the per-month genre popularity history for the current user (now uses CSV data).
+/// `tz_offset_minutes` shifts month boundaries into the user's local time and defaults to UTC.
+#[get("/stats/<username>/genre_history?<tz_offset_minutes>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_genre_history(
+    username: String,
+    tz_offset_minutes: Option<i32>,
+) -> Result<RawJson<String>, ApiError> {
+    let start = Instant::now();
+    let tz_offset_minutes = tz_offset_minutes.unwrap_or(0);
+    // Offsets are part of the cache key, so only real ones are accepted to bound the key count
+    if tz_offset_minutes.abs() > crate::time_util::MAX_TZ_OFFSET_MINUTES {
+        return Err(ApiError::BadRequest(format!(
+            "`tz_offset_minutes` must be between -{0} and {0}",
+            crate::time_util::MAX_TZ_OFFSET_MINUTES
+        )));
+    }
+
+    let res = cached_stats_response("get_genre_history", tz_offset_minutes, async {
+        let csv_data = require_csv_data().await?;
+
+        let (timestamps, history_by_genre) = crate::csv_loader::calculate_genre_history(
+            &csv_data.entries,
+            CONF.genre_history_fill_gaps,
+            tz_offset_minutes,
+        );
+        serialize_response(&GenresHistory {
+            timestamps,
+            history_by_genre,
+        })
+    })
+    .await;
+    observe_endpoint_response_time("get_genre_history", start.elapsed());
+    res
+}
+
+/// Default smallest share of a month's listening that a genre needs to get its own series in
+/// `/genre_shares`
+const DEFAULT_GENRE_SHARE_THRESHOLD: f64 = 0.02;
+
+/// Retrieves each genre's share (0-1) of the time played in each month for a stacked area chart.
+/// Genres with less than `min_share` (default 0.02) of a month's listening are merged into an
+/// "other" series.  `tz_offset_minutes` shifts month boundaries into the user's local time and
+/// defaults to UTC (uses CSV data).
+#[get("/stats/<username>/genre_shares?<min_share>&<tz_offset_minutes>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_genre_shares(
+    username: String,
+    min_share: Option<f64>,
+    tz_offset_minutes: Option<i32>,
+) -> Result<Json<crate::csv_loader::GenreShares>, ApiError> {
+    let start = Instant::now();
+
+    let min_share = min_share.unwrap_or(DEFAULT_GENRE_SHARE_THRESHOLD);
+    if !(0.0..=1.0).contains(&min_share) {
+        return Err(ApiError::BadRequest(
+            "`min_share` must be between 0 and 1".to_string(),
+        ));
+    }
+
+    let csv_data = require_csv_data().await?;
+
+    let genre_shares = crate::csv_loader::calculate_genre_shares(
+        &csv_data.entries,
+        CONF.genre_history_fill_gaps,
+        tz_offset_minutes.unwrap_or(0),
+        min_share,
+    );
+    observe_endpoint_response_time("get_genre_shares", start.elapsed());
+    Ok(Json(genre_shares))
+}
+
+/// Returns the total time played in milliseconds for each calendar month between the first and last
+/// play, keyed by the first day of the month.  Months without any listening are included with a
+/// total of zero.  `tz_offset_minutes` shifts month boundaries into the user's local time and
+/// defaults to UTC (uses CSV data).
+#[get("/stats/<username>/monthly_minutes?<tz_offset_minutes>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_monthly_minutes(
+    username: String,
+    tz_offset_minutes: Option<i32>,
+) -> Result<Json<Vec<(NaiveDate, u64)>>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let monthly_ms_played = crate::csv_loader::calculate_monthly_ms_played(
+        &csv_data.entries,
+        tz_offset_minutes.unwrap_or(0),
+    );
+    observe_endpoint_response_time("get_monthly_minutes", start.elapsed());
+    Ok(Json(monthly_ms_played))
+}
+
+/// Lists all genres in the listening history along with total time played (in milliseconds) for
+/// each, sorted from most to least played (uses CSV data)
+#[get("/stats/<username>/genres")]
+#[allow(unused_variables)]
+pub(crate) async fn get_genres(username: String) -> Result<Json<Vec<(String, u64)>>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let genre_play_times = crate::csv_loader::calculate_genre_play_times(&csv_data);
+    observe_endpoint_response_time("get_genres", start.elapsed());
+    Ok(Json(genre_play_times))
+}
+
+/// A CSV file served as a download
+pub(crate) struct CsvAttachment {
+    file_name: &'static str,
+    inner: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for CsvAttachment {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(self.inner.respond_to(req)?)
+            .header(ContentType::CSV)
+            .raw_header(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", self.file_name),
+            )
+            .ok()
+    }
+}
+
+/// Exports the top artists or tracks for every timeframe, or the total time played for every
+/// genre, as a CSV file (uses CSV data)
+#[get("/stats/<username>/export?<kind>")]
+#[allow(unused_variables)]
+pub(crate) async fn export_stats(
+    username: String,
+    kind: crate::csv_loader::ExportKind,
+) -> Result<CsvAttachment, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let exported = crate::csv_loader::export_csv(&csv_data, kind)?;
+    observe_endpoint_response_time("export_stats", start.elapsed());
+    Ok(CsvAttachment {
+        file_name: kind.file_name(),
+        inner: exported,
+    })
+}
+
+/// Returns up to `limit` (default 50, max 500) of the most played genres for each of the short,
+/// medium, and long timeframes along with their total time played in milliseconds (uses CSV data)
+#[get("/stats/<username>/top_genres?<limit>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_top_genres(
+    username: String,
+    limit: Option<usize>,
+) -> Result<Json<TimeFrames<(String, u64)>>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let top_genres = crate::csv_loader::calculate_top_genres(
+        &csv_data,
+        CONF.timeframe_windows,
+        limit
+            .unwrap_or(crate::csv_loader::PRECOMPUTED_TOP_N)
+            .min(MAX_TOP_N_LIMIT),
+    );
+    observe_endpoint_response_time("get_top_genres", start.elapsed());
+    Ok(Json(top_genres))
+}
+
+/// Returns aggregate listening time for the current user, optionally scoped to an inclusive
+/// `from`/`to` (`YYYY-MM-DD`) date range (uses CSV data)
+#[get("/stats/<username>/listening_time?<from>&<to>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_listening_time(
+    username: String,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Json<crate::csv_loader::ListeningTime>, ApiError> {
+    let start = Instant::now();
+    let date_range = parse_date_range(from, to).map_err(ApiError::BadRequest)?;
+
+    let csv_data = require_csv_data().await?;
+
+    let listening_time =
+        crate::csv_loader::calculate_listening_time(&csv_data.entries, date_range.as_ref());
+    observe_endpoint_response_time("get_listening_time", start.elapsed());
+    Ok(Json(listening_time))
+}
+
+/// Returns total time played and play count for each hour of the day (0-23).  `tz_offset_minutes`
+/// shifts the buckets into the user's local time and defaults to UTC (uses CSV data).
+#[get("/stats/<username>/listening_clock?<tz_offset_minutes>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_listening_clock(
+    username: String,
+    tz_offset_minutes: Option<i32>,
+) -> Result<Json<[crate::csv_loader::ListeningBucket; 24]>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let clock = crate::csv_loader::calculate_listening_clock(
+        &csv_data.entries,
+        tz_offset_minutes.unwrap_or(0),
+    );
+    observe_endpoint_response_time("get_listening_clock", start.elapsed());
+    Ok(Json(clock))
+}
+
+/// Returns total time played and play count for each day of the week, Monday through Sunday.
+/// `tz_offset_minutes` shifts the buckets into the user's local time and defaults to UTC (uses CSV
+/// data).
+#[get("/stats/<username>/listening_weekdays?<tz_offset_minutes>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_listening_weekdays(
+    username: String,
+    tz_offset_minutes: Option<i32>,
+) -> Result<Json<[crate::csv_loader::ListeningBucket; 7]>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let weekdays = crate::csv_loader::calculate_listening_weekdays(
+        &csv_data.entries,
+        tz_offset_minutes.unwrap_or(0),
+    );
+    observe_endpoint_response_time("get_listening_weekdays", start.elapsed());
+    Ok(Json(weekdays))
+}
+
+/// Returns the tracks played on `month`/`day` (defaulting to today) in every year of the listening
+/// history.  `tz_offset_minutes` shifts day boundaries into the user's local time and defaults to
+/// UTC (uses CSV data).
+#[get("/stats/<username>/on_this_day?<month>&<day>&<tz_offset_minutes>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_on_this_day(
+    username: String,
+    month: Option<u32>,
+    day: Option<u32>,
+    tz_offset_minutes: Option<i32>,
+) -> Result<Json<crate::csv_loader::OnThisDay>, ApiError> {
+    let start = Instant::now();
+
+    let tz_offset_minutes = tz_offset_minutes.unwrap_or(0);
+    let today = crate::time_util::to_local_time(Utc::now(), tz_offset_minutes);
+    let (month, day) = (month.unwrap_or(today.month()), day.unwrap_or(today.day()));
+    // Checked against a leap year so that February 29th is allowed
+    if NaiveDate::from_ymd_opt(2000, month, day).is_none() {
+        return Err(ApiError::BadRequest(format!(
+            "Invalid date: month={}, day={}",
+            month, day
+        )));
+    }
+
+    let csv_data = require_csv_data().await?;
+
+    let on_this_day =
+        crate::csv_loader::calculate_on_this_day(&csv_data.entries, month, day, tz_offset_minutes);
+    observe_endpoint_response_time("get_on_this_day", start.elapsed());
+    Ok(Json(on_this_day))
+}
+
+/// Returns the longest and current consecutive-day listening streaks along with the total number
+/// of days with any listening.  `tz_offset_minutes` shifts day boundaries into the user's local
+/// time and defaults to UTC (uses CSV data).
+#[get("/stats/<username>/streaks?<tz_offset_minutes>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_listening_streaks(
+    username: String,
+    tz_offset_minutes: Option<i32>,
+) -> Result<Json<crate::csv_loader::ListeningStreaks>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let streaks = crate::csv_loader::calculate_listening_streaks(
+        &csv_data.entries,
+        tz_offset_minutes.unwrap_or(0),
+    );
+    observe_endpoint_response_time("get_listening_streaks", start.elapsed());
+    Ok(Json(streaks))
+}
+
+/// Splits the listening history into sessions, starting a new one whenever there's a gap of more
+/// than `gap_minutes` (default 30) between plays.  Also returns the average session length (uses
+/// CSV data).
+#[get("/stats/<username>/sessions?<gap_minutes>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_listening_sessions(
+    username: String,
+    gap_minutes: Option<u32>,
+) -> Result<Json<crate::csv_loader::ListeningSessions>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let gap_minutes = gap_minutes.unwrap_or(crate::csv_loader::DEFAULT_SESSION_GAP_MINUTES);
+    let sessions = crate::csv_loader::calculate_listening_sessions(
+        &csv_data.entries,
+        chrono::Duration::minutes(gap_minutes as i64),
+    );
+    observe_endpoint_response_time("get_listening_sessions", start.elapsed());
+    Ok(Json(sessions))
+}
+
+/// Returns the all-time #1 artist, track, and genre, the day with the most listening, and the
+/// longest listening session, each with their total time played.  Sessions are split the same way
+/// as `/sessions`, and fields that can't be computed are null (uses CSV data).
+#[get("/stats/<username>/records?<gap_minutes>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_records(
+    username: String,
+    gap_minutes: Option<u32>,
+) -> Result<Json<crate::csv_loader::ListeningRecords>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let gap_minutes = gap_minutes.unwrap_or(crate::csv_loader::DEFAULT_SESSION_GAP_MINUTES);
+    let records = crate::csv_loader::calculate_records(
+        &csv_data,
+        chrono::Duration::minutes(gap_minutes as i64),
+    );
+    observe_endpoint_response_time("get_records", start.elapsed());
+    Ok(Json(records))
+}
+
+/// Returns overall, per-artist, and per-track skip rates.  Plays of tracks with a known duration
+/// count as skips if less than `min_completion` (default 0.5) of the track was played; otherwise,
+/// plays shorter than `threshold_ms` (default 30 seconds) count as skips.  Artists/tracks with
+/// fewer than `min_plays` (default 5) plays are omitted (uses CSV data).
+#[get("/stats/<username>/skip_stats?<threshold_ms>&<min_completion>&<min_plays>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_skip_stats(
+    username: String,
+    threshold_ms: Option<u64>,
+    min_completion: Option<f64>,
+    min_plays: Option<usize>,
+) -> Result<Json<crate::csv_loader::SkipStats>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let skip_stats = crate::csv_loader::calculate_skip_stats(
+        &csv_data.entries,
+        threshold_ms.unwrap_or(30_000),
+        min_completion.unwrap_or(0.5),
+        min_plays.unwrap_or(5),
+    );
+    observe_endpoint_response_time("get_skip_stats", start.elapsed());
+    Ok(Json(skip_stats))
+}
+
+/// Returns the number of plays by how much of the track was played, in 25% buckets up to 100% plus
+/// a bucket for plays longer than the track.  Plays of tracks with an unknown duration are counted
+/// separately (uses CSV data).
+#[get("/stats/<username>/completion_histogram")]
+#[allow(unused_variables)]
+pub(crate) async fn get_completion_histogram(
+    username: String,
+) -> Result<Json<crate::csv_loader::CompletionHistogram>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let histogram = crate::csv_loader::calculate_completion_histogram(&csv_data.entries);
+    observe_endpoint_response_time("get_completion_histogram", start.elapsed());
+    Ok(Json(histogram))
+}
+
+/// Returns a graph of genres connected by how often they're listened to together, keeping up to
+/// `max_edges_per_genre` (default 10) of the heaviest edges for each genre (uses CSV data).
+#[get("/stats/<username>/genre_graph?<max_edges_per_genre>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_genre_graph(
+    username: String,
+    max_edges_per_genre: Option<usize>,
+) -> Result<Json<crate::csv_loader::GenreGraph>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let genre_graph = crate::csv_loader::calculate_genre_graph(
+        &csv_data.entries,
+        CONF.co_occurrence_window,
+        max_edges_per_genre.unwrap_or(10).min(100),
+    );
+    observe_endpoint_response_time("get_genre_graph", start.elapsed());
+    Ok(Json(genre_graph))
+}
+
+/// Returns the artists and tracks first played during `month` (in `YYYY-MM` format), defaulting to
+/// the latest month in the data.  They're sorted by how much they've been played since to separate
+/// real discoveries from one-offs (uses CSV data).
+#[get("/stats/<username>/discoveries?<month>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_discoveries(
+    username: String,
+    month: Option<&str>,
+) -> Result<Json<crate::csv_loader::Discoveries>, ApiError> {
+    let start = Instant::now();
+
+    let month = month
+        .map(|month| {
+            NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").map_err(|_| {
+                format!(
+                    "Invalid `month` provided; expected a month in YYYY-MM format, got \"{}\"",
+                    month
+                )
+            })
+        })
+        .transpose()
+        .map_err(ApiError::BadRequest)?;
+
+    let csv_data = require_csv_data().await?;
+
+    let discoveries = crate::csv_loader::calculate_discoveries(&csv_data.entries, month)
+        .ok_or_else(|| ApiError::NotFound("No listening history available".to_string()))?;
+    observe_endpoint_response_time("get_discoveries", start.elapsed());
+    Ok(Json(discoveries))
+}
+
+/// Default number of top artists, tracks, and genres included in `/wrapped`
+const DEFAULT_WRAPPED_LIMIT: usize = 10;
+
+/// Returns a year-end summary for the calendar `year` (in UTC) with the top `limit` (default 10)
+/// artists, tracks, and genres, total minutes played, distinct artist count, and the most-played
+/// day (uses CSV data)
+#[get("/stats/<username>/wrapped/<year>?<limit>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_wrapped(
+    username: String,
+    year: i32,
+    limit: Option<usize>,
+) -> Result<Json<crate::csv_loader::WrappedSummary>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let wrapped = crate::csv_loader::calculate_wrapped(
+        &csv_data,
+        year,
+        limit.unwrap_or(DEFAULT_WRAPPED_LIMIT).min(MAX_TOP_N_LIMIT),
+    )
+    .ok_or_else(|| ApiError::NotFound(format!("No listening history for {}", year)))?;
+    observe_endpoint_response_time("get_wrapped", start.elapsed());
+    Ok(Json(wrapped))
+}
+
+/// Default play count band for `/deep_cuts`
+const DEFAULT_DEEP_CUTS_MIN_PLAYS: usize = 2;
+const DEFAULT_DEEP_CUTS_MAX_PLAYS: usize = 5;
 
-    let tok = start();
-    let artist = match crate::spotify_api::fetch_artists(&spotify_access_token, &[&artist_id])
-        .await?
-        .drain(..)
-        .next()
-    {
-        Some(artist) => artist,
-        None => return Ok(None),
-    };
-    mark(tok, "Found matching artist to use");
+/// Returns tracks played at least `min_plays` (default 2) times but fewer than `max_plays`
+/// (default 5) times, sorted by when they were first played so that old forgotten tracks come
+/// first (uses CSV data).
+#[get("/stats/<username>/deep_cuts?<min_plays>&<max_plays>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_deep_cuts(
+    username: String,
+    min_plays: Option<usize>,
+    max_plays: Option<usize>,
+) -> Result<Json<Vec<crate::csv_loader::DeepCut>>, ApiError> {
+    let start = Instant::now();
 
-    let stats = ArtistStats {
-        artist,
-        tracks_by_id,
-        popularity_history: artist_popularity_history,
-        top_tracks: top_track_scores,
-    };
-    endpoint_response_time("get_artists_stats").observe(start_tok.elapsed().as_nanos() as u64);
-    Ok(Some(Json(stats)))
+    let min_plays = min_plays.unwrap_or(DEFAULT_DEEP_CUTS_MIN_PLAYS);
+    let max_plays = max_plays.unwrap_or(DEFAULT_DEEP_CUTS_MAX_PLAYS);
+    if min_plays >= max_plays {
+        return Err(ApiError::BadRequest(format!(
+            "`min_plays` must be less than `max_plays`; got min_plays={}, max_plays={}",
+            min_plays, max_plays
+        )));
+    }
+
+    let csv_data = require_csv_data().await?;
+
+    let deep_cuts = crate::csv_loader::calculate_deep_cuts(&csv_data, min_plays, max_plays);
+    observe_endpoint_response_time("get_deep_cuts", start.elapsed());
+    Ok(Json(deep_cuts))
 }
 
-#[derive(Serialize)]
-pub(crate) struct GenresHistory {
-    pub timestamps: Vec<NaiveDateTime>,
-    pub history_by_genre: HashMap<String, Vec<Option<usize>>>,
+const DEFAULT_ENTRIES_LIMIT: usize = 100;
+const MAX_ENTRIES_LIMIT: usize = 1000;
+
+/// Returns the raw listening entries in chronological order, optionally limited to an inclusive
+/// `from`/`to` (`YYYY-MM-DD`) date range and to plays of the artist named `artist`.  Paginated with
+/// `limit` (default 100, at most 1000) and `offset` (uses CSV data).
+#[get("/stats/<username>/entries?<from>&<to>&<artist>&<limit>&<offset>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_entries(
+    username: String,
+    from: Option<&str>,
+    to: Option<&str>,
+    artist: Option<&str>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<RawJson<String>, ApiError> {
+    let start = Instant::now();
+    let date_range = parse_date_range(from, to).map_err(ApiError::BadRequest)?;
+    let limit = limit
+        .unwrap_or(DEFAULT_ENTRIES_LIMIT)
+        .min(MAX_ENTRIES_LIMIT);
+
+    let csv_data = require_csv_data().await?;
+
+    let page = crate::csv_loader::filter_entries(
+        &csv_data.entries,
+        date_range.as_ref(),
+        artist,
+        limit,
+        offset.unwrap_or(0),
+    );
+    let res = serialize_response(&page).map(RawJson);
+    observe_endpoint_response_time("get_entries", start.elapsed());
+    res
 }
 
-#[get("/stats/<username>/genre_history")]
-pub(crate) async fn get_genre_history(
-    conn: DbConn,
-    token_data: &State<Mutex<SpotifyTokenData>>,
+/// Returns the top albums by total time played (uses CSV data).  If the CSV has no album column,
+/// each artist's tracks are grouped together in place of albums.  Up to `limit` (default 50, max
+/// 500) albums are returned.
+#[get("/stats/<username>/albums?<limit>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_albums(
     username: String,
-) -> Result<Option<Json<GenresHistory>>, String> {
+    limit: Option<usize>,
+) -> Result<Json<Vec<crate::csv_loader::AlbumStats>>, ApiError> {
     let start = Instant::now();
-    let user = match db_util::get_user_by_spotify_id(&conn, username).await? {
-        Some(user) => user,
-        None => {
-            return Ok(None);
-        },
-    };
-    let spotify_access_token = {
-        let token_data = &mut *(&*token_data).lock().await;
-        token_data.get().await
-    }?;
+    let limit = limit
+        .unwrap_or(crate::csv_loader::PRECOMPUTED_TOP_N)
+        .min(MAX_TOP_N_LIMIT);
 
-    // Only include data from the "short" timeframe since we're producing a timeseries
-    let (artists_by_id, artist_stats_history) =
-        match db_util::get_artist_stats_history(&user, conn, &spotify_access_token, Some(0)).await?
-        {
-            Some(res) => res,
-            None => return Ok(None),
-        };
+    let csv_data = require_csv_data().await?;
 
-    let (timestamps, history_by_genre) =
-        crate::stats::get_top_genres_by_artists(&artists_by_id, &artist_stats_history, true);
-    endpoint_response_time("get_genre_history").observe(start.elapsed().as_nanos() as u64);
-    Ok(Some(Json(GenresHistory {
-        timestamps,
-        history_by_genre,
-    })))
+    let albums = crate::csv_loader::calculate_top_albums(&csv_data.entries, limit);
+    observe_endpoint_response_time("get_albums", start.elapsed());
+    Ok(Json(albums))
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub(crate) struct GenreStats {
     pub artists_by_id: HashMap<String, Artist>,
     pub top_artists: Vec<(String, f32)>,
@@ -258,7 +1236,7 @@ pub(crate) async fn get_genre_stats(
     // Compute ranking scores for each of the update items
     let (timestamps, ranking_by_artist_spotify_id_by_timeframe, popularity_history) =
         crate::stats::compute_genre_ranking_history(genre_stats_history);
-    endpoint_response_time("get_genre_stats").observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("get_genre_stats", start.elapsed());
 
     Ok(Some(Json(GenreStats {
         artists_by_id,
@@ -268,7 +1246,65 @@ pub(crate) async fn get_genre_stats(
     })))
 }
 
-#[get("/stats/<username>/timeline?<start_day_id>&<end_day_id>")]
+/// Returns the `limit` (default 20, max 500) most played tracks by artists with `genre` along with
+/// their play counts.  Genres are matched case-insensitively (uses CSV data).
+#[get("/stats/<username>/genre/<genre>/tracks?<limit>")]
+#[allow(unused_variables)]
+pub(crate) async fn get_genre_top_tracks(
+    username: String,
+    genre: String,
+    limit: Option<usize>,
+) -> Result<Json<Vec<(Track, usize)>>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let top_tracks = crate::csv_loader::calculate_genre_top_tracks(
+        &csv_data,
+        &genre,
+        limit.unwrap_or(20).min(MAX_TOP_N_LIMIT),
+    );
+    observe_endpoint_response_time("get_genre_top_tracks", start.elapsed());
+    Ok(Json(top_tracks))
+}
+
+/// Converts an inclusive range of `YYYY-MM-DD` days in the local time of a user `tz_offset_minutes`
+/// ahead of UTC into a half-open `[start, end)` range of UTC timestamps.
+fn parse_timeline_day_range(
+    start_day_id: &str,
+    end_day_id: &str,
+    tz_offset_minutes: i32,
+) -> Result<(NaiveDateTime, NaiveDateTime), String> {
+    let start_day = NaiveDate::parse_from_str(start_day_id, "%Y-%m-%d")
+        .map_err(|_| String::from("Invalid `start_day_id` provided"))?;
+    let end_day = NaiveDate::parse_from_str(end_day_id, "%Y-%m-%d")
+        .map_err(|_| String::from("Invalid `end_day_id` provided"))?;
+    let end_day = end_day
+        .succ_opt()
+        .ok_or_else(|| String::from("Invalid `end_day_id` provided"))?;
+
+    Ok((
+        local_day_start_to_utc(start_day, tz_offset_minutes),
+        local_day_start_to_utc(end_day, tz_offset_minutes),
+    ))
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, FromFormField)]
+pub(crate) enum TimelineEventFilter {
+    Artist,
+    Track,
+    All,
+}
+
+/// Returns artists and tracks first seen between `start_day_id` and `end_day_id` inclusive.  Day
+/// boundaries are in the local time of a user `tz_offset_minutes` ahead of UTC, defaulting to UTC.
+///
+/// Events are sorted by date and can be limited to a single `event_type` (`artist`, `track`, or
+/// `all`) and paginated with `limit` and `offset`.
+#[get(
+    "/stats/<username>/timeline?<start_day_id>&<end_day_id>&<tz_offset_minutes>&<event_type>&\
+     <limit>&<offset>"
+)]
 pub(crate) async fn get_timeline(
     conn: DbConn,
     token_data: &State<Mutex<SpotifyTokenData>>,
@@ -276,81 +1312,125 @@ pub(crate) async fn get_timeline(
     username: String,
     start_day_id: String,
     end_day_id: String,
-) -> Result<Option<Json<Timeline>>, String> {
+    tz_offset_minutes: Option<i32>,
+    event_type: Option<TimelineEventFilter>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<RawJson<String>, ApiError> {
     let start = Instant::now();
-    let start_day = NaiveDateTime::parse_from_str(
-        &format!("{}T08:00:00+08:00", start_day_id),
-        "%Y-%m-%dT%H:%M:%S%z",
-    )
-    .map_err(|_| String::from("Invalid `start_day_id` provided"))?;
-    let end_day = NaiveDateTime::parse_from_str(
-        &format!("{}T08:00:00+08:00", end_day_id),
-        "%Y-%m-%dT%H:%M:%S%z",
-    )
-    .map_err(|_| String::from("Invalid `end_day_id` provided"))?;
+    let (start_day, end_day) =
+        parse_timeline_day_range(&start_day_id, &end_day_id, tz_offset_minutes.unwrap_or(0))
+            .map_err(ApiError::BadRequest)?;
+    let event_type = event_type.unwrap_or(TimelineEventFilter::All);
+
+    let params = (
+        &username,
+        &start_day_id,
+        &end_day_id,
+        tz_offset_minutes,
+        event_type,
+        limit,
+        offset,
+    );
+    let res = cached_stats_response("get_timeline", params, async {
+        let User { id: user_id, .. } =
+            match db_util::get_user_by_spotify_id(&conn, username.clone()).await? {
+                Some(user) => user,
+                None =>
+                    return Err(ApiError::NotFound(format!(
+                        "No user found with username={}",
+                        username
+                    ))),
+            };
+        let spotify_access_token = {
+            let token_data = &mut *(&*token_data).lock().await;
+            token_data.get().await
+        }?;
+
+        // Only look up the kinds of events that were requested
+        let (artist_events, track_events) = tokio::join!(
+            async {
+                if event_type == TimelineEventFilter::Track {
+                    return Ok(Vec::new());
+                }
+                crate::db_util::get_artist_timeline_events(&conn, user_id, start_day, end_day)
+                    .await
+                    .map_err(crate::db_util::stringify_diesel_err)
+            },
+            async {
+                if event_type == TimelineEventFilter::Artist {
+                    return Ok(Vec::new());
+                }
+                crate::db_util::get_track_timeline_events(&conn_2, user_id, start_day, end_day)
+                    .await
+                    .map_err(crate::db_util::stringify_diesel_err)
+            },
+        );
+        let (artist_events, track_events) = (artist_events?, track_events?);
 
-    let User { id: user_id, .. } = match db_util::get_user_by_spotify_id(&conn, username).await? {
-        Some(user) => user,
-        None => {
-            return Ok(None);
-        },
-    };
-    let spotify_access_token = {
-        let token_data = &mut *(&*token_data).lock().await;
-        token_data.get().await
-    }?;
+        // Sort and paginate before fetching metadata so that we only fetch what's returned.  Events
+        // are tagged with whether they're for an artist (`true`) or a track (`false`).
+        let mut all_events: Vec<(bool, String, NaiveDateTime)> = artist_events
+            .into_iter()
+            .map(|(id, first_seen)| (true, id, first_seen))
+            .chain(
+                track_events
+                    .into_iter()
+                    .map(|(id, first_seen)| (false, id, first_seen)),
+            )
+            .collect();
+        all_events.sort_by_key(|(_, _, first_seen)| first_seen.date());
+        let total = all_events.len();
+        let offset = offset.unwrap_or(0);
+        let page: Vec<(usize, bool, String, NaiveDateTime)> = all_events
+            .into_iter()
+            .enumerate()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(ix, (is_artist, id, first_seen))| (ix + 1, is_artist, id, first_seen))
+            .collect();
+        let (artist_events, track_events): (Vec<_>, Vec<_>) =
+            page.into_iter().partition(|(_, is_artist, ..)| *is_artist);
 
-    let (artist_events, track_events) = tokio::join!(
-        crate::db_util::get_artist_timeline_events(&conn, user_id, start_day, end_day)
-            .map_err(crate::db_util::stringify_diesel_err),
-        crate::db_util::get_track_timeline_events(&conn_2, user_id, start_day, end_day)
-            .map_err(crate::db_util::stringify_diesel_err),
-    );
-    let (artist_events, track_events) = (artist_events?, track_events?);
+        let artist_ids = artist_events
+            .iter()
+            .map(|evt| evt.2.as_str())
+            .collect::<Vec<_>>();
+        let track_ids = track_events
+            .iter()
+            .map(|evt| evt.2.as_str())
+            .collect::<Vec<_>>();
 
-    let artist_ids = artist_events
-        .iter()
-        .map(|evt| evt.0.as_str())
-        .collect::<Vec<_>>();
-    let track_ids = track_events
-        .iter()
-        .map(|evt| evt.0.as_str())
-        .collect::<Vec<_>>();
+        // Join to artist/track metadata
+        let items = tokio::try_join!(
+            crate::spotify_api::fetch_artists(&spotify_access_token, &artist_ids),
+            crate::spotify_api::fetch_tracks(&spotify_access_token, &track_ids),
+        )
+        .map_err(ApiError::Upstream)?;
+        let (artists, tracks) = items;
 
-    // Join to artist/track metadata
-    let items = tokio::try_join!(
-        crate::spotify_api::fetch_artists(&spotify_access_token, &artist_ids),
-        crate::spotify_api::fetch_tracks(&spotify_access_token, &track_ids),
-    )?;
-    let (artists, tracks) = items;
-
-    let mut events = Vec::new();
-    let mut event_count = 0;
-    events.extend(artist_events.into_iter().zip(artists.into_iter()).map(
-        |((_artist_id, first_seen), artist)| {
-            event_count += 1;
-            TimelineEvent {
+        let mut events = Vec::new();
+        events.extend(artist_events.into_iter().zip(artists.into_iter()).map(
+            |((id, _, _artist_id, first_seen), artist)| TimelineEvent {
                 event_type: TimelineEventType::ArtistFirstSeen { artist },
                 date: first_seen.date(),
-                id: event_count,
-            }
-        },
-    ));
-    events.extend(track_events.into_iter().zip(tracks.into_iter()).map(
-        |((_track_id, first_seen), track)| {
-            event_count += 1;
-            TimelineEvent {
+                id,
+            },
+        ));
+        events.extend(track_events.into_iter().zip(tracks.into_iter()).map(
+            |((id, _, _track_id, first_seen), track)| TimelineEvent {
                 event_type: TimelineEventType::TopTrackFirstSeen { track },
                 date: first_seen.date(),
-                id: event_count,
-            }
-        },
-    ));
-
-    events.sort_unstable_by_key(|evt| evt.date);
-    endpoint_response_time("get_timeline").observe(start.elapsed().as_nanos() as u64);
+                id,
+            },
+        ));
 
-    Ok(Some(Json(Timeline { events })))
+        events.sort_unstable_by_key(|evt| (evt.date, evt.id));
+        serialize_response(&Timeline { events, total })
+    })
+    .await;
+    observe_endpoint_response_time("get_timeline", start.elapsed());
+    res
 }
 
 /// Redirects to the Spotify authorization page for the application
@@ -434,7 +1514,7 @@ async fn generate_shared_playlist(
     )
     .await?;
 
-    endpoint_response_time("generate_shared_playlist").observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("generate_shared_playlist", start.elapsed());
     Ok(Some(created_playlist))
 }
 
@@ -468,48 +1548,19 @@ async fn validate_api_token(api_token_data: rocket::data::Data<'_>) -> Result<bo
             String::from("Error reading post data body")
         })?
         .into_inner();
-    Ok(api_token == CONF.admin_api_token)
+    Ok(is_valid_api_token(&api_token))
 }
 
+fn is_valid_api_token(api_token: &str) -> bool { api_token == CONF.admin_api_token }
+
+/// Fetches and stores a fresh stats snapshot for `user`.  If `skip_if_recently_updated` is set,
+/// users updated more recently than the minimum update interval are skipped with an error.
 async fn update_user_inner(
     conn: &DbConn,
-    user_id: Option<String>,
+    mut user: User,
+    skip_if_recently_updated: bool,
 ) -> Result<(), status::Custom<String>> {
-    use crate::schema::users::dsl::*;
-
-    // Get the least recently updated user
-    let mut user: User = match user_id.clone().map(|s| -> Result<String, _> {
-        let s = RawStr::new(s.as_str());
-        match s.percent_decode() {
-            Ok(decoded) => Ok(decoded.into()),
-            Err(err) => Err(err),
-        }
-    }) {
-        Some(s) => {
-            let user_id: String = s.map_err(|_| {
-                error!("Invalid `user_id` param provided to `/update/user`");
-                status::Custom(
-                    Status::BadRequest,
-                    String::from("Invalid `user_id` param; couldn't decode"),
-                )
-            })?;
-
-            conn.run(move |conn| users.filter(spotify_id.eq(user_id)).first(conn))
-                .await
-        },
-        None =>
-            conn.run(move |conn| users.order_by(last_update_time).first(conn))
-                .await,
-    }
-    .map_err(|err| {
-        error!("{:?}", err);
-        status::Custom(
-            Status::InternalServerError,
-            "Error querying user to update from database".into(),
-        )
-    })?;
-
-    if let Some(res) = db_util::refresh_user_access_token(&conn, &mut user)
+    if let Some(res) = db_util::refresh_user_access_token(conn, &mut user)
         .await
         .map_err(|err| status::Custom(Status::InternalServerError, err))?
     {
@@ -520,7 +1571,7 @@ async fn update_user_inner(
     let min_update_interval_seconds = crate::conf::CONF.min_update_interval;
     let now = chrono::Utc::now().naive_utc();
     let diff = now - user.last_update_time;
-    if user_id.is_none() && diff < min_update_interval_seconds {
+    if skip_if_recently_updated && diff < min_update_interval_seconds {
         let msg = format!(
             "{} since last update; not updating anything right now.",
             diff
@@ -531,7 +1582,7 @@ async fn update_user_inner(
     info!("{diff} since last update; proceeding with update.");
 
     if let Err(err) =
-        crate::db_util::update_user_last_updated(&user, &conn, Utc::now().naive_utc()).await
+        crate::db_util::update_user_last_updated(&user, conn, Utc::now().naive_utc()).await
     {
         error!(
             "Error updating user {:?} last updated time: {:?}",
@@ -555,6 +1606,16 @@ async fn update_user_inner(
                 "No data from Spotify API for that user".into(),
             ));
         },
+        Err(err) if err == crate::spotify_api::RATE_LIMITED_ERROR => {
+            warn!(
+                "Rate limited when fetching stats for user {}",
+                user.spotify_id
+            );
+            return Err(status::Custom(
+                Status::TooManyRequests,
+                "Rate limited by the Spotify API".into(),
+            ));
+        },
         Err(err) => {
             error!("Error fetching user stats: {:?}", err);
             return Err(status::Custom(
@@ -564,7 +1625,7 @@ async fn update_user_inner(
         },
     };
 
-    crate::spotify_api::store_stats_snapshot(&conn, &user, stats)
+    crate::spotify_api::store_stats_snapshot(conn, &user, stats)
         .await
         .map_err(|err| status::Custom(Status::InternalServerError, err))?;
 
@@ -573,87 +1634,374 @@ async fn update_user_inner(
     Ok(())
 }
 
+#[derive(Default, Serialize)]
+pub(crate) struct UpdateUsersSummary {
+    pub succeeded: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Upper bound for the delay between user updates when backing off after being rate limited
+const MAX_USER_UPDATE_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Picks how long an update slot waits before starting its next user update.  `base_delay` is
+/// doubled for every consecutive update that was rate limited, up to `MAX_USER_UPDATE_DELAY`, and
+/// `jitter_fraction` (between 0 and 1) of `jitter` is added on top.
+fn user_update_delay(
+    base_delay: std::time::Duration,
+    jitter: std::time::Duration,
+    consecutive_rate_limits: u32,
+    jitter_fraction: f64,
+) -> std::time::Duration {
+    let backoff = base_delay
+        .saturating_mul(2u32.saturating_pow(consecutive_rate_limits))
+        .min(MAX_USER_UPDATE_DELAY);
+    backoff + jitter.mul_f64(jitter_fraction.clamp(0., 1.))
+}
+
 /// This route is internal and hit by the cron job that is called to periodically update the stats
 /// for the least recently updated user.
-#[post("/update_user?<user_id>&<count>", data = "<api_token_data>")]
+///
+/// If no `user_id` is provided, the `count` least recently updated users are updated, up to
+/// `concurrency` (default 1, max 5) at a time.  Each slot waits a jittered delay between users,
+/// backing off further while Spotify is rate limiting us.  Returns the IDs of the users that were
+/// and weren't successfully updated.
+#[post(
+    "/update_user?<user_id>&<count>&<concurrency>",
+    data = "<api_token_data>"
+)]
 pub(crate) async fn update_user(
     conn: DbConn,
     api_token_data: rocket::data::Data<'_>,
     user_id: Option<String>,
     count: Option<usize>,
-) -> Result<status::Custom<String>, String> {
-    if !validate_api_token(api_token_data).await? {
-        return Ok(status::Custom(
+    concurrency: Option<usize>,
+) -> Result<Json<UpdateUsersSummary>, status::Custom<String>> {
+    if !validate_api_token(api_token_data)
+        .await
+        .map_err(|err| status::Custom(Status::InternalServerError, err))?
+    {
+        return Err(status::Custom(
             Status::Unauthorized,
             "Invalid API token supplied".into(),
         ));
     }
 
     if let Some(user_id) = user_id {
-        if let Err(status) = update_user_inner(&conn, Some(user_id)).await {
+        let user_id: String = RawStr::new(user_id.as_str())
+            .percent_decode()
+            .map_err(|_| {
+                error!("Invalid `user_id` param provided to `/update/user`");
+                status::Custom(
+                    Status::BadRequest,
+                    String::from("Invalid `user_id` param; couldn't decode"),
+                )
+            })?
+            .into();
+        let user = db_util::get_user_by_spotify_id(&conn, user_id)
+            .await
+            .map_err(|err| status::Custom(Status::InternalServerError, err))?
+            .ok_or_else(|| status::Custom(Status::NotFound, String::from("User not found")))?;
+
+        let spotify_id = user.spotify_id.clone();
+        if let Err(status) = update_user_inner(&conn, user, false).await {
             user_updates_failure_total().inc();
-            return Ok(status);
+            return Err(status);
         }
         user_updates_success_total().inc();
-        return Ok(status::Custom(Status::Ok, "User updated".into()));
+        return Ok(Json(UpdateUsersSummary {
+            succeeded: vec![spotify_id],
+            failed: Vec::new(),
+        }));
     }
 
     let count = count.unwrap_or(1);
-    let mut success_count = 0usize;
-    let mut fail_count = 0usize;
-    for _ in 0..count {
-        let success = update_user_inner(&conn, None).await.is_ok();
-        if success {
-            user_updates_success_total().inc();
-            success_count += 1;
-        } else {
-            user_updates_failure_total().inc();
-            fail_count += 1;
-        }
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    let users_to_update: Vec<User> = conn
+        .run(move |conn| {
+            use crate::schema::users::dsl::*;
+            users
+                .order_by(last_update_time)
+                .limit(count as i64)
+                .load(conn)
+        })
+        .await
+        .map_err(|err| {
+            error!("Error querying users to update from database: {:?}", err);
+            status::Custom(
+                Status::InternalServerError,
+                "Error querying users to update from database".into(),
+            )
+        })?;
+
+    let concurrency = concurrency.unwrap_or(1).clamp(1, 5);
+    let user_count = users_to_update.len();
+    let started_count = AtomicUsize::new(0);
+    let consecutive_rate_limits = AtomicU32::new(0);
+    let summary = Mutex::new(UpdateUsersSummary::default());
+    futures::stream::iter(users_to_update)
+        .for_each_concurrent(Some(concurrency), |user| {
+            let (conn, summary) = (&conn, &summary);
+            let (started_count, consecutive_rate_limits) =
+                (&started_count, &consecutive_rate_limits);
+            async move {
+                started_count.fetch_add(1, Ordering::Relaxed);
+                let spotify_id = user.spotify_id.clone();
+                let res = update_user_inner(conn, user, true).await;
+
+                let rate_limited = matches!(
+                    &res,
+                    Err(status::Custom(status, _)) if *status == Status::TooManyRequests
+                );
+                if rate_limited {
+                    consecutive_rate_limits.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    consecutive_rate_limits.store(0, Ordering::Relaxed);
+                }
+                let mut summary = summary.lock().await;
+                match res {
+                    Ok(()) => {
+                        user_updates_success_total().inc();
+                        summary.succeeded.push(spotify_id);
+                    },
+                    Err(_) => {
+                        user_updates_failure_total().inc();
+                        summary.failed.push(spotify_id);
+                    },
+                }
+                drop(summary);
+
+                // No need to wait if there are no more users for this slot to update
+                if started_count.load(Ordering::Relaxed) == user_count {
+                    return;
+                }
+                let delay = user_update_delay(
+                    CONF.user_update_delay,
+                    CONF.user_update_delay_jitter,
+                    consecutive_rate_limits.load(Ordering::Relaxed),
+                    rand::random(),
+                );
+                tokio::time::sleep(delay).await;
+            }
+        })
+        .await;
+
+    let summary = summary.into_inner();
+    info!(
+        "Successfully updated {} user(s); failed to update {} user(s)",
+        summary.succeeded.len(),
+        summary.failed.len()
+    );
+    Ok(Json(summary))
+}
+
+#[post("/populate_tracks_artists_mapping_table", data = "<api_token_data>")]
+pub(crate) async fn populate_tracks_artists_mapping_table(
+    conn: DbConn,
+    api_token_data: rocket::data::Data<'_>,
+    token_data: &State<Mutex<SpotifyTokenData>>,
+) -> Result<status::Custom<String>, String> {
+    if !validate_api_token(api_token_data).await? {
+        return Ok(status::Custom(
+            Status::Unauthorized,
+            "Invalid API token supplied".into(),
+        ));
+    }
+
+    let spotify_access_token = {
+        let token_data = &mut *(&*token_data).lock().await;
+        token_data.get().await
+    }?;
+
+    crate::db_util::populate_tracks_artists_table(&conn, &spotify_access_token).await?;
+
+    Ok(status::Custom(
+        Status::Ok,
+        "Sucessfully populated mapping table".into(),
+    ))
+}
+
+#[post("/populate_artists_genres_mapping_table", data = "<api_token_data>")]
+pub(crate) async fn populate_artists_genres_mapping_table(
+    conn: DbConn,
+    api_token_data: rocket::data::Data<'_>,
+    token_data: &State<Mutex<SpotifyTokenData>>,
+) -> Result<status::Custom<String>, String> {
+    if !validate_api_token(api_token_data).await? {
+        return Ok(status::Custom(
+            Status::Unauthorized,
+            "Invalid API token supplied".into(),
+        ));
+    }
+
+    let spotify_access_token = {
+        let token_data = &mut *(&*token_data).lock().await;
+        token_data.get().await
+    }?;
+
+    crate::db_util::populate_artists_genres_table(&conn, &spotify_access_token).await?;
+
+    Ok(status::Custom(
+        Status::Ok,
+        "Sucessfully populated mapping table".into(),
+    ))
+}
+
+/// Re-reads the listening history CSV from disk and swaps it in for the currently loaded data.  If
+/// the new file fails to parse, the previously loaded data is kept.  Fails with a `409` if another
+/// reload is already in progress.
+#[post("/reload_csv", data = "<api_token_data>")]
+pub(crate) async fn reload_csv(
+    api_token_data: rocket::data::Data<'_>,
+) -> Result<status::Custom<String>, String> {
+    let start = Instant::now();
+    if !validate_api_token(api_token_data).await? {
+        return Ok(status::Custom(
+            Status::Unauthorized,
+            "Invalid API token supplied".into(),
+        ));
     }
 
+    let csv_data = match crate::csv_loader::reload_csv_data().await {
+        Some(Ok(csv_data)) => csv_data,
+        None => {
+            return Ok(status::Custom(
+                Status::Conflict,
+                "A CSV reload is already in progress".into(),
+            ));
+        },
+        Some(Err(err)) => {
+            error!(
+                "Error reloading CSV data; keeping previously loaded data: {}",
+                err
+            );
+            return Ok(status::Custom(
+                Status::InternalServerError,
+                format!("Error reloading CSV data: {}", err),
+            ));
+        },
+    };
+
+    observe_endpoint_response_time("reload_csv", start.elapsed());
     Ok(status::Custom(
         Status::Ok,
         format!(
-            "Successfully updated {} user(s); failed to update {} user(s)",
-            success_count, fail_count
+            "Successfully reloaded CSV data; entries={}, artists={}, tracks={}",
+            csv_data.entries.len(),
+            csv_data.artists.len(),
+            csv_data.tracks.len()
         ),
     ))
 }
 
-#[post("/populate_tracks_artists_mapping_table", data = "<api_token_data>")]
-pub(crate) async fn populate_tracks_artists_mapping_table(
-    conn: DbConn,
-    api_token_data: rocket::data::Data<'_>,
-    token_data: &State<Mutex<SpotifyTokenData>>,
-) -> Result<status::Custom<String>, String> {
-    if !validate_api_token(api_token_data).await? {
-        return Ok(status::Custom(
+/// Maximum size of a CSV file uploaded to `/validate_csv` or `/upload_comparison_csv`
+const MAX_CSV_UPLOAD_SIZE_MEBIBYTES: usize = 512;
+
+/// Reads an uploaded CSV file, rejecting files larger than `MAX_CSV_UPLOAD_SIZE_MEBIBYTES`
+async fn read_csv_upload(
+    csv_data: rocket::data::Data<'_>,
+) -> Result<Vec<u8>, status::Custom<String>> {
+    let csv_bytes = csv_data
+        .open(MAX_CSV_UPLOAD_SIZE_MEBIBYTES.mebibytes())
+        .into_bytes()
+        .await
+        .map_err(|err| {
+            error!("Error reading uploaded CSV: {:?}", err);
+            status::Custom(
+                Status::InternalServerError,
+                String::from("Error reading post data body"),
+            )
+        })?;
+    if !csv_bytes.is_complete() {
+        return Err(status::Custom(
+            Status::PayloadTooLarge,
+            format!(
+                "Uploaded CSV is larger than the {} MiB limit",
+                MAX_CSV_UPLOAD_SIZE_MEBIBYTES
+            ),
+        ));
+    }
+    Ok(csv_bytes.into_inner())
+}
+
+/// Reads an uploaded CSV file like `read_csv_upload`.  Like the body of other admin routes, the
+/// upload starts with the admin API token, which is followed by the file itself on the next line.
+async fn read_authenticated_csv_upload(
+    csv_data: rocket::data::Data<'_>,
+) -> Result<Vec<u8>, status::Custom<String>> {
+    let mut upload = read_csv_upload(csv_data).await?;
+    let token_len = upload
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .unwrap_or(upload.len());
+    let api_token = std::str::from_utf8(&upload[..token_len]).unwrap_or_default();
+    if !is_valid_api_token(api_token.trim_end_matches('\r')) {
+        return Err(status::Custom(
             Status::Unauthorized,
             "Invalid API token supplied".into(),
         ));
     }
+    Ok(upload.split_off((token_len + 1).min(upload.len())))
+}
 
-    let spotify_access_token = {
-        let token_data = &mut *(&*token_data).lock().await;
-        token_data.get().await
-    }?;
+/// Parses an uploaded listening history CSV row by row and reports the rows that failed to parse
+/// along with their line numbers.  The currently loaded data is left untouched.  The admin API
+/// token goes on the first line of the request body, followed by the file.
+#[post("/validate_csv", data = "<csv_data>")]
+pub(crate) async fn validate_csv(
+    csv_data: rocket::data::Data<'_>,
+) -> Result<Json<crate::csv_loader::CsvValidationReport>, status::Custom<String>> {
+    let start = Instant::now();
 
-    crate::db_util::populate_tracks_artists_table(&conn, &spotify_access_token).await?;
+    let csv_bytes = read_authenticated_csv_upload(csv_data).await?;
+    let report = spawn_blocking(move || {
+        crate::csv_loader::validate_csv(csv_bytes.as_slice(), CONF.genre_column_precedence)
+    })
+    .await
+    .unwrap()
+    .map_err(|err| status::Custom(Status::BadRequest, err))?;
 
-    Ok(status::Custom(
-        Status::Ok,
-        "Sucessfully populated mapping table".into(),
+    observe_endpoint_response_time("validate_csv", start.elapsed());
+    Ok(Json(report))
+}
+
+/// Parses an uploaded listening history CSV and registers it as the dataset for `username` so that
+/// it can be compared against other users' data with `/compare`.  The admin API token goes on the
+/// first line of the request body, followed by the file.
+#[post("/upload_comparison_csv/<username>", data = "<csv_data>")]
+pub(crate) async fn upload_comparison_csv(
+    username: String,
+    csv_data: rocket::data::Data<'_>,
+) -> Result<String, status::Custom<String>> {
+    let start = Instant::now();
+
+    let csv_bytes = read_authenticated_csv_upload(csv_data).await?;
+    let csv_data =
+        spawn_blocking(move || crate::csv_loader::parse_uploaded_csv(csv_bytes.as_slice()))
+            .await
+            .unwrap()
+            .map_err(|err| {
+                status::Custom(Status::BadRequest, format!("Error parsing CSV: {}", err))
+            })?;
+    let csv_data = crate::csv_loader::register_named_csv_data(username.clone(), csv_data).await;
+
+    observe_endpoint_response_time("upload_comparison_csv", start.elapsed());
+    Ok(format!(
+        "Successfully registered CSV data for {}; entries={}, artists={}, tracks={}",
+        username,
+        csv_data.entries.len(),
+        csv_data.artists.len(),
+        csv_data.tracks.len()
     ))
 }
 
-#[post("/populate_artists_genres_mapping_table", data = "<api_token_data>")]
-pub(crate) async fn populate_artists_genres_mapping_table(
-    conn: DbConn,
+/// Parses the listening history file at `path` on the server and registers it as the dataset for
+/// `username` so that it can be compared against other users' data
+#[post("/register_csv/<username>?<path>", data = "<api_token_data>")]
+pub(crate) async fn register_csv(
     api_token_data: rocket::data::Data<'_>,
-    token_data: &State<Mutex<SpotifyTokenData>>,
+    username: String,
+    path: String,
 ) -> Result<status::Custom<String>, String> {
+    let start = Instant::now();
     if !validate_api_token(api_token_data).await? {
         return Ok(status::Custom(
             Status::Unauthorized,
@@ -661,16 +2009,30 @@ pub(crate) async fn populate_artists_genres_mapping_table(
         ));
     }
 
-    let spotify_access_token = {
-        let token_data = &mut *(&*token_data).lock().await;
-        token_data.get().await
-    }?;
-
-    crate::db_util::populate_artists_genres_table(&conn, &spotify_access_token).await?;
+    let csv_data = match crate::csv_loader::load_named_csv_data(username.clone(), &path).await {
+        Ok(csv_data) => csv_data,
+        Err(err) => {
+            error!(
+                "Error loading CSV data for {} from {}: {}",
+                username, path, err
+            );
+            return Ok(status::Custom(
+                Status::InternalServerError,
+                format!("Error loading CSV data: {}", err),
+            ));
+        },
+    };
 
+    observe_endpoint_response_time("register_csv", start.elapsed());
     Ok(status::Custom(
         Status::Ok,
-        "Sucessfully populated mapping table".into(),
+        format!(
+            "Successfully registered CSV data for {}; entries={}, artists={}, tracks={}",
+            username,
+            csv_data.entries.len(),
+            csv_data.artists.len(),
+            csv_data.tracks.len()
+        ),
     ))
 }
 
@@ -715,17 +2077,28 @@ async fn compute_comparison(
     }?;
     let spotify_access_token_clone = spotify_access_token.clone();
 
+    // Shared genres are weighted by rank, so they're found using the artists from each user's
+    // latest update since the set of all of their top artists isn't ordered
+    let get_artists = |conn: DbConn, user_id: i64| async move {
+        let all_artists = crate::db_util::get_all_top_artists_for_user(&conn, user_id).await?;
+        let ranked_artists =
+            crate::db_util::get_latest_ranked_top_artists_for_user(&conn, user_id).await?;
+        Ok::<_, diesel::result::Error>((all_artists, ranked_artists))
+    };
     let stats = tokio::try_join!(
         crate::db_util::get_all_top_tracks_for_user(&conn1, user1_id)
             .map_err(db_util::stringify_diesel_err),
         crate::db_util::get_all_top_tracks_for_user(&conn2, user2_id)
             .map_err(db_util::stringify_diesel_err),
-        crate::db_util::get_all_top_artists_for_user(&conn3, user1_id)
-            .map_err(db_util::stringify_diesel_err),
-        crate::db_util::get_all_top_artists_for_user(&conn4, user2_id)
-            .map_err(db_util::stringify_diesel_err),
+        get_artists(conn3, user1_id).map_err(db_util::stringify_diesel_err),
+        get_artists(conn4, user2_id).map_err(db_util::stringify_diesel_err),
     )?;
-    let (user1_tracks, user2_tracks, user1_artists, user2_artists) = stats;
+    let (
+        user1_tracks,
+        user2_tracks,
+        (user1_artists, user1_ranked_artists),
+        (user2_artists, user2_ranked_artists),
+    ) = stats;
 
     let tracks_intersection = async move {
         let mut intersection = user1_tracks;
@@ -737,7 +2110,7 @@ async fn compute_comparison(
             .collect::<Vec<_>>();
         crate::spotify_api::fetch_tracks(&spotify_access_token, &spotify_ids).await
     };
-    let artists_intersection = async move {
+    let artists_intersection = async {
         let mut intersection = user1_artists;
         intersection.retain(|(id, _)| user2_artists.iter().any(|(o_id, _)| *o_id == *id));
 
@@ -747,18 +2120,60 @@ async fn compute_comparison(
             .collect::<Vec<_>>();
         crate::spotify_api::fetch_artists(&spotify_access_token_clone, &spotify_ids).await
     };
-    let intersections = tokio::try_join!(tracks_intersection, artists_intersection)?;
-    let (tracks_intersection, artists_intersection) = intersections;
+    let fetch_ranked_artists = |ranked_artists: Vec<(u8, String)>| {
+        let spotify_access_token = &spotify_access_token_clone;
+        async move {
+            let spotify_ids = ranked_artists
+                .iter()
+                .map(|(_, spotify_id)| spotify_id.as_str())
+                .collect::<Vec<_>>();
+            let artists = crate::spotify_api::fetch_artists(spotify_access_token, &spotify_ids)
+                .await?
+                .into_iter();
+            Ok::<_, String>(
+                ranked_artists
+                    .iter()
+                    .map(|(timeframe_id, _)| *timeframe_id)
+                    .zip(artists)
+                    .collect::<Vec<_>>(),
+            )
+        }
+    };
+    let (tracks_intersection, artists_intersection, user1_ranked_artists, user2_ranked_artists) = tokio::try_join!(
+        tracks_intersection,
+        artists_intersection,
+        fetch_ranked_artists(user1_ranked_artists),
+        fetch_ranked_artists(user2_ranked_artists),
+    )?;
+
+    let genres = crate::stats::get_shared_genres(&user1_ranked_artists, &user2_ranked_artists);
 
     Ok(Some(UserComparison {
         tracks: tracks_intersection,
         artists: artists_intersection,
-        genres: Vec::new(), // TODO
+        genres,
         user1_username: user1.username,
         user2_username: user2.username,
     }))
 }
 
+/// Compares the CSV datasets registered for two users, if both of them have one
+async fn compare_named_datasets(user1: &str, user2: &str) -> Option<UserComparison> {
+    let (user1_data, user2_data) = (
+        crate::csv_loader::get_named_csv_data(user1).await?,
+        crate::csv_loader::get_named_csv_data(user2).await?,
+    );
+    Some(crate::csv_loader::calculate_comparison(
+        user1.to_owned(),
+        &user1_data,
+        user2.to_owned(),
+        &user2_data,
+    ))
+}
+
+/// Compares the top tracks, artists, and genres of two users.  If both users have a dataset
+/// registered via `/register_csv` or `/upload_comparison_csv`, they're compared using that CSV
+/// data.  Otherwise, users' stats are read from the database.
 #[get("/compare/<user1>/<user2>")]
 pub(crate) async fn compare_users(
     conn1: DbConn,
@@ -770,67 +2185,159 @@ pub(crate) async fn compare_users(
     user2: String,
 ) -> Result<Option<Json<UserComparison>>, String> {
     let start = Instant::now();
+    if let Some(comparison) = compare_named_datasets(&user1, &user2).await {
+        observe_endpoint_response_time("compare_users", start.elapsed());
+        return Ok(Some(Json(comparison)));
+    }
+
     let res = compute_comparison(user1, user2, conn1, conn2, conn3, conn4, token_data)
         .await
         .map(|res| res.map(Json))?;
-    endpoint_response_time("compare_users").observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("compare_users", start.elapsed());
     Ok(res)
 }
 
+/// Adds the co-listen count of each edge in `graph` along with its weight, normalized to 0-1 per
+/// source artist, for the source artists that have co-listen data in `csv_data`.  Edges from those
+/// artists that were co-listened fewer than `min_weight` times are removed.
+fn add_co_listen_weights(
+    graph: &mut RelatedArtistsGraph,
+    csv_data: &crate::csv_loader::CsvData,
+    min_weight: u32,
+) {
+    for (artist_id, related_artist_ids) in graph.related_artists.iter_mut() {
+        let co_listen_counts: HashMap<&str, u32> = match csv_data.related_artists.get(artist_id) {
+            Some(related_artists) => related_artists
+                .iter()
+                .map(|(id, count)| (id.as_str(), *count))
+                .collect(),
+            None => continue,
+        };
+        let co_listen_count = |id: &str| co_listen_counts.get(id).copied().unwrap_or(0);
+
+        related_artist_ids.retain(|id| co_listen_count(id) >= min_weight);
+        let counts: Vec<u32> = related_artist_ids
+            .iter()
+            .map(|id| co_listen_count(id))
+            .collect();
+        let max_count = counts.iter().copied().max().unwrap_or(0).max(1);
+        graph.related_artist_weights.insert(
+            artist_id.clone(),
+            counts
+                .iter()
+                .map(|&count| count as f32 / max_count as f32)
+                .collect(),
+        );
+        graph
+            .related_artist_counts
+            .insert(artist_id.clone(), counts);
+    }
+}
+
+/// Removes all edges in `graph` to or from artists that aren't in `extra_artists` so that clients
+/// never have to render an edge to a missing node
+fn drop_unknown_related_artists(graph: &mut RelatedArtistsGraph) {
+    let RelatedArtistsGraph {
+        extra_artists,
+        related_artists,
+        related_artist_weights,
+        related_artist_counts,
+    } = graph;
+    related_artists.retain(|id, _| extra_artists.contains_key(id));
+    related_artist_weights.retain(|id, _| related_artists.contains_key(id));
+    related_artist_counts.retain(|id, _| related_artists.contains_key(id));
+
+    fn retain_known<T>(items: &mut Vec<T>, is_known: &[bool]) {
+        let mut is_known = is_known.iter();
+        items.retain(|_| *is_known.next().unwrap());
+    }
+    for (artist_id, related_artist_ids) in related_artists.iter_mut() {
+        let is_known: Vec<bool> = related_artist_ids
+            .iter()
+            .map(|id| extra_artists.contains_key(id))
+            .collect();
+        retain_known(related_artist_ids, &is_known);
+        if let Some(weights) = related_artist_weights.get_mut(artist_id) {
+            retain_known(weights, &is_known);
+        }
+        if let Some(counts) = related_artist_counts.get_mut(artist_id) {
+            retain_known(counts, &is_known);
+        }
+    }
+}
+
+/// Builds a graph of Spotify's related artists for each of the provided artists.  Co-listen
+/// weights are added from the loaded CSV data for the artists that have any, and edges between them
+/// that were co-listened fewer than `min_weight` times are left out.
 async fn build_related_artists_graph(
     spotify_access_token: String,
     artist_ids: &[&str],
-) -> Result<RelatedArtistsGraph, String> {
-    // Get related artists for all of them
-    let related_artists =
-        get_multiple_related_artists(spotify_access_token.clone(), artist_ids).await?;
-
-    let all_artist_ids: FnvHashSet<String> = artist_ids
-        .iter()
-        .copied()
-        .map(String::from)
-        .chain(
-            related_artists
-                .iter()
-                .flat_map(|related_artists| related_artists.iter().cloned()),
-        )
-        .collect();
+    min_weight: u32,
+) -> Result<RelatedArtistsGraph, ApiError> {
+    // Get related artists for all of them.  Artists whose related artists couldn't be fetched are
+    // treated as not having any.
+    let related_artists = get_multiple_related_artists(
+        spotify_access_token.clone(),
+        artist_ids,
+        DEFAULT_RELATED_ARTISTS_FETCH_CONCURRENCY,
+        DEFAULT_RELATED_ARTISTS_FETCH_BATCH_SIZE,
+    )
+    .await
+    .map_err(ApiError::Upstream)?;
 
     let mut related_artists_by_id = HashMap::default();
-    for (&artist_id, related_artists) in artist_ids.into_iter().zip(related_artists.iter()) {
-        related_artists_by_id.insert(artist_id.to_owned(), related_artists.clone());
+    for (&artist_id, related_artists) in artist_ids.iter().zip(related_artists) {
+        related_artists_by_id.insert(artist_id.to_owned(), related_artists.unwrap_or_default());
     }
-
-    let all_artist_ids: Vec<_> = all_artist_ids.iter().map(String::as_str).collect();
-    let extra_artists_list = fetch_artists(&spotify_access_token, &all_artist_ids).await?;
-    let mut extra_artists = HashMap::default();
-    for artist in extra_artists_list {
-        extra_artists.insert(artist.id.clone(), artist);
+    let mut graph = RelatedArtistsGraph {
+        extra_artists: HashMap::default(),
+        related_artists: related_artists_by_id,
+        related_artist_weights: HashMap::default(),
+        related_artist_counts: HashMap::default(),
+    };
+    if let Some(csv_data) = crate::csv_loader::get_csv_data().await {
+        add_co_listen_weights(&mut graph, &csv_data, min_weight);
     }
 
-    Ok(RelatedArtistsGraph {
-        extra_artists,
-        related_artists: related_artists_by_id,
-    })
+    let all_artist_ids: FnvHashSet<&str> = artist_ids
+        .iter()
+        .copied()
+        .chain(graph.related_artists.values().flatten().map(String::as_str))
+        .collect();
+    let all_artist_ids: Vec<&str> = all_artist_ids.into_iter().collect();
+    let extra_artists_list = fetch_artists(&spotify_access_token, &all_artist_ids)
+        .await
+        .map_err(ApiError::Upstream)?;
+    let extra_artists = extra_artists_list
+        .into_iter()
+        .map(|artist| (artist.id.clone(), artist))
+        .collect();
+    graph.extra_artists = extra_artists;
+    drop_unknown_related_artists(&mut graph);
+
+    Ok(graph)
 }
 
-#[get("/stats/<user_id>/related_artists_graph")]
+/// Returns the graph of related artists for all of the user's top artists across all timeframes.
+/// Co-listen weights and counts are included for artists that have them in the loaded CSV data,
+/// and edges between those co-listened fewer than `min_weight` times are left out so that clients
+/// can filter further.
+#[get("/stats/<user_id>/related_artists_graph?<min_weight>")]
 pub(crate) async fn get_related_artists_graph(
     conn: DbConn,
     user_id: String,
+    min_weight: Option<u32>,
     token_data: &State<Mutex<SpotifyTokenData>>,
-) -> Result<Option<Json<RelatedArtistsGraph>>, String> {
+) -> Result<Json<RelatedArtistsGraph>, ApiError> {
     let start = Instant::now();
-    let User { id: user_id, .. } = match db_util::get_user_by_spotify_id(&conn, user_id).await? {
-        Some(user) => user,
-        None => {
-            return Ok(None);
-        },
-    };
+    let User { id: user_id, .. } = db_util::get_user_by_spotify_id(&conn, user_id.clone())
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No user found with id={}", user_id)))?;
     let spotify_access_token = {
         let token_data = &mut *(&*token_data).lock().await;
         token_data.get().await
-    }?;
+    }
+    .map_err(ApiError::Upstream)?;
 
     // Start off by getting all artists for the user from all timeframes
     let all_artists_for_user =
@@ -838,46 +2345,82 @@ pub(crate) async fn get_related_artists_graph(
             .await
             .map_err(|err| {
                 error!("Error fetching all artists for user: {:?}", err);
-                String::from("Internal DB error")
+                ApiError::Internal(String::from("Internal DB error"))
             })?;
-    let all_artist_ids_for_user: Vec<&str> = all_artists_for_user
+    let all_artist_ids_for_user: FnvHashSet<&str> = all_artists_for_user
         .iter()
         .map(|(_internal_id, spotify_id)| spotify_id.as_str())
         .collect();
+    let all_artist_ids_for_user: Vec<&str> = all_artist_ids_for_user.into_iter().collect();
 
-    let out = build_related_artists_graph(spotify_access_token, &all_artist_ids_for_user).await?;
-    endpoint_response_time("get_related_artists_graph").observe(start.elapsed().as_nanos() as u64);
-    Ok(Some(Json(out)))
+    let out = build_related_artists_graph(
+        spotify_access_token,
+        &all_artist_ids_for_user,
+        min_weight.unwrap_or(0),
+    )
+    .await?;
+    observe_endpoint_response_time("get_related_artists_graph", start.elapsed());
+    Ok(Json(out))
 }
 
+/// Returns the graph of related artists for each of Spotify's related artists for the provided
+/// artist, with co-listen weights and counts where the loaded CSV data has them
 #[get("/related_artists/<artist_id>")]
 pub(crate) async fn get_related_artists(
     artist_id: String,
     token_data: &State<Mutex<SpotifyTokenData>>,
-) -> Result<Option<Json<RelatedArtistsGraph>>, String> {
+) -> Result<Json<RelatedArtistsGraph>, ApiError> {
     let start = Instant::now();
     let spotify_access_token = {
         let token_data = &mut *(&*token_data).lock().await;
         token_data.get().await
-    }?;
+    }
+    .map_err(ApiError::Upstream)?;
 
-    let related_artist_ids =
-        get_multiple_related_artists(spotify_access_token.clone(), &[&artist_id]).await?;
-    let related_artist_ids = match related_artist_ids.into_iter().next() {
-        Some(ids) => ids,
-        None => {
-            error!("Empty vec returned from `get_multiple_related_artists`");
-            return Ok(None);
-        },
-    };
+    let related_artist_ids = get_multiple_related_artists(
+        spotify_access_token.clone(),
+        &[&artist_id],
+        DEFAULT_RELATED_ARTISTS_FETCH_CONCURRENCY,
+        DEFAULT_RELATED_ARTISTS_FETCH_BATCH_SIZE,
+    )
+    .await
+    .map_err(ApiError::Upstream)?
+    .into_iter()
+    .next()
+    .flatten()
+    .ok_or_else(|| {
+        ApiError::Upstream(format!(
+            "Failed to fetch related artists for artist id={}",
+            artist_id
+        ))
+    })?;
     let related_artist_ids = related_artist_ids
         .iter()
         .map(String::as_str)
         .collect::<Vec<_>>();
 
-    let out = build_related_artists_graph(spotify_access_token, &related_artist_ids).await?;
-    endpoint_response_time("get_related_artists").observe(start.elapsed().as_nanos() as u64);
-    Ok(Some(Json(out)))
+    let out = build_related_artists_graph(spotify_access_token, &related_artist_ids, 0).await?;
+    observe_endpoint_response_time("get_related_artists", start.elapsed());
+    Ok(Json(out))
+}
+
+/// Returns up to `limit` (default 20) of the artists most often listened to alongside the provided
+/// artist in this listening history along with how strongly they're co-listened, normalized so
+/// that the most co-listened artist has a score of 1 (uses CSV data)
+#[get("/artist/<artist_id>/also_listened?<limit>")]
+pub(crate) async fn get_also_listened_artists(
+    artist_id: String,
+    limit: Option<usize>,
+) -> Result<Json<Vec<crate::csv_loader::AlsoListenedArtist>>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let also_listened =
+        crate::csv_loader::calculate_also_listened(&csv_data, &artist_id, limit.unwrap_or(20))
+            .ok_or_else(|| ApiError::NotFound(format!("No artist found with id={}", artist_id)))?;
+    observe_endpoint_response_time("get_also_listened_artists", start.elapsed());
+    Ok(Json(also_listened))
 }
 
 #[get("/display_name/<username>")]
@@ -898,7 +2441,7 @@ pub(crate) async fn get_display_name(
                 }
             });
 
-            endpoint_response_time("get_display_name").observe(start.elapsed().as_nanos() as u64);
+            observe_endpoint_response_time("get_display_name", start.elapsed());
 
             Ok(Some(user.username))
         },
@@ -968,8 +2511,7 @@ pub(crate) async fn dump_redis_related_artists_to_database(
             })?;
     }
 
-    endpoint_response_time("dump_redis_related_artists_to_database")
-        .observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("dump_redis_related_artists_to_database", start.elapsed());
 
     Ok(status::Custom(
         Status::Ok,
@@ -977,10 +2519,19 @@ pub(crate) async fn dump_redis_related_artists_to_database(
     ))
 }
 
-#[post("/crawl_related_artists", data = "<api_token_data>")]
+/// Fetches the related artists of the related artists of a random sample of artists in the Redis
+/// cache.  Related artists are fetched in batches of `batch_size` with at most `concurrency`
+/// requests to Spotify in flight at a time.  Artists whose related artists fail to be fetched are
+/// counted and reported rather than failing the whole crawl.
+#[post(
+    "/crawl_related_artists?<concurrency>&<batch_size>",
+    data = "<api_token_data>"
+)]
 pub(crate) async fn crawl_related_artists(
     api_token_data: rocket::Data<'_>,
     token_data: &State<Mutex<SpotifyTokenData>>,
+    concurrency: Option<usize>,
+    batch_size: Option<usize>,
 ) -> Result<status::Custom<String>, String> {
     let start = Instant::now();
 
@@ -996,62 +2547,231 @@ pub(crate) async fn crawl_related_artists(
         token_data.get().await
     }?;
 
-    let mut redis_conn = get_redis_conn()?;
-    let artist_ids: Vec<String> = block_in_place(|| {
+    let mut redis_conn = get_redis_conn()?;
+    let artist_ids: Vec<String> = block_in_place(|| {
+        redis::cmd("HRANDFIELD")
+            .arg("related_artists")
+            .arg("8")
+            .query::<Vec<String>>(&mut *redis_conn)
+    })
+    .map_err(|err| {
+        error!(
+            "Error getting random related artist keys from Redis cache: {:?}",
+            err
+        );
+        String::from("Redis error")
+    })?;
+
+    let mut all_related_artists: Vec<String> = Vec::new();
+
+    let related_artists_jsons: Vec<String> = block_in_place(|| {
+        redis_conn
+            .hget("related_artists", artist_ids)
+            .map_err(|err| {
+                error!("Error getting related artist from Redis: {:?}", err);
+                String::from("Redis error")
+            })
+    })?;
+
+    for related_artists_json in related_artists_jsons {
+        let Ok(related_artist_ids) = serde_json::from_str::<Vec<String>>(&related_artists_json)
+        else {
+            error!(
+                "Invalid entry in related artists Redis; can't parse into array of strings; \
+                 found={}",
+                related_artists_json
+            );
+            continue;
+        };
+
+        all_related_artists.extend(related_artist_ids.into_iter());
+    }
+
+    info!("Crawling {} related artists...", all_related_artists.len());
+    let mut all_related_artists: Vec<&str> =
+        all_related_artists.iter().map(String::as_str).collect();
+    all_related_artists.sort_unstable();
+    all_related_artists.dedup();
+
+    let fetched = get_multiple_related_artists(
+        spotify_access_token.clone(),
+        &all_related_artists,
+        concurrency.unwrap_or(DEFAULT_RELATED_ARTISTS_FETCH_CONCURRENCY),
+        batch_size.unwrap_or(DEFAULT_RELATED_ARTISTS_FETCH_BATCH_SIZE),
+    )
+    .await?;
+    let failed_count = fetched.iter().filter(|related| related.is_none()).count();
+    observe_endpoint_response_time("crawl_related_artists", start.elapsed());
+    Ok(status::Custom(
+        Status::Ok,
+        format!(
+            "Fetched related artists for {} artists to populate related artists Redis hash; {} \
+             failed",
+            fetched.len() - failed_count,
+            failed_count
+        ),
+    ))
+}
+
+const DEFAULT_RELATED_ARTISTS_CACHE_SAMPLE_SIZE: usize = 10;
+
+#[derive(Serialize)]
+pub(crate) struct RelatedArtistsCacheStats {
+    /// Number of artists with cached related artists
+    pub artist_count: usize,
+    /// Spotify IDs of a random sample of the cached artists
+    pub sample_artist_spotify_ids: Vec<String>,
+    /// Number of cached values that can't be parsed as a list of related artist IDs
+    pub invalid_entry_count: usize,
+}
+
+/// Returns the number of cached related artists values that can't be parsed as a list of Spotify
+/// IDs
+fn count_invalid_related_artists_entries(related_artists_jsons: &[String]) -> usize {
+    related_artists_jsons
+        .iter()
+        .filter(|json| serde_json::from_str::<Vec<String>>(json).is_err())
+        .count()
+}
+
+/// Number of related artists cache entries fetched at once with `HSCAN` when checking them
+const RELATED_ARTISTS_CACHE_SCAN_BATCH_SIZE: usize = 1000;
+
+/// Returns the size of the related artists Redis cache along with a random sample of `sample_size`
+/// (default 10) of its keys and the number of values that are invalid.  Useful to gauge the
+/// progress of `crawl_related_artists`.
+#[post(
+    "/related_artists_cache_stats?<sample_size>",
+    data = "<api_token_data>"
+)]
+pub(crate) async fn get_related_artists_cache_stats(
+    api_token_data: rocket::Data<'_>,
+    sample_size: Option<usize>,
+) -> Result<Json<RelatedArtistsCacheStats>, status::Custom<String>> {
+    let start = Instant::now();
+
+    let internal_error = |msg: String| status::Custom(Status::InternalServerError, msg);
+    if !validate_api_token(api_token_data)
+        .await
+        .map_err(internal_error)?
+    {
+        return Err(status::Custom(
+            Status::Unauthorized,
+            "Invalid API token supplied".into(),
+        ));
+    }
+
+    let mut redis_conn = get_redis_conn().map_err(internal_error)?;
+    let artist_count: usize =
+        block_in_place(|| redis_conn.hlen("related_artists")).map_err(|err| {
+            error!("Error with HLEN on related artists data: {:?}", err);
+            internal_error("Redis error".into())
+        })?;
+    let sample_artist_spotify_ids: Vec<String> = block_in_place(|| {
+        redis::cmd("HRANDFIELD")
+            .arg("related_artists")
+            .arg(sample_size.unwrap_or(DEFAULT_RELATED_ARTISTS_CACHE_SAMPLE_SIZE))
+            .query::<Vec<String>>(&mut *redis_conn)
+    })
+    .map_err(|err| {
+        error!(
+            "Error getting random related artist keys from Redis cache: {:?}",
+            err
+        );
+        internal_error("Redis error".into())
+    })?;
+    // Entries are scanned in batches rather than all being loaded at once since the cache can get
+    // very large
+    let mut invalid_entry_count = 0;
+    let mut cursor = 0u64;
+    loop {
+        let (next_cursor, entries): (u64, Vec<(String, String)>) = block_in_place(|| {
+            redis::cmd("HSCAN")
+                .arg("related_artists")
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(RELATED_ARTISTS_CACHE_SCAN_BATCH_SIZE)
+                .query(&mut *redis_conn)
+        })
+        .map_err(|err| {
+            error!("Error with HSCAN on related artists data: {:?}", err);
+            internal_error("Redis error".into())
+        })?;
+        let related_artists_jsons: Vec<String> =
+            entries.into_iter().map(|(_, json)| json).collect();
+        invalid_entry_count += count_invalid_related_artists_entries(&related_artists_jsons);
+
+        if next_cursor == 0 {
+            break;
+        }
+        cursor = next_cursor;
+    }
+
+    observe_endpoint_response_time("get_related_artists_cache_stats", start.elapsed());
+    Ok(Json(RelatedArtistsCacheStats {
+        artist_count,
+        sample_artist_spotify_ids,
+        invalid_entry_count,
+    }))
+}
+
+/// Default number of cached tracks sampled by `/tracks_missing_previews`
+const DEFAULT_MISSING_PREVIEWS_SAMPLE_SIZE: usize = 100;
+
+/// Keeps only the tracks without a preview URL, skipping placeholders for tracks that Spotify
+/// didn't return at all
+fn filter_tracks_missing_previews(tracks: impl IntoIterator<Item = Track>) -> Vec<Track> {
+    tracks
+        .into_iter()
+        .filter(|track| track.preview_url.is_none() && !track.id.is_empty())
+        .collect()
+}
+
+/// Samples `count` (default 100) random tracks from the tracks cache and returns the ones that
+/// don't have a preview URL.  Useful for finding popular tracks that can't be previewed when
+/// curating the averaging feature.
+#[post("/tracks_missing_previews?<count>", data = "<api_token_data>")]
+pub(crate) async fn get_tracks_missing_previews(
+    api_token_data: rocket::Data<'_>,
+    count: Option<usize>,
+) -> Result<Json<Vec<Track>>, status::Custom<String>> {
+    let start = Instant::now();
+
+    let internal_error = |msg: String| status::Custom(Status::InternalServerError, msg);
+    if !validate_api_token(api_token_data)
+        .await
+        .map_err(internal_error)?
+    {
+        return Err(status::Custom(
+            Status::Unauthorized,
+            "Invalid API token supplied".into(),
+        ));
+    }
+
+    let mut redis_conn = get_redis_conn().map_err(internal_error)?;
+    let track_spotify_ids: Vec<String> = block_in_place(|| {
         redis::cmd("HRANDFIELD")
-            .arg("related_artists")
-            .arg("8")
+            .arg(&CONF.tracks_cache_hash_name)
+            .arg(count.unwrap_or(DEFAULT_MISSING_PREVIEWS_SAMPLE_SIZE))
             .query::<Vec<String>>(&mut *redis_conn)
     })
     .map_err(|err| {
         error!(
-            "Error getting random related artist keys from Redis cache: {:?}",
+            "Error getting random track keys from Redis cache: {:?}",
             err
         );
-        String::from("Redis error")
-    })?;
-
-    let mut all_related_artists: Vec<String> = Vec::new();
-
-    let related_artists_jsons: Vec<String> = block_in_place(|| {
-        redis_conn
-            .hget("related_artists", artist_ids)
-            .map_err(|err| {
-                error!("Error getting related artist from Redis: {:?}", err);
-                String::from("Redis error")
-            })
+        internal_error("Redis error".into())
     })?;
+    let track_spotify_ids: Vec<&str> = track_spotify_ids.iter().map(String::as_str).collect();
+    let tracks = block_in_place(|| {
+        get_hash_items::<Track>(&CONF.tracks_cache_hash_name, &track_spotify_ids)
+    })
+    .map_err(internal_error)?;
 
-    for related_artists_json in related_artists_jsons {
-        let Ok(related_artist_ids) = serde_json::from_str::<Vec<String>>(&related_artists_json)
-        else {
-            error!(
-                "Invalid entry in related artists Redis; can't parse into array of strings; \
-                 found={}",
-                related_artists_json
-            );
-            continue;
-        };
-
-        all_related_artists.extend(related_artist_ids.into_iter());
-    }
-
-    info!("Crawling {} related artists...", all_related_artists.len());
-    let mut all_related_artists: Vec<&str> =
-        all_related_artists.iter().map(String::as_str).collect();
-    all_related_artists.sort_unstable();
-    all_related_artists.dedup();
-
-    let fetched =
-        get_multiple_related_artists(spotify_access_token.clone(), &all_related_artists).await?;
-    endpoint_response_time("crawl_related_artists").observe(start.elapsed().as_nanos() as u64);
-    Ok(status::Custom(
-        Status::Ok,
-        format!(
-            "Successfully fetched {} related artists to poulate related artists Redis hash",
-            fetched.len()
-        ),
-    ))
+    observe_endpoint_response_time("get_tracks_missing_previews", start.elapsed());
+    Ok(Json(filter_tracks_missing_previews(
+        tracks.into_iter().flatten(),
+    )))
 }
 
 pub(crate) struct UserAgent(String);
@@ -1071,18 +2791,28 @@ impl<'a, 'r> rocket::request::FromRequest<'r> for UserAgent {
     }
 }
 
-#[get("/search_artist?<q>")]
+/// Searches Spotify for artists by name, returning their Spotify IDs along with their internal IDs
+/// if they have one.  `count` is accepted as an alias for `limit`.
+#[get("/search_artist?<q>&<count>&<offset>&<limit>")]
 pub(crate) async fn search_artist(
     conn: DbConn,
     token_data: &State<Mutex<SpotifyTokenData>>,
     q: String,
+    count: Option<usize>,
+    offset: Option<usize>,
+    limit: Option<usize>,
     user_agent: UserAgent,
-) -> Result<Json<Vec<ArtistSearchResult>>, String> {
+) -> Result<Json<ArtistSearchResponse>, ApiError> {
     let start = Instant::now();
-    let spotify_access_token = {
-        let token_data = &mut *(&*token_data).lock().await;
-        token_data.get().await
-    }?;
+    let paginate = |search_results: Vec<ArtistSearchResult>| {
+        let total = search_results.len();
+        let results = search_results
+            .into_iter()
+            .skip(offset.unwrap_or(0))
+            .take(limit.or(count).unwrap_or(20).min(100))
+            .collect();
+        Json(ArtistSearchResponse { results, total })
+    };
 
     // First check cache
     let cached_item =
@@ -1097,7 +2827,7 @@ pub(crate) async fn search_artist(
 
     if let Some(cached_item) = cached_item {
         info!("Found hit in cache for artist search query={}", q);
-        return Ok(Json(cached_item));
+        return Ok(paginate(cached_item));
     }
 
     if user_agent.0.to_ascii_lowercase().starts_with("python") {
@@ -1105,66 +2835,244 @@ pub(crate) async fn search_artist(
             "Returning empty response for artist search query from Python user agent: ({}): {q}",
             user_agent.0
         );
-        return Ok(Json(Vec::new()));
+        return Ok(paginate(Vec::new()));
     }
 
+    let spotify_access_token = {
+        let token_data = &mut *(&*token_data).lock().await;
+        token_data.get().await
+    }
+    .map_err(ApiError::Upstream)?;
+
     // Hit the Spotify API and store in the cache
-    let search_results = search_artists(&conn, spotify_access_token, &q).await?;
-    set_hash_items::<Vec<ArtistSearchResult>>("artistSearch", &[(&q, search_results.clone())])
-        .map_err(|err| {
-            error!("Error storing artist search in cache: {}", err);
-            String::from("Internal error with cache")
-        })?;
+    let search_results = search_artists(&conn, spotify_access_token, &q, &CONF.spotify_market)
+        .await
+        .map_err(ApiError::Upstream)?;
+    block_in_place(|| {
+        set_hash_items::<Vec<ArtistSearchResult>>("artistSearch", &[(&q, search_results.clone())])
+    })
+    .map_err(|err| {
+        error!("Error storing artist search in cache: {}", err);
+        String::from("Internal error with cache")
+    })?;
     info!(
         "Successfully hit Spotify API for artist search query={:?} and stored in cache",
         q
     );
 
-    endpoint_response_time("search_artist").observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("search_artist", start.elapsed());
 
-    Ok(Json(search_results))
+    Ok(paginate(search_results))
 }
 
-#[get(
-    "/average_artists/<artist_1_spotify_id>/<artist_2_spotify_id>?<count>&<artist_1_bias>&\
-     <artist_2_bias>"
-)]
-pub(crate) async fn get_average_artists_route(
-    conn: DbConn,
-    artist_1_spotify_id: String,
-    artist_2_spotify_id: String,
-    count: Option<usize>,
-    artist_1_bias: Option<f32>,
-    artist_2_bias: Option<f32>,
-    token_data: &State<Mutex<SpotifyTokenData>>,
-) -> Result<Json<AverageArtistsResponse>, String> {
-    let start = Instant::now();
+/// Kinds of results that `/search` can return
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SearchKinds {
+    artists: bool,
+    tracks: bool,
+}
 
-    // Look up internal IDs for provided spotify IDs
-    let internal_ids_by_spotify_id = get_internal_ids_by_spotify_id(
-        &conn,
-        [artist_1_spotify_id.clone(), artist_2_spotify_id.clone()].iter(),
-    )
-    .await?;
-    let artist_1_id = match internal_ids_by_spotify_id.get(&artist_1_spotify_id) {
-        Some(id) => *id,
-        None => return Err(format!("No artist found with id={}", artist_1_spotify_id)),
+impl Default for SearchKinds {
+    fn default() -> Self {
+        SearchKinds {
+            artists: true,
+            tracks: false,
+        }
+    }
+}
+
+/// Parses a comma-separated list of result kinds such as `artist,track`
+fn parse_search_kinds(kinds: &str) -> Result<SearchKinds, ApiError> {
+    let mut parsed = SearchKinds {
+        artists: false,
+        tracks: false,
     };
-    let artist_2_id = match internal_ids_by_spotify_id.get(&artist_2_spotify_id) {
-        Some(id) => *id,
-        None => return Err(format!("No artist found with id={}", artist_2_spotify_id)),
+    for kind in kinds
+        .split(',')
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty())
+    {
+        match kind {
+            "artist" => parsed.artists = true,
+            "track" => parsed.tracks = true,
+            _ =>
+                return Err(ApiError::BadRequest(format!(
+                    "Invalid search kind `{}`; must be `artist` or `track`",
+                    kind
+                ))),
+        }
+    }
+    if !parsed.artists && !parsed.tracks {
+        return Ok(SearchKinds::default());
+    }
+    Ok(parsed)
+}
+
+/// Finds the artists and/or tracks whose names contain `query`, case-insensitively.  Results of
+/// both kinds are ranked together by play count, with ties broken by name.
+fn search_csv_data(
+    csv_data: &crate::csv_loader::CsvData,
+    query: &str,
+    kinds: SearchKinds,
+) -> Vec<SearchResult> {
+    let query = query.to_lowercase();
+    let mut matches: Vec<(SearchResult, usize)> = Vec::new();
+
+    if kinds.artists {
+        for artist in csv_data.artists.values() {
+            if !artist.name.to_lowercase().contains(&query) {
+                continue;
+            }
+
+            let play_count = csv_data
+                .artist_play_counts
+                .get(&artist.name)
+                .copied()
+                .unwrap_or(0);
+            let result = SearchResult::Artist(CsvArtistSearchResult {
+                id: artist.id.clone(),
+                name: artist.name.clone(),
+            });
+            matches.push((result, play_count));
+        }
+    }
+
+    if kinds.tracks {
+        for track in csv_data.tracks.values() {
+            if !track.name.to_lowercase().contains(&query) {
+                continue;
+            }
+
+            let artist_name = track
+                .artists
+                .first()
+                .map(|artist| artist.name.clone())
+                .unwrap_or_default();
+            let play_count = csv_data
+                .track_play_counts
+                .get(&(track.name.clone(), artist_name.clone()))
+                .copied()
+                .unwrap_or(0);
+            let result = SearchResult::Track(TrackSearchResult {
+                id: track.id.clone(),
+                name: track.name.clone(),
+                artist_name,
+            });
+            matches.push((result, play_count));
+        }
+    }
+
+    fn sort_key(result: &SearchResult) -> (&str, u8, &str) {
+        match result {
+            SearchResult::Artist(artist) => (&artist.name, 0, &artist.id),
+            SearchResult::Track(track) => (&track.name, 1, &track.id),
+        }
+    }
+    matches.sort_unstable_by(|(result_a, plays_a), (result_b, plays_b)| {
+        plays_b
+            .cmp(plays_a)
+            .then_with(|| sort_key(result_a).cmp(&sort_key(result_b)))
+    });
+    matches
+        .into_iter()
+        .map(|(result, _play_count)| result)
+        .collect()
+}
+
+/// Searches the loaded listening history for artists and/or tracks by name.  `kinds` is a
+/// comma-separated list of `artist` and `track` and defaults to just artists.  Results are tagged
+/// with their `kind` and ranked by play count across both kinds (uses CSV data).
+#[get("/search?<q>&<kinds>&<offset>&<limit>")]
+pub(crate) async fn search(
+    q: String,
+    kinds: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    let start = Instant::now();
+
+    let kinds = match kinds {
+        Some(kinds) => parse_search_kinds(&kinds)?,
+        None => SearchKinds::default(),
     };
-    let count = count.unwrap_or(10).min(50);
-    assert!(artist_1_id > 0);
-    assert!(artist_2_id > 0);
+    let csv_data = require_csv_data().await?;
 
-    let mut average_artists = match get_average_artists(
-        artist_1_id as usize,
-        artist_1_bias.unwrap_or(1.),
-        artist_2_id as usize,
-        artist_2_bias.unwrap_or(1.),
-        count,
-    ) {
+    let matches = search_csv_data(&csv_data, &q, kinds);
+    let total = matches.len();
+    let results = matches
+        .into_iter()
+        .skip(offset.unwrap_or(0))
+        .take(limit.unwrap_or(20).min(100))
+        .collect();
+    observe_endpoint_response_time("search", start.elapsed());
+
+    Ok(Json(SearchResponse { results, total }))
+}
+
+/// Number of average artists returned when no `count` is provided
+const DEFAULT_AVERAGE_ARTISTS_COUNT: usize = 10;
+
+/// Resolves the requested `count` of average artists, capping it at `max_count`.  A count of 0 is
+/// kept as-is so that the request can be answered with an empty list without doing any work.
+fn resolve_average_artists_count(count: Option<usize>, max_count: usize) -> usize {
+    count
+        .unwrap_or(DEFAULT_AVERAGE_ARTISTS_COUNT)
+        .min(max_count)
+}
+
+/// Drops average artists that are less similar to the averaged point than `min_similarity`.  This
+/// is the cosine similarity between positions in the artist embedding, the same metric that
+/// `ArtistEmbeddingContext::similarity` uses, so it ranges from -1 to 1.
+fn filter_by_min_similarity(
+    average_artists: &mut Vec<AverageArtistDescriptor>,
+    min_similarity: Option<f32>,
+) {
+    if let Some(min_similarity) = min_similarity {
+        average_artists.retain(|d| d.similarity_to_target_point >= min_similarity);
+    }
+}
+
+/// Collects the top tracks fetched for each artist, keyed by artist Spotify ID.  Artists whose top
+/// tracks failed to be fetched are left out, which drops them from the results rather than failing
+/// the whole request.  Returns whether any of the fetches failed.
+fn collect_top_tracks(
+    results: Vec<(String, Result<Vec<Track>, String>)>,
+) -> (HashMap<String, Vec<Track>>, bool) {
+    let mut partial = false;
+    let top_tracks_by_artist_spotify_id = results
+        .into_iter()
+        .filter_map(|(artist_spotify_id, res)| match res {
+            Ok(top_tracks) => Some((artist_spotify_id, top_tracks)),
+            Err(err) => {
+                warn!(
+                    "Error fetching top tracks for artist_spotify_id={}; dropping it from average \
+                     artists: {}",
+                    artist_spotify_id, err
+                );
+                partial = true;
+                None
+            },
+        })
+        .collect();
+    (top_tracks_by_artist_spotify_id, partial)
+}
+
+/// Finds the artists closest to the weighted centroid of the provided `(internal_id, bias)` seeds
+/// and fetches their metadata and top tracks from Spotify, sorted by descending score.  Returns the
+/// averaged artists along with whether some of them were dropped because their data failed to be
+/// fetched from Spotify.
+async fn compute_average_artists(
+    conn: &DbConn,
+    seeds: &[(usize, f32)],
+    count: usize,
+    min_similarity: Option<f32>,
+    token_data: &State<Mutex<SpotifyTokenData>>,
+) -> Result<(Vec<AverageArtistItem>, bool), String> {
+    if count == 0 {
+        return Ok((Vec::new(), false));
+    }
+
+    let mut average_artists = match get_weighted_average_artists(seeds, count) {
         Ok(res) => res,
         Err(err) => match err {
             ArtistEmbeddingError::ArtistIdNotFound(id) =>
@@ -1174,10 +3082,12 @@ pub(crate) async fn get_average_artists_route(
                 )),
         },
     };
+    // Filtered before fetching anything from Spotify so that we don't fetch artists we'll drop
+    filter_by_min_similarity(&mut average_artists, min_similarity);
 
     let all_artist_internal_ids: Vec<i32> = average_artists.iter().map(|d| d.id as i32).collect();
     let artist_spotify_ids_by_internal_id: HashMap<i32, String> =
-        get_artist_spotify_ids_by_internal_id(&conn, all_artist_internal_ids)
+        get_artist_spotify_ids_by_internal_id(conn, all_artist_internal_ids)
             .await
             .map_err(|err| {
                 error!(
@@ -1200,22 +3110,39 @@ pub(crate) async fn get_average_artists_route(
 
     let top_tracks_for_artists = FuturesUnordered::new();
     for artist_spotify_id in &all_spotify_ids {
-        let artist_spotify_id_clone = String::from(*artist_spotify_id);
-        top_tracks_for_artists.push(
-            fetch_top_tracks_for_artist(&spotify_access_token, artist_spotify_id)
-                .map_ok(move |res| (artist_spotify_id_clone, res)),
-        );
+        let spotify_access_token = &spotify_access_token;
+        top_tracks_for_artists.push(async move {
+            let res = fetch_top_tracks_for_artist(
+                spotify_access_token,
+                artist_spotify_id,
+                &CONF.spotify_market,
+            )
+            .await;
+            (String::from(*artist_spotify_id), res)
+        });
     }
 
-    let (top_tracks, fetched_artists) = tokio::try_join!(
-        top_tracks_for_artists.try_collect::<Vec<_>>(),
+    // Only a failure to fetch the artists themselves fails the request since nothing can be
+    // returned without them
+    let (top_tracks, fetched_artists) = tokio::join!(
+        top_tracks_for_artists.collect::<Vec<_>>(),
         fetch_artists(&spotify_access_token, &all_spotify_ids)
-    )?;
-    let mut top_tracks_by_artist_spotify_id: HashMap<String, Vec<Track>> =
-        top_tracks.into_iter().collect();
+    );
+    let fetched_artists = fetched_artists?;
+    let (mut top_tracks_by_artist_spotify_id, mut partial) = collect_top_tracks(top_tracks);
+    if CONF.preview_url_fallback {
+        fill_missing_preview_urls(
+            top_tracks_by_artist_spotify_id
+                .values_mut()
+                .map(|top_tracks| top_tracks.as_mut_slice())
+                .collect(),
+        )
+        .await;
+    }
 
     if fetched_artists.len() != average_artists.len() {
         assert!(fetched_artists.len() < average_artists.len());
+        partial = true;
         average_artists.retain(|d| {
             let avg_artist_spotify_id = match artist_spotify_ids_by_internal_id.get(&(d.id as i32))
             {
@@ -1298,17 +3225,72 @@ pub(crate) async fn get_average_artists_route(
                 artist,
                 top_tracks,
                 similarity_to_target_point: d.similarity_to_target_point,
-                similarity_to_artist_1: d.similarity_to_artist_1,
-                similarity_to_artist_2: d.similarity_to_artist_2,
+                similarity_to_artist_1: d.similarity_to_seeds.get(0).copied().unwrap_or(0.),
+                similarity_to_artist_2: d.similarity_to_seeds.get(1).copied().unwrap_or(0.),
+                similarity_to_seeds: d.similarity_to_seeds,
             })
         })
         .collect();
 
     out_artists.sort_unstable_by_key(|item| Reverse(item.score()));
 
+    Ok((out_artists, partial))
+}
+
+/// Averages two seed artists.  If `min_similarity` is provided, artists less similar than it to
+/// the averaged point are dropped from the results; see `filter_by_min_similarity`.
+///
+/// Each artist's top tracks without a preview URL are sorted last.  If `PREVIEW_URL_FALLBACK` is
+/// enabled, previews for them are first looked up on Deezer by ISRC.
+#[get(
+    "/average_artists/<artist_1_spotify_id>/<artist_2_spotify_id>?<count>&<artist_1_bias>&\
+     <artist_2_bias>&<min_similarity>"
+)]
+pub(crate) async fn get_average_artists_route(
+    conn: DbConn,
+    artist_1_spotify_id: String,
+    artist_2_spotify_id: String,
+    count: Option<usize>,
+    artist_1_bias: Option<f32>,
+    artist_2_bias: Option<f32>,
+    min_similarity: Option<f32>,
+    token_data: &State<Mutex<SpotifyTokenData>>,
+) -> Result<Json<AverageArtistsResponse>, String> {
+    let start = Instant::now();
+
+    // Look up internal IDs for provided spotify IDs
+    let internal_ids_by_spotify_id = get_internal_ids_by_spotify_id(
+        &conn,
+        [artist_1_spotify_id.clone(), artist_2_spotify_id.clone()].iter(),
+    )
+    .await?;
+    let artist_1_id = match internal_ids_by_spotify_id.get(&artist_1_spotify_id) {
+        Some(id) => *id,
+        None => return Err(format!("No artist found with id={}", artist_1_spotify_id)),
+    };
+    let artist_2_id = match internal_ids_by_spotify_id.get(&artist_2_spotify_id) {
+        Some(id) => *id,
+        None => return Err(format!("No artist found with id={}", artist_2_spotify_id)),
+    };
+    let count = resolve_average_artists_count(count, CONF.max_average_artists_count);
+    assert!(artist_1_id > 0);
+    assert!(artist_2_id > 0);
+
+    let (out_artists, partial) = compute_average_artists(
+        &conn,
+        &[
+            (artist_1_id as usize, artist_1_bias.unwrap_or(1.)),
+            (artist_2_id as usize, artist_2_bias.unwrap_or(1.)),
+        ],
+        count,
+        min_similarity,
+        token_data,
+    )
+    .await?;
+
     let ctx = get_artist_embedding_ctx();
 
-    endpoint_response_time("get_average_artists").observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("get_average_artists", start.elapsed());
 
     Ok(Json(AverageArtistsResponse {
         artists: out_artists,
@@ -1318,6 +3300,136 @@ pub(crate) async fn get_average_artists_route(
         similarity: ctx
             .similarity(artist_1_id as usize, artist_2_id as usize)
             .unwrap(),
+        partial,
+    }))
+}
+
+/// Averages any number of seed artists, each with an optional bias that defaults to 1.
+#[post("/average_artists", data = "<request>")]
+pub(crate) async fn get_weighted_average_artists_route(
+    conn: DbConn,
+    request: Json<AverageArtistsRequest>,
+    token_data: &State<Mutex<SpotifyTokenData>>,
+) -> Result<Json<WeightedAverageArtistsResponse>, String> {
+    let start = Instant::now();
+
+    let AverageArtistsRequest {
+        artists,
+        count,
+        min_similarity,
+    } = request.into_inner();
+    if artists.len() < 2 {
+        return Err(String::from(
+            "At least two artists must be provided to average",
+        ));
+    }
+
+    let internal_ids_by_spotify_id =
+        get_internal_ids_by_spotify_id(&conn, artists.iter().map(|seed| &seed.spotify_id)).await?;
+    let seeds = artists
+        .iter()
+        .map(
+            |seed| match internal_ids_by_spotify_id.get(&seed.spotify_id) {
+                Some(&id) => Ok((id as usize, seed.bias.unwrap_or(1.))),
+                None => Err(format!("No artist found with id={}", seed.spotify_id)),
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+    let count = resolve_average_artists_count(count, CONF.max_average_artists_count);
+
+    let (out_artists, partial) =
+        compute_average_artists(&conn, &seeds, count, min_similarity, token_data).await?;
+
+    observe_endpoint_response_time("get_weighted_average_artists", start.elapsed());
+
+    Ok(Json(WeightedAverageArtistsResponse {
+        artists: out_artists,
+        partial,
+    }))
+}
+
+/// Returns the time span, size, and version of the loaded CSV dataset
+#[get("/dataset_info")]
+pub(crate) async fn get_dataset_info() -> Result<Json<crate::csv_loader::DatasetInfo>, ApiError> {
+    let start = Instant::now();
+
+    let csv_data = require_csv_data().await?;
+
+    let dataset_info =
+        crate::csv_loader::calculate_dataset_info(&csv_data, CONF.genre_history_fill_gaps);
+    observe_endpoint_response_time("get_dataset_info", start.elapsed());
+    Ok(Json(dataset_info))
+}
+
+/// Sets an `X-Dataset-Version` header with the version of the loaded CSV data on responses from
+/// stats routes so that clients know when to invalidate their own caches
+pub(crate) struct DatasetVersionFairing;
+
+#[rocket::async_trait]
+impl Fairing for DatasetVersionFairing {
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let path = req.uri().path().as_str();
+        let path = path.strip_prefix("/api").unwrap_or(path);
+        if !path.starts_with("/stats/") {
+            return;
+        }
+
+        if let Some(csv_data) = crate::csv_loader::get_csv_data().await {
+            res.set_raw_header("X-Dataset-Version", csv_data.version.to_string());
+        }
+    }
+
+    fn info(&self) -> Info {
+        Info {
+            name: "Dataset Version Fairing",
+            kind: Kind::Response,
+        }
+    }
+}
+
+/// Returns the distance and similarity between two artists in the artist embedding without doing
+/// any averaging or fetching metadata from Spotify.
+#[get("/artist_similarity/<artist_1_spotify_id>/<artist_2_spotify_id>")]
+pub(crate) async fn get_artist_similarity(
+    conn: DbConn,
+    artist_1_spotify_id: String,
+    artist_2_spotify_id: String,
+) -> Result<Json<ArtistSimilarityResponse>, ApiError> {
+    let start = Instant::now();
+
+    let internal_ids_by_spotify_id = get_internal_ids_by_spotify_id(
+        &conn,
+        [artist_1_spotify_id.clone(), artist_2_spotify_id.clone()].iter(),
+    )
+    .await?;
+    let lookup_internal_id = |spotify_id: &String| {
+        internal_ids_by_spotify_id
+            .get(spotify_id)
+            .map(|&id| id as usize)
+            .ok_or_else(|| ApiError::NotFound(format!("No artist found with id={}", spotify_id)))
+    };
+    let artist_1_id = lookup_internal_id(&artist_1_spotify_id)?;
+    let artist_2_id = lookup_internal_id(&artist_2_spotify_id)?;
+
+    let ctx = get_artist_embedding_ctx();
+    let (distance, similarity) = match (
+        ctx.distance(artist_1_id, artist_2_id),
+        ctx.similarity(artist_1_id, artist_2_id),
+    ) {
+        (Ok(distance), Ok(similarity)) => (distance, similarity),
+        (Err(ArtistEmbeddingError::ArtistIdNotFound(id)), _)
+        | (_, Err(ArtistEmbeddingError::ArtistIdNotFound(id))) =>
+            return Err(ApiError::NotFound(format!(
+                "No artist found in embedding with internal id={}",
+                id
+            ))),
+    };
+
+    observe_endpoint_response_time("get_artist_similarity", start.elapsed());
+
+    Ok(Json(ArtistSimilarityResponse {
+        distance,
+        similarity,
     }))
 }
 
@@ -1325,25 +3437,50 @@ pub(crate) async fn get_average_artists_route(
 pub(crate) async fn get_artist_image_url(
     artist_spotify_id: String,
     token_data: &State<Mutex<SpotifyTokenData>>,
-) -> Result<String, String> {
+) -> Result<String, ApiError> {
     let start = Instant::now();
 
     let spotify_access_token = {
         let token_data = &mut *(&*token_data).lock().await;
         token_data.get().await
-    }?;
+    }
+    .map_err(ApiError::Upstream)?;
+
+    // Artists from CSV data have synthetic IDs, so their resolved Spotify IDs are used instead
+    let is_csv_artist = crate::csv_loader::get_csv_data()
+        .await
+        .is_some_and(|csv_data| csv_data.artists.contains_key(&artist_spotify_id));
+    let artist_spotify_id = if is_csv_artist {
+        block_in_place(|| crate::csv_loader::get_resolved_spotify_ids(&[&artist_spotify_id]))?
+            .into_iter()
+            .next()
+            .flatten()
+            .ok_or_else(|| {
+                ApiError::NotFound(format!(
+                    "CSV artist {} hasn't been resolved to a Spotify artist",
+                    artist_spotify_id
+                ))
+            })?
+    } else {
+        artist_spotify_id
+    };
 
     let artist: Option<Artist> = fetch_artists(&spotify_access_token, &[&artist_spotify_id])
-        .await?
+        .await
+        .map_err(ApiError::Upstream)?
         .into_iter()
         .next();
     let image = match artist
         .and_then(|artist| artist.images.and_then(|images| images.into_iter().next()))
     {
         Some(image) => image,
-        None => return Err(String::from("Not found")),
+        None =>
+            return Err(ApiError::NotFound(format!(
+                "No image found for artist {}",
+                artist_spotify_id
+            ))),
     };
-    endpoint_response_time("get_artist_image_url").observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("get_artist_image_url", start.elapsed());
     Ok(image.url)
 }
 
@@ -1426,8 +3563,7 @@ pub(crate) async fn refetch_cached_artists_missing_popularity(
         .collect();
     fetch_artists(&spotify_access_token, &artist_ids_needing_refetch).await?;
 
-    endpoint_response_time("refetch_cached_artists_missing_popularity")
-        .observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("refetch_cached_artists_missing_popularity", start.elapsed());
 
     Ok(status::Custom(
         Status::Ok,
@@ -1440,12 +3576,85 @@ pub(crate) async fn refetch_cached_artists_missing_popularity(
 
 /// Needed so that the MIME type on packed binary stuff that still should be compressed is picked up
 /// by the CDN as being compressable.
-#[derive(Responder)]
-#[response(status = 200, content_type = "application/json")]
+///
+/// Also sets an `ETag` derived from the packed bytes and responds with `304 Not Modified` if the
+/// request's `If-None-Match` header already matches it.  The body is gzipped if the client accepts
+/// it since the packed data compresses very well.
 pub(crate) struct JSONMimeTypeSetterResponder {
     inner: Vec<u8>,
 }
 
+impl JSONMimeTypeSetterResponder {
+    fn etag(&self) -> String {
+        let mut hasher = fnv::FnvHasher::default();
+        hasher.write(&self.inner);
+        format!("\"{:016x}\"", hasher.finish())
+    }
+}
+
+fn accepts_gzip(req: &Request<'_>) -> bool {
+    req.headers()
+        .get("Accept-Encoding")
+        .flat_map(|val| val.split(','))
+        .any(|encoding| {
+            let mut parts = encoding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let is_disabled = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.)
+            });
+            (name.eq_ignore_ascii_case("gzip") || name == "*") && !is_disabled
+        })
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+impl<'r> Responder<'r, 'static> for JSONMimeTypeSetterResponder {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let etag = self.etag();
+        let is_not_modified = req
+            .headers()
+            .get("If-None-Match")
+            .flat_map(|val| val.split(','))
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == etag || tag == "*");
+        if is_not_modified {
+            return Response::build()
+                .status(Status::NotModified)
+                .raw_header("ETag", etag)
+                .ok();
+        }
+
+        if accepts_gzip(req) {
+            match gzip(&self.inner) {
+                // The compressed bytes differ from the original, so the tag is only weakly valid
+                Ok(compressed) =>
+                    return Response::build_from(compressed.respond_to(req)?)
+                        .header(ContentType::JSON)
+                        .raw_header("Content-Encoding", "gzip")
+                        .raw_header("Vary", "Accept-Encoding")
+                        .raw_header("ETag", format!("W/{}", etag))
+                        .ok(),
+                Err(err) => error!("Error gzipping packed response: {:?}", err),
+            }
+        }
+
+        Response::build_from(self.inner.respond_to(req)?)
+            .header(ContentType::JSON)
+            .raw_header("Vary", "Accept-Encoding")
+            .raw_header("ETag", etag)
+            .ok()
+    }
+}
+
 #[get("/packed_3d_artist_coords")]
 pub(crate) async fn get_packed_3d_artist_coords_route(
     conn: DbConn,
@@ -1462,6 +3671,27 @@ pub(crate) async fn get_packed_3d_artist_coords_route(
     })
 }
 
+/// Returns the position of a single artist in the 3D artist map, or `null` if they're not in it.
+/// This lets the UI locate an artist without decoding the whole packed map.
+#[get("/artist_3d_coords/<artist_internal_id>")]
+pub(crate) async fn get_artist_3d_coords_route(
+    conn: DbConn,
+    token_data: &State<Mutex<SpotifyTokenData>>,
+    artist_internal_id: usize,
+) -> Result<Json<Option<ArtistCoords3d>>, String> {
+    let start = Instant::now();
+
+    let spotify_access_token = {
+        let token_data = &mut *(&*token_data).lock().await;
+        token_data.get().await
+    }?;
+
+    let map_ctx_3d = get_map_3d_artist_ctx(&conn, &spotify_access_token).await;
+    let coords = get_artist_3d_coords(map_ctx_3d, artist_internal_id);
+    observe_endpoint_response_time("get_artist_3d_coords", start.elapsed());
+    Ok(Json(coords))
+}
+
 #[post("/map_artist_data_by_internal_ids", data = "<artist_internal_ids>")]
 pub(crate) async fn get_artists_by_internal_ids(
     conn: DbConn,
@@ -1507,26 +3737,32 @@ pub(crate) async fn get_artists_by_internal_ids(
         })
         .collect();
 
-    endpoint_response_time("get_artists_by_internal_ids")
-        .observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("get_artists_by_internal_ids", start.elapsed());
 
     Ok(Json(res))
 }
 
-fn pack_artist_relationships(artist_relationships: Vec<Vec<i32>>) -> Vec<u8> {
+fn pack_artist_relationships(mut artist_relationships: Vec<Vec<i32>>) -> Vec<u8> {
     // Encoding:
     // artist count * u8: related artist count
     // 0-3 bytes of padding to make total byte count divisible by 4
-    // The rest: u32s, in order, for each artist.
+    // The rest: little-endian u32s, in order, for each artist.
     let mut packed: Vec<u8> = Vec::new();
-    for related_artists in &artist_relationships {
-        let artist_count = related_artists.len();
-        assert!(artist_count <= 255);
-        packed.push(artist_count as u8);
+    for related_artists in &mut artist_relationships {
+        // Related artist counts have to fit in a single byte
+        if related_artists.len() > u8::MAX as usize {
+            warn!(
+                "Artist has {} related artists which is more than the max of {}; truncating",
+                related_artists.len(),
+                u8::MAX
+            );
+            related_artists.truncate(u8::MAX as usize);
+        }
+        packed.push(related_artists.len() as u8);
     }
 
     // padding
-    let padding_byte_count = 4 - (packed.len() % 4);
+    let padding_byte_count = (4 - (packed.len() % 4)) % 4;
     for _ in 0..padding_byte_count {
         packed.push(0);
     }
@@ -1536,10 +3772,7 @@ fn pack_artist_relationships(artist_relationships: Vec<Vec<i32>>) -> Vec<u8> {
         // Might help with compression ratio, who knows
         related_artists.sort_unstable();
         for id in related_artists {
-            let bytes: [u8; 4] = unsafe { std::mem::transmute(id as u32) };
-            for byte in bytes {
-                packed.push(byte);
-            }
+            packed.extend_from_slice(&(id as u32).to_le_bytes());
         }
     }
     assert_eq!(packed.len() % 4, 0);
@@ -1574,8 +3807,17 @@ async fn get_packed_artist_relationships_by_internal_ids_inner(
         .collect::<Vec<_>>();
 
     let tok = start();
-    let related_artists =
-        get_multiple_related_artists(spotify_access_token, &artist_spotify_ids).await?;
+    // Artists whose related artists couldn't be fetched are treated as not having any
+    let related_artists: Vec<Vec<String>> = get_multiple_related_artists(
+        spotify_access_token,
+        &artist_spotify_ids,
+        DEFAULT_RELATED_ARTISTS_FETCH_CONCURRENCY,
+        DEFAULT_RELATED_ARTISTS_FETCH_BATCH_SIZE,
+    )
+    .await?
+    .into_iter()
+    .map(Option::unwrap_or_default)
+    .collect();
     mark(tok, "Got related artists");
     assert_eq!(related_artists.len(), artist_spotify_ids.len());
 
@@ -1629,15 +3871,20 @@ pub(crate) async fn get_packed_artist_relationships_by_internal_ids(
         artist_internal_ids,
     )
     .await?;
-    endpoint_response_time("get_packed_artist_relationships_by_internal_ids")
-        .observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time(
+        "get_packed_artist_relationships_by_internal_ids",
+        start.elapsed(),
+    );
     Ok(JSONMimeTypeSetterResponder { inner: packed })
 }
 
 lazy_static::lazy_static! {
+    /// Packed relationship chunks keyed by `(chunk_size, chunk_ix)`
     pub static ref ARTIST_RELATIONSHIPS_BY_INTERNAL_IDS_CACHE:
-        Arc<Mutex<HashMap<(u32, u32), Vec<u8>>>> =
-            Arc::new(Mutex::new(HashMap::default()));
+        Arc<Mutex<LruCache<(u32, u32), Vec<u8>>>> = Arc::new(Mutex::new(LruCache::new(
+            CONF.relationship_chunk_cache_capacity,
+            None,
+        )));
 }
 
 #[get("/map_artist_relationships_chunk?<chunk_size>&<chunk_ix>")]
@@ -1688,12 +3935,218 @@ pub(crate) async fn get_artist_relationships_chunk(
         cache.insert(cache_key, packed.clone());
     }
 
-    endpoint_response_time("get_artist_relationships_chunk")
-        .observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("get_artist_relationships_chunk", start.elapsed());
 
     Ok(JSONMimeTypeSetterResponder { inner: packed })
 }
 
+/// Computes and caches the relationship chunk for `chunk` if it isn't already cached.  Returns
+/// `true` if the chunk was computed.
+async fn warm_relationship_chunk(
+    conn: &DbConn,
+    spotify_access_token: String,
+    cache_key: (u32, u32),
+    chunk: &[usize],
+) -> Result<bool, String> {
+    if ARTIST_RELATIONSHIPS_BY_INTERNAL_IDS_CACHE
+        .lock()
+        .await
+        .get(&cache_key)
+        .is_some()
+    {
+        return Ok(false);
+    }
+
+    let artist_internal_ids = chunk.iter().map(|&id| id as i32).collect();
+    let packed = get_packed_artist_relationships_by_internal_ids_inner(
+        conn,
+        spotify_access_token,
+        artist_internal_ids,
+    )
+    .await?;
+    ARTIST_RELATIONSHIPS_BY_INTERNAL_IDS_CACHE
+        .lock()
+        .await
+        .insert(cache_key, packed);
+    Ok(true)
+}
+
+/// Computes every artist relationship chunk of size `chunk_size` and stores them in the cache so
+/// that users loading the 3D map never hit a cold chunk.  Chunks that are already cached are
+/// skipped.  Up to one chunk is computed at a time on each of the database connections.
+#[post("/warm_relationship_chunks?<chunk_size>", data = "<api_token_data>")]
+pub(crate) async fn warm_relationship_chunks(
+    api_token_data: rocket::Data<'_>,
+    conn1: DbConn,
+    conn2: DbConn,
+    conn3: DbConn,
+    conn4: DbConn,
+    token_data: &State<Mutex<SpotifyTokenData>>,
+    chunk_size: u32,
+) -> Result<status::Custom<String>, String> {
+    let start = Instant::now();
+    if !validate_api_token(api_token_data).await? {
+        return Ok(status::Custom(
+            Status::Unauthorized,
+            "Invalid API token supplied".into(),
+        ));
+    }
+    if chunk_size == 0 {
+        return Ok(status::Custom(
+            Status::BadRequest,
+            "`chunk_size` must be greater than 0".into(),
+        ));
+    }
+
+    let spotify_access_token = {
+        let token_data = &mut *(&*token_data).lock().await;
+        token_data.get().await
+    }?;
+
+    let sorted_artist_ids = &get_map_3d_artist_ctx(&conn1, &spotify_access_token)
+        .await
+        .sorted_artist_ids;
+    let chunks: Vec<&[usize]> = sorted_artist_ids.chunks(chunk_size as usize).collect();
+    let chunk_count = chunks.len();
+    if chunk_count > CONF.relationship_chunk_cache_capacity {
+        warn!(
+            "Warming {} relationship chunks, but the cache only holds {}",
+            chunk_count, CONF.relationship_chunk_cache_capacity
+        );
+    }
+
+    // Each connection takes the next chunk that hasn't been started yet until they're all done
+    let next_chunk_ix = AtomicUsize::new(0);
+    let warm_chunks = |conn: DbConn| {
+        let (chunks, next_chunk_ix) = (&chunks, &next_chunk_ix);
+        let spotify_access_token = &spotify_access_token;
+        async move {
+            let mut warmed_chunk_count = 0usize;
+            loop {
+                let chunk_ix = next_chunk_ix.fetch_add(1, Ordering::Relaxed);
+                let chunk = match chunks.get(chunk_ix) {
+                    Some(chunk) => chunk,
+                    None => break,
+                };
+                let cache_key = (chunk_size, chunk_ix as u32);
+                let warmed =
+                    warm_relationship_chunk(&conn, spotify_access_token.clone(), cache_key, chunk)
+                        .await?;
+                warmed_chunk_count += warmed as usize;
+            }
+            Ok::<_, String>(warmed_chunk_count)
+        }
+    };
+    let warmed_chunk_count: usize =
+        futures::future::try_join_all([conn1, conn2, conn3, conn4].into_iter().map(warm_chunks))
+            .await?
+            .into_iter()
+            .sum();
+
+    observe_endpoint_response_time("warm_relationship_chunks", start.elapsed());
+    Ok(status::Custom(
+        Status::Ok,
+        format!(
+            "Warmed {} of {} relationship chunks with chunk_size={}",
+            warmed_chunk_count, chunk_count, chunk_size
+        ),
+    ))
+}
+
+/// Maximum number of Spotify artist searches run at once when resolving CSV artist IDs
+const CSV_ARTIST_RESOLUTION_CONCURRENCY: usize = 4;
+
+/// Searches Spotify for each artist in the loaded CSV data that doesn't have a resolved Spotify ID
+/// yet and stores the ID of the best match.  Ambiguous matches with a confidence that isn't above
+/// `MIN_ARTIST_MATCH_CONFIDENCE` are skipped.
+#[post("/resolve_csv_artist_ids", data = "<api_token_data>")]
+pub(crate) async fn resolve_csv_artist_ids(
+    api_token_data: rocket::Data<'_>,
+    conn: DbConn,
+    token_data: &State<Mutex<SpotifyTokenData>>,
+) -> Result<status::Custom<String>, String> {
+    let start = Instant::now();
+    if !validate_api_token(api_token_data).await? {
+        return Ok(status::Custom(
+            Status::Unauthorized,
+            "Invalid API token supplied".into(),
+        ));
+    }
+
+    let csv_data = crate::csv_loader::get_csv_data()
+        .await
+        .ok_or_else(|| "CSV data not loaded".to_string())?;
+    let spotify_access_token = {
+        let token_data = &mut *(&*token_data).lock().await;
+        token_data.get().await
+    }?;
+
+    let artists: Vec<&Artist> = csv_data.artists.values().collect();
+    let artist_ids: Vec<&str> = artists.iter().map(|artist| artist.id.as_str()).collect();
+    let resolved_spotify_ids =
+        block_in_place(|| crate::csv_loader::get_resolved_spotify_ids(&artist_ids))?;
+    let unresolved_artists: Vec<&Artist> = artists
+        .into_iter()
+        .zip(resolved_spotify_ids)
+        .filter(|(_artist, resolved_spotify_id)| resolved_spotify_id.is_none())
+        .map(|(artist, _resolved_spotify_id)| artist)
+        .collect();
+    let search_results: Vec<_> = futures::stream::iter(unresolved_artists.iter())
+        .map(|artist| {
+            search_artists(
+                &conn,
+                spotify_access_token.clone(),
+                &artist.name,
+                &CONF.spotify_market,
+            )
+            .map_ok(move |results| (*artist, results))
+        })
+        .buffer_unordered(CSV_ARTIST_RESOLUTION_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut resolved_ids = HashMap::default();
+    let (mut low_confidence_count, mut failed_count) = (0usize, 0usize);
+    for res in search_results {
+        let (artist, results) = match res {
+            Ok(res) => res,
+            Err(err) => {
+                error!("Error searching Spotify for CSV artist: {}", err);
+                failed_count += 1;
+                continue;
+            },
+        };
+
+        match crate::spotify_api::pick_artist_search_match(&artist.name, &results) {
+            Some((spotify_id, confidence)) if confidence > MIN_ARTIST_MATCH_CONFIDENCE => {
+                resolved_ids.insert(artist.id.clone(), spotify_id);
+            },
+            Some((spotify_id, confidence)) => {
+                info!(
+                    "Skipping ambiguous Spotify match for CSV artist {:?}: id={}, confidence={}",
+                    artist.name, spotify_id, confidence
+                );
+                low_confidence_count += 1;
+            },
+            None => low_confidence_count += 1,
+        }
+    }
+    block_in_place(|| crate::csv_loader::set_resolved_spotify_ids(&resolved_ids))?;
+    let resolved_count = resolved_ids.len();
+
+    observe_endpoint_response_time("resolve_csv_artist_ids", start.elapsed());
+    Ok(status::Custom(
+        Status::Ok,
+        format!(
+            "Resolved {} of {} unresolved CSV artists; {} had no confident match and {} failed",
+            resolved_count,
+            unresolved_artists.len(),
+            low_confidence_count,
+            failed_count
+        ),
+    ))
+}
+
 #[get("/get_preview_urls_by_internal_id/<artist_internal_id>")]
 pub(crate) async fn get_preview_urls_by_internal_id(
     conn: DbConn,
@@ -1723,10 +4176,11 @@ pub(crate) async fn get_preview_urls_by_internal_id(
         None => return Ok(Json(None)),
     };
 
-    let top_tracks = fetch_top_tracks_for_artist(&spotify_access_token, &spotify_id).await?;
+    let top_tracks =
+        fetch_top_tracks_for_artist(&spotify_access_token, &spotify_id, &CONF.spotify_market)
+            .await?;
 
-    endpoint_response_time("get_preview_urls_by_internal_id")
-        .observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("get_preview_urls_by_internal_id", start.elapsed());
 
     if top_tracks.is_empty() {
         return Ok(Json(None));
@@ -1761,8 +4215,7 @@ pub(crate) async fn get_top_artists_internal_ids_for_user(
             String::from("Internal DB error")
         })?;
 
-    endpoint_response_time("get_top_artists_internal_ids_for_user")
-        .observe(start.elapsed().as_nanos() as u64);
+    observe_endpoint_response_time("get_top_artists_internal_ids_for_user", start.elapsed());
 
     Ok(Some(Json(
         top_artists
@@ -1772,6 +4225,11 @@ pub(crate) async fn get_top_artists_internal_ids_for_user(
     )))
 }
 
+/// Returns `true` if a warning should be logged before transferring `user`'s data to external
+/// storage, which is the case when their external data has already been retrieved.  This matches
+/// the check made before retrieving it in `transfer_user_data_from_external_storage`.
+fn should_warn_before_external_transfer(user: &User) -> bool { user.external_data_retrieved }
+
 #[post(
     "/transfer_user_data_to_external_storage/<user_id>",
     data = "<api_token_data>"
@@ -1795,7 +4253,7 @@ pub(crate) async fn transfer_user_data_to_external_storage(
         },
     };
 
-    if !user.external_data_retrieved {
+    if should_warn_before_external_transfer(&user) {
         warn!(
             "User {} already has external user data stored; downloading + merging and re-storing \
              everything...",
@@ -1846,8 +4304,19 @@ pub(crate) async fn transfer_user_data_from_external_storage(
     Ok(status::Custom(Status::Ok, String::new()))
 }
 
+#[derive(Serialize)]
+pub(crate) struct BulkTransferCandidate {
+    pub spotify_id: String,
+    pub last_viewed: NaiveDateTime,
+    pub last_external_data_store: NaiveDateTime,
+}
+
+/// Transfers data for up to `user_count` users who haven't viewed their profile recently to
+/// external storage.  If `dry_run` is set, the users that would be transferred are returned without
+/// transferring anything.
 #[post(
-    "/bulk_transfer_user_data_to_external_storage/<user_count>?<only_already_stored>&<concurrency>",
+    "/bulk_transfer_user_data_to_external_storage/<user_count>?<only_already_stored>&\
+     <concurrency>&<dry_run>",
     data = "<api_token_data>"
 )]
 pub(crate) async fn bulk_transfer_user_data_to_external_storage(
@@ -1860,12 +4329,13 @@ pub(crate) async fn bulk_transfer_user_data_to_external_storage(
     user_count: u32,
     only_already_stored: Option<bool>,
     concurrency: Option<usize>,
-) -> Result<status::Custom<String>, String> {
+    dry_run: Option<bool>,
+) -> Result<Either<status::Custom<String>, Json<Vec<BulkTransferCandidate>>>, String> {
     if !validate_api_token(api_token_data).await? {
-        return Ok(status::Custom(
+        return Ok(Either::Left(status::Custom(
             Status::Unauthorized,
             "Invalid API token supplied".into(),
-        ));
+        )));
     }
 
     // Only transfer data for users that haven't viewed their profile in the past 4 months
@@ -1891,6 +4361,19 @@ pub(crate) async fn bulk_transfer_user_data_to_external_storage(
             error!("Error getting users from DB for bulk transfer: {err:?}");
             String::from("Internal DB error")
         })?;
+
+    if dry_run == Some(true) {
+        let candidates = users
+            .into_iter()
+            .map(|user| BulkTransferCandidate {
+                spotify_id: user.spotify_id,
+                last_viewed: user.last_viewed,
+                last_external_data_store: user.last_external_data_store,
+            })
+            .collect();
+        return Ok(Either::Right(Json(candidates)));
+    }
+
     let usernames = users
         .iter()
         .map(|user| user.spotify_id.clone())
@@ -1903,7 +4386,7 @@ pub(crate) async fn bulk_transfer_user_data_to_external_storage(
         .for_each_concurrent(Some(concurrency), |user| {
             let conns = Arc::clone(&conns);
             async move {
-                if !user.external_data_retrieved {
+                if should_warn_before_external_transfer(&user) {
                     warn!(
                         "User {} already has external user data stored; downloading + merging and \
                          re-storing everything...",
@@ -1937,5 +4420,563 @@ pub(crate) async fn bulk_transfer_user_data_to_external_storage(
         })
         .await;
 
-    Ok(status::Custom(Status::Ok, String::new()))
+    Ok(Either::Left(status::Custom(Status::Ok, String::new())))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::{Header, Status},
+        local::{asynchronous::Client as AsyncClient, blocking::Client},
+    };
+
+    use chrono::NaiveDate;
+
+    use super::{
+        add_co_listen_weights, build_artist_summary, build_artists_stats, collect_top_tracks,
+        compare_named_datasets, count_invalid_related_artists_entries,
+        drop_unknown_related_artists, filter_by_min_similarity, pack_artist_relationships,
+        parse_search_kinds, parse_timeline_day_range, resolve_average_artists_count,
+        search_csv_data, should_warn_before_external_transfer, upload_comparison_csv,
+        user_update_delay, ApiError, JSONMimeTypeSetterResponder, SearchKinds,
+        DEFAULT_AVERAGE_ARTISTS_COUNT, MAX_USER_UPDATE_DELAY,
+    };
+    use crate::{
+        artist_embedding::AverageArtistDescriptor,
+        conf::CONF,
+        csv_loader::parse_csv,
+        models::{RelatedArtistsGraph, SearchResult, Track, User},
+    };
+
+    #[get("/packed")]
+    fn packed() -> JSONMimeTypeSetterResponder {
+        JSONMimeTypeSetterResponder {
+            inner: vec![1, 2, 3, 4],
+        }
+    }
+
+    #[get("/bad_request")]
+    fn bad_request() -> Result<&'static str, ApiError> {
+        Err(ApiError::BadRequest("Invalid `from` provided".to_string()))
+    }
+
+    #[test]
+    fn test_api_error_response_body() {
+        let client = Client::tracked(rocket::build().mount("/", routes![bad_request])).unwrap();
+
+        let res = client.get("/bad_request").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+        let body: serde_json::Value = res.into_json().expect("Response body should be JSON");
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": { "code": "bad_request", "message": "Invalid `from` provided" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_packed_etag_not_modified() {
+        let client = Client::tracked(rocket::build().mount("/", routes![packed])).unwrap();
+
+        let res = client.get("/packed").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        let etag = res
+            .headers()
+            .get_one("ETag")
+            .expect("Missing ETag header")
+            .to_owned();
+        assert_eq!(res.into_bytes(), Some(vec![1, 2, 3, 4]));
+
+        let res = client
+            .get("/packed")
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        assert_eq!(res.status(), Status::NotModified);
+        assert_eq!(res.headers().get_one("ETag"), Some(etag.as_str()));
+
+        let res = client
+            .get("/packed")
+            .header(Header::new("If-None-Match", "\"0000000000000000\""))
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    /// Decodes the output of `pack_artist_relationships` back into related artist IDs
+    fn unpack_artist_relationships(packed: &[u8], artist_count: usize) -> Vec<Vec<u32>> {
+        assert_eq!(packed.len() % 4, 0);
+        let counts = &packed[..artist_count];
+        let ids_start = artist_count + (4 - artist_count % 4) % 4;
+        let mut ids = packed[ids_start..]
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+
+        counts
+            .iter()
+            .map(|&count| ids.by_ref().take(count as usize).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_pack_artist_relationships_truncates_overflow() {
+        let relationships = vec![(1..=300).rev().collect::<Vec<i32>>(), vec![7, 3]];
+
+        let packed = pack_artist_relationships(relationships);
+        // 2 count bytes + 2 bytes of padding + 257 IDs
+        assert_eq!(packed.len(), 4 + 257 * 4);
+        let unpacked = unpack_artist_relationships(&packed, 2);
+        assert_eq!(unpacked[0], (46..=300).collect::<Vec<u32>>());
+        assert_eq!(unpacked[1], vec![3, 7]);
+    }
+
+    #[test]
+    fn test_pack_artist_relationships_aligned_has_no_padding() {
+        let relationships = vec![vec![1], vec![2, 3], vec![], vec![4]];
+
+        let packed = pack_artist_relationships(relationships);
+        assert_eq!(packed.len(), 4 + 4 * 4);
+        assert_eq!(&packed[..4], &[1, 2, 0, 1]);
+        assert_eq!(unpack_artist_relationships(&packed, 4), vec![
+            vec![1],
+            vec![2, 3],
+            vec![],
+            vec![4]
+        ]);
+    }
+
+    #[test]
+    fn test_pack_artist_relationships_byte_format() {
+        let relationships = vec![vec![0x0102_0304, 1], vec![256]];
+
+        let packed = pack_artist_relationships(relationships);
+        assert_eq!(packed, vec![
+            2, 1, 0, 0, // related artist counts + padding
+            1, 0, 0, 0, // 1
+            4, 3, 2, 1, // 0x01020304
+            0, 1, 0, 0, // 256
+        ]);
+    }
+
+    #[test]
+    fn test_timeline_day_range_includes_boundary_days() {
+        let at = |month: u32, day: u32, hour: u32| {
+            NaiveDate::from_ymd_opt(2023, month, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+        };
+
+        let (start, end) = parse_timeline_day_range("2023-03-01", "2023-03-31", 0).unwrap();
+        assert_eq!((start, end), (at(3, 1, 0), at(4, 1, 0)));
+        // A play late in the evening of the last day is still within the range
+        let last_evening = at(3, 31, 23);
+        assert!(start <= last_evening && last_evening < end);
+
+        // Day boundaries depend only on the provided offset, not the server's timezone
+        let (start, end) = parse_timeline_day_range("2023-03-01", "2023-03-01", -300).unwrap();
+        assert_eq!((start, end), (at(3, 1, 5), at(3, 2, 5)));
+
+        assert!(parse_timeline_day_range("2023-03-01T08:00:00", "2023-03-02", 0).is_err());
+    }
+
+    #[test]
+    fn test_user_update_delay() {
+        let base = std::time::Duration::from_millis(1000);
+        let jitter = std::time::Duration::from_millis(500);
+
+        assert_eq!(user_update_delay(base, jitter, 0, 0.), base);
+        assert_eq!(
+            user_update_delay(base, jitter, 0, 0.5),
+            std::time::Duration::from_millis(1250)
+        );
+        // Backs off exponentially while rate limited
+        assert_eq!(
+            user_update_delay(base, jitter, 2, 0.),
+            std::time::Duration::from_millis(4000)
+        );
+        assert_eq!(
+            user_update_delay(base, jitter, 40, 0.),
+            MAX_USER_UPDATE_DELAY
+        );
+        assert_eq!(
+            user_update_delay(base, jitter, 40, 1.),
+            MAX_USER_UPDATE_DELAY + jitter
+        );
+    }
+
+    #[test]
+    fn test_resolve_average_artists_count() {
+        assert_eq!(
+            resolve_average_artists_count(None, 50),
+            DEFAULT_AVERAGE_ARTISTS_COUNT
+        );
+        // A count of 0 is answered with an empty list
+        assert_eq!(resolve_average_artists_count(Some(0), 50), 0);
+        assert_eq!(resolve_average_artists_count(Some(1), 50), 1);
+        assert_eq!(resolve_average_artists_count(Some(1000), 50), 50);
+        assert_eq!(resolve_average_artists_count(Some(1000), 20), 20);
+    }
+
+    #[test]
+    fn test_min_similarity_trims_average_artists() {
+        let average_artists = [0.9, 0.75, 0.4, 0.1]
+            .iter()
+            .enumerate()
+            .map(|(id, &similarity)| AverageArtistDescriptor {
+                id,
+                similarity_to_target_point: similarity,
+                similarity_to_seeds: vec![similarity, similarity],
+            })
+            .collect::<Vec<_>>();
+
+        let mut unfiltered = average_artists.clone();
+        filter_by_min_similarity(&mut unfiltered, None);
+        assert_eq!(unfiltered.len(), 4);
+
+        let mut filtered = average_artists;
+        filter_by_min_similarity(&mut filtered, Some(0.7));
+        let ids: Vec<usize> = filtered.iter().map(|d| d.id).collect();
+        assert_eq!(ids, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_count_invalid_related_artists_entries() {
+        let related_artists_jsons = [
+            r#"["4Z8W4fKeB5YxbusRsdQVPb","3AA28KZvwAUcZuOKwyblJQ"]"#,
+            "[]",
+            r#"{"artists":[]}"#,
+            "not json",
+            "[1,2]",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            count_invalid_related_artists_entries(&related_artists_jsons),
+            3
+        );
+        assert_eq!(count_invalid_related_artists_entries(&[]), 0);
+    }
+
+    #[test]
+    fn test_filter_tracks_missing_previews() {
+        let track = |id: &str, preview_url: Option<&str>| Track {
+            id: id.to_owned(),
+            preview_url: preview_url.map(str::to_owned),
+            ..Track::new_unknown()
+        };
+        let tracks = vec![
+            track("with_preview", Some("https://p.scdn.co/mp3-preview/a")),
+            track("missing_1", None),
+            // Placeholder for a track that Spotify didn't return
+            Track::new_unknown(),
+            track("missing_2", None),
+        ];
+
+        let missing: Vec<String> = filter_tracks_missing_previews(tracks)
+            .into_iter()
+            .map(|track| track.id)
+            .collect();
+        assert_eq!(missing, ["missing_1", "missing_2"]);
+    }
+
+    #[test]
+    fn test_failed_top_tracks_fetch_drops_only_that_artist() {
+        let track = |id: &str| Track {
+            id: id.to_owned(),
+            ..Track::new_unknown()
+        };
+        let results = vec![
+            ("artist_a".to_owned(), Ok(vec![track("a1"), track("a2")])),
+            (
+                "artist_b".to_owned(),
+                Err("Spotify returned 502".to_owned()),
+            ),
+            ("artist_c".to_owned(), Ok(vec![track("c1")])),
+        ];
+
+        let (top_tracks_by_artist_spotify_id, partial) = collect_top_tracks(results);
+        assert!(partial);
+        assert_eq!(top_tracks_by_artist_spotify_id.len(), 2);
+        assert_eq!(top_tracks_by_artist_spotify_id["artist_a"].len(), 2);
+        assert_eq!(top_tracks_by_artist_spotify_id["artist_c"][0].id, "c1");
+        assert!(!top_tracks_by_artist_spotify_id.contains_key("artist_b"));
+
+        let (_, partial) = collect_top_tracks(vec![("artist_a".to_owned(), Ok(vec![track("a1")]))]);
+        assert!(!partial);
+    }
+
+    #[test]
+    fn test_build_artists_stats() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,120000,rock,rock
+2023-01-01T10:05:00Z,Song B,Artist One,60000,rock,rock
+2023-01-01T10:10:00Z,Song A,Artist One,180000,rock,rock
+2023-01-01T10:15:00Z,Song C,Artist Two,200000,pop,pop
+2023-01-01T10:20:00Z,Song D,Artist Three,30000,jazz,jazz
+";
+        let csv_data = parse_csv(csv.as_bytes()).expect("CSV should parse");
+        let artist_ids = ["csv_artist_one", "csv_artist_two", "csv_artist_three"];
+
+        let stats = build_artists_stats(&csv_data, &artist_ids, None);
+        assert_eq!(stats.len(), 3);
+        let artist_one = &stats["csv_artist_one"];
+        assert_eq!(artist_one.artist.name, "Artist One");
+        let play_counts: Vec<usize> = artist_one
+            .top_tracks
+            .iter()
+            .map(|(_track_id, play_count)| *play_count)
+            .collect();
+        assert_eq!(play_counts, [2, 1]);
+        assert_eq!(artist_one.tracks_by_id.len(), 2);
+        assert_eq!(stats["csv_artist_two"].top_tracks.len(), 1);
+
+        let stats = build_artists_stats(&csv_data, &["csv_artist_one", "csv_missing"], Some(1));
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats["csv_artist_one"].top_tracks.len(), 1);
+        assert_eq!(stats["csv_artist_one"].tracks_by_id.len(), 1);
+    }
+
+    #[test]
+    fn test_build_artist_summary() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,120000,rock,rock
+2023-01-01T10:05:00Z,Song B,Artist One,60000,rock,rock
+2023-01-01T10:10:00Z,Song A,Artist One,180000,rock,rock
+2023-01-01T10:15:00Z,Song C,Artist Two,200000,pop,pop
+";
+        let csv_data = parse_csv(csv.as_bytes()).expect("CSV should parse");
+
+        let summary = build_artist_summary(&csv_data, "csv_artist_one").unwrap();
+        assert_eq!(summary.artist.name, "Artist One");
+        assert_eq!(summary.total_plays, 3);
+        assert_eq!(summary.total_ms, 360_000);
+        assert_eq!(summary.top_tracks, [
+            ("Song A".to_string(), 2),
+            ("Song B".to_string(), 1)
+        ]);
+        assert!(build_artist_summary(&csv_data, "csv_missing").is_none());
+    }
+
+    #[test]
+    fn test_search_matches_track_names() {
+        let csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Midnight City,M83,120000,electronic,electronic
+2023-01-01T10:05:00Z,Midnight City,M83,120000,electronic,electronic
+2023-01-01T10:10:00Z,Wait,M83,120000,electronic,electronic
+2023-01-01T10:15:00Z,Song C,Midnight Oil,200000,rock,rock
+";
+        let csv_data = parse_csv(csv.as_bytes()).expect("CSV should parse");
+
+        let artist_only = search_csv_data(&csv_data, "midnight", SearchKinds::default());
+        assert_eq!(artist_only.len(), 1);
+        assert!(matches!(
+            &artist_only[0],
+            SearchResult::Artist(artist) if artist.name == "Midnight Oil"
+        ));
+
+        let kinds = parse_search_kinds("artist,track").unwrap();
+        let results = search_csv_data(&csv_data, "midnight", kinds);
+        assert_eq!(results.len(), 2);
+        // The track has been played more than the artist, so it comes first
+        match &results[0] {
+            SearchResult::Track(track) => {
+                assert_eq!(
+                    track.id,
+                    crate::csv_loader::track_id("Midnight City", "M83")
+                );
+                assert_eq!(track.name, "Midnight City");
+                assert_eq!(track.artist_name, "M83");
+            },
+            _ => panic!("expected a track result"),
+        }
+        assert_eq!(
+            serde_json::to_value(&results[0]).unwrap()["kind"],
+            serde_json::json!("track")
+        );
+
+        assert!(parse_search_kinds("album").is_err());
+        assert_eq!(parse_search_kinds("").unwrap(), SearchKinds::default());
+    }
+
+    #[tokio::test]
+    async fn test_upload_comparison_csvs() {
+        let user1_csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Shared Song,Shared Artist,120000,rock,rock
+2023-01-02T10:00:00Z,Only Mine,Artist One,60000,rock,rock
+";
+        let user2_csv = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T12:00:00Z,Shared Song,Shared Artist,200000,rock,rock
+2023-01-03T10:00:00Z,Only Theirs,Artist Two,60000,jazz,jazz
+";
+        let rocket = rocket::build().mount("/", routes![upload_comparison_csv]);
+        let client = AsyncClient::tracked(rocket).await.unwrap();
+
+        let res = client
+            .post("/upload_comparison_csv/__test_upload_one")
+            .body(format!("wrong token\n{}", user1_csv))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Unauthorized);
+        assert!(
+            compare_named_datasets("__test_upload_one", "__test_upload_two")
+                .await
+                .is_none()
+        );
+
+        for (username, csv) in [
+            ("__test_upload_one", user1_csv),
+            ("__test_upload_two", user2_csv),
+        ] {
+            let res = client
+                .post(format!("/upload_comparison_csv/{}", username))
+                .body(format!("{}\n{}", CONF.admin_api_token, csv))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::Ok);
+        }
+
+        let comparison = compare_named_datasets("__test_upload_one", "__test_upload_two")
+            .await
+            .expect("Both datasets should be registered");
+        let track_names: Vec<&str> = comparison.tracks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(track_names, ["Shared Song"]);
+        let artist_names: Vec<&str> = comparison.artists.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(artist_names, ["Shared Artist"]);
+    }
+
+    #[test]
+    fn test_should_warn_before_external_transfer() {
+        let timestamp = NaiveDate::from_ymd_opt(2023, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let user = |external_data_retrieved| User {
+            id: 1,
+            creation_time: timestamp,
+            last_update_time: timestamp,
+            spotify_id: "user".to_string(),
+            username: "user".to_string(),
+            token: String::new(),
+            refresh_token: String::new(),
+            external_data_retrieved,
+            last_viewed: timestamp,
+            last_external_data_store: timestamp,
+        };
+
+        assert!(should_warn_before_external_transfer(&user(true)));
+        assert!(!should_warn_before_external_transfer(&user(false)));
+    }
+
+    const CO_LISTEN_CSV: &str = "ts,Track Name,Artist Name(s),ms_played,Genres,Artist Genres
+2023-01-01T10:00:00Z,Song A,Artist One,120000,rock,rock
+2023-01-01T10:05:00Z,Song B,Artist Two,60000,pop,pop
+2023-01-01T10:10:00Z,Song A,Artist One,180000,rock,rock
+2023-01-01T10:15:00Z,Song C,Artist Three,200000,jazz,
+2023-01-01T10:20:00Z,Song B,Artist Two,30000,pop,pop
+";
+
+    fn related_artists_graph(related_artists: &[(&str, &[&str])]) -> RelatedArtistsGraph {
+        RelatedArtistsGraph {
+            extra_artists: Default::default(),
+            related_artists: related_artists
+                .iter()
+                .map(|(id, related_ids)| {
+                    let related_ids = related_ids.iter().map(|id| id.to_string()).collect();
+                    (id.to_string(), related_ids)
+                })
+                .collect(),
+            related_artist_weights: Default::default(),
+            related_artist_counts: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_co_listen_weights() {
+        let csv_data = parse_csv(CO_LISTEN_CSV.as_bytes()).expect("CSV should parse");
+        let graph = |min_weight: u32| {
+            let mut graph = related_artists_graph(&[
+                ("csv_artist_one", &[
+                    "csv_artist_two",
+                    "csv_artist_three",
+                    "spotify_only",
+                ]),
+                ("spotify_source", &["csv_artist_one"]),
+            ]);
+            add_co_listen_weights(&mut graph, &csv_data, min_weight);
+            graph
+        };
+
+        // Artist One and Artist Two are played twice each, so they're co-listened 4 times while
+        // Artist Three is only co-listened twice with each of them
+        let graph_0 = graph(0);
+        assert_eq!(graph_0.related_artist_counts["csv_artist_one"], vec![
+            4, 2, 0
+        ]);
+        assert_eq!(graph_0.related_artist_weights["csv_artist_one"], vec![
+            1., 0.5, 0.
+        ]);
+        // Artists without co-listen data keep all of their related artists
+        assert!(!graph_0.related_artist_counts.contains_key("spotify_source"));
+        assert_eq!(graph_0.related_artists["spotify_source"].len(), 1);
+
+        let edge_count = |graph: &RelatedArtistsGraph| {
+            graph.related_artists.values().map(Vec::len).sum::<usize>()
+        };
+        let graph_3 = graph(3);
+        assert!(edge_count(&graph_3) < edge_count(&graph_0));
+        assert_eq!(graph_3.related_artists["csv_artist_one"], vec![
+            "csv_artist_two"
+        ]);
+        assert_eq!(graph_3.related_artist_counts["csv_artist_one"], vec![4]);
+        assert_eq!(graph_3.related_artists["spotify_source"].len(), 1);
+    }
+
+    #[test]
+    fn test_drop_unknown_related_artists() {
+        let csv_data = parse_csv(CO_LISTEN_CSV.as_bytes()).expect("CSV should parse");
+        let mut graph = related_artists_graph(&[
+            ("csv_artist_one", &["csv_artist_two", "csv_missing_artist"]),
+            ("csv_missing_source", &["csv_artist_one"]),
+        ]);
+        add_co_listen_weights(&mut graph, &csv_data, 0);
+        graph.extra_artists = ["csv_artist_one", "csv_artist_two"]
+            .iter()
+            .map(|id| (id.to_string(), csv_data.artists[*id].clone()))
+            .collect();
+
+        drop_unknown_related_artists(&mut graph);
+        assert!(!graph.related_artists.contains_key("csv_missing_source"));
+        assert_eq!(graph.related_artists["csv_artist_one"], vec![
+            "csv_artist_two"
+        ]);
+        assert_eq!(graph.related_artist_counts["csv_artist_one"], vec![4]);
+        assert_eq!(graph.related_artist_weights["csv_artist_one"], vec![1.]);
+    }
+
+    #[test]
+    fn test_packed_gzip_negotiation() {
+        use std::io::Read;
+
+        let client = Client::tracked(rocket::build().mount("/", routes![packed])).unwrap();
+
+        let res = client
+            .get("/packed")
+            .header(Header::new("Accept-Encoding", "deflate, gzip;q=0.8"))
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.headers().get_one("Content-Encoding"), Some("gzip"));
+        let compressed = res.into_bytes().expect("Missing response body");
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .expect("Response body should be valid gzip");
+        assert_eq!(decompressed, vec![1, 2, 3, 4]);
+
+        let res = client
+            .get("/packed")
+            .header(Header::new("Accept-Encoding", "gzip;q=0"))
+            .dispatch();
+        assert_eq!(res.headers().get_one("Content-Encoding"), None);
+        assert_eq!(res.into_bytes(), Some(vec![1, 2, 3, 4]));
+    }
 }
@@ -1,11 +1,12 @@
 use std::{
-    sync::Arc,
+    future::Future,
     time::{Duration, Instant},
 };
 
 use chrono::Utc;
 use diesel::prelude::*;
-use fnv::FnvHashMap as HashMap;
+use fnv::{FnvHashMap as HashMap, FnvHashSet as HashSet};
+use futures::StreamExt;
 use reqwest::{self, StatusCode};
 use rocket::http::RawStr;
 use serde::{Deserialize, Serialize};
@@ -15,6 +16,7 @@ use tokio::{
 };
 
 use crate::{
+    cache::lru::LruCache,
     conf::CONF,
     db_util::get_internal_ids_by_spotify_id,
     metrics::{
@@ -23,7 +25,7 @@ use crate::{
     },
     models::{
         AccessTokenResponse, Album, Artist, ArtistGenrePair, ArtistSearchResult,
-        CreatePlaylistRequest, GetRelatedArtistsResponse, NewArtistHistoryEntry,
+        CreatePlaylistRequest, ExternalIds, GetRelatedArtistsResponse, NewArtistHistoryEntry,
         NewTrackHistoryEntry, Playlist, SpotifyBatchArtistsResponse, SpotifyBatchTracksResponse,
         SpotifyResponse, StatsSnapshot, TopArtistsResponse, TopTracksResponse, Track,
         TrackArtistPair, UpdatePlaylistResponse, User, UserProfile,
@@ -42,6 +44,12 @@ const REQWEST_CLIENT_LIFETIME_SECS: u64 = 60 * 5;
 
 lazy_static::lazy_static! {
     static ref REQWEST_CLIENT_CACHE: RwLock<(Instant, reqwest::Client)> = RwLock::new((Instant::now(), reqwest::Client::new()));
+    /// Sits in front of the Redis cache so that repeated requests for popular artists don't need
+    /// to go over the network at all
+    static ref ARTIST_METADATA_CACHE: Mutex<LruCache<String, Artist>> = Mutex::new(LruCache::new(
+        CONF.artist_metadata_cache_capacity,
+        Some(CONF.artist_metadata_cache_ttl),
+    ));
 }
 
 pub(crate) async fn get_reqwest_client() -> reqwest::Client {
@@ -67,6 +75,84 @@ fn get_top_entities_url(entity_type: &str, timeframe: &str) -> String {
     )
 }
 
+/// Error returned when Spotify responds with a 429
+pub(crate) const RATE_LIMITED_ERROR: &str = "Rate Limited";
+
+/// Delay before the first retry of a failed request.  Each following retry waits twice as long.
+const BASE_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Upper bound for the exponential backoff delay.  Delays requested by Spotify via `Retry-After`
+/// are always honored in full.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Returns how long to wait before retrying a request that got `res` back, or `None` if it
+/// shouldn't be retried.  Only rate limited requests and server errors are retried.
+fn get_retry_delay(res: &reqwest::Response, retry_ix: u32) -> Option<Duration> {
+    let status = res.status();
+    if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+        return None;
+    }
+
+    let retry_after = res
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|retry_after| retry_after.to_str().ok())
+        .and_then(|retry_after| retry_after.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    Some(retry_after.unwrap_or_else(|| {
+        BASE_RETRY_BACKOFF
+            .saturating_mul(2u32.saturating_pow(retry_ix))
+            .min(MAX_RETRY_BACKOFF)
+    }))
+}
+
+/// Sends the request built by `build_request`, retrying with exponential backoff if it's rate
+/// limited or fails with a 5xx.  Once `max_attempts` requests have been made, the last response is
+/// returned as-is.  Errors sending the request aren't retried.
+async fn send_with_retry(
+    build_request: impl Fn() -> reqwest::RequestBuilder,
+    endpoint_name: &'static str,
+    max_attempts: u32,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let mut attempt = 1;
+    loop {
+        let res = build_request().send().await?;
+        if res.status() == StatusCode::TOO_MANY_REQUESTS {
+            spotify_api_requests_rate_limited_total(endpoint_name).inc();
+        }
+
+        let delay = match get_retry_delay(&res, attempt - 1) {
+            Some(delay) if attempt < max_attempts => delay,
+            _ => return Ok(res),
+        };
+        warn!(
+            "Got status code {} when hitting url={}; retrying in {:?} (attempt {}/{})",
+            res.status(),
+            res.url(),
+            delay,
+            attempt,
+            max_attempts
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Records success/failure metrics for a completed request to the Spotify API
+fn record_spotify_res<T>(
+    res: Result<T, String>,
+    endpoint_name: &'static str,
+    start: Instant,
+) -> Result<T, String> {
+    match &res {
+        Ok(_) => {
+            spotify_api_requests_success_total(endpoint_name).inc();
+            spotify_api_response_time(endpoint_name).observe(start.elapsed().as_nanos() as u64);
+        },
+        Err(_) => spotify_api_requests_failure_total(endpoint_name).inc(),
+    }
+    res
+}
+
 async fn process_spotify_res<R: for<'de> Deserialize<'de> + Clone + std::fmt::Debug>(
     url: &str,
     res: Result<reqwest::Response, reqwest::Error>,
@@ -78,7 +164,7 @@ async fn process_spotify_res<R: for<'de> Deserialize<'de> + Clone + std::fmt::De
 
     if res.status() == StatusCode::TOO_MANY_REQUESTS {
         warn!("Rate limited when making request to URL={}", url);
-        return Err("Rate Limited".into());
+        return Err(RATE_LIMITED_ERROR.into());
     }
 
     if !res.status().is_success() {
@@ -109,27 +195,14 @@ pub(crate) async fn spotify_user_api_request<
     spotify_api_requests_total(endpoint_name).inc();
     let client = get_reqwest_client().await;
 
-    let mut start = Instant::now();
-    loop {
-        let res = client.get(url).bearer_auth(token).send().await;
-
-        match process_spotify_res(&url, res).await {
-            Ok(res) => {
-                spotify_api_requests_success_total(endpoint_name).inc();
-                spotify_api_response_time(endpoint_name).observe(start.elapsed().as_nanos() as u64);
-                return Ok(res);
-            },
-            Err(err) if err.contains("Rate Limited") => {
-                spotify_api_requests_rate_limited_total(endpoint_name).inc();
-                tokio::time::sleep(Duration::from_secs(5)).await;
-                start = Instant::now();
-            },
-            Err(err) => {
-                spotify_api_requests_failure_total(endpoint_name).inc();
-                return Err(err);
-            },
-        }
-    }
+    let start = Instant::now();
+    let res = send_with_retry(
+        || client.get(url).bearer_auth(token),
+        endpoint_name,
+        CONF.spotify_api_max_attempts,
+    )
+    .await;
+    record_spotify_res(process_spotify_res(url, res).await, endpoint_name, start)
 }
 
 pub(crate) async fn get_user_profile_info(token: &str) -> Result<UserProfile, String> {
@@ -145,36 +218,23 @@ pub(crate) async fn spotify_server_api_request<
 ) -> Result<T, String> {
     let client = get_reqwest_client().await;
 
-    let mut start = Instant::now();
-    loop {
-        info!(
-            "Hitting Spotify API POST at URL {}, params: {:?}",
-            url, params
-        );
-        let res = client
-            .post(url)
-            .header("Authorization", CONF.get_authorization_header_content())
-            .form(&params)
-            .send()
-            .await;
-
-        match process_spotify_res(&url, res).await {
-            Ok(res) => {
-                spotify_api_requests_success_total(endpoint_name).inc();
-                spotify_api_response_time(endpoint_name).observe(start.elapsed().as_nanos() as u64);
-                return Ok(res);
-            },
-            Err(err) if err.contains("Rate Limited") => {
-                spotify_api_requests_rate_limited_total(endpoint_name).inc();
-                tokio::time::sleep(Duration::from_secs(5)).await;
-                start = Instant::now();
-            },
-            Err(err) => {
-                spotify_api_requests_failure_total(endpoint_name).inc();
-                return Err(err);
-            },
-        }
-    }
+    info!(
+        "Hitting Spotify API POST at URL {}, params: {:?}",
+        url, params
+    );
+    let start = Instant::now();
+    let res = send_with_retry(
+        || {
+            client
+                .post(url)
+                .header("Authorization", CONF.get_authorization_header_content())
+                .form(&params)
+        },
+        endpoint_name,
+        CONF.spotify_api_max_attempts,
+    )
+    .await;
+    record_spotify_res(process_spotify_res(url, res).await, endpoint_name, start)
 }
 
 pub(crate) async fn spotify_server_get_request<
@@ -186,36 +246,19 @@ pub(crate) async fn spotify_server_get_request<
 ) -> Result<T, String> {
     let client = get_reqwest_client().await;
 
-    let mut start = Instant::now();
-    loop {
-        info!("Hitting Spotify API GET at URL {}", url,);
-        let res = client
-            .get(url)
-            .header("Authorization", format!("Bearer {}", bearer_token))
-            .send()
-            .await;
-
-        match process_spotify_res(&url, res).await {
-            Ok(res) => {
-                spotify_api_requests_success_total(endpoint_name).inc();
-                spotify_api_response_time(endpoint_name).observe(start.elapsed().as_nanos() as u64);
-                return Ok(res);
-            },
-            Err(err) if err.contains("Rate Limited") => {
-                warn!(
-                    "Rate limited when hitting url={}, waiting 5 seconds before retrying...",
-                    url
-                );
-                spotify_api_requests_rate_limited_total(endpoint_name).inc();
-                tokio::time::sleep(Duration::from_secs(5)).await;
-                start = Instant::now();
-            },
-            Err(err) => {
-                spotify_api_requests_failure_total(endpoint_name).inc();
-                return Err(err);
-            },
-        }
-    }
+    info!("Hitting Spotify API GET at URL {}", url,);
+    let start = Instant::now();
+    let res = send_with_retry(
+        || {
+            client
+                .get(url)
+                .header("Authorization", format!("Bearer {}", bearer_token))
+        },
+        endpoint_name,
+        CONF.spotify_api_max_attempts,
+    )
+    .await;
+    record_spotify_res(process_spotify_res(url, res).await, endpoint_name, start)
 }
 
 async fn spotify_user_json_api_get_request<
@@ -227,32 +270,15 @@ async fn spotify_user_json_api_get_request<
 ) -> Result<R, String> {
     let client = get_reqwest_client().await;
 
-    let mut start = Instant::now();
-    loop {
-        info!("Hitting Spotify API at URL {}", url);
-
-        let res = client.get(&url).bearer_auth(bearer_token).send().await;
-        match process_spotify_res(&url, res).await {
-            Ok(res) => {
-                spotify_api_requests_success_total(endpoint_name).inc();
-                spotify_api_response_time(endpoint_name).observe(start.elapsed().as_nanos() as u64);
-                return Ok(res);
-            },
-            Err(err) if err.contains("Rate Limited") => {
-                warn!(
-                    "Rate limited when hitting url={}, waiting 5 seconds before retrying...",
-                    url
-                );
-                spotify_api_requests_rate_limited_total(endpoint_name).inc();
-                tokio::time::sleep(Duration::from_secs(5)).await;
-                start = Instant::now();
-            },
-            Err(err) => {
-                spotify_api_requests_failure_total(endpoint_name).inc();
-                return Err(err);
-            },
-        }
-    }
+    info!("Hitting Spotify API at URL {}", url);
+    let start = Instant::now();
+    let res = send_with_retry(
+        || client.get(&url).bearer_auth(bearer_token),
+        endpoint_name,
+        CONF.spotify_api_max_attempts,
+    )
+    .await;
+    record_spotify_res(process_spotify_res(&url, res).await, endpoint_name, start)
 }
 
 pub(crate) async fn spotify_user_json_api_request<
@@ -372,9 +398,15 @@ pub(crate) async fn fetch_cur_stats(user: &User) -> Result<Option<StatsSnapshot>
     // Wait for all 6 requests to return back and then
     info!("Waiting for all 6 inner stats requests to return...");
     for _ in 0..6 {
-        match rx.recv().await.unwrap() {
+        let (entity_type, timeframe, res) = rx.recv().await.unwrap();
+        let res = res?;
+        if res.status() == StatusCode::TOO_MANY_REQUESTS {
+            warn!("Rate limited when fetching top {entity_type} for timeframe {timeframe}");
+            return Err(RATE_LIMITED_ERROR.into());
+        }
+
+        match (entity_type, timeframe, res) {
             ("tracks", timeframe, res) => {
-                let res = res?;
                 if res.status() != StatusCode::OK {
                     error!(
                         "Error fetching top tracks for timeframe {}: got status code {}",
@@ -407,11 +439,10 @@ pub(crate) async fn fetch_cur_stats(user: &User) -> Result<Option<StatsSnapshot>
                 }
             },
             ("artists", timeframe, res) => {
-                let parsed_res: TopArtistsResponse =
-                    res?.json().await.map_err(|err| -> String {
-                        error!("Error parsing top artists response: {:?}", err);
-                        "Error parsing response from Spotify".into()
-                    })?;
+                let parsed_res: TopArtistsResponse = res.json().await.map_err(|err| -> String {
+                    error!("Error parsing top artists response: {:?}", err);
+                    "Error parsing response from Spotify".into()
+                })?;
 
                 if parsed_res
                     .items
@@ -624,6 +655,7 @@ async fn fetch_batch_entities<'a, T: for<'de> Deserialize<'de>>(
     token: &str,
     spotify_entity_ids: &[&str],
     endpoint_name: &'static str,
+    max_attempts: u32,
 ) -> Result<T, String> {
     let url = if base_url.contains('?') {
         base_url.into()
@@ -632,61 +664,94 @@ async fn fetch_batch_entities<'a, T: for<'de> Deserialize<'de>>(
     };
     let client = get_reqwest_client().await;
 
-    loop {
-        let start = Instant::now();
-        spotify_api_requests_total(endpoint_name).inc();
-        let res = client
-            .get(&url)
-            .bearer_auth(token)
-            .send()
-            .await
-            .map_err(|err| {
-                error!("Error requesting batch data from the Spotify API: {}", err);
-                String::from("Error requesting batch data from the Spotify API")
-            })?;
+    let start = Instant::now();
+    spotify_api_requests_total(endpoint_name).inc();
+    let res = send_with_retry(
+        || client.get(&url).bearer_auth(token),
+        endpoint_name,
+        max_attempts,
+    )
+    .await
+    .map_err(|err| {
+        error!("Error requesting batch data from the Spotify API: {}", err);
+        String::from("Error requesting batch data from the Spotify API")
+    })?;
 
-        if res.status() == StatusCode::TOO_MANY_REQUESTS {
-            warn!("Rate limited when hitting URL={}", url);
-            spotify_api_requests_rate_limited_total(endpoint_name).inc();
-            tokio::time::sleep(Duration::from_secs(5)).await;
-            continue;
-        }
+    if res.status().is_success() {
+        spotify_api_requests_success_total(endpoint_name).inc();
+        spotify_api_response_time(endpoint_name).observe(start.elapsed().as_nanos() as u64);
+    } else {
+        error!(
+            "Got bad status code of {} from Spotify API: {:?}",
+            res.status(),
+            res.text().await
+        );
+        spotify_api_requests_failure_total(endpoint_name).inc();
+        return Err("Got bad response from Spotify API".into());
+    }
 
-        if res.status().is_success() {
-            spotify_api_requests_success_total(endpoint_name).inc();
-            spotify_api_response_time(endpoint_name).observe(start.elapsed().as_nanos() as u64);
-        } else {
+    if cfg!(debug_assertions) {
+        let res = res.text().await.map_err(|err| -> String {
+            error!("Error reading response from Spotify API: {:?}", err);
+            "Error reading response from the Spotify API".into()
+        })?;
+        serde_json::from_str(&res).map_err(|err| -> String {
             error!(
-                "Got bad status code of {} from Spotify API: {:?}",
-                res.status(),
-                res.text().await
+                "Error decoding JSON from Spotify API: {:?}, url={}, res={}",
+                err, url, res
+            );
+            "Error reading data from the Spotify API".into()
+        })
+    } else {
+        res.json().await.map_err(|err| -> String {
+            error!(
+                "Error decoding JSON from Spotify API: {:?}, url={}",
+                err, url
+            );
+            "Error reading data from the Spotify API".into()
+        })
+    }
+}
+
+/// Fetches all of `spotify_ids` in batches of up to `MAX_BATCH_ENTITY_COUNT`, the most that Spotify
+/// allows in a single request.  `on_batch` is called with the IDs and items of each batch as soon
+/// as it's fetched.  Items are returned in the same order as `spotify_ids`.
+async fn fetch_in_batches<ResponseType: for<'de> Deserialize<'de>, T>(
+    api_url: &str,
+    endpoint_name: &'static str,
+    spotify_access_token: &str,
+    spotify_ids: &[&str],
+    max_attempts: u32,
+    map_response_to_items: fn(ResponseType) -> Result<Vec<T>, String>,
+    mut on_batch: impl FnMut(&[&str], &[T]) -> Result<(), String>,
+) -> Result<Vec<T>, String> {
+    let mut fetched_entities = Vec::with_capacity(spotify_ids.len());
+    for (chunk_ix, chunk) in spotify_ids.chunks(MAX_BATCH_ENTITY_COUNT).enumerate() {
+        info!("Fetching chunk {}...", chunk_ix);
+        let res: ResponseType = fetch_batch_entities(
+            api_url,
+            spotify_access_token,
+            chunk,
+            endpoint_name,
+            max_attempts,
+        )
+        .await?;
+        let fetched_chunk = map_response_to_items(res)?;
+        if fetched_chunk.len() != chunk.len() {
+            error!(
+                "Requested {} items from the Spotify API but got {} back",
+                chunk.len(),
+                fetched_chunk.len()
             );
-            spotify_api_requests_failure_total(endpoint_name).inc();
             return Err("Got bad response from Spotify API".into());
         }
 
-        if cfg!(debug_assertions) {
-            let res = res.text().await.map_err(|err| -> String {
-                error!("Error reading response from Spotify API: {:?}", err);
-                "Error reading response from the Spotify API".into()
-            })?;
-            return serde_json::from_str(&res).map_err(|err| -> String {
-                error!(
-                    "Error decoding JSON from Spotify API: {:?}, url={}, res={}",
-                    err, url, res
-                );
-                "Error reading data from the Spotify API".into()
-            });
-        } else {
-            return res.json().await.map_err(|err| -> String {
-                error!(
-                    "Error decoding JSON from Spotify API: {:?}, url={}",
-                    err, url
-                );
-                "Error reading data from the Spotify API".into()
-            });
-        };
+        on_batch(chunk, &fetched_chunk)?;
+        fetched_entities.extend(fetched_chunk);
     }
+    info!("Fetched all chunks.");
+
+    Ok(fetched_entities)
 }
 
 async fn fetch_with_cache<
@@ -700,98 +765,149 @@ async fn fetch_with_cache<
     spotify_ids: &[&str],
     map_response_to_items: fn(ResponseType) -> Result<Vec<T>, String>,
 ) -> Result<Vec<T>, String> {
+    // Duplicate IDs are only looked up and fetched once
+    let mut seen_ids = HashSet::default();
+    let unique_ids: Vec<&str> = spotify_ids
+        .iter()
+        .copied()
+        .filter(|id| seen_ids.insert(*id))
+        .collect();
+
     // First, try to get as many items as we can from the cache
-    info!("Checking cache for {} spotify ids...", spotify_ids.len());
-    let cache_res = block_in_place(|| crate::cache::get_hash_items::<T>(cache_key, spotify_ids))?;
-
-    // Fire off a request to Spotify to fill in the missing items
-    let mut missing_indices = Vec::new();
-    let mut missing_ids = Vec::new();
-    for (i, datum) in cache_res.iter().enumerate() {
-        if datum.is_none() {
-            missing_indices.push(i);
-            missing_ids.push(spotify_ids[i]);
-        }
-    }
+    info!("Checking cache for {} spotify ids...", unique_ids.len());
+    let cache_res = block_in_place(|| crate::cache::get_hash_items::<T>(cache_key, &unique_ids))?;
+
+    // Fire off requests to Spotify to fill in the missing items
+    let missing_ids: Vec<&str> = unique_ids
+        .iter()
+        .zip(&cache_res)
+        .filter(|(_, datum)| datum.is_none())
+        .map(|(id, _)| *id)
+        .collect();
     info!(
         "{}/{} items found in the cache.",
-        cache_res.len() - missing_indices.len(),
-        spotify_ids.len()
+        unique_ids.len() - missing_ids.len(),
+        unique_ids.len()
     );
 
-    if missing_indices.is_empty() {
-        return Ok(cache_res.into_iter().map(Option::unwrap).collect());
-    }
-
-    let mut fetched_entities = Vec::with_capacity(missing_indices.len());
-    for (chunk_ix, chunk) in missing_ids.chunks(MAX_BATCH_ENTITY_COUNT).enumerate() {
-        info!("Fetching chunk {}...", chunk_ix);
-        let res: ResponseType =
-            fetch_batch_entities(api_url, spotify_access_token, chunk, endpoint_name).await?;
-        let fetched_artist_data = map_response_to_items(res)?;
-
-        for i in 0..chunk.len() {
-            debug_assert_eq!(
-                chunk[i],
-                missing_ids[(chunk_ix * MAX_BATCH_ENTITY_COUNT) + i]
-            );
-        }
+    let fetched_entities = fetch_in_batches(
+        api_url,
+        endpoint_name,
+        spotify_access_token,
+        &missing_ids,
+        CONF.spotify_api_max_attempts,
+        map_response_to_items,
+        |chunk, fetched_chunk| {
+            // Update the cache with the missing items
+            block_in_place(|| {
+                crate::cache::set_hash_items(
+                    cache_key,
+                    &chunk.iter().copied().zip(fetched_chunk).collect::<Vec<_>>(),
+                )
+            })
+        },
+    )
+    .await?;
 
-        // Update the cache with the missing items
-        block_in_place(|| {
-            crate::cache::set_hash_items(
-                cache_key,
-                &fetched_artist_data
-                    .iter()
-                    .enumerate()
-                    .map(|(i, datum)| (chunk[i], datum))
-                    .collect::<Vec<_>>(),
+    let mut fetched_entities = fetched_entities.into_iter();
+    let items_by_id: HashMap<&str, T> = unique_ids
+        .into_iter()
+        .zip(cache_res)
+        .map(|(id, datum)| {
+            // `fetch_in_batches` returns exactly one item for each missing ID, in order
+            (
+                id,
+                datum.unwrap_or_else(|| fetched_entities.next().unwrap()),
             )
-        })?;
+        })
+        .collect();
+    Ok(spotify_ids
+        .iter()
+        .map(|id| items_by_id[id].clone())
+        .collect())
+}
 
-        fetched_entities.extend(fetched_artist_data)
+/// Returns the items for `spotify_ids`, taking them from `cache` where possible.  `fetch` is called
+/// with the IDs of any missing items, and the items it returns are added to `cache`.  Items are
+/// returned in the same order as `spotify_ids`.
+async fn fetch_with_local_cache<'a, T, F>(
+    cache: &Mutex<LruCache<String, T>>,
+    spotify_ids: &[&'a str],
+    fetch: impl FnOnce(Vec<&'a str>) -> F,
+) -> Result<Vec<T>, String>
+where
+    T: Clone,
+    F: Future<Output = Result<Vec<T>, String>>,
+{
+    let mut items: Vec<Option<T>> = {
+        let mut cache = cache.lock().await;
+        spotify_ids
+            .iter()
+            .map(|id| cache.get(*id).cloned())
+            .collect()
+    };
+    let missing_ids: Vec<&str> = spotify_ids
+        .iter()
+        .zip(&items)
+        .filter(|(_, item)| item.is_none())
+        .map(|(id, _)| *id)
+        .collect();
+    if missing_ids.is_empty() {
+        return Ok(items.into_iter().map(Option::unwrap).collect());
     }
-    info!("Fetched all chunks.");
 
-    let mut i = 0;
-    let combined_results = cache_res
-        .into_iter()
-        .map(|opt| {
-            opt.unwrap_or_else(|| {
-                // We could avoid this clone by reversing the direction in which we fetch the items
-                // but that's 100% premature and likely useless optimization
-                let val = fetched_entities[i].clone();
-                i += 1;
-                val
-            })
-        })
-        .collect::<Vec<_>>();
-    Ok(combined_results)
+    let fetched = fetch(missing_ids.clone()).await?;
+    if fetched.len() != missing_ids.len() {
+        error!(
+            "Expected {} items to be fetched but got {}",
+            missing_ids.len(),
+            fetched.len()
+        );
+        return Err("Error fetching data from the Spotify API".into());
+    }
+
+    let mut cache = cache.lock().await;
+    let mut fetched = fetched.into_iter();
+    for (id, item) in spotify_ids.iter().zip(items.iter_mut()) {
+        if item.is_none() {
+            let fetched_item = fetched.next().unwrap();
+            cache.insert(id.to_string(), fetched_item.clone());
+            *item = Some(fetched_item);
+        }
+    }
+    Ok(items.into_iter().map(Option::unwrap).collect())
 }
 
 pub(crate) async fn fetch_artists(
     spotify_access_token: &str,
     spotify_ids: &[&str],
 ) -> Result<Vec<Artist>, String> {
-    let mut entities = fetch_with_cache::<SpotifyBatchArtistsResponse, _>(
-        &CONF.artists_cache_hash_name,
-        SPOTIFY_BATCH_ARTISTS_URL,
-        "fetch_artists",
-        spotify_access_token,
+    fetch_with_local_cache(
+        &ARTIST_METADATA_CACHE,
         spotify_ids,
-        |res: SpotifyBatchArtistsResponse| Ok(res.artists),
-    )
-    .await?;
+        |missing_ids| async move {
+            let mut entities = fetch_with_cache::<SpotifyBatchArtistsResponse, _>(
+                &CONF.artists_cache_hash_name,
+                SPOTIFY_BATCH_ARTISTS_URL,
+                "fetch_artists",
+                spotify_access_token,
+                &missing_ids,
+                |res: SpotifyBatchArtistsResponse| Ok(res.artists),
+            )
+            .await?;
 
-    for artist in &mut entities {
-        if let Some(images) = artist.images.as_mut() {
-            while images.len() > 1 {
-                images.pop();
+            for artist in &mut entities {
+                if let Some(images) = artist.images.as_mut() {
+                    while images.len() > 1 {
+                        images.pop();
+                    }
+                }
             }
-        }
-    }
 
-    Ok(entities)
+            Ok(entities)
+        },
+    )
+    .await
 }
 
 pub(crate) async fn fetch_tracks(
@@ -888,138 +1004,134 @@ pub(crate) async fn get_related_artists(
     Ok(res.artists)
 }
 
-/// `artist_ids` must not have any duplicates
+/// Default number of requests for related artists made to Spotify at once
+pub(crate) const DEFAULT_RELATED_ARTISTS_FETCH_CONCURRENCY: usize = 4;
+/// Default number of artists whose related artists are fetched before the results are cached
+pub(crate) const DEFAULT_RELATED_ARTISTS_FETCH_BATCH_SIZE: usize = 50;
+
+/// Calls `fetch` for each of `ids` in batches of `batch_size`, with at most `concurrency` calls in
+/// flight at a time.  `on_batch` is called with the results of each batch once it completes, before
+/// the next one is started.  A failed call doesn't affect any of the others.
+///
+/// Returns the result for each of `ids` in order.
+async fn fetch_in_bounded_batches<T, Fut>(
+    ids: &[&str],
+    batch_size: usize,
+    concurrency: usize,
+    fetch: impl Fn(String) -> Fut,
+    mut on_batch: impl FnMut(&[&str], &[Result<T, String>]) -> Result<(), String>,
+) -> Result<Vec<Result<T, String>>, String>
+where
+    Fut: Future<Output = Result<T, String>>,
+{
+    let mut results = Vec::with_capacity(ids.len());
+    for batch in ids.chunks(batch_size.max(1)) {
+        let batch_results: Vec<Result<T, String>> = futures::stream::iter(batch)
+            .map(|id| fetch(id.to_string()))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+        on_batch(batch, &batch_results)?;
+        results.extend(batch_results);
+    }
+    Ok(results)
+}
+
+/// Returns the IDs of the related artists of each of `artist_ids`, pulling them from the cache
+/// where possible.  Uncached artists are fetched from Spotify in batches of `batch_size` with at
+/// most `concurrency` requests in flight at a time, and each batch is cached as soon as it's done.
+///
+/// Entries are `None` for artists whose related artists failed to be fetched.  `artist_ids` must
+/// not have any duplicates.
 pub(crate) async fn get_multiple_related_artists(
     bearer_token: String,
     artist_ids: &[&str],
-) -> Result<Vec<Vec<String>>, String> {
+    concurrency: usize,
+    batch_size: usize,
+) -> Result<Vec<Option<Vec<String>>>, String> {
     // Pull those from the cache that can be pulled
     let cache_results = block_in_place(|| {
         crate::cache::get_hash_items::<Vec<String>>("related_artists", artist_ids)
     })?;
 
     let mut output = vec![None; artist_ids.len()];
-    let mut uncached_ids: Vec<String> = Vec::new();
+    let mut uncached_ids: Vec<&str> = Vec::new();
     for (i, cache_res) in cache_results.into_iter().enumerate() {
         if let Some(related) = cache_res {
             output[i] = Some(related);
             continue;
         }
 
-        uncached_ids.push(artist_ids[i].to_owned());
+        uncached_ids.push(artist_ids[i]);
     }
 
     // Fetch all uncached ids and store in the cache
-    const CONCURRENT_FETCHES: usize = 4;
-    let total_to_fetch = uncached_ids.len();
-    let uncached_ids_clone = uncached_ids.clone();
-    let uncached_ids_clone_2 = uncached_ids_clone.clone();
-    let (tx, rx) = std::sync::mpsc::sync_channel(1);
-
-    let work = Arc::new(Mutex::new(uncached_ids_clone_2));
-
-    for _ in 0..CONCURRENT_FETCHES {
+    let fetch = |artist_id: String| {
         let bearer_token = bearer_token.clone();
-        let tx = tx.clone();
-        let work = Arc::clone(&work);
-
-        tokio::task::spawn(async move {
-            loop {
-                let artist_id = match { work.lock().await.pop() } {
-                    Some(id) => id,
-                    None => {
-                        debug!("No more items to fetch, worker exiting");
-                        break;
-                    },
-                };
-
-                let related_artists_res = get_related_artists(&bearer_token, &artist_id).await;
-                if let Err(_) = tx.send((artist_id, related_artists_res)) {
-                    warn!("Receiver dropped; exiting related artists fetch worker");
-                    break;
-                }
-            }
-        });
-    }
-
-    let fetched_results = block_in_place(|| {
-        let mut fetched = vec![Vec::new(); total_to_fetch];
-        let mut fetched_so_far = 0;
-        while fetched_so_far < total_to_fetch {
-            let (artist_id, related_artists) = match rx.recv_timeout(Duration::from_secs(30)) {
-                Ok((artist_id, Ok(res))) => (artist_id, res),
-                Ok((artist_id, Err(err))) => {
+        async move {
+            let related_artists = get_related_artists(&bearer_token, &artist_id)
+                .await
+                .map_err(|err| {
                     error!(
                         "Error fetching related artist for artist_id={}: {:?}",
                         artist_id, err
                     );
-                    (artist_id, Vec::new())
-                },
-                Err(_) => {
-                    error!(
-                        "No response on channel in 30 seconds when fetching related artists; \
-                         giving up"
-                    );
-                    return Err(String::from(
-                        "Error fetching related artists from Spotify API",
-                    ));
-                },
-            };
-            fetched_so_far += 1;
-
-            let ix = uncached_ids_clone
-                .iter()
-                .position(|id| *id == artist_id)
-                .expect("Received artist ID for related artist we didn't ask for");
-            assert!(fetched[ix].is_empty());
-            fetched[ix] = related_artists
+                    err
+                })?;
+            Ok(related_artists
                 .into_iter()
                 .map(|artist| artist.id)
-                .collect();
+                .collect::<Vec<String>>())
         }
-        Ok(fetched)
-    })?;
-
-    let mut kv_pairs_to_cache: Vec<(&str, Vec<String>)> = Vec::with_capacity(uncached_ids.len());
-    for (i, related_artists) in fetched_results.into_iter().enumerate() {
-        let artist_id = &uncached_ids[i];
-        let output_ix = artist_ids
+    };
+    let cache_batch = |batch: &[&str], batch_results: &[Result<Vec<String>, String>]| {
+        // Failures aren't cached so that they're retried next time
+        let kv_pairs_to_cache: Vec<(&str, &Vec<String>)> = batch
             .iter()
-            .position(|o_artist_id| *o_artist_id == artist_id.as_str())
-            .unwrap();
-        output[output_ix] = Some(related_artists.clone());
+            .zip(batch_results)
+            .filter_map(|(artist_id, res)| Some((*artist_id, res.as_ref().ok()?)))
+            .collect();
+        block_in_place(|| crate::cache::set_hash_items("related_artists", &kv_pairs_to_cache))
+    };
+    let fetched_results =
+        fetch_in_bounded_batches(&uncached_ids, batch_size, concurrency, fetch, cache_batch)
+            .await?;
 
-        kv_pairs_to_cache.push((artist_id, related_artists));
+    let output_ix_by_artist_id: HashMap<&str, usize> = artist_ids
+        .iter()
+        .enumerate()
+        .map(|(ix, artist_id)| (*artist_id, ix))
+        .collect();
+    for (artist_id, res) in uncached_ids.iter().zip(fetched_results) {
+        output[output_ix_by_artist_id[artist_id]] = res.ok();
     }
-    block_in_place(|| crate::cache::set_hash_items("related_artists", &kv_pairs_to_cache))?;
 
-    Ok(output
-        .into_iter()
-        .map(|opt| {
-            opt.expect(
-                "All artists must have been filled in at this point by either the cache or \
-                 dynamic fetching",
-            )
-        })
-        .collect())
+    Ok(output)
 }
 
+fn top_tracks_url(artist_spotify_id: &str, market: &str) -> String {
+    format!(
+        "https://api.spotify.com/v1/artists/{}/top-tracks?market={}",
+        artist_spotify_id, market
+    )
+}
+
+/// Fetches the top tracks of an artist in the Spotify `market` (an ISO 3166-1 alpha-2 country
+/// code).  They're cached separately for each market since availability differs between them.
 pub(crate) async fn fetch_top_tracks_for_artist(
     spotify_access_token: &str,
     artist_spotify_id: &str,
+    market: &str,
 ) -> Result<Vec<Track>, String> {
     #[derive(Deserialize)]
     struct FetchTopTracksForArtistResponse {
         pub tracks: Vec<Track>,
     }
 
-    let url = format!(
-        "https://api.spotify.com/v1/artists/{}/top-tracks?market=us",
-        artist_spotify_id
-    );
+    let url = top_tracks_url(artist_spotify_id, market);
 
     Ok(fetch_with_cache::<FetchTopTracksForArtistResponse, _>(
-        "top-tracks",
+        &format!("top-tracks-{}", market.to_ascii_lowercase()),
         &url,
         "fetch_top_tracks_for_artist",
         spotify_access_token,
@@ -1032,10 +1144,159 @@ pub(crate) async fn fetch_top_tracks_for_artist(
     .unwrap())
 }
 
+fn search_artists_url(query: &str, market: &str) -> String {
+    format!(
+        "https://api.spotify.com/v1/search?q={}&type=artist&market={}",
+        RawStr::new(query).percent_encode(),
+        market
+    )
+}
+
+/// Deezer's catalog can be looked up by ISRC and has previews for most tracks
+const DEEZER_TRACK_BY_ISRC_URL: &str = "https://api.deezer.com/track/isrc:";
+/// Most tracks in a single list that previews are looked up for from the fallback source
+const MAX_FALLBACK_PREVIEW_LOOKUPS: usize = 5;
+/// Most fallback preview lookups run at once for a single request
+const FALLBACK_PREVIEW_LOOKUP_CONCURRENCY: usize = 5;
+
+/// Picks which of `tracks` to look up fallback previews for: the first
+/// `MAX_FALLBACK_PREVIEW_LOOKUPS` of them without a preview URL that have an ISRC to look one up
+/// by.  Returns their indices along with their ISRCs.
+pub(crate) fn select_fallback_preview_candidates(tracks: &[Track]) -> Vec<(usize, &str)> {
+    tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, track)| track.preview_url.is_none())
+        .filter_map(|(ix, track)| {
+            let isrc = track.external_ids.as_ref()?.isrc.as_deref()?;
+            Some((ix, isrc)).filter(|(_, isrc)| !isrc.is_empty())
+        })
+        .take(MAX_FALLBACK_PREVIEW_LOOKUPS)
+        .collect()
+}
+
+/// Looks up a preview URL for the recording with `isrc` on Deezer.  Returns `None` if Deezer
+/// doesn't have the recording or a preview for it.
+async fn fetch_fallback_preview_url(isrc: &str) -> Result<Option<String>, String> {
+    #[derive(Deserialize)]
+    struct DeezerTrackResponse {
+        /// Missing if the lookup failed, in which case an `error` is returned instead
+        #[serde(default)]
+        pub preview: Option<String>,
+    }
+
+    let url = format!("{}{}", DEEZER_TRACK_BY_ISRC_URL, isrc);
+    let res = get_reqwest_client()
+        .await
+        .get(&url)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|err| format!("Error fetching fallback preview for isrc={}: {}", isrc, err))?;
+    let res: DeezerTrackResponse = res
+        .json()
+        .await
+        .map_err(|err| format!("Error decoding fallback preview for isrc={}: {}", isrc, err))?;
+    Ok(res.preview.filter(|preview| !preview.is_empty()))
+}
+
+/// Previews looked up from the fallback source, keyed by ISRC.  Lookups that found no preview are
+/// cached too so that they aren't repeated.
+const FALLBACK_PREVIEWS_CACHE_HASH_NAME: &str = "fallbackPreviews";
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedFallbackPreview {
+    preview_url: Option<String>,
+}
+
+/// Fills in preview URLs from a fallback source for tracks in any of `track_lists` that Spotify
+/// didn't return one for.  Only the tracks picked by `select_fallback_preview_candidates` from each
+/// list are looked up.  Lookups are cached in Redis by ISRC and uncached ones are run with at most
+/// `FALLBACK_PREVIEW_LOOKUP_CONCURRENCY` at once across all lists.  Failed lookups are logged and
+/// leave the track without a preview.
+///
+/// ISRCs only come with tracks fetched since `Track::external_ids` was added, so top tracks that
+/// were already cached in Redis before then don't get fallback previews until they're refetched.
+pub(crate) async fn fill_missing_preview_urls(mut track_lists: Vec<&mut [Track]>) {
+    let candidates: Vec<(usize, usize, String)> = track_lists
+        .iter()
+        .enumerate()
+        .flat_map(|(list_ix, tracks)| {
+            select_fallback_preview_candidates(tracks)
+                .into_iter()
+                .map(move |(track_ix, isrc)| (list_ix, track_ix, isrc.to_owned()))
+        })
+        .collect();
+    let mut isrcs: Vec<&str> = candidates
+        .iter()
+        .map(|(_, _, isrc)| isrc.as_str())
+        .collect();
+    isrcs.sort_unstable();
+    isrcs.dedup();
+
+    let cached = block_in_place(|| {
+        crate::cache::get_hash_items::<CachedFallbackPreview>(
+            FALLBACK_PREVIEWS_CACHE_HASH_NAME,
+            &isrcs,
+        )
+    })
+    .unwrap_or_else(|err| {
+        warn!("Error reading cached fallback previews: {}", err);
+        vec![None; isrcs.len()]
+    });
+    let mut previews_by_isrc: HashMap<&str, Option<String>> = HashMap::default();
+    let mut uncached_isrcs = Vec::new();
+    for (isrc, cached) in isrcs.into_iter().zip(cached) {
+        match cached {
+            Some(cached) => {
+                previews_by_isrc.insert(isrc, cached.preview_url);
+            },
+            None => uncached_isrcs.push(isrc),
+        }
+    }
+
+    let fetched: Vec<(&str, Option<String>)> = futures::stream::iter(uncached_isrcs)
+        .map(|isrc| async move {
+            match fetch_fallback_preview_url(isrc).await {
+                Ok(preview_url) => Some((isrc, preview_url)),
+                Err(err) => {
+                    warn!("{}", err);
+                    None
+                },
+            }
+        })
+        .buffer_unordered(FALLBACK_PREVIEW_LOOKUP_CONCURRENCY)
+        .filter_map(futures::future::ready)
+        .collect()
+        .await;
+    let to_cache: Vec<(&str, CachedFallbackPreview)> = fetched
+        .iter()
+        .map(|(isrc, preview_url)| {
+            let preview_url = preview_url.clone();
+            (*isrc, CachedFallbackPreview { preview_url })
+        })
+        .collect();
+    if let Err(err) = block_in_place(|| {
+        crate::cache::set_hash_items(FALLBACK_PREVIEWS_CACHE_HASH_NAME, &to_cache)
+    }) {
+        warn!("Error caching fallback previews: {}", err);
+    }
+    previews_by_isrc.extend(fetched);
+
+    for (list_ix, track_ix, isrc) in &candidates {
+        if let Some(Some(preview_url)) = previews_by_isrc.get(isrc.as_str()) {
+            track_lists[*list_ix][*track_ix].preview_url = Some(preview_url.clone());
+        }
+    }
+}
+
+/// Searches for artists matching `query` that are available in the Spotify `market` (an ISO
+/// 3166-1 alpha-2 country code)
 pub(crate) async fn search_artists(
     conn: &DbConn,
     bearer_token: String,
     query: &str,
+    market: &str,
 ) -> Result<Vec<ArtistSearchResult>, String> {
     #[derive(Clone, Debug, Deserialize)]
     struct SpotifyArtistsSearchResponseInner {
@@ -1049,10 +1310,7 @@ pub(crate) async fn search_artists(
         pub artists: SpotifyArtistsSearchResponseInner,
     }
 
-    let url = format!(
-        "https://api.spotify.com/v1/search?q={}&type=artist",
-        RawStr::new(query).percent_encode()
-    );
+    let url = search_artists_url(query, market);
     let res = spotify_server_get_request::<SpotifyArtistsSearchResponse>(
         &bearer_token,
         &url,
@@ -1074,3 +1332,275 @@ pub(crate) async fn search_artists(
         })
         .collect())
 }
+
+/// Matches picked by `pick_artist_search_match` need a confidence above this to be used.  Any
+/// match that isn't the only one with the artist's name is too ambiguous.
+pub(crate) const MIN_ARTIST_MATCH_CONFIDENCE: f32 = 0.5;
+
+/// Picks the search result that best matches an artist named `name`, returning its Spotify ID and
+/// a confidence between 0 and 1.  Only results whose names match case- and whitespace-insensitively
+/// are considered, and the confidence is split evenly between them when there are several since
+/// there's no way to tell which one is meant.  In that case the highest-ranked result is picked.
+pub(crate) fn pick_artist_search_match(
+    name: &str,
+    results: &[ArtistSearchResult],
+) -> Option<(String, f32)> {
+    let normalize = |name: &str| {
+        name.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    let name = normalize(name);
+
+    let mut matches = results
+        .iter()
+        .filter(|result| normalize(&result.name) == name);
+    let best_match = matches.next()?;
+    let match_count = 1 + matches.count();
+    Some((best_match.spotify_id.clone(), 1. / match_count as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Starts a server on a random local port that replies to the `i`th request it receives with
+    /// `responses[i]`.  Returns the server's URL and a counter of the requests it has received.
+    async fn start_mock_server(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let request_count = Arc::new(AtomicUsize::new(0));
+
+        let server_request_count = Arc::clone(&request_count);
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                server_request_count.fetch_add(1, Ordering::SeqCst);
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.unwrap();
+            }
+        });
+
+        (url, request_count)
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_retries_rate_limited_requests() {
+        let (url, request_count) = start_mock_server(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: \
+             close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+        ])
+        .await;
+
+        let client = reqwest::Client::new();
+        let res = send_with_retry(|| client.get(&url), "test", 3)
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(request_count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_in_batches_splits_requests() {
+        // Each response holds the number of items in the batch it's responding to
+        let full_batch =
+            "HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\n{\"n\":50}";
+        let partial_batch =
+            "HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\n{\"n\":20}";
+        let (url, request_count) =
+            start_mock_server(vec![full_batch, full_batch, partial_batch]).await;
+
+        let spotify_ids: Vec<String> = (0..120).map(|i| format!("artist_{}", i)).collect();
+        let spotify_ids: Vec<&str> = spotify_ids.iter().map(String::as_str).collect();
+        let mut batch_sizes = Vec::new();
+        let fetched = fetch_in_batches(
+            &url,
+            "test",
+            "token",
+            &spotify_ids,
+            1,
+            |res: serde_json::Value| Ok(vec![(); res["n"].as_u64().unwrap() as usize]),
+            |chunk, _| {
+                batch_sizes.push(chunk.len());
+                Ok(())
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(request_count.load(Ordering::SeqCst), 3);
+        assert_eq!(batch_sizes, vec![50, 50, 20]);
+        assert_eq!(fetched.len(), 120);
+    }
+
+    #[tokio::test]
+    async fn test_bounded_batches_report_failures_individually() {
+        let ids: Vec<String> = (0..10).map(|i| format!("artist_{}", i)).collect();
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+        // The second batch is rate limited partway through
+        let fetch = |id: String| {
+            let (in_flight, max_in_flight) = (&in_flight, &max_in_flight);
+            async move {
+                let now_in_flight = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(now_in_flight, Ordering::SeqCst);
+                tokio::task::yield_now().await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                match id.as_str() {
+                    "artist_5" | "artist_7" => Err(RATE_LIMITED_ERROR.to_string()),
+                    _ => Ok(id.to_uppercase()),
+                }
+            }
+        };
+        let mut batches = Vec::new();
+        let on_batch = |batch: &[&str], results: &[Result<String, String>]| {
+            let failed_count = results.iter().filter(|res| res.is_err()).count();
+            batches.push((batch.len(), failed_count));
+            Ok(())
+        };
+
+        let results = fetch_in_bounded_batches(&ids, 4, 2, fetch, on_batch)
+            .await
+            .unwrap();
+        assert_eq!(batches, vec![(4, 0), (4, 2), (2, 0)]);
+        assert!(max_in_flight.load(Ordering::SeqCst) <= 2);
+        assert_eq!(results.len(), 10);
+        assert_eq!(results[4], Ok("ARTIST_4".to_string()));
+        assert_eq!(results[5], Err(RATE_LIMITED_ERROR.to_string()));
+        assert_eq!(results.iter().filter(|res| res.is_ok()).count(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_local_cache_skips_fetch_for_cached_ids() {
+        let cache = Mutex::new(LruCache::new(10, None));
+        let fetched_ids = std::sync::Mutex::new(Vec::new());
+        let fetch = |ids: Vec<&str>| {
+            fetched_ids.lock().unwrap().push(ids.join(","));
+            let items = ids.iter().map(|id| id.to_uppercase()).collect();
+            async move { Ok(items) }
+        };
+
+        let items = fetch_with_local_cache(&cache, &["a", "b"], fetch)
+            .await
+            .unwrap();
+        assert_eq!(items, vec!["A", "B"]);
+        let items = fetch_with_local_cache(&cache, &["b", "a"], fetch)
+            .await
+            .unwrap();
+        assert_eq!(items, vec!["B", "A"]);
+        let items = fetch_with_local_cache(&cache, &["a", "c"], fetch)
+            .await
+            .unwrap();
+        assert_eq!(items, vec!["A", "C"]);
+
+        assert_eq!(*fetched_ids.lock().unwrap(), vec!["a,b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_gives_up_after_max_attempts() {
+        let (url, request_count) =
+            start_mock_server(vec![
+                "HTTP/1.1 503 Service Unavailable\r\nRetry-After: \
+                 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                3
+            ])
+            .await;
+
+        let client = reqwest::Client::new();
+        let res = send_with_retry(|| client.get(&url), "test", 2)
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(request_count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_select_fallback_preview_candidates() {
+        let track = |id: &str, preview_url: Option<&str>, isrc: Option<&str>| Track {
+            id: id.to_owned(),
+            preview_url: preview_url.map(str::to_owned),
+            external_ids: Some(ExternalIds {
+                isrc: isrc.map(str::to_owned),
+            }),
+            ..Track::new_unknown()
+        };
+
+        let tracks = vec![
+            track(
+                "has_preview",
+                Some("https://p.scdn.co/a"),
+                Some("USRC17607839"),
+            ),
+            track("no_isrc", None, None),
+            track("candidate", None, Some("GBAYE0601498")),
+            Track::new_unknown(),
+            track("empty_isrc", None, Some("")),
+        ];
+        assert_eq!(select_fallback_preview_candidates(&tracks), vec![(
+            2,
+            "GBAYE0601498"
+        )]);
+
+        // Lookups are capped per list of tracks
+        let tracks: Vec<Track> = (0..MAX_FALLBACK_PREVIEW_LOOKUPS + 3)
+            .map(|i| track(&format!("track_{}", i), None, Some("USRC17607839")))
+            .collect();
+        let candidates = select_fallback_preview_candidates(&tracks);
+        assert_eq!(candidates.len(), MAX_FALLBACK_PREVIEW_LOOKUPS);
+        assert_eq!(candidates[0].0, 0);
+    }
+
+    #[test]
+    fn test_market_is_included_in_request_urls() {
+        assert_eq!(
+            top_tracks_url("4Z8W4fKeB5YxbusRsdQVPb", "SE"),
+            "https://api.spotify.com/v1/artists/4Z8W4fKeB5YxbusRsdQVPb/top-tracks?market=SE"
+        );
+        assert_eq!(
+            search_artists_url("Bjork", "IS"),
+            "https://api.spotify.com/v1/search?q=Bjork&type=artist&market=IS"
+        );
+    }
+
+    #[test]
+    fn test_pick_artist_search_match() {
+        let result = |spotify_id: &str, name: &str| ArtistSearchResult {
+            spotify_id: spotify_id.to_string(),
+            internal_id: None,
+            name: name.to_string(),
+        };
+
+        let results = [result("1", "Björk Tribute"), result("2", "björk")];
+        assert_eq!(
+            pick_artist_search_match("Björk", &results),
+            Some(("2".to_string(), 1.))
+        );
+
+        let results = [result("1", "Nirvana"), result("2", "Nirvana ")];
+        let (spotify_id, confidence) = pick_artist_search_match("Nirvana", &results).unwrap();
+        assert_eq!((spotify_id.as_str(), confidence), ("1", 0.5));
+        // It's a coin flip which artist is meant, so the match isn't confident enough to be used
+        assert!(confidence <= MIN_ARTIST_MATCH_CONFIDENCE);
+
+        assert_eq!(
+            pick_artist_search_match("Artist  One", &[result("1", "Artist Two")]),
+            None
+        );
+    }
+}
@@ -1,5 +1,9 @@
+use std::{future::Future, time::Duration};
+
 use chrono;
 
+use crate::{conf::CONF, models::AccessTokenResponse};
+
 pub(crate) struct SpotifyTokenData {
     pub token: String,
     pub expiry: chrono::DateTime<chrono::Local>,
@@ -26,11 +30,36 @@ impl SpotifyTokenData {
     }
 
     pub(crate) async fn refresh(&mut self) -> Result<(), String> {
-        let crate::models::AccessTokenResponse {
+        self.refresh_with(
+            crate::spotify_api::fetch_auth_token(),
+            CONF.spotify_token_fetch_timeout,
+        )
+        .await
+    }
+
+    /// Sets the token returned by `fetch_token`.  Gives up with an error if it takes longer than
+    /// `timeout` so that a hanging token endpoint doesn't block everything waiting on the token.
+    async fn refresh_with(
+        &mut self,
+        fetch_token: impl Future<Output = Result<AccessTokenResponse, String>>,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let AccessTokenResponse {
             access_token,
             expires_in,
             ..
-        } = crate::spotify_api::fetch_auth_token().await?;
+        } = tokio::time::timeout(timeout, fetch_token)
+            .await
+            .map_err(|_| {
+                error!(
+                    "Timed out after {:?} fetching Spotify access token",
+                    timeout
+                );
+                format!(
+                    "Upstream timeout: Spotify didn't issue an access token within {:?}",
+                    timeout
+                )
+            })??;
         self.token = access_token;
         info!(
             "Got new Spotify access token; expires in: {} seconds",
@@ -57,3 +86,26 @@ impl SpotifyTokenData {
         Ok(self.token.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SpotifyTokenData;
+
+    #[tokio::test]
+    async fn test_token_refresh_times_out() {
+        let expiry = chrono::Local::now();
+        let mut token_data = SpotifyTokenData {
+            token: String::new(),
+            expiry,
+        };
+
+        let err = token_data
+            .refresh_with(std::future::pending(), Duration::from_millis(20))
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Upstream timeout"));
+        assert_eq!(token_data.expiry, expiry);
+    }
+}
@@ -1,70 +1,18 @@
 use std::cmp::Reverse;
 
 use chrono::NaiveDateTime;
-use fnv::{FnvHashMap as HashMap, FnvHashSet as HashSet};
+use fnv::FnvHashMap as HashMap;
 
 use crate::models::{Artist, TimeFrames};
 
 /// This is a pretty arbitrary algorithm with the goal of assigning a score to an item based on how
 /// many total items there are and the item's rank in the collection.  It is used to construct the
 /// genres treemap on the frontend.
-fn weight_data_point(total_items: usize, ranking: usize) -> usize {
+pub(crate) fn weight_data_point(total_items: usize, ranking: usize) -> usize {
     (((total_items - ranking) as f32)
         .powf(2.7 * ((total_items - ranking) as f32 / total_items as f32))) as usize
 }
 
-/// Give an array of top artists, extrapolates the most listened-to genres for each update.
-pub(crate) fn get_top_genres_by_artists(
-    artists_by_id: &HashMap<String, Artist>,
-    updates: &[(NaiveDateTime, TimeFrames<String>)],
-    weight: bool,
-) -> (Vec<NaiveDateTime>, HashMap<String, Vec<Option<usize>>>) {
-    let mut all_timestamps: Vec<NaiveDateTime> = Vec::with_capacity(updates.len());
-    let mut all_genre_counts: Vec<HashMap<String, usize>> = Vec::new();
-    let mut all_genres: HashSet<String> = HashSet::default();
-
-    for (dt, update) in updates {
-        all_timestamps.push(*dt);
-        let mut genre_counts = HashMap::default();
-
-        for (_tf, artist_ids) in update.iter() {
-            let artist_count = artist_ids.len();
-
-            for (i, artist_id) in artist_ids.into_iter().enumerate() {
-                let artist = artists_by_id.get(&*artist_id);
-                if let Some(genres) =
-                    artist.and_then(|artist| artist.genres.as_ref().map(|v| v.as_slice()))
-                {
-                    for genre in genres {
-                        all_genres.insert(genre.clone());
-                        let count = genre_counts.entry(genre.clone()).or_insert(0);
-                        *count += if weight {
-                            weight_data_point(artist_count, i)
-                        } else {
-                            1
-                        };
-                    }
-                }
-            }
-        }
-
-        all_genre_counts.push(genre_counts);
-    }
-
-    let mut counts_by_genre = HashMap::default();
-    for genre in all_genres {
-        counts_by_genre.insert(genre, Vec::with_capacity(all_timestamps.len()));
-    }
-    for counts_by_genre_for_update in all_genre_counts {
-        for (genre, scores) in counts_by_genre.iter_mut() {
-            let count_for_period = counts_by_genre_for_update.get(genre).copied();
-            scores.push(count_for_period);
-        }
-    }
-
-    (all_timestamps, counts_by_genre)
-}
-
 /// Gets a list of all tracks for a given artist that a user has ever had in their top tracks for
 /// any time period, sorted by their frequency of appearance and ranking when appeared.
 pub(crate) fn compute_track_popularity_scores(
@@ -130,3 +78,95 @@ pub(crate) fn compute_genre_ranking_history(
 
     (timestamps, artist_rankings, popularity_history)
 }
+
+/// Finds the genres that appear among both users' top artists, sorted by their combined weight.
+/// Artists are given as `(timeframe_id, artist)` from each user's latest update, ordered by rank
+/// within each timeframe.  Each genre is weighted by the ranks of the artists it belongs to within
+/// their timeframe, the same way as for the genres treemap.
+pub(crate) fn get_shared_genres(
+    user1_artists: &[(u8, Artist)],
+    user2_artists: &[(u8, Artist)],
+) -> Vec<String> {
+    let weigh_genres = |artists: &[(u8, Artist)]| {
+        let mut artists_by_timeframe: HashMap<u8, Vec<&Artist>> = HashMap::default();
+        for (timeframe_id, artist) in artists {
+            artists_by_timeframe
+                .entry(*timeframe_id)
+                .or_insert_with(Vec::new)
+                .push(artist);
+        }
+
+        let mut weights: HashMap<&str, usize> = HashMap::default();
+        for artists in artists_by_timeframe.values() {
+            for (i, artist) in artists.iter().enumerate() {
+                for genre in artist.genres.iter().flatten() {
+                    *weights.entry(genre.as_str()).or_insert(0) +=
+                        weight_data_point(artists.len(), i);
+                }
+            }
+        }
+        weights
+    };
+    let user1_weights = weigh_genres(user1_artists);
+    let user2_weights = weigh_genres(user2_artists);
+
+    let mut shared_genres: Vec<(&str, usize)> = user1_weights
+        .into_iter()
+        .filter_map(|(genre, weight)| {
+            user2_weights
+                .get(genre)
+                .map(|other_weight| (genre, weight + other_weight))
+        })
+        .collect();
+    shared_genres.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    shared_genres
+        .into_iter()
+        .map(|(genre, _)| genre.to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artist(id: &str, genres: &[&str]) -> (u8, Artist) {
+        (0, Artist {
+            genres: Some(genres.iter().map(|genre| genre.to_string()).collect()),
+            id: id.to_string(),
+            images: None,
+            name: id.to_string(),
+            popularity: None,
+        })
+    }
+
+    #[test]
+    fn test_shared_genres() {
+        let user1_artists = vec![artist("a", &["rock", "indie"]), artist("b", &["jazz"])];
+        let user2_artists = vec![artist("c", &["pop"]), artist("d", &["indie", "folk"])];
+
+        assert_eq!(get_shared_genres(&user1_artists, &user2_artists), vec![
+            "indie".to_string()
+        ]);
+        assert!(get_shared_genres(&user1_artists, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_shared_genres_weighted_by_rank() {
+        let user1_artists = vec![
+            artist("a", &["rock"]),
+            artist("b", &["indie"]),
+            artist("c", &["jazz"]),
+        ];
+        let user2_artists = vec![
+            artist("d", &["rock"]),
+            artist("e", &["indie"]),
+            artist("f", &["jazz"]),
+        ];
+
+        assert_eq!(get_shared_genres(&user1_artists, &user2_artists), vec![
+            "rock".to_string(),
+            "indie".to_string(),
+            "jazz".to_string(),
+        ]);
+    }
+}
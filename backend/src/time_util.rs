@@ -0,0 +1,89 @@
+//! Helpers for bucketing timestamps by day, hour, weekday, or month in a user's local time.
+//!
+//! Timezones are given as a fixed `tz_offset_minutes` ahead of UTC (so UTC-5 is `-300`), matching
+//! the `tz_offset_minutes` param accepted by routes.  DST isn't accounted for; the same offset is
+//! applied to every timestamp.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc, Weekday};
+
+/// The parts of a timestamp in a user's local time that listening is bucketed by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct LocalTimeParts {
+    pub date: NaiveDate,
+    /// Hour of the day (0-23)
+    pub hour: u32,
+    pub weekday: Weekday,
+}
+
+/// Largest offset from UTC of any real timezone, in minutes (UTC+14)
+pub(crate) const MAX_TZ_OFFSET_MINUTES: i32 = 14 * 60;
+
+/// Converts a UTC timestamp into local time for a user `tz_offset_minutes` ahead of UTC
+pub(crate) fn to_local_time(timestamp: DateTime<Utc>, tz_offset_minutes: i32) -> NaiveDateTime {
+    timestamp.naive_utc() + Duration::minutes(tz_offset_minutes as i64)
+}
+
+/// Returns the local date, hour, and weekday of a UTC timestamp for a user `tz_offset_minutes`
+/// ahead of UTC
+pub(crate) fn local_time_parts(timestamp: DateTime<Utc>, tz_offset_minutes: i32) -> LocalTimeParts {
+    let local_time = to_local_time(timestamp, tz_offset_minutes);
+    LocalTimeParts {
+        date: local_time.date(),
+        hour: local_time.hour(),
+        weekday: local_time.weekday(),
+    }
+}
+
+/// Returns the UTC time at which `day` starts for a user `tz_offset_minutes` ahead of UTC
+pub(crate) fn local_day_start_to_utc(day: NaiveDate, tz_offset_minutes: i32) -> NaiveDateTime {
+    day.and_hms_opt(0, 0, 0).unwrap() - Duration::minutes(tz_offset_minutes as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc, Weekday};
+
+    use super::{local_day_start_to_utc, local_time_parts, LocalTimeParts};
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_negative_offset_rolls_back_a_day() {
+        // Monday 2023-01-02 03:30 UTC is still Sunday evening in UTC-5
+        let timestamp = Utc.with_ymd_and_hms(2023, 1, 2, 3, 30, 0).unwrap();
+        assert_eq!(local_time_parts(timestamp, -300), LocalTimeParts {
+            date: date(2023, 1, 1),
+            hour: 22,
+            weekday: Weekday::Sun,
+        });
+        assert_eq!(local_time_parts(timestamp, 0), LocalTimeParts {
+            date: date(2023, 1, 2),
+            hour: 3,
+            weekday: Weekday::Mon,
+        });
+    }
+
+    #[test]
+    fn test_fixed_offsets() {
+        // Fixed offsets are applied as-is regardless of the time of year, including partial hours
+        let winter = Utc.with_ymd_and_hms(2023, 1, 15, 20, 0, 0).unwrap();
+        let summer = Utc.with_ymd_and_hms(2023, 7, 15, 20, 0, 0).unwrap();
+        assert_eq!(local_time_parts(winter, 330).hour, 1);
+        assert_eq!(local_time_parts(summer, 330).hour, 1);
+        assert_eq!(local_time_parts(winter, 330).date, date(2023, 1, 16));
+        assert_eq!(local_time_parts(summer, 480).weekday, Weekday::Sun);
+    }
+
+    #[test]
+    fn test_local_day_start_to_utc() {
+        let utc = |day, hour| date(2023, 3, day).and_hms_opt(hour, 0, 0).unwrap();
+        assert_eq!(local_day_start_to_utc(date(2023, 3, 1), 0), utc(1, 0));
+        assert_eq!(local_day_start_to_utc(date(2023, 3, 1), -300), utc(1, 5));
+        assert_eq!(
+            local_day_start_to_utc(date(2023, 3, 1), 480),
+            date(2023, 2, 28).and_hms_opt(16, 0, 0).unwrap()
+        );
+    }
+}
@@ -9,7 +9,7 @@ export const getArtistAutocompleteSuggestions = (
   ) => Promise<AutocompleteSuggestion[]> | AutocompleteSuggestion[]
 ): Promise<AutocompleteSuggestion[]> => {
   const url = `${API_BASE_URL}/search_artist?q=${encodeURIComponent(query)}`;
-  return fetch(url).then(async (res) => filterResults(await res.json()));
+  return fetch(url).then(async (res) => filterResults((await res.json()).results));
 };
 
 export interface AverageArtistItem {
@@ -24,6 +24,7 @@ export interface AverageArtistsResponse {
   artists: AverageArtistItem[];
   similarity: number;
   distance: number;
+  partial: boolean;
 }
 
 export const getAverageArtists = (
@@ -916,7 +916,8 @@ pub fn handle_artist_relationship_data(
         .skip(chunk_ix as usize)
         .next()
         .unwrap_or_default();
-    let artist_ids_byte_offset = artist_ids.len() + 4 - (artist_ids.len() % 4);
+    // Related artist counts are padded to a multiple of 4 bytes, with no padding if already aligned
+    let artist_ids_byte_offset = artist_ids.len() + (4 - (artist_ids.len() % 4)) % 4;
 
     assert_eq!(packed_relationship_data.len() % 4, 0);
     let u32_view = unsafe {
@@ -95,6 +95,7 @@ export type TimelineEvent = {
 
 export interface TimelineData {
   events: TimelineEvent[];
+  total: number;
   // firstUpdate: Date;
 }
 